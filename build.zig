@@ -4,12 +4,58 @@ const SupportedArchs = enum {
     x86_64,
 };
 
+/// Binary assets embedded into the kernel, reachable with `@embedFile(name)`.
+const kernel_assets = [_]struct { name: []const u8, path: []const u8 }{
+    .{ .name = "console_font", .path = "assets/fonts/console-8x16.psf" },
+};
+
+/// Vectors for which the CPU pushes an error code onto the stack.
+const error_code_vectors = [_]u8{ 8, 10, 11, 12, 13, 14, 17, 21, 29, 30 };
+
+/// Where the kernel heap places allocations among the free blocks that fit.
+const HeapPolicy = enum {
+    first_fit,
+    next_fit,
+    best_fit,
+};
+
+/// Compile-time configuration exposed to the kernel as `build_options`.
+const KernelOptions = struct {
+    fault_injection: bool,
+    mitigations: bool,
+    heap_policy: HeapPolicy,
+    legacy_pic: bool,
+    frame_owners: bool,
+    testing: bool,
+
+    fn create(self: KernelOptions, b: *std.Build) *std.Build.Step.Options {
+        const options = b.addOptions();
+        inline for (std.meta.fields(KernelOptions)) |field| {
+            options.addOption(field.type, field.name, @field(self, field.name));
+        }
+        return options;
+    }
+};
+
 pub fn build(b: *std.Build) void {
     const arch = b.option(SupportedArchs, "arch", "Target Architecture") orelse .x86_64;
     const optimize = b.standardOptimizeOption(.{});
 
-    const kernel = configure_kernel(b, arch, optimize);
-    const iso = prepare_iso(b, kernel, arch, optimize);
+    var options = KernelOptions{
+        .fault_injection = b.option(bool, "fault-injection", "Randomly fail allocations and I/O to exercise error paths") orelse false,
+        .mitigations = b.option(bool, "mitigations", "Enable speculative execution mitigations (IBRS/STIBP/SSBD)") orelse false,
+        .heap_policy = b.option(HeapPolicy, "heap-policy", "Default placement policy of the kernel heap") orelse .first_fit,
+        .legacy_pic = b.option(bool, "legacy-pic", "Deliver device interrupts through the 8259 PICs instead of the APIC") orelse false,
+        .frame_owners = b.option(bool, "frame-owners", "Record which subsystem allocated every physical frame") orelse false,
+        .testing = false,
+    };
+
+    const kernel = configure_kernel(b, arch, optimize, options.create(b));
+    const iso = prepare_iso(b, embed_symbols(b, kernel), arch, optimize);
+
+    options.testing = true;
+    const test_kernel = configure_kernel(b, arch, optimize, options.create(b));
+    const test_iso = prepare_iso(b, embed_symbols(b, test_kernel), arch, optimize);
 
     {
         const compile_kernel = b.step("kernel", "Compile the kernel");
@@ -35,14 +81,43 @@ pub fn build(b: *std.Build) void {
             "smm=off",
             "-device",
             "isa-debug-exit,iobase=0xf4,iosize=0x0f",
+            "-netdev",
+            "user,id=net0,hostfwd=tcp::8080-:80",
+            "-device",
+            "virtio-net-pci,netdev=net0",
             "-cdrom",
         });
         qemu.addFileArg(iso.source);
         run_iso.dependOn(&qemu.step);
     }
+
+    {
+        const tools = b.step("tools", "Build the host-side tools");
+        const trace_decode = b.addExecutable(.{
+            .name = "trace_decode",
+            .root_source_file = b.path("tools/trace_decode.zig"),
+            .target = b.host,
+            .optimize = optimize,
+        });
+        tools.dependOn(&b.addInstallArtifact(trace_decode, .{}).step);
+    }
+
+    {
+        const run_tests = b.step("test", "Run the in-kernel tests in QEMU");
+        const test_runner = b.addExecutable(.{
+            .name = "test_runner",
+            .root_source_file = b.path("tools/test_runner.zig"),
+            .target = b.host,
+            .optimize = .Debug,
+        });
+        const runner = b.addRunArtifact(test_runner);
+        runner.addArg("qemu-system-" ++ @tagName(arch));
+        runner.addFileArg(test_iso.source);
+        run_tests.dependOn(&runner.step);
+    }
 }
 
-pub fn configure_kernel(b: *std.Build, arch: SupportedArchs, optimize: std.builtin.OptimizeMode) *std.Build.Step.Compile {
+pub fn configure_kernel(b: *std.Build, arch: SupportedArchs, optimize: std.builtin.OptimizeMode, options: *std.Build.Step.Options) *std.Build.Step.Compile {
     const limine_zig = b.dependency("limine_zig", .{});
     const target = configure_target(b, arch);
 
@@ -54,6 +129,11 @@ pub fn configure_kernel(b: *std.Build, arch: SupportedArchs, optimize: std.built
         .pic = true,
     });
     kernel_libs.addImport("kernel", kernel_libs);
+    kernel_libs.addImport("limine", limine_zig.module("limine"));
+    kernel_libs.addOptions("build_options", options);
+    for (kernel_assets) |asset| {
+        kernel_libs.addAnonymousImport(asset.name, .{ .root_source_file = b.path(asset.path) });
+    }
 
     switch (arch) {
         .x86_64 => {
@@ -68,17 +148,70 @@ pub fn configure_kernel(b: *std.Build, arch: SupportedArchs, optimize: std.built
 
             kernel.addAssemblyFile(b.path("kernel/arch/x86_64/load_gdt.S"));
             kernel.addAssemblyFile(b.path("kernel/arch/x86_64/interrupt_handlers.S"));
+            kernel.addAssemblyFile(generate_interrupt_stubs(b));
 
             kernel.root_module.addImport("limine", limine_zig.module("limine"));
             kernel.root_module.addImport("kernel", kernel_libs);
 
             kernel.want_lto = false; // Disable LTO. This prevents issues with limine requests
+            kernel.pie = true; // Keep the relocations so Limine can load the kernel anywhere (KASLR)
             kernel.setLinkerScriptPath(b.path("kernel/arch/x86_64/linker.ld"));
             return kernel;
         },
     }
 }
 
+/// Generates one entry stub per interrupt vector plus `interrupt_stub_table`,
+/// the array of their addresses the IDT is filled from. Stubs for vectors
+/// without a CPU-pushed error code push a zero so every frame looks alike.
+pub fn generate_interrupt_stubs(b: *std.Build) std.Build.LazyPath {
+    var source = std.ArrayList(u8).init(b.allocator);
+    const writer = source.writer();
+
+    writer.writeAll(
+        \\.code64
+        \\.intel_syntax noprefix
+        \\.text
+        \\
+        \\
+    ) catch @panic("OOM");
+
+    for (0..256) |vector| {
+        writer.print(
+            \\.align 16
+            \\.global interrupt_handler{0}
+            \\interrupt_handler{0}:
+            \\
+        , .{vector}) catch @panic("OOM");
+
+        if (std.mem.indexOfScalar(u8, &error_code_vectors, @intCast(vector)) == null) {
+            writer.writeAll("  push qword ptr 0\n") catch @panic("OOM");
+        }
+
+        writer.print(
+            \\  push qword ptr {0}
+            \\  jmp common_interrupt_handler
+            \\
+            \\
+        , .{vector}) catch @panic("OOM");
+    }
+
+    writer.writeAll(
+        \\.section .data.rel.ro
+        \\.align 8
+        \\.global interrupt_stub_table
+        \\interrupt_stub_table:
+        \\
+    ) catch @panic("OOM");
+
+    for (0..256) |vector| {
+        writer.print("  .quad interrupt_handler{}\n", .{vector}) catch @panic("OOM");
+    }
+
+    const files = b.addWriteFiles();
+    return files.add("interrupt_stubs.S", source.items);
+}
+
 pub fn configure_target(b: *std.Build, arch: SupportedArchs) std.Build.ResolvedTarget {
     var target: std.zig.CrossTarget = .{
         .cpu_arch = .x86_64,
@@ -105,11 +238,42 @@ pub fn configure_target(b: *std.Build, arch: SupportedArchs) std.Build.ResolvedT
     }
 }
 
-pub fn prepare_iso(b: *std.Build, kernel: *std.Build.Step.Compile, arch: SupportedArchs, optimize: std.builtin.OptimizeMode) *std.Build.Step.InstallFile {
+/// Writes the kernel's symbol map into its `.kernel_symbols` section, which
+/// grows to fit the map, returning the patched kernel.
+pub fn embed_symbols(b: *std.Build, kernel: *std.Build.Step.Compile) std.Build.LazyPath {
+    const tool = b.addExecutable(.{
+        .name = "symbol_map",
+        .root_source_file = b.path("tools/symbol_map.zig"),
+        .target = b.host,
+        .optimize = .Debug,
+    });
+
+    const map = b.addRunArtifact(tool);
+    map.addFileArg(kernel.getEmittedBin());
+    const symbols = map.addOutputFileArg("kernel.sym");
+
+    const objcopy = b.addSystemCommand(&.{ "objcopy", "--update-section" });
+    objcopy.addPrefixedFileArg(".kernel_symbols=", symbols);
+    objcopy.addFileArg(kernel.getEmittedBin());
+    return objcopy.addOutputFileArg("kernel");
+}
+
+/// Packs the `initramfs` directory into the ustar archive Limine loads as a
+/// module next to the kernel.
+pub fn pack_initramfs(b: *std.Build) std.Build.LazyPath {
+    const tar = b.addSystemCommand(&.{ "tar", "--format=ustar", "--owner=0", "--group=0", "-cf" });
+    const archive = tar.addOutputFileArg("initramfs.tar");
+    tar.addArg("-C");
+    tar.addDirectoryArg(b.path("initramfs"));
+    tar.addArg(".");
+    return archive;
+}
+
+pub fn prepare_iso(b: *std.Build, kernel: std.Build.LazyPath, arch: SupportedArchs, optimize: std.builtin.OptimizeMode) *std.Build.Step.InstallFile {
     const limine = b.dependency("limine", .{});
     const limine_exe = b.addExecutable(.{
         .name = "limine",
-        .target = b.standardTargetOptions(.{}),
+        .target = b.host,
         .optimize = optimize,
     });
     limine_exe.addCSourceFile(.{ .file = limine.path("limine.c"), .flags = &.{"-std=c99"} });
@@ -121,7 +285,8 @@ pub fn prepare_iso(b: *std.Build, kernel: *std.Build.Step.Compile, arch: Support
     _ = iso_root.addCopyFile(limine.path("limine-uefi-cd.bin"), "boot/limine/limine-uefi-cd.bin");
     _ = iso_root.addCopyFile(limine.path("BOOTX64.EFI"), "boot/EFI/BOOT/BOOTX64.EFI");
     _ = iso_root.addCopyFile(limine.path("BOOTIA32.EFI"), "boot/EFI/BOOT/BOOTIA32.EFI");
-    _ = iso_root.addCopyFile(kernel.getEmittedBin(), "boot/kernel");
+    _ = iso_root.addCopyFile(kernel, "boot/kernel");
+    _ = iso_root.addCopyFile(pack_initramfs(b), "boot/initramfs.tar");
     _ = iso_root.addCopyFile(b.path("limine.cfg"), "limine.cfg");
 
     const xorriso = b.addSystemCommand(&.{
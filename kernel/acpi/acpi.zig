@@ -0,0 +1,269 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log.scoped(.acpi);
+const kassert = @import("kernel").utils.kassert;
+const Error = @import("kernel").Error;
+
+pub const aml = @import("aml.zig");
+pub const madt = @import("madt.zig");
+pub const phys_read = @import("phys_read.zig");
+
+pub const SdtHeader = extern struct {
+    signature: [4]u8,
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [6]u8,
+    oem_table_id: [8]u8,
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+
+    const Self = @This();
+
+    /// The whole table, header included.
+    pub fn bytes(self: *const Self) []const u8 {
+        const start: [*]const u8 = @ptrCast(self);
+        return start[0..self.length];
+    }
+};
+
+const Rsdp = extern struct {
+    signature: [8]u8,
+    checksum: u8,
+    oem_id: [6]u8,
+    revision: u8,
+    rsdt_address: u32,
+
+    // only valid when revision >= 2
+    length: u32,
+    xsdt_address: u64 align(4),
+    extended_checksum: u8,
+    __reserved: [3]u8,
+};
+
+comptime {
+    kassert.comptimeAssert(@sizeOf(SdtHeader) == 36, "SdtHeader is {} bytes, not 36", .{@sizeOf(SdtHeader)});
+    kassert.comptimeAssert(@sizeOf(Rsdp) == 36, "Rsdp is {} bytes, not 36", .{@sizeOf(Rsdp)});
+}
+
+// Offsets of the DSDT pointers inside the FADT.
+const FADT_DSDT_OFFSET = 40;
+const FADT_X_DSDT_OFFSET = 140;
+
+// Offsets of the fields inside the FADT needed to power off.
+const FADT_SMI_COMMAND_OFFSET = 48;
+const FADT_ACPI_ENABLE_OFFSET = 52;
+const FADT_PM1A_CONTROL_OFFSET = 64;
+const FADT_PM1B_CONTROL_OFFSET = 68;
+
+// Offset of the CMOS index of the RTC's century register, 0 if there is none.
+const FADT_CENTURY_OFFSET = 108;
+
+const PM1_SCI_ENABLE = 1 << 0;
+const PM1_SLEEP_TYPE_SHIFT = 10;
+const PM1_SLEEP_ENABLE = 1 << 13;
+const PM1_SLEEP_MASK = 0x7 << PM1_SLEEP_TYPE_SHIFT | PM1_SLEEP_ENABLE;
+
+const MAX_TABLES = 32;
+
+// NOTE:
+// Firmware tables are copied into kernel-owned memory as soon as they are
+// validated, nothing holds on to the firmware's copy afterwards. This keeps
+// the ACPI reclaimable regions free to be handed to the memory manager.
+var tables: [MAX_TABLES]*const SdtHeader = undefined;
+var table_count: usize = 0;
+
+fn sum(bytes: []const u8) u8 {
+    var total: u8 = 0;
+    for (bytes) |byte| {
+        total +%= byte;
+    }
+    return total;
+}
+
+/// Validates the table at `physical` in place and copies it into kernel
+/// memory.
+fn copyTable(physical: u64) Error!*const SdtHeader {
+    const header = try phys_read.bytes(physical, @sizeOf(SdtHeader));
+    const length = try phys_read.field(u32, header, @offsetOf(SdtHeader, "length"));
+    if (length < @sizeOf(SdtHeader)) {
+        return error.Corrupted;
+    }
+
+    const source = try phys_read.bytes(physical, length);
+    if (sum(source) != 0) {
+        return error.Corrupted;
+    }
+
+    const copy = try memory.allocator().alignedAlloc(u8, @alignOf(SdtHeader), source.len);
+    @memcpy(copy, source);
+    return @ptrCast(copy.ptr);
+}
+
+fn register(table: *const SdtHeader) Error!void {
+    if (table_count == MAX_TABLES) {
+        return error.OutOfMemory;
+    }
+
+    tables[table_count] = table;
+    table_count += 1;
+
+    log.info("{s} rev {} ({} bytes, OEM '{s}')", .{
+        table.signature,
+        table.revision,
+        table.length,
+        table.oem_id,
+    });
+}
+
+fn addTable(physical: u64) void {
+    const table = copyTable(physical) catch |err| {
+        log.warn("Skipping table at 0x{x}: {s}", .{ physical, @errorName(err) });
+        return;
+    };
+
+    register(table) catch {
+        log.warn("Too many tables, dropping {s}", .{table.signature});
+    };
+}
+
+pub fn init() Error!void {
+    const response = boot.rsdp_request.response orelse return error.NotFound;
+    const rsdp: *align(1) const Rsdp = @ptrCast(response.address);
+
+    if (!std.mem.eql(u8, &rsdp.signature, "RSD PTR ")) {
+        return error.Corrupted;
+    }
+    if (sum(std.mem.asBytes(rsdp)[0..20]) != 0) {
+        return error.Corrupted;
+    }
+
+    const use_xsdt = rsdp.revision >= 2 and rsdp.xsdt_address != 0;
+    const root = try copyTable(if (use_xsdt) rsdp.xsdt_address else rsdp.rsdt_address);
+
+    const entry_size: usize = if (use_xsdt) 8 else 4;
+    const entries = root.bytes()[@sizeOf(SdtHeader)..];
+
+    var offset: usize = 0;
+    while (offset + entry_size <= entries.len) : (offset += entry_size) {
+        const physical = if (use_xsdt)
+            try phys_read.field(u64, entries, offset)
+        else
+            try phys_read.field(u32, entries, offset);
+
+        addTable(physical);
+    }
+
+    // the DSDT is only referenced from the FADT
+    if (findHeader("FACP")) |fadt| {
+        const bytes = fadt.bytes();
+        // older FADTs end before the 64-bit field
+        var dsdt = phys_read.field(u64, bytes, FADT_X_DSDT_OFFSET) catch 0;
+        if (dsdt == 0) {
+            dsdt = phys_read.field(u32, bytes, FADT_DSDT_OFFSET) catch 0;
+        }
+        if (dsdt != 0) {
+            addTable(dsdt);
+        }
+    }
+
+    log.info("Copied {} tables into kernel memory", .{table_count});
+
+    aml.init();
+
+    madt.init() catch |err| {
+        log.warn("No usable MADT: {s}", .{@errorName(err)});
+    };
+}
+
+/// Every table copied at boot, in the order they were found.
+pub fn allTables() []const *const SdtHeader {
+    return tables[0..table_count];
+}
+
+/// Looks up a table by its signature, e.g. "APIC" for the MADT.
+pub fn findHeader(signature: *const [4]u8) ?*const SdtHeader {
+    return findHeaderAt(signature, 0);
+}
+
+/// Like `findHeader`, but returns the `index`-th table with that signature
+/// for tables that may appear more than once (SSDTs).
+pub fn findHeaderAt(signature: *const [4]u8, index: usize) ?*const SdtHeader {
+    var remaining = index;
+    for (tables[0..table_count]) |table| {
+        if (!std.mem.eql(u8, &table.signature, signature)) {
+            continue;
+        }
+        if (remaining == 0) {
+            return table;
+        }
+        remaining -= 1;
+    }
+    return null;
+}
+
+/// The CMOS register holding the RTC's century, if the firmware keeps one.
+pub fn rtcCenturyRegister() ?u8 {
+    const fadt = findHeader("FACP") orelse return null;
+    const register = phys_read.field(u8, fadt.bytes(), FADT_CENTURY_OFFSET) catch return null;
+    return if (register == 0) null else register;
+}
+
+/// An I/O port the FADT points to, 0 when the field is unused.
+fn fadtPort(fadt: []const u8, offset: usize) Error!u16 {
+    const value = try phys_read.field(u32, fadt, offset);
+    return std.math.cast(u16, value) orelse error.Corrupted;
+}
+
+/// Switches from legacy mode to ACPI mode if the firmware left the system
+/// in the former, otherwise writes to the PM1 control registers are ignored.
+fn enableAcpiMode(fadt: []const u8, pm1a_control: u16) Error!void {
+    const arch = @import("kernel").arch;
+    if (arch.cpu.readWord(pm1a_control) & PM1_SCI_ENABLE != 0) {
+        return;
+    }
+
+    const smi_command = try fadtPort(fadt, FADT_SMI_COMMAND_OFFSET);
+    const acpi_enable = try phys_read.field(u8, fadt, FADT_ACPI_ENABLE_OFFSET);
+    if (smi_command == 0 or acpi_enable == 0) {
+        return error.Unsupported;
+    }
+
+    arch.cpu.writeByte(smi_command, acpi_enable);
+    var attempts: usize = 0;
+    while (arch.cpu.readWord(pm1a_control) & PM1_SCI_ENABLE == 0) : (attempts += 1) {
+        if (attempts == 1_000_000) {
+            return error.Timeout;
+        }
+        std.atomic.spinLoopHint();
+    }
+}
+
+/// Puts the machine into the soft-off state (S5). Only returns when that
+/// is not possible or did not take.
+pub fn powerOff() Error!void {
+    const arch = @import("kernel").arch;
+    const fadt = (findHeader("FACP") orelse return error.NotFound).bytes();
+    const sleep_type = aml.s5SleepType() orelse return error.NotFound;
+
+    const pm1a_control = try fadtPort(fadt, FADT_PM1A_CONTROL_OFFSET);
+    const pm1b_control = try fadtPort(fadt, FADT_PM1B_CONTROL_OFFSET);
+    if (pm1a_control == 0) {
+        return error.Unsupported;
+    }
+
+    try enableAcpiMode(fadt, pm1a_control);
+
+    // keep SCI_EN and the other bits, only SLP_TYP and SLP_EN change
+    const sleep_a = (sleep_type.a & 0x7) << PM1_SLEEP_TYPE_SHIFT | PM1_SLEEP_ENABLE;
+    arch.cpu.writeWord(pm1a_control, arch.cpu.readWord(pm1a_control) & ~@as(u16, PM1_SLEEP_MASK) | sleep_a);
+    if (pm1b_control != 0) {
+        const sleep_b = (sleep_type.b & 0x7) << PM1_SLEEP_TYPE_SHIFT | PM1_SLEEP_ENABLE;
+        arch.cpu.writeWord(pm1b_control, arch.cpu.readWord(pm1b_control) & ~@as(u16, PM1_SLEEP_MASK) | sleep_b);
+    }
+
+    // the machine should be off by now
+    return error.Timeout;
+}
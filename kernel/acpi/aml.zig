@@ -0,0 +1,698 @@
+const std = @import("std");
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log.scoped(.aml);
+
+const acpi = @import("acpi.zig");
+
+// NOTE:
+// This is not a full AML interpreter. It walks the DSDT/SSDTs, follows scopes
+// and devices and records every `Name` whose value is a constant: integers,
+// strings, buffers, packages and references to other names. Method bodies and
+// anything that needs evaluation are skipped, which is enough for the `_S5`
+// package, method-less `_PRT` tables and link devices whose `_CRS` is a
+// constant resource template. Everything with a PkgLength can be skipped
+// whole without understanding it. The length of anything else depends on its
+// operands, so when an unsupported opcode shows up the parser looks for the
+// next byte that starts a definition it knows, a `Name`, `Scope`, `Method` or
+// `Device` whose PkgLength fits the enclosing scope and whose name is well
+// formed, and carries on from there. Only when there is none is the rest of
+// the scope skipped.
+
+const ZERO_OP = 0x00;
+const ONE_OP = 0x01;
+const ALIAS_OP = 0x06;
+const NAME_OP = 0x08;
+const BYTE_PREFIX = 0x0A;
+const WORD_PREFIX = 0x0B;
+const DWORD_PREFIX = 0x0C;
+const STRING_PREFIX = 0x0D;
+const QWORD_PREFIX = 0x0E;
+const SCOPE_OP = 0x10;
+const BUFFER_OP = 0x11;
+const PACKAGE_OP = 0x12;
+const VAR_PACKAGE_OP = 0x13;
+const METHOD_OP = 0x14;
+const EXTERNAL_OP = 0x15;
+const DUAL_NAME_PREFIX = 0x2E;
+const MULTI_NAME_PREFIX = 0x2F;
+const EXT_OP_PREFIX = 0x5B;
+const ROOT_CHAR = '\\';
+const PARENT_PREFIX = '^';
+const IF_OP = 0xA0;
+const ELSE_OP = 0xA1;
+const WHILE_OP = 0xA2;
+const ONES_OP = 0xFF;
+
+const MUTEX_OP = 0x01;
+const EVENT_OP = 0x02;
+const REVISION_OP = 0x30;
+const OP_REGION_OP = 0x80;
+const FIELD_OP = 0x81;
+const DEVICE_OP = 0x82;
+const PROCESSOR_OP = 0x83;
+const POWER_RES_OP = 0x84;
+const THERMAL_ZONE_OP = 0x85;
+const INDEX_FIELD_OP = 0x86;
+const BANK_FIELD_OP = 0x87;
+
+const MAX_DEPTH = 16;
+
+const ParseError = error{ Truncated, Unsupported, OutOfMemory };
+
+pub const Segment = [4]u8;
+
+/// An absolute path in the ACPI namespace.
+pub const Path = struct {
+    segments: [MAX_DEPTH]Segment = undefined,
+    len: u8 = 0,
+
+    const Self = @This();
+
+    /// Parses a dotted path such as `\_SB_.PCI0._PRT`, short segments are
+    /// padded with underscores like the AML compiler does.
+    pub fn parse(text: []const u8) ?Self {
+        var path = Self{};
+        var parts = std.mem.tokenizeScalar(u8, std.mem.trimLeft(u8, text, "\\"), '.');
+        while (parts.next()) |part| {
+            if (part.len > 4) {
+                return null;
+            }
+            var segment: Segment = "____".*;
+            @memcpy(segment[0..part.len], part);
+            path.append(segment) catch return null;
+        }
+        return path;
+    }
+
+    fn append(self: *Self, segment: Segment) ParseError!void {
+        if (self.len == MAX_DEPTH) {
+            return error.Unsupported;
+        }
+        self.segments[self.len] = segment;
+        self.len += 1;
+    }
+
+    pub fn slice(self: *const Self) []const Segment {
+        return self.segments[0..self.len];
+    }
+
+    pub fn last(self: *const Self) ?Segment {
+        return if (self.len == 0) null else self.segments[self.len - 1];
+    }
+
+    pub fn eql(self: *const Self, other: *const Self) bool {
+        if (self.len != other.len) {
+            return false;
+        }
+        for (self.slice(), other.slice()) |a, b| {
+            if (!std.mem.eql(u8, &a, &b)) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn format(self: Self, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+        try writer.writeByte('\\');
+        for (self.slice(), 0..) |segment, i| {
+            if (i != 0) {
+                try writer.writeByte('.');
+            }
+            try writer.writeAll(&segment);
+        }
+    }
+};
+
+pub const Object = union(enum) {
+    integer: u64,
+    string: []const u8,
+    buffer: []const u8,
+    /// A reference to another named object, e.g. a PCI link device.
+    name: Path,
+    package: []const Object,
+};
+
+const Node = struct {
+    path: Path,
+    object: Object,
+};
+
+var nodes: std.ArrayListUnmanaged(Node) = .{};
+
+const Parser = struct {
+    bytes: []const u8,
+    position: usize = 0,
+
+    const Self = @This();
+
+    fn byte(self: *Self) ParseError!u8 {
+        const value = try self.peek();
+        self.position += 1;
+        return value;
+    }
+
+    fn peek(self: *Self) ParseError!u8 {
+        if (self.position >= self.bytes.len) {
+            return error.Truncated;
+        }
+        return self.bytes[self.position];
+    }
+
+    fn take(self: *Self, len: usize) ParseError![]const u8 {
+        if (self.position + len > self.bytes.len) {
+            return error.Truncated;
+        }
+        defer self.position += len;
+        return self.bytes[self.position..][0..len];
+    }
+
+    fn int(self: *Self, comptime T: type) ParseError!T {
+        const bytes = try self.take(@sizeOf(T));
+        return std.mem.readInt(T, bytes[0..@sizeOf(T)], .little);
+    }
+
+    /// Parses a PkgLength and returns the position where the package ends.
+    fn packageEnd(self: *Self) ParseError!usize {
+        const start = self.position;
+        const lead = try self.byte();
+        const extra: u3 = @truncate(lead >> 6);
+
+        var length: usize = if (extra == 0) lead & 0x3F else lead & 0x0F;
+        for (0..extra) |i| {
+            const shift: u6 = @intCast(4 + 8 * i);
+            length |= @as(usize, try self.byte()) << shift;
+        }
+
+        const end = start + length;
+        if (end > self.bytes.len) {
+            return error.Truncated;
+        }
+        return end;
+    }
+
+    fn isLeadNameChar(value: u8) bool {
+        return switch (value) {
+            'A'...'Z', '_' => true,
+            else => false,
+        };
+    }
+
+    fn isNameChar(value: u8) bool {
+        return isLeadNameChar(value) or std.ascii.isDigit(value);
+    }
+
+    /// Whether a NameString made of plain NameSegs starts at the current
+    /// position.
+    fn atNameString(self: *Self) bool {
+        var position = self.position;
+        while (position < self.bytes.len and (self.bytes[position] == ROOT_CHAR or self.bytes[position] == PARENT_PREFIX)) {
+            position += 1;
+        }
+        if (position + 4 > self.bytes.len or !isLeadNameChar(self.bytes[position])) {
+            return false;
+        }
+        for (self.bytes[position + 1 ..][0..3]) |char| {
+            if (!isNameChar(char)) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    fn isNameStart(value: u8) bool {
+        return switch (value) {
+            'A'...'Z', '_', ROOT_CHAR, PARENT_PREFIX, DUAL_NAME_PREFIX, MULTI_NAME_PREFIX => true,
+            else => false,
+        };
+    }
+
+    fn nameString(self: *Self, scope: Path) ParseError!Path {
+        var path = scope;
+        if (try self.peek() == ROOT_CHAR) {
+            self.position += 1;
+            path = .{};
+        } else {
+            while (try self.peek() == PARENT_PREFIX) {
+                self.position += 1;
+                path.len -|= 1;
+            }
+        }
+
+        const count: usize = switch (try self.peek()) {
+            ZERO_OP => blk: {
+                self.position += 1;
+                break :blk 0;
+            },
+            DUAL_NAME_PREFIX => blk: {
+                self.position += 1;
+                break :blk 2;
+            },
+            MULTI_NAME_PREFIX => blk: {
+                self.position += 1;
+                break :blk try self.byte();
+            },
+            else => 1,
+        };
+
+        for (0..count) |_| {
+            try path.append((try self.take(4))[0..4].*);
+        }
+        return path;
+    }
+
+    fn package(self: *Self, scope: Path, variable: bool) ParseError!Object {
+        const end = try self.packageEnd();
+        if (variable) {
+            _ = try self.dataObject(scope);
+        } else {
+            _ = try self.byte();
+        }
+
+        var elements: std.ArrayListUnmanaged(Object) = .{};
+        while (self.position < end) {
+            const element = if (isNameStart(try self.peek()))
+                Object{ .name = try self.nameString(scope) }
+            else
+                try self.dataObject(scope);
+            try elements.append(memory.allocator(), element);
+        }
+
+        return .{ .package = elements.items };
+    }
+
+    fn dataObject(self: *Self, scope: Path) ParseError!Object {
+        return switch (try self.byte()) {
+            ZERO_OP => .{ .integer = 0 },
+            ONE_OP => .{ .integer = 1 },
+            ONES_OP => .{ .integer = std.math.maxInt(u64) },
+            BYTE_PREFIX => .{ .integer = try self.int(u8) },
+            WORD_PREFIX => .{ .integer = try self.int(u16) },
+            DWORD_PREFIX => .{ .integer = try self.int(u32) },
+            QWORD_PREFIX => .{ .integer = try self.int(u64) },
+            STRING_PREFIX => blk: {
+                const rest = self.bytes[self.position..];
+                const len = std.mem.indexOfScalar(u8, rest, 0) orelse return error.Truncated;
+                self.position += len + 1;
+                break :blk .{ .string = rest[0..len] };
+            },
+            BUFFER_OP => blk: {
+                const end = try self.packageEnd();
+                _ = try self.dataObject(scope);
+                if (end < self.position) {
+                    return error.Truncated;
+                }
+                const contents = self.bytes[self.position..end];
+                self.position = end;
+                break :blk .{ .buffer = contents };
+            },
+            PACKAGE_OP => try self.package(scope, false),
+            VAR_PACKAGE_OP => try self.package(scope, true),
+            EXT_OP_PREFIX => switch (try self.byte()) {
+                REVISION_OP => .{ .integer = 2 },
+                else => error.Unsupported,
+            },
+            else => error.Unsupported,
+        };
+    }
+
+    fn define(_: *Self, path: Path, object: Object) ParseError!void {
+        try nodes.append(memory.allocator(), .{ .path = path, .object = object });
+    }
+
+    fn skipTo(self: *Self, end: usize) void {
+        self.position = end;
+    }
+
+    /// Parses a scope-like object (Scope, Device, ...) whose body ends at
+    /// `end`. A body that cannot be parsed is skipped as a whole.
+    fn scopedTermList(self: *Self, scope: Path, end: usize) ParseError!void {
+        self.termList(scope, end) catch |err| switch (err) {
+            error.Unsupported => log.debug("Skipped part of {}", .{scope}),
+            else => return err,
+        };
+        self.skipTo(end);
+    }
+
+    fn termList(self: *Self, scope: Path, end: usize) ParseError!void {
+        while (self.position < end) {
+            const start = self.position;
+            self.term(scope) catch |err| switch (err) {
+                error.Unsupported => {
+                    const opcode = self.bytes[start];
+                    if (!self.resynchronize(start + 1, end)) {
+                        return err;
+                    }
+                    log.debug("Skipped {} bytes after opcode 0x{x:0>2} in {}", .{ self.position - start, opcode, scope });
+                },
+                else => return err,
+            };
+        }
+    }
+
+    fn term(self: *Self, scope: Path) ParseError!void {
+        switch (try self.byte()) {
+            NAME_OP => {
+                const path = try self.nameString(scope);
+                try self.define(path, try self.dataObject(scope));
+            },
+            ALIAS_OP => {
+                _ = try self.nameString(scope);
+                _ = try self.nameString(scope);
+            },
+            SCOPE_OP => {
+                const body_end = try self.packageEnd();
+                try self.scopedTermList(try self.nameString(scope), body_end);
+            },
+            EXTERNAL_OP => {
+                _ = try self.nameString(scope);
+                // ObjectType and ArgumentCount
+                _ = try self.take(2);
+            },
+            METHOD_OP, IF_OP, ELSE_OP, WHILE_OP, BUFFER_OP, PACKAGE_OP, VAR_PACKAGE_OP => self.skipTo(try self.packageEnd()),
+            EXT_OP_PREFIX => try self.extendedTerm(scope),
+            else => return error.Unsupported,
+        }
+    }
+
+    /// Moves to the first position from `start` on, before `end`, where a
+    /// definition `startsDefinition` recognizes begins.
+    fn resynchronize(self: *Self, start: usize, end: usize) bool {
+        for (start..end) |position| {
+            if (self.startsDefinition(position, end)) {
+                self.position = position;
+                return true;
+            }
+        }
+        return false;
+    }
+
+    fn startsDefinition(self: *const Self, position: usize, end: usize) bool {
+        var probe = Self{ .bytes = self.bytes[0..end], .position = position };
+        const opcode = probe.byte() catch return false;
+        const has_body = switch (opcode) {
+            NAME_OP => false,
+            SCOPE_OP, METHOD_OP => true,
+            EXT_OP_PREFIX => if ((probe.byte() catch return false) == DEVICE_OP) true else return false,
+            else => return false,
+        };
+        if (has_body) {
+            const body_end = probe.packageEnd() catch return false;
+            if (body_end <= probe.position) {
+                return false;
+            }
+        }
+        return probe.atNameString();
+    }
+
+    fn extendedTerm(self: *Self, scope: Path) ParseError!void {
+        switch (try self.byte()) {
+            DEVICE_OP, THERMAL_ZONE_OP => {
+                const body_end = try self.packageEnd();
+                try self.scopedTermList(try self.nameString(scope), body_end);
+            },
+            PROCESSOR_OP => {
+                const body_end = try self.packageEnd();
+                const path = try self.nameString(scope);
+                // ProcID, PblkAddr and PblkLen
+                _ = try self.take(1 + 4 + 1);
+                try self.scopedTermList(path, body_end);
+            },
+            POWER_RES_OP => {
+                const body_end = try self.packageEnd();
+                const path = try self.nameString(scope);
+                // SystemLevel and ResourceOrder
+                _ = try self.take(1 + 2);
+                try self.scopedTermList(path, body_end);
+            },
+            FIELD_OP, INDEX_FIELD_OP, BANK_FIELD_OP => self.skipTo(try self.packageEnd()),
+            MUTEX_OP => {
+                _ = try self.nameString(scope);
+                _ = try self.byte();
+            },
+            EVENT_OP => _ = try self.nameString(scope),
+            OP_REGION_OP => {
+                _ = try self.nameString(scope);
+                _ = try self.byte();
+                _ = try self.dataObject(scope);
+                _ = try self.dataObject(scope);
+            },
+            else => return error.Unsupported,
+        }
+    }
+};
+
+fn load(table: *const acpi.SdtHeader) void {
+    var parser = Parser{ .bytes = table.bytes(), .position = @sizeOf(acpi.SdtHeader) };
+    parser.scopedTermList(.{}, parser.bytes.len) catch |err| {
+        log.warn("Failed to load {s}: {s}", .{ table.signature, @errorName(err) });
+    };
+}
+
+/// Loads the constant parts of the DSDT and every SSDT into the namespace.
+pub fn init() void {
+    if (acpi.findHeader("DSDT")) |dsdt| {
+        load(dsdt);
+    }
+
+    var i: usize = 0;
+    while (acpi.findHeaderAt("SSDT", i)) |ssdt| : (i += 1) {
+        load(ssdt);
+    }
+
+    log.info("Loaded {} named objects", .{nodes.items.len});
+}
+
+pub fn find(path: Path) ?*const Object {
+    for (nodes.items) |*node| {
+        if (node.path.eql(&path)) {
+            return &node.object;
+        }
+    }
+    return null;
+}
+
+pub const SleepType = struct {
+    a: u16,
+    b: u16,
+};
+
+/// SLP_TYPa/SLP_TYPb for the soft-off state, written to the PM1 control
+/// registers to power the machine off.
+pub fn s5SleepType() ?SleepType {
+    const object = find(Path.parse("\\_S5_").?) orelse return null;
+    if (object.* != .package or object.package.len < 2) {
+        return null;
+    }
+
+    const elements = object.package;
+    if (elements[0] != .integer or elements[1] != .integer) {
+        return null;
+    }
+
+    return .{
+        .a = @truncate(elements[0].integer),
+        .b = @truncate(elements[1].integer),
+    };
+}
+
+/// One entry of a `_PRT` package.
+pub const PciRoute = struct {
+    /// The `_PRT` this entry belongs to, its parent is the PCI bridge.
+    table: Path,
+    device: u16,
+    /// INTA# to INTD# as 0 to 3.
+    pin: u8,
+    /// The link device to get the IRQ from, null for hard-wired entries.
+    source: ?Path,
+    /// The GSI for hard-wired entries, otherwise the index within `source`.
+    source_index: u32,
+};
+
+pub const PciRouteIterator = struct {
+    node: usize = 0,
+    element: usize = 0,
+
+    const Self = @This();
+
+    pub fn next(self: *Self) ?PciRoute {
+        while (self.node < nodes.items.len) : ({
+            self.node += 1;
+            self.element = 0;
+        }) {
+            const node = &nodes.items[self.node];
+            if (!isRouteTable(node)) {
+                continue;
+            }
+
+            while (self.element < node.object.package.len) {
+                const entry = node.object.package[self.element];
+                self.element += 1;
+                if (decodeRoute(node.path, entry)) |route| {
+                    return route;
+                }
+            }
+        }
+        return null;
+    }
+
+    /// A method-less `_PRT`, or a package of nothing but routing entries.
+    /// The latter is for QEMU's q35, whose `_PRT` method returns one of two
+    /// such packages declared next to it: `PRTP` for the PICs and `PRTA`
+    /// for the I/O APIC.
+    fn isRouteTable(node: *const Node) bool {
+        if (node.object != .package) {
+            return false;
+        }
+        const name = node.path.last() orelse return false;
+        if (std.mem.eql(u8, &name, "_PRT")) {
+            return true;
+        }
+
+        const entries = node.object.package;
+        for (entries) |entry| {
+            if (decodeRoute(node.path, entry) == null) {
+                return false;
+            }
+        }
+        return entries.len != 0;
+    }
+
+    fn decodeRoute(table: Path, entry: Object) ?PciRoute {
+        if (entry != .package or entry.package.len != 4) {
+            return null;
+        }
+
+        const fields = entry.package;
+        if (fields[0] != .integer or fields[1] != .integer or fields[3] != .integer) {
+            return null;
+        }
+
+        return .{
+            .table = table,
+            .device = @truncate(fields[0].integer >> 16),
+            .pin = @truncate(fields[1].integer),
+            .source = switch (fields[2]) {
+                .name => |path| path,
+                else => null,
+            },
+            .source_index = @truncate(fields[3].integer),
+        };
+    }
+};
+
+pub fn pciRoutes() PciRouteIterator {
+    return .{};
+}
+
+/// An interrupt a resource template describes.
+pub const Interrupt = struct {
+    gsi: u32,
+    active_low: bool,
+    level_triggered: bool,
+};
+
+const RESOURCE_LARGE = 0x80;
+const RESOURCE_SMALL_IRQ = 0x04;
+const RESOURCE_SMALL_END = 0x0f;
+const RESOURCE_EXTENDED_INTERRUPT = 0x89;
+
+/// The interrupt at `index` in the `_CRS` of the PCI link device
+/// `link`, null when the link has no constant `_CRS` or it holds fewer
+/// interrupts.
+pub fn linkInterrupt(link: Path, index: u32) ?Interrupt {
+    const resources = findChild(link, "_CRS".*) orelse return null;
+    if (resources.* != .buffer) {
+        return null;
+    }
+    return resourceInterrupt(resources.buffer, index);
+}
+
+/// `child` of the object `reference` names. References are recorded
+/// relative to the scope they appear in, so like AML's own lookup this
+/// tries that scope first and then each one enclosing it.
+fn findChild(reference: Path, child: Segment) ?*const Object {
+    const name = reference.last() orelse return null;
+    var scope = reference;
+    scope.len -= 1;
+    while (true) : (scope.len -= 1) {
+        var path = scope;
+        path.append(name) catch return null;
+        path.append(child) catch return null;
+        if (find(path)) |object| {
+            return object;
+        }
+        if (scope.len == 0) {
+            return null;
+        }
+    }
+}
+
+fn resourceInterrupt(bytes: []const u8, index: u32) ?Interrupt {
+    var position: usize = 0;
+    var remaining = index;
+    while (position < bytes.len) {
+        const tag = bytes[position];
+
+        if (tag & RESOURCE_LARGE == 0) {
+            // type in bits 3 to 6, length in bits 0 to 2
+            const body = position + 1;
+            const len: usize = tag & 0x7;
+            if (body + len > bytes.len) {
+                return null;
+            }
+            position = body + len;
+
+            switch (tag >> 3) {
+                RESOURCE_SMALL_END => return null,
+                RESOURCE_SMALL_IRQ => {
+                    if (len < 2) {
+                        continue;
+                    }
+                    // without the flags byte the IRQ is edge triggered and
+                    // active high
+                    const flags = if (len >= 3) bytes[body + 2] else 0x01;
+                    const mask = std.mem.readInt(u16, bytes[body..][0..2], .little);
+                    for (0..16) |irq| {
+                        if (mask & (@as(u16, 1) << @intCast(irq)) == 0) {
+                            continue;
+                        }
+                        if (remaining == 0) {
+                            return .{ .gsi = @intCast(irq), .active_low = flags & 0x08 != 0, .level_triggered = flags & 0x01 == 0 };
+                        }
+                        remaining -= 1;
+                    }
+                },
+                else => {},
+            }
+            continue;
+        }
+
+        if (position + 3 > bytes.len) {
+            return null;
+        }
+        const body = position + 3;
+        const len: usize = std.mem.readInt(u16, bytes[position + 1 ..][0..2], .little);
+        if (body + len > bytes.len) {
+            return null;
+        }
+        position = body + len;
+
+        if (tag != RESOURCE_EXTENDED_INTERRUPT or len < 2) {
+            continue;
+        }
+        const flags = bytes[body];
+        const count = bytes[body + 1];
+        if (2 + @as(usize, count) * 4 > len) {
+            return null;
+        }
+        if (remaining < count) {
+            return .{
+                .gsi = std.mem.readInt(u32, bytes[body + 2 + remaining * 4 ..][0..4], .little),
+                .active_low = flags & 0x04 != 0,
+                .level_triggered = flags & 0x02 == 0,
+            };
+        }
+        remaining -= count;
+    }
+    return null;
+}
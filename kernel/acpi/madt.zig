@@ -0,0 +1,173 @@
+const std = @import("std");
+const log = @import("kernel").utils.log.scoped(.acpi);
+const Error = @import("kernel").Error;
+
+const acpi = @import("acpi.zig");
+
+// NOTE:
+// The MADT is a fixed header followed by variable-length interrupt controller
+// structures, each starting with a type and a length byte. Only the kinds the
+// interrupt code needs are kept, the rest are skipped by their length.
+
+const MAX_PROCESSORS = 64;
+const MAX_IO_APICS = 8;
+const MAX_OVERRIDES = 16;
+
+const PROCESSOR_LOCAL_APIC = 0;
+const IO_APIC = 1;
+const INTERRUPT_SOURCE_OVERRIDE = 2;
+const LOCAL_APIC_ADDRESS_OVERRIDE = 5;
+const PROCESSOR_LOCAL_X2APIC = 9;
+
+/// Set in the MADT flags when the system also has the legacy 8259 PICs.
+const PCAT_COMPAT = 1 << 0;
+
+pub const Processor = struct {
+    acpi_id: u32,
+    apic_id: u32,
+    /// Usable right away, as opposed to only being online capable.
+    enabled: bool,
+};
+
+pub const IoApic = struct {
+    id: u8,
+    address: u32,
+    gsi_base: u32,
+};
+
+pub const Polarity = enum(u2) {
+    bus_default = 0,
+    active_high = 1,
+    active_low = 3,
+    _,
+};
+
+pub const Trigger = enum(u2) {
+    bus_default = 0,
+    edge = 1,
+    level = 3,
+    _,
+};
+
+/// An ISA interrupt that is not identity-mapped onto the global system
+/// interrupts, e.g. the PIT's IRQ 0 usually arrives on GSI 2.
+pub const InterruptOverride = struct {
+    source: u8,
+    gsi: u32,
+    polarity: Polarity,
+    trigger: Trigger,
+};
+
+pub var local_apic_address: u64 = 0;
+pub var has_legacy_pics: bool = false;
+
+var processor_list: [MAX_PROCESSORS]Processor = undefined;
+var processor_count: usize = 0;
+var io_apic_list: [MAX_IO_APICS]IoApic = undefined;
+var io_apic_count: usize = 0;
+var override_list: [MAX_OVERRIDES]InterruptOverride = undefined;
+var override_count: usize = 0;
+
+fn read(comptime T: type, bytes: []const u8, offset: usize) T {
+    return std.mem.readInt(T, bytes[offset..][0..@sizeOf(T)], .little);
+}
+
+fn append(comptime T: type, list: []T, count: *usize, item: T) void {
+    if (count.* == list.len) {
+        log.warn("MADT: too many {s} entries, ignoring the rest", .{@typeName(T)});
+        return;
+    }
+    list[count.*] = item;
+    count.* += 1;
+}
+
+pub fn init() Error!void {
+    const table = acpi.findHeader("APIC") orelse return error.NotFound;
+    const bytes = table.bytes();
+
+    const entries_start = @sizeOf(acpi.SdtHeader) + 8;
+    if (bytes.len < entries_start) {
+        return error.Corrupted;
+    }
+
+    local_apic_address = read(u32, bytes, @sizeOf(acpi.SdtHeader));
+    has_legacy_pics = read(u32, bytes, @sizeOf(acpi.SdtHeader) + 4) & PCAT_COMPAT != 0;
+
+    var offset: usize = entries_start;
+    while (offset + 2 <= bytes.len) {
+        const kind = bytes[offset];
+        const length = bytes[offset + 1];
+        if (length < 2 or offset + length > bytes.len) {
+            return error.Corrupted;
+        }
+
+        const entry = bytes[offset..][0..length];
+        switch (kind) {
+            PROCESSOR_LOCAL_APIC => if (length >= 8) {
+                append(Processor, &processor_list, &processor_count, .{
+                    .acpi_id = entry[2],
+                    .apic_id = entry[3],
+                    .enabled = read(u32, entry, 4) & 1 != 0,
+                });
+            },
+            PROCESSOR_LOCAL_X2APIC => if (length >= 16) {
+                append(Processor, &processor_list, &processor_count, .{
+                    .acpi_id = read(u32, entry, 12),
+                    .apic_id = read(u32, entry, 4),
+                    .enabled = read(u32, entry, 8) & 1 != 0,
+                });
+            },
+            IO_APIC => if (length >= 12) {
+                append(IoApic, &io_apic_list, &io_apic_count, .{
+                    .id = entry[2],
+                    .address = read(u32, entry, 4),
+                    .gsi_base = read(u32, entry, 8),
+                });
+            },
+            INTERRUPT_SOURCE_OVERRIDE => if (length >= 10) {
+                const flags = read(u16, entry, 8);
+                append(InterruptOverride, &override_list, &override_count, .{
+                    .source = entry[3],
+                    .gsi = read(u32, entry, 4),
+                    .polarity = @enumFromInt(@as(u2, @truncate(flags))),
+                    .trigger = @enumFromInt(@as(u2, @truncate(flags >> 2))),
+                });
+            },
+            LOCAL_APIC_ADDRESS_OVERRIDE => if (length >= 12) {
+                local_apic_address = read(u64, entry, 4);
+            },
+            else => {},
+        }
+
+        offset += length;
+    }
+
+    log.info("MADT: local APIC at 0x{x}, {} processors, {} I/O APICs, {} overrides", .{
+        local_apic_address,
+        processor_count,
+        io_apic_count,
+        override_count,
+    });
+}
+
+pub fn processors() []const Processor {
+    return processor_list[0..processor_count];
+}
+
+pub fn ioApics() []const IoApic {
+    return io_apic_list[0..io_apic_count];
+}
+
+pub fn overrides() []const InterruptOverride {
+    return override_list[0..override_count];
+}
+
+/// Maps an ISA IRQ to the global system interrupt it is wired to.
+pub fn isaIrqToGsi(irq: u8) u32 {
+    for (overrides()) |entry| {
+        if (entry.source == irq) {
+            return entry.gsi;
+        }
+    }
+    return irq;
+}
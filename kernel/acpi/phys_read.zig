@@ -0,0 +1,57 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+// NOTE:
+// Physical addresses in ACPI tables come straight from the firmware. Before
+// anything behind one is touched it is checked against the memory map: the
+// whole range has to sit inside a single entry the higher half direct map
+// covers, otherwise a bad pointer would fault instead of being skipped.
+// Fields inside tables are read a byte at a time, the packed layouts leave
+// most of them unaligned.
+
+fn isMapped(entry: anytype) bool {
+    return switch (entry.kind) {
+        .usable,
+        .acpi_reclaimable,
+        .acpi_nvs,
+        .bootloader_reclaimable,
+        .kernel_and_modules,
+        .framebuffer,
+        => true,
+        else => false,
+    };
+}
+
+/// Checks that `len` bytes at `physical` can be read through the direct map.
+pub fn validate(physical: u64, len: usize) Error!void {
+    const end = std.math.add(u64, physical, len) catch return error.Corrupted;
+    const response = boot.memory_map_request.response orelse return error.NotFound;
+
+    for (response.entries()) |entry| {
+        if (physical >= entry.base and end <= entry.base + entry.length) {
+            if (!isMapped(entry)) {
+                return error.Corrupted;
+            }
+            return;
+        }
+    }
+    return error.Corrupted;
+}
+
+/// The `len` bytes at `physical`, after checking they are mapped.
+pub fn bytes(physical: u64, len: usize) Error![]const u8 {
+    try validate(physical, len);
+    const start: [*]const u8 = @ptrFromInt(memory.physicalToVirtual(physical));
+    return start[0..len];
+}
+
+/// Reads a little endian integer `offset` bytes into `table`, failing
+/// instead of reading past its end.
+pub fn field(comptime T: type, table: []const u8, offset: usize) Error!T {
+    if (offset > table.len or table.len - offset < @sizeOf(T)) {
+        return error.Corrupted;
+    }
+    return std.mem.readInt(T, table[offset..][0..@sizeOf(T)], .little);
+}
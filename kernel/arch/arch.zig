@@ -1,18 +1,165 @@
 const builtin = @import("builtin");
+const options = @import("build_options");
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
 
 pub const cpu = switch (builtin.cpu.arch) {
     .x86_64 => @import("x86_64/cpu.zig"),
     else => unreachable,
 };
 
-pub fn init() void {
+pub const cpuinfo = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/cpuinfo.zig"),
+    else => unreachable,
+};
+
+pub const sanity = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/sanity.zig"),
+    else => unreachable,
+};
+
+pub const exception_tests = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/exception_tests.zig"),
+    else => unreachable,
+};
+
+pub const paging_tests = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/paging_tests.zig"),
+    else => unreachable,
+};
+
+pub const decoder_tests = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/decoder_tests.zig"),
+    else => unreachable,
+};
+
+pub const stack = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/stack.zig"),
+    else => unreachable,
+};
+
+pub const interrupts = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/interrupts.zig"),
+    else => unreachable,
+};
+
+pub const apic = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/apic.zig"),
+    else => unreachable,
+};
+
+pub const irq = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/irq.zig"),
+    else => unreachable,
+};
+
+pub const idt = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/idt.zig"),
+    else => unreachable,
+};
+
+pub const pic = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/pic.zig"),
+    else => unreachable,
+};
+
+pub const apic_timer = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/apic_timer.zig"),
+    else => unreachable,
+};
+
+pub const paging = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/paging.zig"),
+    else => unreachable,
+};
+
+pub const address_space = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/address_space.zig"),
+    else => unreachable,
+};
+
+pub const AddressSpace = address_space.AddressSpace;
+
+pub const mtrr = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/mtrr.zig"),
+    else => unreachable,
+};
+
+pub const page_table_view = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/page_table_view.zig"),
+    else => unreachable,
+};
+
+pub const tsc = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/tsc.zig"),
+    else => unreachable,
+};
+
+pub const hypervisor = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/hypervisor.zig"),
+    else => unreachable,
+};
+
+pub const kvmclock = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/kvmclock.zig"),
+    else => unreachable,
+};
+
+pub const extable = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/extable.zig"),
+    else => unreachable,
+};
+
+pub const decoder = switch (builtin.cpu.arch) {
+    .x86_64 => @import("x86_64/decoder.zig"),
+    else => unreachable,
+};
+
+pub fn init() Error!void {
     switch (builtin.cpu.arch) {
         .x86_64 => {
             const gdt = @import("x86_64/gdt.zig");
-            const idt = @import("x86_64/idt.zig");
+            const mitigations = @import("x86_64/mitigations.zig");
+            const tls = @import("x86_64/tls.zig");
 
             gdt.install();
             idt.install();
+            try tls.init();
+            mitigations.init();
+            hypervisor.init();
+            address_space.init();
+            mtrr.init();
+
+            _ = sanity.check();
+        },
+        else => unreachable,
+    }
+}
+
+/// Brings up the interrupt controllers. Unlike `init` this needs the memory
+/// manager and the ACPI tables.
+pub fn initInterrupts() Error!void {
+    switch (builtin.cpu.arch) {
+        .x86_64 => {
+            pic.init();
+
+            if (options.legacy_pic) {
+                log.info("Using the legacy PICs for device interrupts", .{});
+                pic.active = true;
+                return;
+            }
+
+            apic.init() catch |err| switch (err) {
+                error.Unsupported => {
+                    log.warn("No local APIC, falling back to the legacy PICs", .{});
+                    pic.active = true;
+                    return;
+                },
+                else => return err,
+            };
+
+            const ioapic = @import("x86_64/ioapic.zig");
+            try ioapic.init();
         },
         else => unreachable,
     }
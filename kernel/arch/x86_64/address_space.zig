@@ -0,0 +1,157 @@
+const std = @import("std");
+const log = @import("kernel").utils.log.scoped(.vmm);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+
+// NOTE:
+// With CR4.PCIDE set the low 12 bits of CR3 tag TLB entries with a process
+// context identifier, so switching address spaces no longer throws away
+// what the previous one had cached. Setting bit 63 on the CR3 write keeps
+// the entries of the new PCID as well, which is only correct if nothing
+// changed while it was inactive. Spaces remember when that happened
+// (`stale`) and flush their own PCID on the next switch instead. PCID 0 is
+// the kernel's, spaces share it once all the others are taken and then
+// always flush.
+
+const CR4_PCIDE = 1 << 17;
+const CR3_NO_FLUSH = 1 << 63;
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const PCID_COUNT = 4096;
+const SHARED_PCID = 0;
+
+/// Entries of the PML4 covering the higher half, shared by every space.
+const KERNEL_HALF = 256;
+
+var pcid_enabled = false;
+var invpcid_supported = false;
+var pcids_used = std.StaticBitSet(PCID_COUNT).initEmpty();
+
+var kernel_space: AddressSpace = undefined;
+var active: *AddressSpace = &kernel_space;
+
+pub const AddressSpace = struct {
+    /// Physical address of the PML4.
+    root: u64,
+    pcid: u12,
+    /// TLB entries tagged with `pcid` may be out of date.
+    stale: bool = true,
+
+    const Self = @This();
+
+    /// A new space with the kernel half of the kernel's and nothing mapped
+    /// below it. Only the PML4 entries are copied, kernel mappings added
+    /// later are seen as long as they land under an existing entry.
+    pub fn create() Error!Self {
+        const root = try memory.pmm.allocPagesFor(1, .page_tables);
+        const entries: *[512]u64 = @ptrFromInt(memory.physicalToVirtual(root));
+        const kernel_entries: *const [512]u64 = @ptrFromInt(memory.physicalToVirtual(kernel_space.root));
+
+        @memset(entries[0..KERNEL_HALF], 0);
+        @memcpy(entries[KERNEL_HALF..], kernel_entries[KERNEL_HALF..]);
+
+        return .{ .root = root, .pcid = allocPcid() };
+    }
+
+    /// Frees the PML4. The space must not be active.
+    pub fn destroy(self: *Self) void {
+        std.debug.assert(self != active);
+
+        // whoever gets the PCID next starts out stale and flushes it
+        if (self.pcid != SHARED_PCID) {
+            pcids_used.unset(self.pcid);
+        }
+        memory.pmm.freePages(self.root, 1);
+    }
+
+    /// Switches to this space, keeping its cached translations when that
+    /// is safe.
+    pub fn activate(self: *Self) void {
+        var cr3 = self.root & ADDRESS_MASK;
+        if (pcid_enabled) {
+            cr3 |= self.pcid;
+            if (!self.stale and self.pcid != SHARED_PCID) {
+                cr3 |= CR3_NO_FLUSH;
+            }
+        }
+
+        cpu.writeCr3(cr3);
+        self.stale = false;
+        active = self;
+    }
+
+    /// Drops the cached translation of `virtual` after its mapping changed.
+    pub fn invalidate(self: *Self, virtual: usize) void {
+        if (self == active) {
+            cpu.invalidatePage(virtual);
+        } else if (pcid_enabled and invpcid_supported) {
+            cpu.invpcid(.address, self.pcid, virtual);
+        } else {
+            // flushed on the next switch, without PCIDs that happens anyway
+            self.stale = true;
+        }
+    }
+
+    /// Drops every cached translation of the space.
+    pub fn invalidateAll(self: *Self) void {
+        if (pcid_enabled and invpcid_supported) {
+            cpu.invpcid(.context, self.pcid, 0);
+        } else if (self == active) {
+            cpu.writeCr3(cpu.readCr3() & ~@as(u64, CR3_NO_FLUSH));
+        } else {
+            self.stale = true;
+        }
+    }
+};
+
+fn allocPcid() u12 {
+    if (!pcid_enabled) {
+        return SHARED_PCID;
+    }
+
+    var unused = pcids_used.iterator(.{ .kind = .unset });
+    const pcid = unused.next() orelse return SHARED_PCID;
+    pcids_used.set(pcid);
+    return @intCast(pcid);
+}
+
+pub fn init() void {
+    const cr3 = cpu.readCr3();
+    kernel_space = .{ .root = cr3 & ADDRESS_MASK, .pcid = SHARED_PCID, .stale = false };
+    pcids_used.set(SHARED_PCID);
+
+    const has_pcid = cpu.cpuid(1, 0).ecx & (1 << 17) != 0;
+    invpcid_supported = cpu.cpuid(0, 0).eax >= 7 and cpu.cpuid(7, 0).ebx & (1 << 10) != 0;
+
+    // PCIDE can only be set while the current PCID is 0
+    if (!has_pcid or cr3 & 0xfff != 0) {
+        log.info("PCIDs unavailable, address space switches flush the TLB", .{});
+        return;
+    }
+
+    cpu.writeCr4(cpu.readCr4() | CR4_PCIDE);
+    pcid_enabled = true;
+    log.info("PCIDs enabled (invpcid: {})", .{invpcid_supported});
+}
+
+/// Whether TLB entries are tagged with PCIDs.
+pub fn pcidsEnabled() bool {
+    return pcid_enabled;
+}
+
+/// Whether entries of other PCIDs can be dropped with INVPCID.
+pub fn hasInvpcid() bool {
+    return pcid_enabled and invpcid_supported;
+}
+
+/// The space the kernel booted on, which every other one shares its
+/// higher half with.
+pub fn kernel() *AddressSpace {
+    return &kernel_space;
+}
+
+pub fn current() *AddressSpace {
+    return active;
+}
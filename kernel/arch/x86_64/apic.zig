@@ -0,0 +1,140 @@
+const std = @import("std");
+const acpi = @import("kernel").acpi;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+const hypervisor = @import("hypervisor.zig");
+const idt = @import("idt.zig");
+const mtrr = @import("mtrr.zig");
+const paging = @import("paging.zig");
+
+// NOTE:
+// The local APIC is driven through the x2APIC MSRs when the CPU supports
+// them and through its MMIO page otherwise. Register offsets are given as in
+// the xAPIC layout, the x2APIC MSR for a register is 0x800 + offset / 16.
+
+const IA32_APIC_BASE = 0x1b;
+const APIC_BASE_ENABLE = 1 << 11;
+const APIC_BASE_X2APIC = 1 << 10;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const X2APIC_MSR_BASE = 0x800;
+
+pub const Register = enum(u16) {
+    id = 0x20,
+    version = 0x30,
+    task_priority = 0x80,
+    eoi = 0xb0,
+    spurious = 0xf0,
+    error_status = 0x280,
+    interrupt_command_low = 0x300,
+    interrupt_command_high = 0x310,
+    lvt_timer = 0x320,
+    lvt_lint0 = 0x350,
+    lvt_lint1 = 0x360,
+    lvt_error = 0x370,
+    timer_initial_count = 0x380,
+    timer_current_count = 0x390,
+    timer_divide = 0x3e0,
+};
+
+/// Vector of spurious interrupts, these must not be acknowledged.
+pub const SPURIOUS_VECTOR = 0xff;
+
+const SOFTWARE_ENABLE = 1 << 8;
+pub const LVT_MASKED = 1 << 16;
+const DELIVERY_PENDING = 1 << 12;
+
+pub const DeliveryMode = enum(u3) {
+    fixed = 0,
+    nmi = 4,
+    init = 5,
+    startup = 6,
+};
+
+var x2apic = false;
+var mmio: usize = 0;
+
+pub fn read(register: Register) u32 {
+    const offset = @intFromEnum(register);
+    if (x2apic) {
+        return @truncate(cpu.readMsr(X2APIC_MSR_BASE + offset / 16));
+    }
+    return @as(*volatile u32, @ptrFromInt(mmio + offset)).*;
+}
+
+pub fn write(register: Register, value: u32) void {
+    const offset = @intFromEnum(register);
+    if (x2apic) {
+        return cpu.writeMsr(X2APIC_MSR_BASE + offset / 16, value);
+    }
+    @as(*volatile u32, @ptrFromInt(mmio + offset)).* = value;
+}
+
+pub fn init() Error!void {
+    const features = cpu.cpuid(1, 0);
+    if (features.edx & (1 << 9) == 0) {
+        return error.Unsupported;
+    }
+
+    var base = cpu.readMsr(IA32_APIC_BASE);
+    x2apic = features.ecx & (1 << 21) != 0;
+
+    if (x2apic) {
+        base |= APIC_BASE_ENABLE | APIC_BASE_X2APIC;
+    } else {
+        const physical = if (acpi.madt.local_apic_address != 0)
+            acpi.madt.local_apic_address
+        else
+            base & APIC_BASE_ADDRESS_MASK;
+
+        mmio = try paging.mapMmio(physical, paging.PAGE_SIZE);
+        _ = mtrr.checkRange("Local APIC", .mmio, physical, paging.PAGE_SIZE, mmio);
+        base |= APIC_BASE_ENABLE;
+    }
+    cpu.writeMsr(IA32_APIC_BASE, base);
+
+    // mask every local interrupt source until someone asks for it
+    write(.lvt_timer, LVT_MASKED);
+    write(.lvt_lint0, LVT_MASKED);
+    write(.lvt_lint1, LVT_MASKED);
+    write(.lvt_error, LVT_MASKED);
+
+    idt.setHandler(SPURIOUS_VECTOR, spurious);
+    write(.task_priority, 0);
+    write(.spurious, SPURIOUS_VECTOR | SOFTWARE_ENABLE);
+
+    log.info("Local APIC {} enabled in {s} mode", .{ id(), if (x2apic) "x2APIC" else "xAPIC" });
+}
+
+fn spurious(_: *idt.InterruptContext) void {}
+
+pub fn id() u32 {
+    const value = read(.id);
+    return if (x2apic) value else value >> 24;
+}
+
+/// Acknowledges the interrupt currently being serviced.
+pub fn eoi() void {
+    if (hypervisor.consumeEoi()) {
+        return;
+    }
+    write(.eoi, 0);
+}
+
+pub fn sendIpi(destination: u32, vector: u8, mode: DeliveryMode) void {
+    const command = @as(u32, vector) | (@as(u32, @intFromEnum(mode)) << 8);
+
+    if (x2apic) {
+        // the x2APIC ICR is a single 64-bit MSR
+        const msr = X2APIC_MSR_BASE + @intFromEnum(Register.interrupt_command_low) / 16;
+        return cpu.writeMsr(msr, (@as(u64, destination) << 32) | command);
+    }
+
+    write(.interrupt_command_high, destination << 24);
+    write(.interrupt_command_low, command);
+    while (read(.interrupt_command_low) & DELIVERY_PENDING != 0) {
+        std.atomic.spinLoopHint();
+    }
+}
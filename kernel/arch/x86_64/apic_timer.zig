@@ -0,0 +1,98 @@
+const std = @import("std");
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const apic = @import("apic.zig");
+const idt = @import("idt.zig");
+const pic = @import("pic.zig");
+const pit = @import("pit.zig");
+
+// NOTE:
+// The local APIC timer counts down at the bus (or crystal) clock divided by
+// `DIVIDE`, a rate no CPUID leaf reliably reports. It is measured against the
+// PIT the same way the TSC is, and callers only ever deal in hertz and
+// microseconds.
+
+pub const VECTOR = 0x40;
+
+const CALIBRATION_MS = 10;
+const CALIBRATION_RUNS = 3;
+
+/// Divide configuration for a divisor of 16.
+const DIVIDE_BY_16 = 0b0011;
+
+const MODE_ONE_SHOT = 0b00 << 17;
+const MODE_PERIODIC = 0b01 << 17;
+
+pub const Callback = *const fn () void;
+
+var ticks_per_ms: u64 = 0;
+var callback: ?Callback = null;
+
+pub fn calibrate() Error!void {
+    if (pic.active) {
+        return error.Unsupported;
+    }
+
+    apic.write(.timer_divide, DIVIDE_BY_16);
+    apic.write(.lvt_timer, apic.LVT_MASKED);
+
+    const pit_ticks = pit.FREQUENCY * CALIBRATION_MS / 1000;
+
+    // like the TSC, the shortest run is the one nothing interfered with
+    var best: u64 = std.math.maxInt(u64);
+    for (0..CALIBRATION_RUNS) |_| {
+        pit.startOneShot(pit_ticks);
+        apic.write(.timer_initial_count, std.math.maxInt(u32));
+        while (!pit.oneShotExpired()) {
+            std.atomic.spinLoopHint();
+        }
+        best = @min(best, std.math.maxInt(u32) - apic.read(.timer_current_count));
+    }
+    apic.write(.timer_initial_count, 0);
+
+    ticks_per_ms = best / CALIBRATION_MS;
+    if (ticks_per_ms == 0) {
+        return error.Unsupported;
+    }
+
+    idt.setHandler(VECTOR, handle);
+    log.info("APIC timer runs at {} kHz", .{ticks_per_ms});
+}
+
+fn handle(_: *idt.InterruptContext) void {
+    if (callback) |function| {
+        function();
+    }
+    apic.eoi();
+}
+
+fn start(mode: u32, ticks: u64, function: Callback) Error!void {
+    std.debug.assert(ticks_per_ms != 0);
+    if (ticks == 0 or ticks > std.math.maxInt(u32)) {
+        return error.InvalidArgument;
+    }
+
+    callback = function;
+    apic.write(.lvt_timer, VECTOR | mode);
+    apic.write(.timer_initial_count, @intCast(ticks));
+}
+
+/// Calls `function` from the timer interrupt `hz` times a second.
+pub fn setPeriodic(hz: u32, function: Callback) Error!void {
+    if (hz == 0) {
+        return error.InvalidArgument;
+    }
+    try start(MODE_PERIODIC, ticks_per_ms * 1000 / hz, function);
+}
+
+/// Calls `function` from the timer interrupt once, `us` microseconds from now.
+pub fn setOneShot(us: u64, function: Callback) Error!void {
+    try start(MODE_ONE_SHOT, @max(1, ticks_per_ms * us / 1000), function);
+}
+
+pub fn stop() void {
+    apic.write(.lvt_timer, apic.LVT_MASKED);
+    apic.write(.timer_initial_count, 0);
+    callback = null;
+}
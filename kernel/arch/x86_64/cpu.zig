@@ -9,6 +9,246 @@ pub fn writeByte(port: u16, value: u8) void {
     );
 }
 
+pub fn readByte(port: u16) u8 {
+    return asm volatile ("inb %[port], %[value]"
+        : [value] "={al}" (-> u8),
+        : [port] "N{dx}" (port),
+    );
+}
+
+pub fn writeWord(port: u16, value: u16) void {
+    asm volatile ("outw %[value], %[port]"
+        :
+        : [value] "{ax}" (value),
+          [port] "N{dx}" (port),
+    );
+}
+
+pub fn readWord(port: u16) u16 {
+    return asm volatile ("inw %[port], %[value]"
+        : [value] "={ax}" (-> u16),
+        : [port] "N{dx}" (port),
+    );
+}
+
+pub fn writeLong(port: u16, value: u32) void {
+    asm volatile ("outl %[value], %[port]"
+        :
+        : [value] "{eax}" (value),
+          [port] "N{dx}" (port),
+    );
+}
+
+pub fn readLong(port: u16) u32 {
+    return asm volatile ("inl %[port], %[value]"
+        : [value] "={eax}" (-> u32),
+        : [port] "N{dx}" (port),
+    );
+}
+
+pub inline fn readTsc() u64 {
+    var low: u32 = undefined;
+    var high: u32 = undefined;
+
+    asm volatile ("rdtsc"
+        : [low] "={eax}" (low),
+          [high] "={edx}" (high),
+    );
+
+    return (@as(u64, high) << 32) | low;
+}
+
+pub inline fn stackPointer() usize {
+    return asm volatile ("movq %%rsp, %[rsp]"
+        : [rsp] "=r" (-> usize),
+    );
+}
+
+pub inline fn readFlags() u64 {
+    return asm volatile (
+        \\pushfq
+        \\popq %[flags]
+        : [flags] "=r" (-> u64),
+    );
+}
+
+/// Interrupt flag in RFLAGS.
+pub const FLAGS_IF = 1 << 9;
+
+pub inline fn interruptsEnabled() bool {
+    return readFlags() & FLAGS_IF != 0;
+}
+
+pub inline fn enableInterrupts() void {
+    asm volatile ("sti" ::: "memory");
+}
+
+pub inline fn disableInterrupts() void {
+    asm volatile ("cli" ::: "memory");
+}
+
+/// Moves RSP to `stack_top` and calls `entry` on the new stack. Everything
+/// living on the old stack is lost, hence `entry` must never return.
+pub fn switchStack(stack_top: usize, entry: *const fn () callconv(.C) noreturn) noreturn {
+    asm volatile (
+        \\movq %[stack_top], %%rsp
+        \\xorq %%rbp, %%rbp
+        \\callq *%[entry]
+        :
+        : [stack_top] "r" (stack_top),
+          [entry] "r" (entry),
+        : "memory"
+    );
+    unreachable;
+}
+
+pub fn readCr0() u64 {
+    return asm volatile ("mov %%cr0, %[value]"
+        : [value] "=r" (-> u64),
+    );
+}
+
+pub fn readCr2() u64 {
+    return asm volatile ("mov %%cr2, %[value]"
+        : [value] "=r" (-> u64),
+    );
+}
+
+pub fn readCr3() u64 {
+    return asm volatile ("mov %%cr3, %[value]"
+        : [value] "=r" (-> u64),
+    );
+}
+
+pub fn writeCr3(value: u64) void {
+    asm volatile ("mov %[value], %%cr3"
+        :
+        : [value] "r" (value),
+        : "memory"
+    );
+}
+
+pub fn invalidatePage(address: usize) void {
+    asm volatile ("invlpg (%[address])"
+        :
+        : [address] "r" (address),
+        : "memory"
+    );
+}
+
+pub fn readCr4() u64 {
+    return asm volatile ("mov %%cr4, %[value]"
+        : [value] "=r" (-> u64),
+    );
+}
+
+pub fn writeCr4(value: u64) void {
+    asm volatile ("mov %[value], %%cr4"
+        :
+        : [value] "r" (value),
+        : "memory"
+    );
+}
+
+pub const InvpcidKind = enum(u64) {
+    /// One address in one PCID.
+    address = 0,
+    /// Every non-global entry of one PCID.
+    context = 1,
+    /// Everything, global entries included.
+    all = 2,
+    /// Every non-global entry of every PCID.
+    all_non_global = 3,
+};
+
+pub fn invpcid(kind: InvpcidKind, pcid: u12, address: usize) void {
+    const descriptor = [2]u64{ pcid, address };
+    asm volatile ("invpcid (%[descriptor]), %[kind]"
+        :
+        : [descriptor] "r" (&descriptor),
+          [kind] "r" (@intFromEnum(kind)),
+        : "memory"
+    );
+}
+
+pub const DescriptorTablePointer = packed struct {
+    limit: u16,
+    base: u64,
+};
+
+pub fn storeGdt() DescriptorTablePointer {
+    var pointer: DescriptorTablePointer = undefined;
+    asm volatile ("sgdt (%[pointer])"
+        :
+        : [pointer] "r" (&pointer),
+        : "memory"
+    );
+    return pointer;
+}
+
+pub fn storeIdt() DescriptorTablePointer {
+    var pointer: DescriptorTablePointer = undefined;
+    asm volatile ("sidt (%[pointer])"
+        :
+        : [pointer] "r" (&pointer),
+        : "memory"
+    );
+    return pointer;
+}
+
+pub fn storeTaskRegister() u16 {
+    return asm volatile ("str %[selector]"
+        : [selector] "=r" (-> u16),
+    );
+}
+
+pub const CpuidResult = struct {
+    eax: u32,
+    ebx: u32,
+    ecx: u32,
+    edx: u32,
+};
+
+pub fn cpuid(leaf: u32, subleaf: u32) CpuidResult {
+    var eax: u32 = undefined;
+    var ebx: u32 = undefined;
+    var ecx: u32 = undefined;
+    var edx: u32 = undefined;
+
+    asm volatile ("cpuid"
+        : [eax] "={eax}" (eax),
+          [ebx] "={ebx}" (ebx),
+          [ecx] "={ecx}" (ecx),
+          [edx] "={edx}" (edx),
+        : [leaf] "{eax}" (leaf),
+          [subleaf] "{ecx}" (subleaf),
+    );
+
+    return .{ .eax = eax, .ebx = ebx, .ecx = ecx, .edx = edx };
+}
+
+pub fn readMsr(msr: u32) u64 {
+    var low: u32 = undefined;
+    var high: u32 = undefined;
+
+    asm volatile ("rdmsr"
+        : [low] "={eax}" (low),
+          [high] "={edx}" (high),
+        : [msr] "{ecx}" (msr),
+    );
+
+    return (@as(u64, high) << 32) | low;
+}
+
+pub fn writeMsr(msr: u32, value: u64) void {
+    asm volatile ("wrmsr"
+        :
+        : [msr] "{ecx}" (msr),
+          [low] "{eax}" (@as(u32, @truncate(value))),
+          [high] "{edx}" (@as(u32, @truncate(value >> 32))),
+    );
+}
+
 pub const Registers = extern struct {
     r15: u64,
     r14: u64,
@@ -26,6 +266,30 @@ pub const Registers = extern struct {
     rax: u64,
 };
 
+/// Stores the general purpose registers into `registers`. The register the
+/// compiler picks to hold `registers` shows its address.
+pub inline fn saveRegisters(registers: *Registers) void {
+    asm volatile (
+        \\movq %%r15, 0x00(%[registers])
+        \\movq %%r14, 0x08(%[registers])
+        \\movq %%r13, 0x10(%[registers])
+        \\movq %%r12, 0x18(%[registers])
+        \\movq %%r11, 0x20(%[registers])
+        \\movq %%r10, 0x28(%[registers])
+        \\movq %%r9, 0x30(%[registers])
+        \\movq %%r8, 0x38(%[registers])
+        \\movq %%rdi, 0x40(%[registers])
+        \\movq %%rsi, 0x48(%[registers])
+        \\movq %%rdx, 0x50(%[registers])
+        \\movq %%rcx, 0x58(%[registers])
+        \\movq %%rbx, 0x60(%[registers])
+        \\movq %%rax, 0x68(%[registers])
+        :
+        : [registers] "r" (registers),
+        : "memory"
+    );
+}
+
 pub const InterruptFrame = extern struct {
     interrupt_number: u64,
     @"error": u64,
@@ -0,0 +1,104 @@
+const std = @import("std");
+const log = @import("kernel").utils.log;
+
+const cpu = @import("cpu.zig");
+
+const IA32_EFER = 0xC0000080;
+
+const CR4_SMEP = 1 << 20;
+const CR4_SMAP = 1 << 21;
+const EFER_NXE = 1 << 11;
+
+fn vendor(buffer: *[12]u8) []const u8 {
+    const leaf = cpu.cpuid(0, 0);
+    std.mem.writeInt(u32, buffer[0..4], leaf.ebx, .little);
+    std.mem.writeInt(u32, buffer[4..8], leaf.edx, .little);
+    std.mem.writeInt(u32, buffer[8..12], leaf.ecx, .little);
+    return buffer;
+}
+
+/// Signature of the hypervisor we run under (e.g. "TCGTCGTCGTCG" for
+/// QEMU's emulator, "KVMKVMKVM" for KVM), null on bare metal.
+pub fn hypervisor(buffer: *[12]u8) ?[]const u8 {
+    // "hypervisor present" in CPUID.1:ECX, always clear on real hardware
+    if (cpu.cpuid(1, 0).ecx & (1 << 31) == 0) {
+        return null;
+    }
+
+    const leaf = cpu.cpuid(0x40000000, 0);
+    std.mem.writeInt(u32, buffer[0..4], leaf.ebx, .little);
+    std.mem.writeInt(u32, buffer[4..8], leaf.ecx, .little);
+    std.mem.writeInt(u32, buffer[8..12], leaf.edx, .little);
+    return std.mem.sliceTo(buffer, 0);
+}
+
+fn brand(buffer: *[48]u8) []const u8 {
+    if (cpu.cpuid(0x80000000, 0).eax < 0x80000004) {
+        return "unknown";
+    }
+
+    for (0..3) |i| {
+        const leaf = cpu.cpuid(0x80000002 + @as(u32, @intCast(i)), 0);
+        const chunk = buffer[i * 16 ..][0..16];
+        std.mem.writeInt(u32, chunk[0..4], leaf.eax, .little);
+        std.mem.writeInt(u32, chunk[4..8], leaf.ebx, .little);
+        std.mem.writeInt(u32, chunk[8..12], leaf.ecx, .little);
+        std.mem.writeInt(u32, chunk[12..16], leaf.edx, .little);
+    }
+    return std.mem.trim(u8, std.mem.sliceTo(buffer, 0), " ");
+}
+
+fn supported(leaf: u32, register: enum { ebx, edx }, bit: u5) bool {
+    if (cpu.cpuid(leaf & 0x80000000, 0).eax < leaf) {
+        return false;
+    }
+
+    const result = cpu.cpuid(leaf, 0);
+    const value = switch (register) {
+        .ebx => result.ebx,
+        .edx => result.edx,
+    };
+    return value & (@as(u32, 1) << bit) != 0;
+}
+
+/// Logs what the CPU is and how it is configured, for crash reports.
+pub fn dump() void {
+    var vendor_buffer: [12]u8 = undefined;
+    var brand_buffer: [48]u8 = undefined;
+
+    const signature = cpu.cpuid(1, 0).eax;
+    const base_family = (signature >> 8) & 0xF;
+    const family = if (base_family == 0xF) base_family + ((signature >> 20) & 0xFF) else base_family;
+    const model = if (base_family == 0x6 or base_family == 0xF)
+        ((signature >> 4) & 0xF) | ((signature >> 12) & 0xF0)
+    else
+        (signature >> 4) & 0xF;
+
+    log.write("CPU: {s} '{s}' family 0x{x} model 0x{x} stepping {}", .{
+        vendor(&vendor_buffer),
+        brand(&brand_buffer),
+        family,
+        model,
+        signature & 0xF,
+    });
+
+    var hypervisor_buffer: [12]u8 = undefined;
+    log.write("Hypervisor: {s}", .{hypervisor(&hypervisor_buffer) orelse "none"});
+
+    const cr0 = cpu.readCr0();
+    const cr4 = cpu.readCr4();
+    const efer = cpu.readMsr(IA32_EFER);
+
+    log.write("CR0: 0x{x:0>16} CR2: 0x{x:0>16}", .{ cr0, cpu.readCr2() });
+    log.write("CR3: 0x{x:0>16} CR4: 0x{x:0>16}", .{ cpu.readCr3(), cr4 });
+    log.write("EFER: 0x{x:0>16}", .{efer});
+
+    log.write("NX: supported={} enabled={}", .{ supported(0x80000001, .edx, 20), efer & EFER_NXE != 0 });
+    log.write("SMEP: supported={} enabled={}", .{ supported(7, .ebx, 7), cr4 & CR4_SMEP != 0 });
+    log.write("SMAP: supported={} enabled={}", .{ supported(7, .ebx, 20), cr4 & CR4_SMAP != 0 });
+
+    const gdtr = cpu.storeGdt();
+    const idtr = cpu.storeIdt();
+    log.write("GDT: base=0x{x:0>16} limit=0x{x}", .{ gdtr.base, gdtr.limit });
+    log.write("IDT: base=0x{x:0>16} limit=0x{x}", .{ idtr.base, idtr.limit });
+}
@@ -0,0 +1,319 @@
+const Error = @import("kernel").Error;
+
+// NOTE:
+// A length decoder, not a disassembler: it finds where an instruction ends
+// and where a relative branch goes, which is what stepping through code in
+// the shell needs. The encoding is prefixes, an optional REX, VEX or EVEX
+// prefix, one to three opcode bytes, ModRM with SIB and displacement when
+// the opcode takes one, and an immediate whose size depends on the opcode
+// and the operand size prefix. Only 64-bit mode is handled, opcodes that
+// don't exist there are reported as corrupted.
+
+pub const MAX_LENGTH = 15;
+
+pub const Kind = enum {
+    other,
+    call,
+    jump,
+    conditional_jump,
+    @"return",
+};
+
+pub const Instruction = struct {
+    length: usize,
+    kind: Kind = .other,
+    /// Where a relative call or jump goes.
+    target: ?usize = null,
+};
+
+/// Immediate sizes, `z` is 2 bytes with an operand size prefix and 4
+/// without, `v` is `z` that REX.W widens to 8.
+const Immediate = enum { none, b, w, z, v, wb, moffs, rel8, relz };
+
+const Map = enum { one, two, three_38, three_3a };
+
+const Opcode = struct {
+    modrm: bool,
+    immediate: Immediate = .none,
+    valid: bool = true,
+};
+
+const ONE_BYTE = table: {
+    @setEvalBranchQuota(10_000);
+    var result: [256]Opcode = undefined;
+    for (&result, 0..) |*entry, opcode| {
+        entry.* = oneByte(@intCast(opcode));
+    }
+    break :table result;
+};
+
+const TWO_BYTE = table: {
+    @setEvalBranchQuota(10_000);
+    var result: [256]Opcode = undefined;
+    for (&result, 0..) |*entry, opcode| {
+        entry.* = twoByte(@intCast(opcode));
+    }
+    break :table result;
+};
+
+fn oneByte(opcode: u8) Opcode {
+    // the ALU block: add, or, adc, sbb, and, sub, xor, cmp
+    if (opcode < 0x40 and opcode & 0x7 < 6) {
+        return switch (opcode & 0x7) {
+            0...3 => .{ .modrm = true },
+            4 => .{ .modrm = false, .immediate = .b },
+            else => .{ .modrm = false, .immediate = .z },
+        };
+    }
+    return switch (opcode) {
+        // segment pushes, daa and friends
+        0x06, 0x07, 0x0e, 0x16, 0x17, 0x1e, 0x1f, 0x27, 0x2f, 0x37, 0x3f => .{ .modrm = false, .valid = false },
+        // segment override prefixes, handled before the table
+        0x26, 0x2e, 0x36, 0x3e => .{ .modrm = false, .valid = false },
+        0x40...0x5f => .{ .modrm = false },
+        0x60, 0x61, 0x62 => .{ .modrm = false, .valid = false },
+        0x63 => .{ .modrm = true },
+        0x68 => .{ .modrm = false, .immediate = .z },
+        0x69 => .{ .modrm = true, .immediate = .z },
+        0x6a => .{ .modrm = false, .immediate = .b },
+        0x6b => .{ .modrm = true, .immediate = .b },
+        0x6c...0x6f => .{ .modrm = false },
+        0x70...0x7f => .{ .modrm = false, .immediate = .rel8 },
+        0x80, 0x83 => .{ .modrm = true, .immediate = .b },
+        0x81 => .{ .modrm = true, .immediate = .z },
+        0x82 => .{ .modrm = false, .valid = false },
+        0x84...0x8f => .{ .modrm = true },
+        0x90...0x99, 0x9b...0x9f => .{ .modrm = false },
+        0x9a => .{ .modrm = false, .valid = false },
+        0xa0...0xa3 => .{ .modrm = false, .immediate = .moffs },
+        0xa4...0xa7, 0xaa...0xaf => .{ .modrm = false },
+        0xa8 => .{ .modrm = false, .immediate = .b },
+        0xa9 => .{ .modrm = false, .immediate = .z },
+        0xb0...0xb7 => .{ .modrm = false, .immediate = .b },
+        0xb8...0xbf => .{ .modrm = false, .immediate = .v },
+        0xc0, 0xc1, 0xc6 => .{ .modrm = true, .immediate = .b },
+        0xc7 => .{ .modrm = true, .immediate = .z },
+        0xc2, 0xca => .{ .modrm = false, .immediate = .w },
+        0xc3, 0xc9, 0xcb, 0xcc, 0xcf => .{ .modrm = false },
+        0xc8 => .{ .modrm = false, .immediate = .wb },
+        0xcd => .{ .modrm = false, .immediate = .b },
+        0xc4, 0xc5, 0xce, 0xd4, 0xd5, 0xd6 => .{ .modrm = false, .valid = false },
+        0xd0...0xd3, 0xd8...0xdf => .{ .modrm = true },
+        0xd7 => .{ .modrm = false },
+        0xe0...0xe3, 0xeb => .{ .modrm = false, .immediate = .rel8 },
+        0xe4...0xe7 => .{ .modrm = false, .immediate = .b },
+        0xe8, 0xe9 => .{ .modrm = false, .immediate = .relz },
+        0xea => .{ .modrm = false, .valid = false },
+        0xec...0xef, 0xf1, 0xf4, 0xf5, 0xf8...0xfd => .{ .modrm = false },
+        // test with an immediate hides in /0 and /1, see `decode`
+        0xf6, 0xf7, 0xfe, 0xff => .{ .modrm = true },
+        // prefixes, handled before the table
+        else => .{ .modrm = false, .valid = false },
+    };
+}
+
+fn twoByte(opcode: u8) Opcode {
+    return switch (opcode) {
+        0x04, 0x0a, 0x0c, 0x0f, 0x24...0x27, 0x36, 0x39, 0x3b...0x3f, 0x7a, 0x7b, 0xa6, 0xa7 => .{ .modrm = false, .valid = false },
+        // syscall, clts, sysret, invd, wbinvd, ud2, femms
+        0x05...0x09, 0x0b, 0x0e => .{ .modrm = false },
+        // wrmsr, rdtsc, rdmsr, rdpmc, sysenter, sysexit, getsec
+        0x30...0x35, 0x37 => .{ .modrm = false },
+        0x38, 0x3a => .{ .modrm = true },
+        0x70...0x73 => .{ .modrm = true, .immediate = .b },
+        // emms
+        0x77 => .{ .modrm = false },
+        0x80...0x8f => .{ .modrm = false, .immediate = .relz },
+        // push/pop fs and gs, cpuid, rsm
+        0xa0...0xa2, 0xa8...0xaa => .{ .modrm = false },
+        0xa4, 0xac, 0xba, 0xc2, 0xc4...0xc6 => .{ .modrm = true, .immediate = .b },
+        // bswap
+        0xc8...0xcf => .{ .modrm = false },
+        else => .{ .modrm = true },
+    };
+}
+
+fn isPrefix(byte: u8) bool {
+    return switch (byte) {
+        0x26, 0x2e, 0x36, 0x3e, 0x64, 0x65, 0x66, 0x67, 0xf0, 0xf2, 0xf3 => true,
+        else => false,
+    };
+}
+
+/// Reads bytes off the front of the instruction, failing once it would
+/// run past the end of the buffer or the architectural limit.
+const Cursor = struct {
+    bytes: []const u8,
+    position: usize = 0,
+
+    fn next(self: *Cursor) Error!u8 {
+        if (self.position >= self.bytes.len or self.position >= MAX_LENGTH) {
+            return error.Corrupted;
+        }
+        defer self.position += 1;
+        return self.bytes[self.position];
+    }
+
+    fn skip(self: *Cursor, count: usize) Error!void {
+        for (0..count) |_| {
+            _ = try self.next();
+        }
+    }
+
+    fn signed(self: *Cursor, count: usize) Error!i64 {
+        var value: u64 = 0;
+        for (0..count) |index| {
+            value |= @as(u64, try self.next()) << @intCast(index * 8);
+        }
+        const shift: u6 = @intCast(64 - count * 8);
+        return @as(i64, @bitCast(value << shift)) >> shift;
+    }
+};
+
+/// Decodes the instruction at the start of `bytes`, which the CPU would
+/// fetch from `address`. `error.Corrupted` when it is truncated or doesn't
+/// exist in 64-bit mode.
+pub fn decode(bytes: []const u8, address: usize) Error!Instruction {
+    var cursor = Cursor{ .bytes = bytes };
+
+    var operand_size_prefix = false;
+    var address_size_prefix = false;
+    var byte = try cursor.next();
+    while (isPrefix(byte)) : (byte = try cursor.next()) {
+        operand_size_prefix = operand_size_prefix or byte == 0x66;
+        address_size_prefix = address_size_prefix or byte == 0x67;
+    }
+
+    var rex_w = false;
+    if (byte & 0xf0 == 0x40) {
+        rex_w = byte & 0x8 != 0;
+        byte = try cursor.next();
+    }
+
+    var map = Map.one;
+    var opcode = byte;
+    switch (byte) {
+        // VEX and EVEX: the prefix names the map and always has ModRM
+        0xc4, 0xc5, 0x62 => {
+            map = if (byte == 0xc5) .two else switch ((try cursor.next()) & 0x3) {
+                1 => .two,
+                2 => .three_38,
+                3 => .three_3a,
+                else => return error.Corrupted,
+            };
+            // the rest of the prefix, one byte of it was read for the map
+            try cursor.skip(if (byte == 0x62) 2 else 1);
+            opcode = try cursor.next();
+            _ = try skipModrm(&cursor);
+            if (map == .three_3a or (map == .two and TWO_BYTE[opcode].immediate == .b)) {
+                try cursor.skip(1);
+            }
+            return .{ .length = cursor.position };
+        },
+        0x0f => {
+            opcode = try cursor.next();
+            map = switch (opcode) {
+                0x38 => .three_38,
+                0x3a => .three_3a,
+                else => .two,
+            };
+            if (map != .two) {
+                opcode = try cursor.next();
+            }
+        },
+        else => {},
+    }
+
+    const entry: Opcode = switch (map) {
+        .one => ONE_BYTE[opcode],
+        .two => TWO_BYTE[opcode],
+        .three_38 => .{ .modrm = true },
+        .three_3a => .{ .modrm = true, .immediate = .b },
+    };
+    if (!entry.valid) {
+        return error.Corrupted;
+    }
+
+    var result = Instruction{ .length = 0, .kind = kindOf(map, opcode) };
+    var immediate = entry.immediate;
+    if (entry.modrm) {
+        const modrm = try skipModrm(&cursor);
+        const reg = (modrm >> 3) & 0x7;
+        // test r/m, imm is the only /0 and /1 form of these with an immediate
+        if (map == .one and (opcode == 0xf6 or opcode == 0xf7) and reg < 2) {
+            immediate = if (opcode == 0xf6) .b else .z;
+        }
+        // indirect calls and jumps
+        if (map == .one and opcode == 0xff) {
+            result.kind = switch (reg) {
+                2, 3 => .call,
+                4, 5 => .jump,
+                else => .other,
+            };
+        }
+    }
+
+    const operand_bytes: usize = if (operand_size_prefix) 2 else 4;
+    switch (immediate) {
+        .none => {},
+        .b => try cursor.skip(1),
+        .w => try cursor.skip(2),
+        .z => try cursor.skip(operand_bytes),
+        .v => try cursor.skip(if (rex_w) 8 else operand_bytes),
+        .wb => try cursor.skip(3),
+        .moffs => try cursor.skip(if (address_size_prefix) 4 else 8),
+        // branch displacements ignore the operand size prefix in 64-bit mode
+        .rel8, .relz => {
+            const displacement = try cursor.signed(if (immediate == .rel8) 1 else 4);
+            const next_address = address +% cursor.position;
+            result.target = next_address +% @as(usize, @bitCast(displacement));
+        },
+    }
+    result.length = cursor.position;
+    return result;
+}
+
+/// Skips ModRM and whatever SIB and displacement it calls for, returning
+/// the ModRM byte.
+fn skipModrm(cursor: *Cursor) Error!u8 {
+    const modrm = try cursor.next();
+    const mod = modrm >> 6;
+    const rm = modrm & 0x7;
+    if (mod == 3) {
+        return modrm;
+    }
+
+    var displacement: usize = switch (mod) {
+        1 => 1,
+        2 => 4,
+        else => 0,
+    };
+    if (rm == 4) {
+        const sib = try cursor.next();
+        if (mod == 0 and sib & 0x7 == 5) {
+            displacement = 4;
+        }
+    } else if (mod == 0 and rm == 5) {
+        // RIP relative
+        displacement = 4;
+    }
+    try cursor.skip(displacement);
+    return modrm;
+}
+
+fn kindOf(map: Map, opcode: u8) Kind {
+    return switch (map) {
+        .one => switch (opcode) {
+            0xe8 => .call,
+            0xe9, 0xeb => .jump,
+            0x70...0x7f, 0xe0...0xe3 => .conditional_jump,
+            0xc2, 0xc3, 0xca, 0xcb, 0xcf => .@"return",
+            else => .other,
+        },
+        .two => switch (opcode) {
+            0x80...0x8f => .conditional_jump,
+            else => .other,
+        },
+        else => .other,
+    };
+}
@@ -0,0 +1,105 @@
+const testdev = @import("kernel").utils.testdev;
+
+const decoder = @import("decoder.zig");
+
+const Case = struct {
+    bytes: []const u8,
+    length: usize,
+};
+
+/// Encodings covering every way the length can grow: prefixes, REX.W
+/// immediates, SIB, RIP relative and 8/32-bit displacements, the two and
+/// three byte maps and VEX.
+const CASES = [_]Case{
+    // ret, push %rbp, nop
+    .{ .bytes = &.{0xc3}, .length = 1 },
+    .{ .bytes = &.{0x55}, .length = 1 },
+    .{ .bytes = &.{0x90}, .length = 1 },
+    // mov %rsp, %rbp
+    .{ .bytes = &.{ 0x48, 0x89, 0xe5 }, .length = 3 },
+    // sub $0x10, %rsp
+    .{ .bytes = &.{ 0x48, 0x83, 0xec, 0x10 }, .length = 4 },
+    // movabs $0x1122334455667788, %rax
+    .{ .bytes = &.{ 0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11 }, .length = 10 },
+    // mov $0x1, %eax
+    .{ .bytes = &.{ 0xb8, 0x01, 0x00, 0x00, 0x00 }, .length = 5 },
+    // mov $0x1, %ax
+    .{ .bytes = &.{ 0x66, 0xb8, 0x01, 0x00 }, .length = 4 },
+    // mov 0x8(%rsp), %rax
+    .{ .bytes = &.{ 0x48, 0x8b, 0x44, 0x24, 0x08 }, .length = 5 },
+    // mov 0x12345678(%rip), %rax
+    .{ .bytes = &.{ 0x48, 0x8b, 0x05, 0x78, 0x56, 0x34, 0x12 }, .length = 7 },
+    // lea 0x100(%rbx,%rcx,8), %rdx
+    .{ .bytes = &.{ 0x48, 0x8d, 0x94, 0xcb, 0x00, 0x01, 0x00, 0x00 }, .length = 8 },
+    // testb $0x1, (%rdi) and notb (%rdi)
+    .{ .bytes = &.{ 0xf6, 0x07, 0x01 }, .length = 3 },
+    .{ .bytes = &.{ 0xf6, 0x17 }, .length = 2 },
+    // movl $0x0, 0x4(%rax)
+    .{ .bytes = &.{ 0xc7, 0x40, 0x04, 0x00, 0x00, 0x00, 0x00 }, .length = 7 },
+    // lock cmpxchg %rcx, (%rdx)
+    .{ .bytes = &.{ 0xf0, 0x48, 0x0f, 0xb1, 0x0a }, .length = 5 },
+    // rdtsc, cpuid
+    .{ .bytes = &.{ 0x0f, 0x31 }, .length = 2 },
+    .{ .bytes = &.{ 0x0f, 0xa2 }, .length = 2 },
+    // nopw 0x0(%rax,%rax,1)
+    .{ .bytes = &.{ 0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00 }, .length = 6 },
+    // pshufb %xmm1, %xmm0 and palignr $0x4, %xmm1, %xmm0
+    .{ .bytes = &.{ 0x66, 0x0f, 0x38, 0x00, 0xc1 }, .length = 5 },
+    .{ .bytes = &.{ 0x66, 0x0f, 0x3a, 0x0f, 0xc1, 0x04 }, .length = 6 },
+    // vpxor %ymm1, %ymm2, %ymm3 and vpshufd $0x1b, %xmm1, %xmm0
+    .{ .bytes = &.{ 0xc5, 0xed, 0xef, 0xd9 }, .length = 4 },
+    .{ .bytes = &.{ 0xc5, 0xf9, 0x70, 0xc1, 0x1b }, .length = 5 },
+    // jmp *%rax
+    .{ .bytes = &.{ 0xff, 0xe0 }, .length = 2 },
+};
+
+fn lengths() !void {
+    for (CASES) |case| {
+        const instruction = try decoder.decode(case.bytes, 0);
+        if (instruction.length != case.length) {
+            return error.WrongLength;
+        }
+    }
+
+    // truncated, and an opcode that is gone in 64-bit mode
+    if (decoder.decode(&.{ 0x48, 0x8b }, 0)) |_| {
+        return error.AcceptedTruncated;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+    if (decoder.decode(&.{0x06}, 0)) |_| {
+        return error.AcceptedInvalid;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+}
+
+fn branchTargets() !void {
+    const base = 0xffff_ffff_8000_1000;
+
+    // call +0x10
+    const call = try decoder.decode(&.{ 0xe8, 0x10, 0x00, 0x00, 0x00 }, base);
+    if (call.kind != .call or (call.target orelse 0) != base + 5 + 0x10) {
+        return error.WrongCall;
+    }
+    // jne -2, a loop on itself
+    const loop = try decoder.decode(&.{ 0x75, 0xfe }, base);
+    if (loop.kind != .conditional_jump or (loop.target orelse 0) != base) {
+        return error.WrongBranch;
+    }
+    // je near -0x100
+    const near = try decoder.decode(&.{ 0x0f, 0x84, 0x00, 0xff, 0xff, 0xff }, base);
+    if (near.kind != .conditional_jump or (near.target orelse 0) != base + 6 - 0x100) {
+        return error.WrongBranch;
+    }
+    // call *0x8(%rax) has no target to follow
+    const indirect = try decoder.decode(&.{ 0xff, 0x50, 0x08 }, base);
+    if (indirect.kind != .call or indirect.target != null) {
+        return error.WrongIndirectCall;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "decoder.lengths", .func = lengths },
+    .{ .name = "decoder.branch_targets", .func = branchTargets },
+};
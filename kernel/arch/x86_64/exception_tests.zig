@@ -0,0 +1,126 @@
+const testdev = @import("kernel").utils.testdev;
+
+const cpu = @import("cpu.zig");
+const extable = @import("extable.zig");
+
+const DIVIDE_ERROR = 0;
+const BREAKPOINT = 3;
+const INVALID_OPCODE = 6;
+const GENERAL_PROTECTION = 13;
+const PAGE_FAULT = 14;
+
+const PageFaultError = packed struct(u64) {
+    present: bool,
+    write: bool,
+    user: bool,
+    reserved_write: bool,
+    instruction_fetch: bool,
+    __reserved: u59,
+};
+
+fn expectFault(vector: u64) !extable.Fault {
+    const fault = extable.takeFault() orelse return error.NoFault;
+    if (fault.vector != vector) {
+        return error.WrongVector;
+    }
+    return fault;
+}
+
+fn divideError() !void {
+    asm volatile (
+        \\xorl %%ecx, %%ecx
+        \\1: divl %%ecx
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        ::: "rax", "rcx", "rdx", "memory");
+
+    _ = try expectFault(DIVIDE_ERROR);
+}
+
+fn breakpoint() !void {
+    // int3 is a trap, the saved RIP already points past it
+    asm volatile (
+        \\int3
+        \\1:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 1b
+        \\.popsection
+        ::: "memory");
+
+    _ = try expectFault(BREAKPOINT);
+}
+
+fn invalidOpcode() !void {
+    asm volatile (
+        \\1: ud2
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        ::: "memory");
+
+    _ = try expectFault(INVALID_OPCODE);
+}
+
+fn generalProtection() !void {
+    // non-canonical addresses raise #GP with a zero error code
+    asm volatile (
+        \\movabsq $0x8000000000000000, %%rax
+        \\1: movq (%%rax), %%rax
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        ::: "rax", "memory");
+
+    const fault = try expectFault(GENERAL_PROTECTION);
+    if (fault.error_code != 0) {
+        return error.WrongErrorCode;
+    }
+}
+
+fn pageFault() !void {
+    // the null page is never mapped, which makes it the guard page at hand
+    asm volatile (
+        \\xorl %%eax, %%eax
+        \\1: movq %%rax, (%%rax)
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        ::: "rax", "memory");
+
+    const fault = try expectFault(PAGE_FAULT);
+    const error_code: PageFaultError = @bitCast(fault.error_code);
+    if (error_code.present or !error_code.write or error_code.user or error_code.instruction_fetch) {
+        return error.WrongErrorCode;
+    }
+    if (fault.address != 0) {
+        return error.WrongFaultAddress;
+    }
+}
+
+/// Taking and recovering from many exceptions must leave the stack exactly
+/// where it was, anything else means the entry/exit path is unbalanced.
+fn repeatedFaults() !void {
+    const before = cpu.stackPointer();
+    for (0..1000) |_| {
+        try invalidOpcode();
+        try divideError();
+        try generalProtection();
+    }
+    if (cpu.stackPointer() != before) {
+        return error.StackImbalance;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "exception.divide_error", .func = divideError },
+    .{ .name = "exception.breakpoint", .func = breakpoint },
+    .{ .name = "exception.invalid_opcode", .func = invalidOpcode },
+    .{ .name = "exception.general_protection", .func = generalProtection },
+    .{ .name = "exception.page_fault", .func = pageFault },
+    .{ .name = "exception.repeated_faults", .func = repeatedFaults },
+};
@@ -0,0 +1,110 @@
+const cpu = @import("cpu.zig");
+
+// NOTE:
+// Code that expects an instruction to fault registers the instruction's
+// address and where to continue in the `.fixup_table` section, e.g.
+//
+//   1: movq (%rax), %rax
+//   2:
+//   .pushsection .fixup_table, "aw"
+//   .quad 1b, 2b
+//   .popsection
+//
+// The interrupt dispatcher consults the table before treating an exception
+// as fatal, records the fault and resumes at the fixup address.
+pub const Entry = extern struct {
+    fault: u64,
+    fixup: u64,
+};
+
+// Defined in `linker.ld`.
+extern const __fixup_table_start: Entry;
+extern const __fixup_table_end: Entry;
+
+pub const Fault = struct {
+    vector: u64,
+    error_code: u64,
+    /// CR2 for page faults, zero otherwise.
+    address: u64,
+};
+
+const PAGE_FAULT = 14;
+
+var last_fault: ?Fault = null;
+
+fn entries() []const Entry {
+    const start: [*]const Entry = @ptrCast(&__fixup_table_start);
+    const len = (@intFromPtr(&__fixup_table_end) - @intFromPtr(start)) / @sizeOf(Entry);
+    return start[0..len];
+}
+
+pub fn search(rip: u64) ?u64 {
+    for (entries()) |entry| {
+        if (entry.fault == rip) {
+            return entry.fixup;
+        }
+    }
+    return null;
+}
+
+/// Resumes at the fixup address if the exception in `frame` was expected.
+pub fn handle(frame: *cpu.InterruptFrame) bool {
+    const fixup = search(frame.rip) orelse return false;
+
+    last_fault = .{
+        .vector = frame.interrupt_number,
+        .error_code = frame.@"error",
+        .address = if (frame.interrupt_number == PAGE_FAULT) cpu.readCr2() else 0,
+    };
+    frame.rip = fixup;
+    return true;
+}
+
+/// Returns the last fault recovered through the table and forgets it.
+pub fn takeFault() ?Fault {
+    defer last_fault = null;
+    return last_fault;
+}
+
+/// Reads the byte at `address`, null if that faults.
+pub fn readByte(address: usize) ?u8 {
+    // anything above 0xff says the load never happened
+    const value = asm volatile (
+        \\movl $0xffffffff, %%eax
+        \\1: movzbl (%%rsi), %%eax
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        : [value] "={eax}" (-> u32),
+        : [address] "{rsi}" (address),
+        : "memory"
+    );
+    if (value > 0xff) {
+        _ = takeFault();
+        return null;
+    }
+    return @intCast(value);
+}
+
+/// Writes `value` to `address`, false if that faults.
+pub fn writeByte(address: usize, value: u8) bool {
+    const written = asm volatile (
+        \\xorl %%eax, %%eax
+        \\1: movb %%dl, (%%rsi)
+        \\movl $1, %%eax
+        \\2:
+        \\.pushsection .fixup_table, "aw"
+        \\.quad 1b, 2b
+        \\.popsection
+        : [written] "={eax}" (-> u32),
+        : [address] "{rsi}" (address),
+          [value] "{dl}" (value),
+        : "memory"
+    );
+    if (written == 0) {
+        _ = takeFault();
+        return false;
+    }
+    return true;
+}
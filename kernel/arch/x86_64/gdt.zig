@@ -1,5 +1,8 @@
-const std = @import("std");
 const log = @import("kernel").utils.log;
+const kassert = @import("kernel").utils.kassert;
+
+const cpu = @import("cpu.zig");
+const stack = @import("stack.zig");
 
 const GdtEntry = packed struct {
     limit_low: u16,
@@ -45,12 +48,43 @@ const GdtPtr = packed struct {
     base: u64,
 };
 
-var Gdt: [5]GdtEntry = undefined;
+// NOTE:
+// The 64-bit TSS no longer does any task switching, it only tells the CPU
+// which stacks to load when entering ring 0 or when an IDT entry requests an
+// interrupt stack.
+const Tss = extern struct {
+    __reserved1: u32 = 0,
+    rsp: [3]u64 align(4) = .{0} ** 3,
+    __reserved2: u64 align(4) = 0,
+    ist: [7]u64 align(4) = .{0} ** 7,
+    __reserved3: u64 align(4) = 0,
+    __reserved4: u16 = 0,
+    iopb_offset: u16 = 0,
+};
+
+comptime {
+    kassert.comptimeAssert(@sizeOf(Tss) == 104, "Tss is {} bytes, the CPU expects 104", .{@sizeOf(Tss)});
+}
+
+// The TSS descriptor is 16 bytes wide and therefore occupies two entries.
+var Gdt: [7]GdtEntry = undefined;
+var tss = Tss{};
 
 extern fn load_gdt(gdtptr: *const GdtPtr) callconv(.C) void;
 
 pub const KERNEL_CODE_SEGMENT = 0x08;
 pub const KERNEL_DATE_SEGMENT = 0x10;
+pub const TSS_SEGMENT = 0x28;
+
+/// IST slot used by the double fault handler, see `stack.double_fault`.
+pub const DOUBLE_FAULT_IST = 1;
+
+/// Sets the stack the CPU switches to when an interrupt arrives while running
+/// in ring 3. Whoever switches threads must call this with the stack of the
+/// thread being switched to.
+pub fn setKernelStack(kernel_stack: stack.KernelStack) void {
+    tss.rsp[0] = kernel_stack.top();
+}
 
 pub fn install() void {
 
@@ -154,6 +188,32 @@ pub fn install() void {
         },
     );
 
+    setKernelStack(stack.boot);
+    tss.ist[DOUBLE_FAULT_IST - 1] = stack.double_fault.top();
+    tss.iopb_offset = @sizeOf(Tss);
+
+    // task state segment
+    const tss_base = @intFromPtr(&tss);
+    Gdt[5] = GdtEntry.init(
+        @truncate(tss_base),
+        @sizeOf(Tss) - 1,
+        .{
+            .accessed = 1,
+            .readble_writable = 0,
+            .direction_conforming = 0,
+            .executable = 1,
+            .descriptor_type = 0,
+            .descriptor_privilege = 0,
+            .present = 1,
+        },
+        .{
+            .long_mode = 0,
+            .size = 0,
+            .granularity = 0,
+        },
+    );
+    Gdt[6] = @bitCast(@as(u64, tss_base >> 32));
+
     const gdtptr = GdtPtr{
         .limit = @sizeOf(GdtEntry) * Gdt.len - 1,
         .base = @intFromPtr(&Gdt),
@@ -161,5 +221,37 @@ pub fn install() void {
 
     load_gdt(&gdtptr);
 
+    asm volatile ("ltr %[selector]"
+        :
+        : [selector] "r" (@as(u16, TSS_SEGMENT)),
+    );
+
     log.info("Loaded GDT!", .{});
 }
+
+/// Checks that the CPU still uses this GDT and that the TSS is the loaded,
+/// busy task. Returns the number of problems found.
+pub fn verify() usize {
+    var problems: usize = 0;
+
+    const gdtr = cpu.storeGdt();
+    if (gdtr.base != @intFromPtr(&Gdt) or gdtr.limit != @sizeOf(GdtEntry) * Gdt.len - 1) {
+        log.warn("GDTR is 0x{x} (limit 0x{x}), expected 0x{x}", .{ gdtr.base, gdtr.limit, @intFromPtr(&Gdt) });
+        problems += 1;
+    }
+
+    const task_register = cpu.storeTaskRegister();
+    if (task_register != TSS_SEGMENT) {
+        log.warn("Task register is 0x{x}, expected 0x{x}", .{ task_register, TSS_SEGMENT });
+        problems += 1;
+    }
+
+    // ltr flips the descriptor type from available (0x9) to busy (0xB)
+    const access = Gdt[TSS_SEGMENT / @sizeOf(GdtEntry)].access;
+    if (access.present != 1 or access.readble_writable != 1) {
+        log.warn("TSS descriptor is not marked busy", .{});
+        problems += 1;
+    }
+
+    return problems;
+}
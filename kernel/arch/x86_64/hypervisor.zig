@@ -0,0 +1,137 @@
+const std = @import("std");
+const kernel_image = @import("kernel").kernel_image;
+const log = @import("kernel").utils.log;
+
+const cpu = @import("cpu.zig");
+const cpuinfo = @import("cpuinfo.zig");
+
+// NOTE:
+// Hypervisors announce themselves with a signature in CPUID leaf 0x40000000.
+// KVM may sit at a later 0x100 step when it also emulates Hyper-V, so its
+// leaves are searched for. Under KVM the paravirtual features it offers are
+// turned on here, `kvmclock.zig` is set up separately by the time code and
+// `SpinLock` asks whether the host pinned our vCPUs before pausing.
+
+pub const Kind = enum {
+    none,
+    kvm,
+    hyperv,
+    vmware,
+    /// QEMU without acceleration.
+    tcg,
+    unknown,
+};
+
+/// Bits of CPUID 0x40000001 EAX under KVM.
+pub const KvmFeature = enum(u5) {
+    clocksource = 0,
+    nop_io_delay = 1,
+    mmu_op = 2,
+    clocksource2 = 3,
+    async_pf = 4,
+    steal_time = 5,
+    pv_eoi = 6,
+    pv_unhalt = 7,
+    clocksource_stable = 24,
+};
+
+/// Bits of CPUID 0x40000001 EDX under KVM.
+const KVM_HINTS_REALTIME = 1 << 0;
+
+const MSR_KVM_PV_EOI_EN = 0x4b564d04;
+const KVM_MSR_ENABLED = 1 << 0;
+
+const KVM_SIGNATURE = "KVMKVMKVM\x00\x00\x00";
+
+var kind = Kind.none;
+var kvm_features: u32 = 0;
+var kvm_hints: u32 = 0;
+
+/// Set by the hypervisor when it already acknowledged the interrupt being
+/// serviced, see `consumeEoi`.
+var pv_eoi: u32 align(4) = 0;
+var pv_eoi_enabled = false;
+
+fn signature(leaf: u32, buffer: *[12]u8) []const u8 {
+    const result = cpu.cpuid(leaf, 0);
+    std.mem.writeInt(u32, buffer[0..4], result.ebx, .little);
+    std.mem.writeInt(u32, buffer[4..8], result.ecx, .little);
+    std.mem.writeInt(u32, buffer[8..12], result.edx, .little);
+    return buffer;
+}
+
+fn findKvm() ?u32 {
+    var buffer: [12]u8 = undefined;
+    var base: u32 = 0x40000000;
+    while (base < 0x40010000) : (base += 0x100) {
+        if (std.mem.eql(u8, signature(base, &buffer), KVM_SIGNATURE)) {
+            return base;
+        }
+    }
+    return null;
+}
+
+pub fn init() void {
+    var buffer: [12]u8 = undefined;
+    const name = cpuinfo.hypervisor(&buffer) orelse return;
+
+    if (findKvm()) |base| {
+        kind = .kvm;
+        const leaf = cpu.cpuid(base + 1, 0);
+        kvm_features = leaf.eax;
+        kvm_hints = leaf.edx;
+    } else if (std.mem.eql(u8, name, "Microsoft Hv")) {
+        kind = .hyperv;
+    } else if (std.mem.eql(u8, name, "VMwareVMware")) {
+        kind = .vmware;
+    } else if (std.mem.eql(u8, name, "TCGTCGTCGTCG")) {
+        kind = .tcg;
+    } else {
+        kind = .unknown;
+    }
+
+    log.info("Running under {s} ('{s}')", .{ @tagName(kind), name });
+
+    if (kind == .kvm) {
+        log.info("KVM features 0x{x}, hints 0x{x}", .{ kvm_features, kvm_hints });
+        enablePvEoi();
+    }
+}
+
+pub fn detected() Kind {
+    return kind;
+}
+
+pub fn hasKvmFeature(feature: KvmFeature) bool {
+    return kind == .kvm and kvm_features & (@as(u32, 1) << @intFromEnum(feature)) != 0;
+}
+
+/// Whether the host promised never to preempt our vCPUs, in which case a
+/// lock holder is always running and pausing in a spin loop only costs a
+/// pause-loop exit.
+pub fn vcpusArePinned() bool {
+    return kind == .kvm and kvm_hints & KVM_HINTS_REALTIME != 0;
+}
+
+fn enablePvEoi() void {
+    if (!hasKvmFeature(.pv_eoi)) {
+        return;
+    }
+
+    const physical = kernel_image.physicalAddress(@intFromPtr(&pv_eoi)) orelse return;
+    cpu.writeMsr(MSR_KVM_PV_EOI_EN, physical | KVM_MSR_ENABLED);
+    pv_eoi_enabled = true;
+    log.info("Paravirtual EOI enabled", .{});
+}
+
+/// Claims a pending paravirtual EOI. When this returns true the hypervisor
+/// acknowledges the interrupt itself and writing the APIC's EOI register
+/// (a VM exit) can be skipped.
+pub fn consumeEoi() bool {
+    if (!pv_eoi_enabled) {
+        return false;
+    }
+
+    // the host sets the bit concurrently, test and clear it in one go
+    return @atomicRmw(u32, &pv_eoi, .And, ~@as(u32, 1), .seq_cst) & 1 != 0;
+}
@@ -1,8 +1,11 @@
 const std = @import("std");
 const log = @import("kernel").utils.log;
+const kernel_image = @import("kernel").kernel_image;
 
 const gdt = @import("gdt.zig");
 const cpu = @import("cpu.zig");
+const stack = @import("stack.zig");
+const extable = @import("extable.zig");
 
 const Privilege = enum(u2) {
     ring0 = 0,
@@ -45,6 +48,12 @@ const IdtEntry = packed struct {
             .interrupt_stack_table = 0,
         };
     }
+
+    pub fn isrAddress(self: Self) u64 {
+        return @as(u64, self.isr_address_high) << 32 |
+            @as(u64, self.isr_address_mid) << 16 |
+            self.isr_address_low;
+    }
 };
 
 const IdtPtr = packed struct {
@@ -54,7 +63,8 @@ const IdtPtr = packed struct {
 
 var Idt: [256]IdtEntry = undefined;
 
-extern fn interrupt_handler0() void;
+// Generated by `build.zig`, the entry stub of every vector.
+extern const interrupt_stub_table: [256]u64;
 
 pub fn install() void {
     const flags: IdtEntry.Flags = .{
@@ -64,9 +74,13 @@ pub fn install() void {
     };
 
     for (0..256) |i| {
-        Idt[i] = IdtEntry.init(@intFromPtr(&interrupt_handler0) + i * 16, flags);
+        Idt[i] = IdtEntry.init(interrupt_stub_table[i], flags);
     }
 
+    // double faults get a stack of their own so that overflowing the kernel
+    // stack can still be reported
+    Idt[8].interrupt_stack_table = gdt.DOUBLE_FAULT_IST;
+
     const idtptr = IdtPtr{
         .limit = @sizeOf(IdtEntry) * Idt.len - 1,
         .base = @intFromPtr(&Idt),
@@ -80,12 +94,46 @@ pub fn install() void {
     log.info("Loaded IDT", .{});
 }
 
-const InterruptContext = extern struct {
+pub const InterruptContext = extern struct {
     cpu: cpu.Registers,
     interrupt: cpu.InterruptFrame,
 };
 
-pub export fn interrupt_dispatch(ctx: *const InterruptContext) callconv(.C) void {
+/// Called for a device interrupt, in charge of acknowledging it with
+/// whichever interrupt controller raised it.
+pub const Handler = *const fn (ctx: *InterruptContext) void;
+
+var handlers = [_]?Handler{null} ** 256;
+/// How often each vector was raised, exceptions included.
+var counts = [_]u64{0} ** 256;
+
+/// Routes `vector`, which must not be a CPU exception, to `handler`.
+pub fn setHandler(vector: u8, handler: ?Handler) void {
+    std.debug.assert(vector >= 32);
+    handlers[vector] = handler;
+}
+
+pub fn handlerOf(vector: u8) ?Handler {
+    return handlers[vector];
+}
+
+/// How often `vector` has been raised since boot.
+pub fn count(vector: u8) u64 {
+    return @atomicLoad(u64, &counts[vector], .monotonic);
+}
+
+pub export fn interrupt_dispatch(ctx: *InterruptContext) callconv(.C) void {
+    stack.checkUsage();
+    _ = @atomicRmw(u64, &counts[@as(u8, @truncate(ctx.interrupt.interrupt_number))], .Add, 1, .monotonic);
+
+    if (handlers[ctx.interrupt.interrupt_number]) |handler| {
+        return handler(ctx);
+    }
+
+    if (ctx.interrupt.interrupt_number < 32 and extable.handle(&ctx.interrupt)) {
+        return;
+    }
+
     log.write("Caught an exception! 0x{x}", .{ctx.interrupt.interrupt_number});
 
     inline for (std.meta.fields(cpu.Registers)) |f| {
@@ -96,3 +144,30 @@ pub export fn interrupt_dispatch(ctx: *const InterruptContext) callconv(.C) void
         log.write("{s}: 0x{x}", .{ f.name, @field(ctx.interrupt, f.name) });
     }
 }
+
+/// Checks that the CPU still uses this IDT and that every entry is present
+/// and points into kernel text. Returns the number of problems found.
+pub fn verify() usize {
+    var problems: usize = 0;
+
+    const idtr = cpu.storeIdt();
+    if (idtr.base != @intFromPtr(&Idt) or idtr.limit != @sizeOf(IdtEntry) * Idt.len - 1) {
+        log.warn("IDTR is 0x{x} (limit 0x{x}), expected 0x{x}", .{ idtr.base, idtr.limit, @intFromPtr(&Idt) });
+        problems += 1;
+    }
+
+    const text = kernel_image.section(.text);
+
+    for (Idt, 0..) |entry, vector| {
+        const address = entry.isrAddress();
+        if (entry.flags.present != 1 or entry.kernel_code_segment != gdt.KERNEL_CODE_SEGMENT) {
+            log.warn("IDT entry 0x{x} is not a present kernel gate", .{vector});
+            problems += 1;
+        } else if (!text.contains(address)) {
+            log.warn("IDT entry 0x{x} points outside kernel text: 0x{x}", .{ vector, address });
+            problems += 1;
+        }
+    }
+
+    return problems;
+}
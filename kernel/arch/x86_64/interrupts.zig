@@ -0,0 +1,42 @@
+const cpu = @import("cpu.zig");
+
+// NOTE:
+// Short regions that must not be interrupted are written as
+//
+//     const guard = interrupts.disable();
+//     defer guard.restore();
+//
+// rather than with bare cli/sti pairs. The guard remembers whether interrupts
+// were on to begin with, so nested regions and code that runs with
+// interrupts already off don't re-enable them too early.
+
+pub const Guard = struct {
+    was_enabled: bool,
+
+    const Self = @This();
+
+    pub fn restore(self: Self) void {
+        if (self.was_enabled) {
+            cpu.enableInterrupts();
+        }
+    }
+};
+
+/// Disables interrupts until the returned guard is restored.
+pub fn disable() Guard {
+    const guard = Guard{ .was_enabled = cpu.interruptsEnabled() };
+    cpu.disableInterrupts();
+    return guard;
+}
+
+fn ReturnType(comptime func: anytype) type {
+    return @typeInfo(@TypeOf(func)).Fn.return_type.?;
+}
+
+/// Calls `func` with `args` with interrupts disabled.
+pub fn criticalSection(comptime func: anytype, args: anytype) ReturnType(func) {
+    const guard = disable();
+    defer guard.restore();
+
+    return @call(.auto, func, args);
+}
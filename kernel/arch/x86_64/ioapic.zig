@@ -0,0 +1,155 @@
+const std = @import("std");
+const acpi = @import("kernel").acpi;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const mtrr = @import("mtrr.zig");
+const paging = @import("paging.zig");
+
+// NOTE:
+// Each I/O APIC is reached through an index register and a data window. Its
+// redirection table maps the global system interrupts (GSIs) starting at
+// `gsi_base` onto vectors, they all start out masked.
+
+const REGISTER_SELECT = 0x00;
+const REGISTER_WINDOW = 0x10;
+
+const VERSION = 0x01;
+const REDIRECTION_TABLE = 0x10;
+
+const ACTIVE_LOW = 1 << 13;
+const LEVEL_TRIGGERED = 1 << 15;
+const MASKED = 1 << 16;
+
+const MAX_IO_APICS = 8;
+
+const IoApic = struct {
+    registers: usize,
+    gsi_base: u32,
+    entries: u32,
+
+    fn read(self: IoApic, register: u32) u32 {
+        @as(*volatile u32, @ptrFromInt(self.registers + REGISTER_SELECT)).* = register;
+        return @as(*volatile u32, @ptrFromInt(self.registers + REGISTER_WINDOW)).*;
+    }
+
+    fn write(self: IoApic, register: u32, value: u32) void {
+        @as(*volatile u32, @ptrFromInt(self.registers + REGISTER_SELECT)).* = register;
+        @as(*volatile u32, @ptrFromInt(self.registers + REGISTER_WINDOW)).* = value;
+    }
+
+    fn readEntry(self: IoApic, index: u32) u64 {
+        const low = self.read(REDIRECTION_TABLE + index * 2);
+        const high = self.read(REDIRECTION_TABLE + index * 2 + 1);
+        return (@as(u64, high) << 32) | low;
+    }
+
+    fn setEntry(self: IoApic, index: u32, value: u64) void {
+        self.write(REDIRECTION_TABLE + index * 2, @truncate(value));
+        self.write(REDIRECTION_TABLE + index * 2 + 1, @truncate(value >> 32));
+    }
+};
+
+var io_apics: [MAX_IO_APICS]IoApic = undefined;
+var io_apic_count: usize = 0;
+
+pub fn init() Error!void {
+    for (acpi.madt.ioApics()) |entry| {
+        if (io_apic_count == MAX_IO_APICS) {
+            break;
+        }
+
+        var io_apic = IoApic{
+            .registers = try paging.mapMmio(entry.address, paging.PAGE_SIZE),
+            .gsi_base = entry.gsi_base,
+            .entries = 0,
+        };
+        io_apic.entries = ((io_apic.read(VERSION) >> 16) & 0xff) + 1;
+        _ = mtrr.checkRange("I/O APIC", .mmio, entry.address, paging.PAGE_SIZE, io_apic.registers);
+
+        for (0..io_apic.entries) |index| {
+            io_apic.setEntry(@intCast(index), MASKED);
+        }
+
+        io_apics[io_apic_count] = io_apic;
+        io_apic_count += 1;
+
+        log.info("I/O APIC {} handles GSIs {}-{}", .{ entry.id, io_apic.gsi_base, io_apic.gsi_base + io_apic.entries - 1 });
+    }
+
+    if (io_apic_count == 0) {
+        return error.NotFound;
+    }
+}
+
+fn find(gsi: u32) ?IoApic {
+    for (io_apics[0..io_apic_count]) |io_apic| {
+        if (gsi >= io_apic.gsi_base and gsi < io_apic.gsi_base + io_apic.entries) {
+            return io_apic;
+        }
+    }
+    return null;
+}
+
+pub const Route = struct {
+    gsi: u32,
+    vector: u8,
+    destination: u32,
+    active_low: bool = false,
+    level_triggered: bool = false,
+};
+
+/// Delivers `route.gsi` as `route.vector` to the local APIC `destination`.
+pub fn route(settings: Route) Error!void {
+    const io_apic = find(settings.gsi) orelse return error.NotFound;
+
+    var entry: u64 = settings.vector;
+    if (settings.active_low) {
+        entry |= ACTIVE_LOW;
+    }
+    if (settings.level_triggered) {
+        entry |= LEVEL_TRIGGERED;
+    }
+    entry |= @as(u64, settings.destination & 0xff) << 56;
+
+    io_apic.setEntry(settings.gsi - io_apic.gsi_base, entry);
+}
+
+pub fn mask(gsi: u32) void {
+    const io_apic = find(gsi) orelse return;
+    io_apic.setEntry(gsi - io_apic.gsi_base, MASKED);
+}
+
+/// Writes every redirection entry of every I/O APIC.
+pub fn dump(writer: anytype) !void {
+    for (io_apics[0..io_apic_count]) |io_apic| {
+        for (0..io_apic.entries) |index| {
+            const value = io_apic.readEntry(@intCast(index));
+            try writer.print("  GSI {:>3} vector 0x{x:0>2} -> APIC {:>3} {s} {s}{s}\n", .{
+                io_apic.gsi_base + index,
+                @as(u8, @truncate(value)),
+                value >> 56,
+                if (value & LEVEL_TRIGGERED != 0) "level" else "edge ",
+                if (value & ACTIVE_LOW != 0) "low " else "high",
+                if (value & MASKED != 0) " masked" else "",
+            });
+        }
+    }
+}
+
+/// Routes an ISA IRQ, honouring the MADT's interrupt source overrides.
+pub fn routeIsa(irq: u8, vector: u8, destination: u32) Error!void {
+    var settings = Route{ .gsi = irq, .vector = vector, .destination = destination };
+
+    for (acpi.madt.overrides()) |override| {
+        if (override.source != irq) {
+            continue;
+        }
+        settings.gsi = override.gsi;
+        // ISA interrupts are active high and edge triggered unless overridden
+        settings.active_low = override.polarity == .active_low;
+        settings.level_triggered = override.trigger == .level;
+    }
+
+    try route(settings);
+}
@@ -0,0 +1,211 @@
+const Error = @import("kernel").Error;
+const symbols = @import("kernel").utils.symbols;
+
+const apic = @import("apic.zig");
+const idt = @import("idt.zig");
+const interrupts = @import("interrupts.zig");
+const ioapic = @import("ioapic.zig");
+const pic = @import("pic.zig");
+
+// NOTE:
+// Drivers for legacy devices ask for their ISA IRQ here instead of talking to
+// an interrupt controller. On the PICs the IRQ arrives on the PIC's remapped
+// vector, otherwise the I/O APIC delivers it on `ISA_VECTOR_BASE + irq` to
+// the local APIC of the CPU that installed it.
+//
+// PCI devices get a `Line` from the PCI code instead. Those are level
+// triggered and may be shared by several devices, so every line gets a
+// vector of its own from `PCI_VECTOR_BASE` on and a chain of handlers that
+// are all called whenever it fires. The vector is acknowledged after the
+// last handler, by which time each device had its chance to stop asserting
+// the line; acknowledging before that would only fire it again.
+
+const ISA_VECTOR_BASE = 0x30;
+const PCI_VECTOR_BASE = 0x50;
+const MAX_PCI_LINES = 16;
+
+/// Installs `handler` for `irq` and unmasks it. The handler must call
+/// `acknowledgeIsa` once it is done with the device.
+pub fn installIsa(irq: u4, handler: idt.Handler) Error!void {
+    if (pic.active) {
+        idt.setHandler(pic.vector(irq), handler);
+        pic.unmask(irq);
+        return;
+    }
+
+    const vector = ISA_VECTOR_BASE + @as(u8, irq);
+    idt.setHandler(vector, handler);
+    try ioapic.routeIsa(irq, vector, apic.id());
+}
+
+pub fn acknowledgeIsa(irq: u4) void {
+    if (pic.active) {
+        pic.eoi(irq);
+    } else {
+        apic.eoi();
+    }
+}
+
+/// Where a PCI function's INTx pin arrives.
+pub const Line = struct {
+    /// The GSI, or the ISA IRQ while the PICs are in use.
+    gsi: u32,
+    active_low: bool,
+    level_triggered: bool,
+};
+
+/// A handler on a line other devices may share. It is called for every
+/// interrupt on the line and has to check whether its device raised it.
+pub const SharedHandler = struct {
+    func: *const fn (handler: *SharedHandler) void,
+    next: ?*SharedHandler = null,
+};
+
+const PciLine = struct {
+    line: Line,
+    vector: u8,
+    handlers: ?*SharedHandler,
+};
+
+var pci_lines: [MAX_PCI_LINES]PciLine = undefined;
+var pci_line_count: usize = 0;
+
+/// Adds `handler` to the handlers of `line`, routing and unmasking the line
+/// if it is the first one.
+pub fn installShared(line: Line, handler: *SharedHandler) Error!void {
+    const guard = interrupts.disable();
+    defer guard.restore();
+
+    for (pci_lines[0..pci_line_count]) |*existing| {
+        if (existing.line.gsi != line.gsi) {
+            continue;
+        }
+        if (existing.line.active_low != line.active_low or existing.line.level_triggered != line.level_triggered) {
+            return error.InvalidArgument;
+        }
+        handler.next = existing.handlers;
+        existing.handlers = handler;
+        return;
+    }
+
+    if (pci_line_count == MAX_PCI_LINES) {
+        return error.OutOfMemory;
+    }
+    if (pic.active and line.gsi >= pic.IRQ_COUNT) {
+        return error.InvalidArgument;
+    }
+
+    const vector = if (pic.active) pic.vector(@intCast(line.gsi)) else PCI_VECTOR_BASE + @as(u8, @intCast(pci_line_count));
+    if (!pic.active) {
+        try ioapic.route(.{
+            .gsi = line.gsi,
+            .vector = vector,
+            .destination = apic.id(),
+            .active_low = line.active_low,
+            .level_triggered = line.level_triggered,
+        });
+    }
+
+    handler.next = null;
+    pci_lines[pci_line_count] = .{ .line = line, .vector = vector, .handlers = handler };
+    pci_line_count += 1;
+    idt.setHandler(vector, dispatchShared);
+    if (pic.active) {
+        pic.unmask(@intCast(line.gsi));
+    }
+}
+
+/// Removes `handler` from `line`. The line stays routed, the device must
+/// have stopped interrupting.
+pub fn uninstallShared(line: Line, handler: *SharedHandler) void {
+    const guard = interrupts.disable();
+    defer guard.restore();
+
+    for (pci_lines[0..pci_line_count]) |*existing| {
+        if (existing.line.gsi != line.gsi) {
+            continue;
+        }
+        var link = &existing.handlers;
+        while (link.*) |current| : (link = &current.next) {
+            if (current == handler) {
+                link.* = current.next;
+                break;
+            }
+        }
+    }
+    handler.next = null;
+}
+
+fn dispatchShared(ctx: *idt.InterruptContext) void {
+    const vector: u8 = @truncate(ctx.interrupt.interrupt_number);
+    for (pci_lines[0..pci_line_count]) |*line| {
+        if (line.vector != vector) {
+            continue;
+        }
+
+        var handler = line.handlers;
+        while (handler) |current| {
+            // the handler may remove itself
+            handler = current.next;
+            current.func(current);
+        }
+
+        if (pic.active) {
+            pic.eoi(@intCast(line.line.gsi));
+        } else {
+            apic.eoi();
+        }
+        return;
+    }
+}
+
+/// Writes how interrupts are routed: the I/O APIC redirection entries or
+/// the PIC masks, then every vector that has a handler or has been raised
+/// with its handler and count. There are no MSIs yet, nothing allocates
+/// vectors besides `installIsa` and `installShared`.
+pub fn dump(writer: anytype) !void {
+    if (pic.active) {
+        try writer.writeAll("PIC:\n");
+        for (0..pic.IRQ_COUNT) |line| {
+            const irq: u4 = @intCast(line);
+            try writer.print("  IRQ {:>2} vector 0x{x:0>2}{s}\n", .{ irq, pic.vector(irq), if (pic.isMasked(irq)) " masked" else "" });
+        }
+    } else {
+        try writer.writeAll("I/O APIC:\n");
+        try ioapic.dump(writer);
+    }
+    for (pci_lines[0..pci_line_count]) |line| {
+        var handlers: usize = 0;
+        var handler = line.handlers;
+        while (handler) |current| : (handler = current.next) {
+            handlers += 1;
+        }
+        try writer.print("PCI GSI {} vector 0x{x:0>2} {s} {s}, {} handlers\n", .{
+            line.line.gsi,
+            line.vector,
+            if (line.line.level_triggered) "level" else "edge",
+            if (line.line.active_low) "low" else "high",
+            handlers,
+        });
+    }
+    try writer.writeAll("MSI: none allocated\n");
+
+    try writer.writeAll("vectors:\n");
+    for (0..256) |index| {
+        const vector: u8 = @intCast(index);
+        const raised = idt.count(vector);
+        const handler = idt.handlerOf(vector) orelse {
+            if (raised != 0) {
+                try writer.print("  0x{x:0>2} {:>10} {s}\n", .{ vector, raised, if (vector < 32) "exception" else "no handler" });
+            }
+            continue;
+        };
+
+        const address = @intFromPtr(handler);
+        if (symbols.lookup(address)) |symbol| {
+            try writer.print("  0x{x:0>2} {:>10} {s}\n", .{ vector, raised, symbol.name });
+        } else {
+            try writer.print("  0x{x:0>2} {:>10} 0x{x}\n", .{ vector, raised, address });
+        }
+    }
+}
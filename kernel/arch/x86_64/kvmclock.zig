@@ -0,0 +1,137 @@
+const std = @import("std");
+const kernel_image = @import("kernel").kernel_image;
+const kassert = @import("kernel").utils.kassert;
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+const hypervisor = @import("hypervisor.zig");
+
+// NOTE:
+// KVM keeps `TimeInfo` updated with the guest's notion of system time at
+// some TSC value and how to scale TSC deltas into nanoseconds. Unlike a TSC
+// frequency measured once at boot this tracks the host changing the CPU's
+// frequency or migrating the VM. The host bumps `version` to an odd number
+// while it is updating the structure, readers retry until they saw the same
+// even version before and after.
+//
+// The host only guarantees that readings never go backwards, across vCPUs
+// and migrations, when it offers `clocksource_stable` and sets
+// `KVM_CLOCK_TSC_STABLE` in the structure. Otherwise readings are clamped
+// to the latest one handed out.
+
+const MSR_KVM_SYSTEM_TIME_NEW = 0x4b564d01;
+const KVM_MSR_ENABLED = 1 << 0;
+/// Bit of `TimeInfo.flags`.
+const KVM_CLOCK_TSC_STABLE = 1 << 0;
+
+const TimeInfo = extern struct {
+    version: u32,
+    pad0: u32,
+    tsc_timestamp: u64,
+    system_time: u64,
+    tsc_to_system_mul: u32,
+    tsc_shift: i8,
+    flags: u8,
+    pad: [2]u8,
+};
+
+comptime {
+    kassert.comptimeAssert(@sizeOf(TimeInfo) == 32, "TimeInfo is {} bytes, KVM writes 32", .{@sizeOf(TimeInfo)});
+}
+
+// must not cross a page boundary
+var info: TimeInfo align(32) = std.mem.zeroes(TimeInfo);
+var enabled = false;
+/// Latest reading returned while the clock isn't marked stable.
+var last_ns: u64 = 0;
+
+pub fn init() Error!void {
+    if (!hypervisor.hasKvmFeature(.clocksource2)) {
+        return error.Unsupported;
+    }
+
+    const physical = kernel_image.physicalAddress(@intFromPtr(&info)) orelse return error.NotFound;
+    cpu.writeMsr(MSR_KVM_SYSTEM_TIME_NEW, physical | KVM_MSR_ENABLED);
+
+    // the host fills the structure in before the write returns
+    if (snapshot().mul == 0) {
+        cpu.writeMsr(MSR_KVM_SYSTEM_TIME_NEW, 0);
+        return error.Unsupported;
+    }
+    enabled = true;
+}
+
+pub fn isEnabled() bool {
+    return enabled;
+}
+
+fn scale(delta: u64, mul: u32, shift: i8) u64 {
+    const shifted = if (shift >= 0) delta << @intCast(shift) else delta >> @intCast(-@as(i16, shift));
+    return @intCast((@as(u128, shifted) * mul) >> 32);
+}
+
+const Snapshot = struct {
+    tsc_timestamp: u64,
+    system_time: u64,
+    mul: u32,
+    shift: i8,
+    flags: u8,
+};
+
+fn snapshot() Snapshot {
+    const shared: *volatile TimeInfo = &info;
+    while (true) {
+        const version = shared.version;
+        if (version & 1 != 0) {
+            std.atomic.spinLoopHint();
+            continue;
+        }
+
+        @fence(.acquire);
+        const result = Snapshot{
+            .tsc_timestamp = shared.tsc_timestamp,
+            .system_time = shared.system_time,
+            .mul = shared.tsc_to_system_mul,
+            .shift = shared.tsc_shift,
+            .flags = shared.flags,
+        };
+        @fence(.acquire);
+
+        if (shared.version == version) {
+            return result;
+        }
+    }
+}
+
+/// Nanoseconds of host system time, only meaningful relative to another
+/// reading.
+pub fn readNs() u64 {
+    std.debug.assert(enabled);
+
+    const current = snapshot();
+    const now = current.system_time +% scale(cpu.readTsc() -% current.tsc_timestamp, current.mul, current.shift);
+    if (hypervisor.hasKvmFeature(.clocksource_stable) and current.flags & KVM_CLOCK_TSC_STABLE != 0) {
+        return now;
+    }
+
+    var last = @atomicLoad(u64, &last_ns, .monotonic);
+    while (true) {
+        if (now <= last) {
+            return last;
+        }
+        last = @cmpxchgWeak(u64, &last_ns, last, now, .monotonic, .monotonic) orelse return now;
+    }
+}
+
+/// The TSC frequency the host scales by, exact unlike a calibration.
+pub fn tscFrequency() u64 {
+    std.debug.assert(enabled);
+
+    // one tick is mul * 2^shift / 2^32 ns
+    const current = snapshot();
+    const frequency = (@as(u128, std.time.ns_per_s) << 32) / current.mul;
+    if (current.shift >= 0) {
+        return @intCast(frequency >> @intCast(current.shift));
+    }
+    return @intCast(frequency << @intCast(-@as(i16, current.shift)));
+}
@@ -0,0 +1,92 @@
+const std = @import("std");
+const log = @import("kernel").utils.log;
+const options = @import("build_options");
+
+const cpu = @import("cpu.zig");
+
+const IA32_SPEC_CTRL = 0x48;
+const IA32_ARCH_CAPABILITIES = 0x10A;
+
+const SpecCtrl = packed struct(u64) {
+    // NOTE:
+    // IBRS restricts indirect branch prediction across privilege levels,
+    // STIBP stops sibling hyperthreads from steering each other's predictions
+    // and SSBD disables speculative store bypass.
+    ibrs: bool = false,
+    stibp: bool = false,
+    ssbd: bool = false,
+    __reserved: u61 = 0,
+};
+
+const ArchCapabilities = packed struct(u64) {
+    rdcl_no: bool,
+    ibrs_all: bool,
+    rsba: bool,
+    skip_l1dfl_vmentry: bool,
+    ssb_no: bool,
+    __reserved: u59,
+};
+
+const Features = struct {
+    spec_ctrl: bool,
+    stibp: bool,
+    ssbd: bool,
+    arch_capabilities: bool,
+
+    fn detect() Features {
+        if (cpu.cpuid(0, 0).eax < 7) {
+            return .{
+                .spec_ctrl = false,
+                .stibp = false,
+                .ssbd = false,
+                .arch_capabilities = false,
+            };
+        }
+
+        const edx = cpu.cpuid(7, 0).edx;
+        return .{
+            .spec_ctrl = edx & (1 << 26) != 0,
+            .stibp = edx & (1 << 27) != 0,
+            .arch_capabilities = edx & (1 << 29) != 0,
+            .ssbd = edx & (1 << 31) != 0,
+        };
+    }
+};
+
+/// Programs IA32_SPEC_CTRL with every mitigation the CPU supports. This is
+/// opt-in through `-Dmitigations=true` since IBRS is expensive on older parts.
+pub fn init() void {
+    if (!options.mitigations) {
+        return;
+    }
+
+    const features = Features.detect();
+
+    var capabilities = std.mem.zeroes(ArchCapabilities);
+    if (features.arch_capabilities) {
+        capabilities = @bitCast(cpu.readMsr(IA32_ARCH_CAPABILITIES));
+    }
+
+    if (!features.spec_ctrl) {
+        log.warn("CPU does not support IA32_SPEC_CTRL, speculation mitigations unavailable", .{});
+        return;
+    }
+
+    const spec_ctrl = SpecCtrl{
+        .ibrs = true,
+        .stibp = features.stibp,
+        .ssbd = features.ssbd and !capabilities.ssb_no,
+    };
+    cpu.writeMsr(IA32_SPEC_CTRL, @bitCast(spec_ctrl));
+
+    log.info("Enabled speculation mitigations: ibrs={} (enhanced={}) stibp={} ssbd={}", .{
+        spec_ctrl.ibrs,
+        capabilities.ibrs_all,
+        spec_ctrl.stibp,
+        spec_ctrl.ssbd,
+    });
+
+    if (!capabilities.rdcl_no) {
+        log.warn("CPU may be vulnerable to Meltdown, user page tables will need isolation", .{});
+    }
+}
@@ -0,0 +1,268 @@
+const std = @import("std");
+const log = @import("kernel").utils.log.scoped(.mtrr);
+
+const cpu = @import("cpu.zig");
+const paging = @import("paging.zig");
+
+// NOTE:
+// The firmware programs the MTRRs to say how physical memory may be cached,
+// the PAT entry a page table entry selects says the same for the mapping,
+// and the CPU combines the two. A mapping's PAT type can only make caching
+// stricter than the MTRRs allow, except for write combining which wins over
+// everything but UC. Firmware that leaves device memory write-back makes
+// drivers misbehave on real hardware in ways QEMU never shows, and a
+// framebuffer that ends up uncached makes drawing crawl, so both are checked
+// when the ranges are mapped. The MTRRs are only read, never changed.
+
+const IA32_MTRRCAP = 0xfe;
+const IA32_MTRR_DEF_TYPE = 0x2ff;
+const IA32_MTRR_PHYSBASE0 = 0x200;
+const IA32_PAT = 0x277;
+
+const CAP_VARIABLE_COUNT = 0xff;
+const CAP_FIXED = 1 << 8;
+const DEF_FIXED_ENABLE = 1 << 10;
+const DEF_ENABLE = 1 << 11;
+const PHYSMASK_VALID = 1 << 11;
+
+pub const MemoryType = enum(u8) {
+    uncacheable = 0,
+    write_combining = 1,
+    write_through = 4,
+    write_protected = 5,
+    write_back = 6,
+    /// PAT only, UC that write combining MTRRs can override.
+    uncached_minus = 7,
+    _,
+
+    pub fn format(self: MemoryType, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+        const name = switch (self) {
+            .uncacheable => "UC",
+            .write_combining => "WC",
+            .write_through => "WT",
+            .write_protected => "WP",
+            .write_back => "WB",
+            .uncached_minus => "UC-",
+            _ => "??",
+        };
+        try writer.writeAll(name);
+    }
+};
+
+/// Fixed range MSRs with how much each of their eight entries covers.
+const FixedRange = struct {
+    msr: u32,
+    base: u32,
+    step: u32,
+};
+
+const FIXED_RANGES = [_]FixedRange{
+    .{ .msr = 0x250, .base = 0x00000, .step = 0x10000 },
+    .{ .msr = 0x258, .base = 0x80000, .step = 0x4000 },
+    .{ .msr = 0x259, .base = 0xa0000, .step = 0x4000 },
+    .{ .msr = 0x268, .base = 0xc0000, .step = 0x1000 },
+    .{ .msr = 0x269, .base = 0xc8000, .step = 0x1000 },
+    .{ .msr = 0x26a, .base = 0xd0000, .step = 0x1000 },
+    .{ .msr = 0x26b, .base = 0xd8000, .step = 0x1000 },
+    .{ .msr = 0x26c, .base = 0xe0000, .step = 0x1000 },
+    .{ .msr = 0x26d, .base = 0xe8000, .step = 0x1000 },
+    .{ .msr = 0x26e, .base = 0xf0000, .step = 0x1000 },
+    .{ .msr = 0x26f, .base = 0xf8000, .step = 0x1000 },
+};
+
+/// The first megabyte, covered by the fixed ranges when they are enabled.
+const FIXED_END = 0x100000;
+
+const MAX_VARIABLE = 32;
+
+const Variable = struct {
+    base: u64,
+    mask: u64,
+    kind: MemoryType,
+
+    fn contains(self: Variable, physical: u64) bool {
+        return physical & self.mask == self.base & self.mask;
+    }
+};
+
+var supported = false;
+var enabled = false;
+var fixed_enabled = false;
+var default_type: MemoryType = .uncacheable;
+var fixed: [FIXED_RANGES.len]u64 = undefined;
+var variables: [MAX_VARIABLE]Variable = undefined;
+var variable_count: usize = 0;
+var pat: u64 = 0;
+
+/// Reads the MTRRs and the PAT and logs the layout.
+pub fn init() void {
+    const features = cpu.cpuid(1, 0).edx;
+    // without a PAT the CPU uses the power-on default, which is this value
+    pat = if (features & (1 << 16) != 0) cpu.readMsr(IA32_PAT) else 0x0007_0406_0007_0406;
+
+    if (features & (1 << 12) == 0) {
+        log.info("No MTRRs", .{});
+        return;
+    }
+    supported = true;
+
+    const capabilities = cpu.readMsr(IA32_MTRRCAP);
+    const def_type = cpu.readMsr(IA32_MTRR_DEF_TYPE);
+    enabled = def_type & DEF_ENABLE != 0;
+    fixed_enabled = enabled and capabilities & CAP_FIXED != 0 and def_type & DEF_FIXED_ENABLE != 0;
+    default_type = @enumFromInt(@as(u8, @truncate(def_type)));
+
+    if (fixed_enabled) {
+        for (FIXED_RANGES, &fixed) |range, *types| {
+            types.* = cpu.readMsr(range.msr);
+        }
+    }
+
+    const address_mask = physicalAddressMask();
+    const count = @min(capabilities & CAP_VARIABLE_COUNT, MAX_VARIABLE);
+    for (0..count) |index| {
+        const msr: u32 = @intCast(IA32_MTRR_PHYSBASE0 + 2 * index);
+        const base = cpu.readMsr(msr);
+        const mask = cpu.readMsr(msr + 1);
+        if (mask & PHYSMASK_VALID == 0) {
+            continue;
+        }
+
+        variables[variable_count] = .{
+            .base = base & address_mask,
+            .mask = mask & address_mask,
+            .kind = @enumFromInt(@as(u8, @truncate(base))),
+        };
+        variable_count += 1;
+    }
+
+    dump();
+}
+
+/// Bits of a physical address the CPU implements, page aligned.
+fn physicalAddressMask() u64 {
+    const bits: u6 = if (cpu.cpuid(0x80000000, 0).eax >= 0x80000008)
+        @truncate(cpu.cpuid(0x80000008, 0).eax)
+    else
+        36;
+    return ((@as(u64, 1) << bits) - 1) & ~@as(u64, paging.PAGE_SIZE - 1);
+}
+
+/// Size of the range a variable MTRR covers, assuming the mask is
+/// contiguous as the SDM requires.
+fn variableSize(variable: Variable, address_mask: u64) u64 {
+    const low_bits = ~variable.mask & address_mask;
+    return low_bits + paging.PAGE_SIZE;
+}
+
+fn dump() void {
+    if (!enabled) {
+        log.warn("MTRRs are disabled, all memory is uncacheable", .{});
+        return;
+    }
+
+    log.info("MTRRs: default {}, fixed ranges {s}", .{ default_type, if (fixed_enabled) "on" else "off" });
+    const address_mask = physicalAddressMask();
+    for (variables[0..variable_count]) |variable| {
+        log.info("  0x{x:0>12} +0x{x} {}", .{ variable.base, variableSize(variable, address_mask), variable.kind });
+    }
+    log.debug("PAT: 0x{x:0>16}", .{pat});
+}
+
+/// The MTRR type of the page at `physical`.
+pub fn typeOf(physical: u64) MemoryType {
+    if (!supported) {
+        return .write_back;
+    }
+    if (!enabled) {
+        return .uncacheable;
+    }
+
+    if (fixed_enabled and physical < FIXED_END) {
+        for (FIXED_RANGES, fixed) |range, types| {
+            const end = range.base + 8 * range.step;
+            if (physical >= range.base and physical < end) {
+                const slot: u6 = @intCast((physical - range.base) / range.step);
+                return @enumFromInt(@as(u8, @truncate(types >> (slot * 8))));
+            }
+        }
+    }
+
+    // overlapping ranges: UC beats everything, WT beats WB, other overlaps
+    // are undefined and the first match is reported
+    var result: ?MemoryType = null;
+    for (variables[0..variable_count]) |variable| {
+        if (!variable.contains(physical)) {
+            continue;
+        }
+        if (variable.kind == .uncacheable) {
+            return .uncacheable;
+        }
+        const previous = result orelse variable.kind;
+        result = if (previous == .write_back and variable.kind == .write_through) .write_through else previous;
+    }
+    return result orelse default_type;
+}
+
+/// The PAT type selected by entry `index`.
+pub fn patType(index: u3) MemoryType {
+    return @enumFromInt(@as(u8, @truncate(pat >> (@as(u6, index) * 8))) & 0x7);
+}
+
+/// What the CPU makes of an MTRR type and a PAT type together.
+pub fn effectiveType(mtrr_type: MemoryType, pat_type: MemoryType) MemoryType {
+    return switch (pat_type) {
+        .uncacheable => .uncacheable,
+        .write_combining => .write_combining,
+        .uncached_minus => if (mtrr_type == .write_combining) .write_combining else .uncacheable,
+        .write_through => switch (mtrr_type) {
+            .write_back, .write_through => .write_through,
+            .write_protected => .write_protected,
+            else => .uncacheable,
+        },
+        .write_protected => switch (mtrr_type) {
+            .write_back, .write_protected => .write_protected,
+            else => .uncacheable,
+        },
+        .write_back => mtrr_type,
+        _ => .uncacheable,
+    };
+}
+
+pub const RangeKind = enum {
+    /// Device registers, must not be cached.
+    mmio,
+    /// Memory drawn to by the CPU, best write combined.
+    framebuffer,
+};
+
+/// Checks how the `size` bytes at `physical`, mapped at `virtual`, end up
+/// cached and warns when that doesn't suit `kind`. Returns the effective
+/// type of the first page.
+pub fn checkRange(name: []const u8, kind: RangeKind, physical: u64, size: u64, virtual: usize) MemoryType {
+    const mtrr_type = typeOf(physical);
+    var page = physical + paging.PAGE_SIZE;
+    while (page < physical + size) : (page += paging.PAGE_SIZE) {
+        if (typeOf(page) != mtrr_type) {
+            log.warn("{s} at 0x{x} spans MTRR types {} and {}", .{ name, physical, mtrr_type, typeOf(page) });
+            break;
+        }
+    }
+
+    const pat_type = patType(paging.walk(virtual).patIndex());
+    const effective = effectiveType(mtrr_type, pat_type);
+
+    switch (kind) {
+        .mmio => if (mtrr_type == .write_back) {
+            log.warn("{s} at 0x{x} lies in write-back memory, the mapping's {} makes it {}", .{ name, physical, pat_type, effective });
+        },
+        .framebuffer => if (effective == .uncacheable) {
+            log.warn("{s} at 0x{x} is uncached (MTRR {}, PAT {}), drawing will be slow", .{ name, physical, mtrr_type, pat_type });
+        } else if (mtrr_type == .write_back and pat_type != .write_back and pat_type != .write_combining) {
+            log.warn("{s} at 0x{x} is write-back in the MTRRs but {} in the PAT", .{ name, physical, pat_type });
+        },
+    }
+    log.debug("{s} at 0x{x}: MTRR {}, PAT {}, effective {}", .{ name, physical, mtrr_type, pat_type, effective });
+
+    return effective;
+}
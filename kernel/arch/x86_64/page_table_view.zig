@@ -0,0 +1,161 @@
+const std = @import("std");
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+const paging = @import("paging.zig");
+const AddressSpace = @import("address_space.zig").AddressSpace;
+
+// NOTE:
+// A debugging view of page tables that does not go through the higher half
+// direct map. Each level of the walk gets a window, a page of the kernel
+// image whose PTE is pointed at the table being read. The windows are
+// mapped read-only, so a bug in a dumper can't scribble over live tables,
+// and any address space can be inspected whether or not its tables are
+// reachable otherwise. Only the windows' own PTEs are found through the
+// direct map, once, in `init`. The windows are shared, so the view is not
+// for interrupt handlers.
+
+const PRESENT = 1 << 0;
+const WRITABLE = 1 << 1;
+const USER = 1 << 2;
+const HUGE = 1 << 7;
+const NO_EXECUTE = 1 << 63;
+
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const Table = [512]u64;
+
+var windows: [4]Table align(paging.PAGE_SIZE) = undefined;
+var window_entries: [4]*u64 = undefined;
+var ready = false;
+
+pub fn init() Error!void {
+    for (&windows, &window_entries) |*window, *entry| {
+        // the kernel image is mapped with 4 KiB pages
+        entry.* = paging.pageEntry(@intFromPtr(window)) orelse return error.Unsupported;
+    }
+    ready = true;
+}
+
+pub fn isReady() bool {
+    return ready;
+}
+
+/// Makes the table at `physical` readable through the window of `level`.
+fn map(level: usize, physical: u64) *const Table {
+    window_entries[level].* = (physical & ADDRESS_MASK) | PRESENT | NO_EXECUTE;
+    cpu.invalidatePage(@intFromPtr(&windows[level]));
+    return &windows[level];
+}
+
+/// Like `paging.walk`, but through the view and for any address space.
+pub fn walk(space: *const AddressSpace, virtual: usize) paging.Walk {
+    std.debug.assert(ready);
+
+    var result = paging.Walk{};
+    var current = map(0, space.root);
+
+    inline for (.{ 39, 30, 21, 12 }, 0..) |shift, level| {
+        const entry = current[(virtual >> shift) & 0x1ff];
+        result.entries[level] = entry;
+        result.depth = level + 1;
+
+        if (entry & PRESENT == 0 or (level != 0 and level != 3 and entry & HUGE != 0)) {
+            return result;
+        }
+        if (level != 3) {
+            current = map(level + 1, entry);
+        }
+    }
+    return result;
+}
+
+/// A run of pages mapped contiguously with the same permissions.
+pub const Range = struct {
+    virtual: usize,
+    physical: u64,
+    size: usize,
+    write: bool,
+    execute: bool,
+    user: bool,
+
+    fn extends(self: Range, next: Range) bool {
+        return self.virtual +% self.size == next.virtual and
+            self.physical + self.size == next.physical and
+            self.write == next.write and self.execute == next.execute and self.user == next.user;
+    }
+};
+
+const Dumper = struct {
+    writer: std.io.AnyWriter,
+    pending: ?Range = null,
+
+    fn add(self: *Dumper, range: Range) anyerror!void {
+        if (self.pending) |*pending| {
+            if (pending.extends(range)) {
+                pending.size += range.size;
+                return;
+            }
+            try self.flush();
+        }
+        self.pending = range;
+    }
+
+    fn flush(self: *Dumper) anyerror!void {
+        const range = self.pending orelse return;
+        try self.writer.print("  0x{x:0>16}-0x{x:0>16} -> 0x{x:0>12} {s}{s}{s}\n", .{
+            range.virtual,
+            range.virtual +% range.size,
+            range.physical,
+            if (range.write) "w" else "-",
+            if (range.execute) "x" else "-",
+            if (range.user) "u" else "-",
+        });
+        self.pending = null;
+    }
+
+    fn visit(self: *Dumper, level: usize, physical: u64, base: usize, parent: Range) anyerror!void {
+        const shift: u6 = @intCast(39 - 9 * level);
+        const entries = map(level, physical);
+
+        for (entries, 0..) |entry, index| {
+            if (entry & PRESENT == 0) {
+                continue;
+            }
+
+            var virtual = base | (index << shift);
+            // addresses in the upper half are sign extended
+            if (level == 0 and index >= 256) {
+                virtual |= 0xffff_0000_0000_0000;
+            }
+            const range = Range{
+                .virtual = virtual,
+                .physical = entry & ADDRESS_MASK,
+                .size = @as(usize, 1) << shift,
+                .write = parent.write and entry & WRITABLE != 0,
+                .execute = parent.execute and entry & NO_EXECUTE == 0,
+                .user = parent.user and entry & USER != 0,
+            };
+
+            if (level == 3 or (level != 0 and entry & HUGE != 0)) {
+                var leaf = range;
+                // the PAT bit of large pages sits in the address field
+                leaf.physical &= ~(@as(u64, range.size) - 1);
+                try self.add(leaf);
+            } else {
+                try self.visit(level + 1, entry, virtual, range);
+            }
+        }
+    }
+};
+
+/// Writes every mapping of `space`, merging neighbouring pages that are
+/// also physically contiguous and equally permissive.
+pub fn dump(space: *const AddressSpace, writer: anytype) !void {
+    std.debug.assert(ready);
+
+    var dumper = Dumper{ .writer = writer.any() };
+    const everything = Range{ .virtual = 0, .physical = 0, .size = 0, .write = true, .execute = true, .user = true };
+    try dumper.visit(0, space.root, 0, everything);
+    try dumper.flush();
+}
@@ -0,0 +1,292 @@
+const std = @import("std");
+const kernel_image = @import("kernel").kernel_image;
+const log = @import("kernel").utils.log.scoped(.vmm);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+const address_space = @import("address_space.zig");
+
+// NOTE:
+// The kernel still runs on the page tables Limine built. They live in
+// bootloader reclaimable memory, which the PMM never hands out, so it is safe
+// to extend them in place. The higher half direct map only covers memory
+// listed in the memory map, device registers have to be mapped explicitly
+// before they can be reached at `memory.physicalToVirtual(address)`.
+
+pub const PAGE_SIZE = 4096;
+
+const PRESENT = 1 << 0;
+const WRITABLE = 1 << 1;
+const WRITE_THROUGH = 1 << 3;
+const CACHE_DISABLE = 1 << 4;
+const HUGE = 1 << 7;
+const GLOBAL = 1 << 8;
+const NO_EXECUTE = 1 << 63;
+
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const Table = [512]u64;
+
+const CR4_PGE = 1 << 7;
+const CR4_PCIDE = 1 << 17;
+
+/// First PML4 entry of the higher half.
+const KERNEL_HALF = 256;
+
+var global_pages = false;
+
+fn table(physical: u64) *Table {
+    return @ptrFromInt(memory.physicalToVirtual(physical & ADDRESS_MASK));
+}
+
+fn nextLevel(entry: *u64) Error!*Table {
+    if (entry.* & PRESENT == 0) {
+        const physical = try memory.pmm.allocPagesFor(1, .page_tables);
+        @memset(table(physical), 0);
+        entry.* = physical | PRESENT | WRITABLE;
+    }
+    return table(entry.*);
+}
+
+/// Maps the page at `virtual`, `error.AlreadyMapped` when it already has a
+/// translation, its own or a large page's, which is left alone.
+fn mapPage(virtual: usize, physical: usize, flags: u64) Error!void {
+    var current = table(cpu.readCr3());
+    inline for (.{ 39, 30, 21 }) |shift| {
+        const entry = &current[(virtual >> shift) & 0x1ff];
+        if (entry.* & (PRESENT | HUGE) == PRESENT | HUGE) {
+            return error.AlreadyMapped;
+        }
+        current = try nextLevel(entry);
+    }
+
+    const entry = &current[(virtual >> 12) & 0x1ff];
+    if (entry.* & PRESENT != 0) {
+        return error.AlreadyMapped;
+    }
+    entry.* = physical | flags | if (global_pages and isKernelAddress(virtual)) @as(u64, GLOBAL) else 0;
+    cpu.invalidatePage(virtual);
+}
+
+fn isKernelAddress(virtual: usize) bool {
+    return (virtual >> 39) & 0x1ff >= KERNEL_HALF;
+}
+
+// NOTE:
+// Every address space shares the kernel half, so its translations are marked
+// global and survive CR3 switches. The price is that reloading CR3 no longer
+// drops them: after changing kernel mappings either invalidate the pages
+// one by one or call `flushKernelTlb`.
+
+fn markGlobal(entries: *Table, depth: usize) void {
+    for (entries) |*entry| {
+        if (entry.* & PRESENT == 0) {
+            continue;
+        }
+        if (depth == 4 or entry.* & HUGE != 0) {
+            entry.* |= GLOBAL;
+        } else {
+            markGlobal(table(entry.*), depth + 1);
+        }
+    }
+}
+
+/// Marks the kernel half global and turns on CR4.PGE.
+pub fn enableGlobalPages() void {
+    if (cpu.cpuid(1, 0).edx & (1 << 13) == 0) {
+        log.info("No global pages, kernel TLB entries are flushed on every switch", .{});
+        return;
+    }
+
+    const pml4 = table(cpu.readCr3());
+    for (pml4[KERNEL_HALF..]) |*entry| {
+        // large pages are not possible at this level
+        if (entry.* & PRESENT != 0) {
+            markGlobal(table(entry.*), 2);
+        }
+    }
+
+    cpu.writeCr4(cpu.readCr4() | CR4_PGE);
+    global_pages = true;
+    log.info("Kernel mappings are global", .{});
+}
+
+/// Drops every cached translation of every PCID, the global kernel ones
+/// included. Only needed when kernel mappings changed in bulk.
+pub fn flushKernelTlb() void {
+    if (address_space.hasInvpcid()) {
+        cpu.invpcid(.all, 0, 0);
+        return;
+    }
+
+    // any change to PGE flushes everything for every PCID, whichever way
+    const cr4 = cpu.readCr4();
+    if (cr4 & CR4_PGE != 0 or cpu.cpuid(1, 0).edx & (1 << 13) != 0) {
+        cpu.writeCr4(cr4 ^ CR4_PGE);
+        cpu.writeCr4(cr4);
+        return;
+    }
+
+    if (!address_space.pcidsEnabled()) {
+        // nothing is global, reloading CR3 drops everything there is
+        cpu.writeCr3(cpu.readCr3());
+        return;
+    }
+
+    // clearing PCIDE flushes every PCID too, but is only allowed on PCID 0
+    const cr3 = cpu.readCr3();
+    cpu.writeCr3(cr3 & ~@as(u64, 0xfff));
+    cpu.writeCr4(cr4 & ~@as(u64, CR4_PCIDE));
+    cpu.writeCr4(cr4);
+    cpu.writeCr3(cr3);
+}
+
+/// Maps the device registers at `physical` uncached into the higher half
+/// direct map and returns their virtual address. Pages the direct map
+/// already covers, or an earlier call mapped, are kept as they are.
+pub fn mapMmio(physical: usize, size: usize) Error!usize {
+    const end = physical + size;
+    var page = std.mem.alignBackward(usize, physical, PAGE_SIZE);
+    while (page < end) : (page += PAGE_SIZE) {
+        const virtual = memory.physicalToVirtual(page);
+        mapPage(virtual, page, PRESENT | WRITABLE | WRITE_THROUGH | CACHE_DISABLE) catch |err| switch (err) {
+            error.AlreadyMapped => {
+                const existing = walk(virtual);
+                if (existing.physical(virtual) != page) {
+                    return err;
+                }
+                // PAT entry 3 is uncached unless someone reprogrammed it
+                if (existing.patIndex() != 3) {
+                    log.warn("MMIO page 0x{x} is already mapped with PAT entry {}", .{ page, existing.patIndex() });
+                }
+            },
+            else => return err,
+        };
+    }
+    return memory.physicalToVirtual(physical);
+}
+
+/// The entries the CPU goes through to translate an address, from the PML4
+/// down. `depth` of them are valid, the walk stops at the first entry that
+/// is not present or maps a large page.
+pub const Walk = struct {
+    entries: [4]u64 = .{ 0, 0, 0, 0 },
+    depth: usize = 0,
+
+    pub fn isPresent(self: Walk) bool {
+        return self.depth != 0 and self.entries[self.depth - 1] & PRESENT != 0;
+    }
+
+    /// Physical address `virtual` translates to, if it is mapped.
+    pub fn physical(self: Walk, virtual: usize) ?usize {
+        if (!self.isPresent()) {
+            return null;
+        }
+        // a leaf at depth 2 maps 1 GiB, at 3 2 MiB and at 4 a single page
+        const page_bits: u6 = switch (self.depth) {
+            2 => 30,
+            3 => 21,
+            else => 12,
+        };
+        const page_mask = (@as(usize, 1) << page_bits) - 1;
+        return (self.entries[self.depth - 1] & ADDRESS_MASK & ~page_mask) | (virtual & page_mask);
+    }
+
+    /// Which PAT entry the leaf selects, from its PWT, PCD and PAT bits.
+    /// The PAT bit of large pages sits where 4 KiB pages keep `HUGE`.
+    pub fn patIndex(self: Walk) u3 {
+        if (!self.isPresent()) {
+            return 0;
+        }
+        const leaf = self.entries[self.depth - 1];
+        const pat_bit: u6 = if (self.depth == 4) 7 else 12;
+
+        var index: u3 = 0;
+        if (leaf & WRITE_THROUGH != 0) {
+            index |= 1;
+        }
+        if (leaf & CACHE_DISABLE != 0) {
+            index |= 2;
+        }
+        if ((leaf >> pat_bit) & 1 != 0) {
+            index |= 4;
+        }
+        return index;
+    }
+
+    /// Effective permissions of the translation, every level has to allow
+    /// a write for it to be writable and none may forbid execution.
+    pub fn flags(self: Walk) kernel_image.Flags {
+        var result = kernel_image.Flags{ .write = true, .execute = true };
+        for (self.entries[0..self.depth]) |entry| {
+            result.write = result.write and entry & WRITABLE != 0;
+            result.execute = result.execute and entry & NO_EXECUTE == 0;
+        }
+        return result;
+    }
+};
+
+pub fn walk(virtual: usize) Walk {
+    var result = Walk{};
+    var current = table(cpu.readCr3());
+
+    inline for (.{ 39, 30, 21, 12 }, 0..) |shift, level| {
+        const entry = current[(virtual >> shift) & 0x1ff];
+        result.entries[level] = entry;
+        result.depth = level + 1;
+
+        if (entry & PRESENT == 0 or (level != 0 and level != 3 and entry & HUGE != 0)) {
+            return result;
+        }
+        if (level != 3) {
+            current = table(entry);
+        }
+    }
+    return result;
+}
+
+/// The page table entry mapping `virtual` with a 4 KiB page, null if it is
+/// not mapped or covered by a large page.
+pub fn pageEntry(virtual: usize) ?*u64 {
+    var current = table(cpu.readCr3());
+    inline for (.{ 39, 30, 21 }) |shift| {
+        const entry = current[(virtual >> shift) & 0x1ff];
+        if (entry & PRESENT == 0 or entry & HUGE != 0) {
+            return null;
+        }
+        current = table(entry);
+    }
+
+    const entry = &current[(virtual >> 12) & 0x1ff];
+    return if (entry.* & PRESENT != 0) entry else null;
+}
+
+/// Checks that every page of the kernel image is mapped no more permissive
+/// than its section asks for, and that no page is both writable and
+/// executable (no section asks for both). Returns the number of offending
+/// sections.
+pub fn verifyKernelImage() usize {
+    var problems: usize = 0;
+
+    for (kernel_image.sections()) |section| {
+        var page = std.mem.alignBackward(usize, section.start, PAGE_SIZE);
+        while (page < section.end) : (page += PAGE_SIZE) {
+            const result = walk(page);
+            if (!result.isPresent()) {
+                log.warn("Kernel {s} page 0x{x} is not mapped", .{ @tagName(section.kind), page });
+                problems += 1;
+                break;
+            }
+
+            const flags = result.flags();
+            if ((flags.write and !section.flags.write) or (flags.execute and !section.flags.execute)) {
+                log.warn("Kernel {s} page 0x{x} is mapped write={} execute={}", .{ @tagName(section.kind), page, flags.write, flags.execute });
+                problems += 1;
+                break;
+            }
+        }
+    }
+
+    return problems;
+}
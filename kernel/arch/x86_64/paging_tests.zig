@@ -0,0 +1,32 @@
+const memory = @import("kernel").memory;
+const testdev = @import("kernel").utils.testdev;
+
+const paging = @import("paging.zig");
+
+/// Heap memory lives in the higher half direct map, so walking the page
+/// tables has to land on the same frame the HHDM arithmetic does.
+fn heapTranslation() !void {
+    const buffer = try memory.allocator().alloc(u8, 64);
+    defer memory.allocator().free(buffer);
+
+    const virtual = @intFromPtr(buffer.ptr);
+    const result = paging.walk(virtual);
+    const physical = result.physical(virtual) orelse return error.NotMapped;
+    if (physical != memory.virtualToPhysical(virtual)) {
+        return error.WrongTranslation;
+    }
+    if (!result.flags().write) {
+        return error.ReadOnly;
+    }
+}
+
+fn kernelImage() !void {
+    if (paging.verifyKernelImage() != 0) {
+        return error.NotWriteXorExecute;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "paging.heap_translation", .func = heapTranslation },
+    .{ .name = "paging.kernel_image", .func = kernelImage },
+};
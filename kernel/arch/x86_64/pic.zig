@@ -0,0 +1,96 @@
+const cpu = @import("cpu.zig");
+
+// NOTE:
+// The two cascaded 8259 PICs come up delivering IRQ 0-15 on vectors 0x08-0x0f
+// and 0x70-0x77, right on top of the CPU exceptions. They are always remapped
+// to `VECTOR_BASE` so that a stray legacy interrupt can never pass for an
+// exception, and stay fully masked unless the kernel runs in pure PIC mode
+// because there is no local APIC.
+
+const MASTER_COMMAND = 0x20;
+const MASTER_DATA = 0x21;
+const SLAVE_COMMAND = 0xa0;
+const SLAVE_DATA = 0xa1;
+
+const ICW1_INIT = 0x10;
+const ICW1_ICW4 = 0x01;
+const ICW4_8086 = 0x01;
+const OCW2_EOI = 0x20;
+
+/// IRQ line of the master the slave is cascaded on.
+const CASCADE_IRQ = 2;
+
+pub const VECTOR_BASE = 0x20;
+pub const IRQ_COUNT = 16;
+
+var mask_bits: u16 = 0xffff;
+
+/// Set when the PICs deliver device interrupts instead of the APIC.
+pub var active = false;
+
+// a write to an unused port gives the PIC time to settle between commands
+fn wait() void {
+    cpu.writeByte(0x80, 0);
+}
+
+/// Remaps both PICs to `VECTOR_BASE` and masks every line.
+pub fn init() void {
+    cpu.writeByte(MASTER_COMMAND, ICW1_INIT | ICW1_ICW4);
+    wait();
+    cpu.writeByte(SLAVE_COMMAND, ICW1_INIT | ICW1_ICW4);
+    wait();
+
+    cpu.writeByte(MASTER_DATA, VECTOR_BASE);
+    wait();
+    cpu.writeByte(SLAVE_DATA, VECTOR_BASE + 8);
+    wait();
+
+    cpu.writeByte(MASTER_DATA, 1 << CASCADE_IRQ);
+    wait();
+    cpu.writeByte(SLAVE_DATA, CASCADE_IRQ);
+    wait();
+
+    cpu.writeByte(MASTER_DATA, ICW4_8086);
+    wait();
+    cpu.writeByte(SLAVE_DATA, ICW4_8086);
+    wait();
+
+    mask_bits = 0xffff;
+    writeMask();
+}
+
+fn writeMask() void {
+    cpu.writeByte(MASTER_DATA, @truncate(mask_bits));
+    cpu.writeByte(SLAVE_DATA, @truncate(mask_bits >> 8));
+}
+
+pub fn mask(irq: u4) void {
+    mask_bits |= @as(u16, 1) << irq;
+    writeMask();
+}
+
+pub fn unmask(irq: u4) void {
+    mask_bits &= ~(@as(u16, 1) << irq);
+    // the slave can only deliver through the cascade line
+    if (irq >= 8) {
+        mask_bits &= ~@as(u16, 1 << CASCADE_IRQ);
+    }
+    writeMask();
+}
+
+/// Acknowledges `irq`, which must be the one currently being serviced.
+pub fn eoi(irq: u4) void {
+    if (irq >= 8) {
+        cpu.writeByte(SLAVE_COMMAND, OCW2_EOI);
+    }
+    cpu.writeByte(MASTER_COMMAND, OCW2_EOI);
+}
+
+/// Whether `irq` is masked.
+pub fn isMasked(irq: u4) bool {
+    return mask_bits & (@as(u16, 1) << irq) != 0;
+}
+
+pub fn vector(irq: u4) u8 {
+    return VECTOR_BASE + @as(u8, irq);
+}
@@ -0,0 +1,34 @@
+const cpu = @import("cpu.zig");
+
+// NOTE:
+// The PIT is only used as a reference clock of known frequency. Channel 2 is
+// the one wired to the PC speaker and, unlike channel 0, can be gated and
+// polled through port 0x61 without taking any interrupts.
+pub const FREQUENCY = 1_193_182;
+
+const CHANNEL2_DATA_PORT = 0x42;
+const COMMAND_PORT = 0x43;
+const SPEAKER_PORT = 0x61;
+
+const SPEAKER_GATE = 1 << 0;
+const SPEAKER_ENABLE = 1 << 1;
+const CHANNEL2_OUTPUT = 1 << 5;
+
+/// Starts channel 2 counting down `ticks` in one-shot mode.
+pub fn startOneShot(ticks: u16) void {
+    // gate low while programming, and keep the speaker quiet
+    const speaker = cpu.readByte(SPEAKER_PORT) & ~@as(u8, SPEAKER_GATE | SPEAKER_ENABLE);
+    cpu.writeByte(SPEAKER_PORT, speaker);
+
+    // channel 2, lobyte/hibyte access, mode 0 (interrupt on terminal count)
+    cpu.writeByte(COMMAND_PORT, 0b10_11_000_0);
+    cpu.writeByte(CHANNEL2_DATA_PORT, @truncate(ticks));
+    cpu.writeByte(CHANNEL2_DATA_PORT, @truncate(ticks >> 8));
+
+    cpu.writeByte(SPEAKER_PORT, speaker | SPEAKER_GATE);
+}
+
+/// Whether the count started by `startOneShot` has reached zero.
+pub fn oneShotExpired() bool {
+    return cpu.readByte(SPEAKER_PORT) & CHANNEL2_OUTPUT != 0;
+}
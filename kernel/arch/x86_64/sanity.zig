@@ -0,0 +1,29 @@
+const log = @import("kernel").utils.log;
+
+const gdt = @import("gdt.zig");
+const idt = @import("idt.zig");
+const paging = @import("paging.zig");
+
+/// Re-reads the descriptor table registers and checks the tables behind them
+/// for corruption. Returns the number of problems found.
+pub fn check() usize {
+    const problems = gdt.verify() + idt.verify();
+    if (problems == 0) {
+        log.info("Descriptor tables are sane", .{});
+    } else {
+        log.warn("Found {} problems with the descriptor tables", .{problems});
+    }
+    return problems;
+}
+
+/// Checks that the kernel image is mapped W^X. Walks the page tables, so
+/// this has to wait for the higher half direct map to be known.
+pub fn checkMappings() usize {
+    const problems = paging.verifyKernelImage();
+    if (problems == 0) {
+        log.info("Kernel image is mapped W^X", .{});
+    } else {
+        log.warn("Found {} kernel sections mapped too permissively", .{problems});
+    }
+    return problems;
+}
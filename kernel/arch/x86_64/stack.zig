@@ -0,0 +1,83 @@
+const builtin = @import("builtin");
+const log = @import("kernel").utils.log;
+const backtrace = @import("kernel").utils.backtrace;
+
+const cpu = @import("cpu.zig");
+
+pub const PAGE_SIZE = 4096;
+
+/// Usage (in percent) past which `checkUsage` starts complaining.
+const WARN_THRESHOLD = 75;
+
+pub const KernelStack = struct {
+    memory: []align(16) u8,
+    /// Deepest usage `checkUsage` has seen so far, in bytes.
+    peak_usage: usize = 0,
+
+    const Self = @This();
+
+    pub fn base(self: Self) usize {
+        return @intFromPtr(self.memory.ptr);
+    }
+
+    /// Stacks grow downwards, so this is the value to load into RSP.
+    pub fn top(self: Self) usize {
+        return self.base() + self.memory.len;
+    }
+
+    pub fn contains(self: Self, address: usize) bool {
+        return address >= self.base() and address < self.top();
+    }
+
+    pub fn usage(self: Self, stack_pointer: usize) usize {
+        return self.top() - stack_pointer;
+    }
+};
+
+var boot_stack_storage: [16 * PAGE_SIZE]u8 align(16) = undefined;
+var double_fault_stack_storage: [4 * PAGE_SIZE]u8 align(16) = undefined;
+
+/// The stack `_start` switches to once Limine hands over control. It doubles
+/// as the kernel stack (RSP0) of the boot thread.
+pub var boot = KernelStack{ .memory = &boot_stack_storage };
+
+/// A known-good stack for the double fault handler, used through the IST so
+/// that a kernel stack overflow does not escalate into a triple fault.
+pub var double_fault = KernelStack{ .memory = &double_fault_stack_storage };
+
+/// The stack of the running thread, whoever switches threads keeps it
+/// up to date.
+pub var current: *KernelStack = &boot;
+
+/// Debug builds only: checks how much of the current stack is in use and
+/// logs a warning with a backtrace the first time it crosses 75%. Sprinkled
+/// over deep call paths it catches stack hogs before they hit the guard.
+pub fn checkUsage() void {
+    if (builtin.mode != .Debug) {
+        return;
+    }
+
+    const stack_pointer = cpu.stackPointer();
+    if (!current.contains(stack_pointer)) {
+        // running on an IST or some foreign stack, nothing to compare with
+        return;
+    }
+
+    const used = current.usage(stack_pointer);
+    if (used <= current.peak_usage) {
+        return;
+    }
+
+    const threshold = current.memory.len * WARN_THRESHOLD / 100;
+    if (used > threshold and current.peak_usage <= threshold) {
+        log.warn("Kernel stack 0x{x} is {}% full ({} of {} bytes)", .{
+            current.base(),
+            used * 100 / current.memory.len,
+            used,
+            current.memory.len,
+        });
+        backtrace.dump(@returnAddress());
+    }
+
+    current.peak_usage = used;
+}
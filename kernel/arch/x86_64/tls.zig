@@ -0,0 +1,91 @@
+const std = @import("std");
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+
+const IA32_FS_BASE = 0xC0000100;
+
+// Defined in `linker.ld`, these delimit the kernel's own PT_TLS segment.
+extern const __tdata_start: u8;
+extern const __tdata_end: u8;
+extern const __tbss_end: u8;
+
+/// Alignment the linker script forces on the kernel's TLS segment.
+const KERNEL_TLS_ALIGNMENT = 64;
+
+// NOTE:
+// x86_64 uses TLS variant II: the thread pointer (FS base) points right past
+// the TLS data, at a control block whose first word points to itself. The
+// linker resolves thread-locals as negative offsets from the thread pointer.
+const Tcb = extern struct {
+    self: usize,
+};
+
+/// A TLS template, either the kernel's own or the PT_TLS segment of an ELF.
+pub const Image = struct {
+    /// Initialized data (.tdata), copied into every block.
+    initial: []const u8,
+    /// Total size of the segment, the part past `initial` is zero-filled.
+    size: usize,
+    alignment: usize,
+
+    const Self = @This();
+
+    fn dataSize(self: Self) usize {
+        return std.mem.alignForward(usize, self.size, self.alignment);
+    }
+
+    /// Number of bytes a thread needs to hold this image and its TCB.
+    pub fn blockSize(self: Self) usize {
+        return self.dataSize() + @sizeOf(Tcb);
+    }
+
+    /// Initializes a TLS block inside `memory` and returns the value to load
+    /// as the thread pointer.
+    pub fn initBlock(self: Self, memory: []u8) usize {
+        std.debug.assert(memory.len >= self.blockSize());
+        std.debug.assert(std.mem.isAligned(@intFromPtr(memory.ptr), self.alignment));
+
+        const data = memory[0..self.dataSize()];
+        @memcpy(data[0..self.initial.len], self.initial);
+        @memset(data[self.initial.len..], 0);
+
+        const thread_pointer = @intFromPtr(memory.ptr) + data.len;
+        const tcb: *align(1) Tcb = @ptrFromInt(thread_pointer);
+        tcb.self = thread_pointer;
+
+        return thread_pointer;
+    }
+};
+
+pub fn kernelImage() Image {
+    const start = @intFromPtr(&__tdata_start);
+    const initial: [*]const u8 = @ptrCast(&__tdata_start);
+
+    return .{
+        .initial = initial[0 .. @intFromPtr(&__tdata_end) - start],
+        .size = @intFromPtr(&__tbss_end) - start,
+        .alignment = KERNEL_TLS_ALIGNMENT,
+    };
+}
+
+/// Makes `thread_pointer` the TLS block used by `threadlocal` variables.
+pub fn activate(thread_pointer: usize) void {
+    cpu.writeMsr(IA32_FS_BASE, thread_pointer);
+}
+
+var boot_block: [4096]u8 align(KERNEL_TLS_ALIGNMENT) = undefined;
+
+/// Sets up TLS for the boot thread, `threadlocal` variables must not be
+/// touched before this runs.
+pub fn init() Error!void {
+    const image = kernelImage();
+    if (image.blockSize() > boot_block.len) {
+        return error.OutOfMemory;
+    }
+
+    activate(image.initBlock(&boot_block));
+
+    log.info("Initialized TLS ({} bytes)", .{image.size});
+}
@@ -0,0 +1,43 @@
+const std = @import("std");
+const Error = @import("kernel").Error;
+
+const cpu = @import("cpu.zig");
+const pit = @import("pit.zig");
+
+pub const read = cpu.readTsc;
+
+/// Length of a single calibration run.
+const CALIBRATION_MS = 10;
+const CALIBRATION_RUNS = 3;
+
+/// Whether the TSC ticks at a constant rate regardless of frequency scaling
+/// and sleep states.
+pub fn isInvariant() bool {
+    if (cpu.cpuid(0x80000000, 0).eax < 0x80000007) {
+        return false;
+    }
+    return cpu.cpuid(0x80000007, 0).edx & (1 << 8) != 0;
+}
+
+/// Measures the TSC frequency in Hz against the PIT.
+pub fn calibrate() Error!u64 {
+    if (cpu.cpuid(1, 0).edx & (1 << 4) == 0) {
+        return error.Unsupported;
+    }
+
+    const ticks = pit.FREQUENCY * CALIBRATION_MS / 1000;
+
+    // an SMI or an emulator hiccup can only make a run longer, so the
+    // shortest of a few runs is the most accurate one
+    var best: u64 = std.math.maxInt(u64);
+    for (0..CALIBRATION_RUNS) |_| {
+        pit.startOneShot(ticks);
+        const start = read();
+        while (!pit.oneShotExpired()) {
+            std.atomic.spinLoopHint();
+        }
+        best = @min(best, read() - start);
+    }
+
+    return best * pit.FREQUENCY / ticks;
+}
@@ -0,0 +1,13 @@
+const limine = @import("limine");
+
+// NOTE:
+// Every request the kernel makes to Limine lives here. Limine finds them by
+// scanning the kernel image, so they only need to be exported.
+pub export var base_revision: limine.BaseRevision = .{ .revision = 2 };
+pub export var framebuffer_request: limine.FramebufferRequest = .{};
+pub export var hhdm_request: limine.HhdmRequest = .{};
+pub export var memory_map_request: limine.MemoryMapRequest = .{};
+pub export var rsdp_request: limine.RsdpRequest = .{};
+pub export var kernel_file_request: limine.KernelFileRequest = .{};
+pub export var kernel_address_request: limine.KernelAddressRequest = .{};
+pub export var module_request: limine.ModuleRequest = .{};
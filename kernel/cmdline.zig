@@ -0,0 +1,103 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const Error = @import("kernel").Error;
+const log = @import("kernel").utils.log;
+
+// NOTE:
+// The kernel command line is what the Limine config passes to the kernel
+// (`KERNEL_CMDLINE=`). Options are separated by whitespace and are
+// either `key=value` or a bare `key`, which reads as true. When an option
+// is given more than once the last one wins. The string lives in bootloader
+// reclaimable memory, which is never handed out, so values can be kept.
+
+pub const Option = struct {
+    key: []const u8,
+    /// Empty for a bare `key`.
+    value: []const u8,
+};
+
+pub const Iterator = struct {
+    arguments: std.mem.TokenIterator(u8, .any),
+
+    pub fn next(self: *Iterator) ?Option {
+        const argument = self.arguments.next() orelse return null;
+        const separator = std.mem.indexOfScalar(u8, argument, '=') orelse {
+            return .{ .key = argument, .value = "" };
+        };
+        return .{ .key = argument[0..separator], .value = argument[separator + 1 ..] };
+    }
+};
+
+/// The whole command line, empty when Limine didn't pass one.
+pub fn raw() []const u8 {
+    const response = boot.kernel_file_request.response orelse return "";
+    return std.mem.sliceTo(response.kernel_file.cmdline, 0);
+}
+
+/// Every option in the order given.
+pub fn options() Iterator {
+    return .{ .arguments = std.mem.tokenizeAny(u8, raw(), " \t") };
+}
+
+fn find(key: []const u8) ?Option {
+    var result: ?Option = null;
+    var iterator = options();
+    while (iterator.next()) |option| {
+        if (std.mem.eql(u8, option.key, key)) {
+            result = option;
+        }
+    }
+    return result;
+}
+
+pub fn has(key: []const u8) bool {
+    return find(key) != null;
+}
+
+/// The value of `key` as a `T`: a string, a bool (`on/off`, `yes/no`,
+/// `true/false`, `1/0`, a bare key is true), an integer in any base
+/// `parseInt` takes or an enum by tag name. Null when the option is absent,
+/// `error.InvalidArgument` when its value doesn't parse.
+pub fn get(comptime T: type, key: []const u8) Error!?T {
+    const option = find(key) orelse return null;
+    return try parse(T, option.value);
+}
+
+/// Like `get`, but falls back to `default` when the option is absent or
+/// malformed, warning about the latter.
+pub fn getOr(comptime T: type, key: []const u8, default: T) T {
+    const value = get(T, key) catch {
+        log.warn("Ignoring malformed {s}", .{key});
+        return default;
+    };
+    return value orelse default;
+}
+
+pub fn parse(comptime T: type, value: []const u8) Error!T {
+    switch (@typeInfo(T)) {
+        .Pointer => {
+            if (T != []const u8) {
+                @compileError("unsupported command line option type " ++ @typeName(T));
+            }
+            return value;
+        },
+        .Bool => {
+            const truthy = [_][]const u8{ "", "on", "yes", "true", "1" };
+            const falsy = [_][]const u8{ "off", "no", "false", "0" };
+            for (truthy) |text| {
+                if (std.mem.eql(u8, value, text)) {
+                    return true;
+                }
+            }
+            for (falsy) |text| {
+                if (std.mem.eql(u8, value, text)) {
+                    return false;
+                }
+            }
+            return error.InvalidArgument;
+        },
+        .Int => return std.fmt.parseInt(T, value, 0) catch error.InvalidArgument,
+        .Enum => return std.meta.stringToEnum(T, value) orelse error.InvalidArgument,
+        else => @compileError("unsupported command line option type " ++ @typeName(T)),
+    }
+}
@@ -0,0 +1,375 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const framebuffer = @import("framebuffer.zig");
+const log = @import("kernel").utils.log;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+const Error = @import("kernel").Error;
+
+const Color = framebuffer.Color;
+
+// NOTE:
+// A text console drawn on one display, or mirrored across all of them, with
+// a PSF2 bitmap font embedded into the kernel. It knows nothing about escape
+// sequences, only newline, carriage return, tab and backspace move the
+// cursor. When the cursor runs off the bottom everything moves up a line.
+
+const PSF2_MAGIC = 0x864ab572;
+const PSF2_HAS_UNICODE_TABLE = 1 << 0;
+const PSF2_SEPARATOR = 0xff;
+const PSF2_START_SEQUENCE = 0xfe;
+
+const Psf2Header = extern struct {
+    magic: u32,
+    version: u32,
+    header_size: u32,
+    flags: u32,
+    glyph_count: u32,
+    bytes_per_glyph: u32,
+    height: u32,
+    width: u32,
+};
+
+const Font = struct {
+    glyphs: []const u8,
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    bytes_per_row: usize,
+    width: usize,
+    height: usize,
+    /// Glyph index of every Latin-1 code point.
+    latin1: [256]u16,
+    /// Glyph drawn for anything the font has no glyph for.
+    fallback: u16,
+
+    const Self = @This();
+
+    fn parse(comptime data: []const u8) Self {
+        @setEvalBranchQuota(100_000);
+
+        const header = std.mem.bytesToValue(Psf2Header, data[0..@sizeOf(Psf2Header)]);
+        if (header.magic != PSF2_MAGIC) {
+            @compileError("console font is not a PSF2 font");
+        }
+
+        const glyphs_size = header.glyph_count * header.bytes_per_glyph;
+        var font = Self{
+            .glyphs = data[header.header_size..][0..glyphs_size],
+            .glyph_count = header.glyph_count,
+            .bytes_per_glyph = header.bytes_per_glyph,
+            .bytes_per_row = (header.width + 7) / 8,
+            .width = header.width,
+            .height = header.height,
+            .latin1 = undefined,
+            .fallback = 0,
+        };
+
+        if (header.flags & PSF2_HAS_UNICODE_TABLE == 0) {
+            // without a table glyphs are assumed to be in code point order
+            for (&font.latin1, 0..) |*glyph, codepoint| {
+                glyph.* = if (codepoint < header.glyph_count) @intCast(codepoint) else 0;
+            }
+            return font;
+        }
+
+        font.latin1 = [_]u16{0} ** 256;
+        const table = data[header.header_size + glyphs_size ..];
+
+        // every glyph's entry is a list of UTF-8 code points, then optional
+        // multi-code-point sequences, terminated by 0xff
+        var glyph: u16 = 0;
+        var offset: usize = 0;
+        var in_sequence = false;
+        while (offset < table.len and glyph < header.glyph_count) {
+            const byte = table[offset];
+            if (byte == PSF2_SEPARATOR) {
+                glyph += 1;
+                offset += 1;
+                in_sequence = false;
+                continue;
+            }
+            if (byte == PSF2_START_SEQUENCE) {
+                offset += 1;
+                in_sequence = true;
+                continue;
+            }
+
+            const length = std.unicode.utf8ByteSequenceLength(byte) catch @compileError("console font has a malformed unicode table");
+            const codepoint = std.unicode.utf8Decode(table[offset..][0..length]) catch @compileError("console font has a malformed unicode table");
+            offset += length;
+
+            if (in_sequence) {
+                continue;
+            }
+            if (codepoint < font.latin1.len) {
+                font.latin1[codepoint] = glyph;
+            } else if (codepoint == std.unicode.replacement_character) {
+                font.fallback = glyph;
+            }
+        }
+        return font;
+    }
+
+    fn glyphIndex(self: *const Self, codepoint: u21) u16 {
+        if (codepoint < self.latin1.len and self.latin1[codepoint] != 0) {
+            return self.latin1[codepoint];
+        }
+        return self.fallback;
+    }
+
+    fn bitmap(self: *const Self, codepoint: u21) []const u8 {
+        const index = self.glyphIndex(codepoint);
+        return self.glyphs[@as(usize, index) * self.bytes_per_glyph ..][0..self.bytes_per_glyph];
+    }
+};
+
+const font = Font.parse(@embedFile("console_font"));
+
+const TAB_WIDTH = 8;
+
+const Display = framebuffer.Display;
+
+/// Displays the console draws on, the selected one first.
+var targets: [framebuffer.MAX_DISPLAYS]*Display = undefined;
+var target_count: usize = 0;
+
+var columns: usize = 0;
+var rows: usize = 0;
+var column: usize = 0;
+var row: usize = 0;
+
+var foreground = Color.white;
+var background = Color.black;
+
+/// A UTF-8 sequence split across writes.
+var partial: [4]u8 = undefined;
+var partial_len: usize = 0;
+
+var lock = SpinLock.init();
+
+const Writer = std.io.Writer(void, error{}, writeFn);
+pub const writer = Writer{ .context = {} };
+
+var log_sink = log.Sink{ .name = "console", .write = logWrite };
+
+/// Sets the console up on the primary display, clears the screen and starts
+/// showing the log. Must be called after `framebuffer.init`.
+pub fn init() Error!void {
+    try select(0, false);
+    log.register(&log_sink);
+}
+
+fn logWrite(bytes: []const u8, _: log.Priority) void {
+    _ = writeFn({}, bytes) catch unreachable;
+}
+
+/// Moves the console to display `index`, and with `mirror` to every other
+/// display as well. Mirrored output is cut to the smallest display.
+pub fn select(index: usize, mirror: bool) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    const display = framebuffer.get(index) orelse return error.NotFound;
+    var count: usize = 1;
+    var new_targets: [framebuffer.MAX_DISPLAYS]*Display = undefined;
+    new_targets[0] = display;
+
+    if (mirror) {
+        for (framebuffer.all()) |*other| {
+            if (other != display) {
+                new_targets[count] = other;
+                count += 1;
+            }
+        }
+    }
+
+    var new_columns: usize = std.math.maxInt(usize);
+    var new_rows: usize = std.math.maxInt(usize);
+    for (new_targets[0..count]) |target| {
+        new_columns = @min(new_columns, target.width() / font.width);
+        new_rows = @min(new_rows, target.height() / font.height);
+    }
+    if (new_columns == 0 or new_rows == 0) {
+        return error.Unsupported;
+    }
+
+    targets = new_targets;
+    target_count = count;
+    columns = new_columns;
+    rows = new_rows;
+    clearLocked();
+}
+
+pub fn isReady() bool {
+    return rows != 0;
+}
+
+/// Changes the colors of everything printed from now on.
+pub fn setColors(fg: Color, bg: Color) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    foreground = fg;
+    background = bg;
+}
+
+/// Fills the screen with the background color and homes the cursor.
+pub fn clear() void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    clearLocked();
+}
+
+fn clearLocked() void {
+    for (targets[0..target_count]) |target| {
+        fillRows(target, 0, target.height());
+    }
+    column = 0;
+    row = 0;
+}
+
+pub fn print(comptime fmt: []const u8, args: anytype) void {
+    std.fmt.format(writer, fmt, args) catch return;
+}
+
+/// Like `print`, but ends the line, the way `log.write` does.
+pub fn write(comptime fmt: []const u8, args: anytype) void {
+    std.fmt.format(writer, fmt ++ "\n", args) catch return;
+}
+
+fn writeFn(_: void, bytes: []const u8) error{}!usize {
+    if (!isReady()) {
+        return bytes.len;
+    }
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    for (bytes) |byte| {
+        if (partial_len == 0) {
+            const length = std.unicode.utf8ByteSequenceLength(byte) catch {
+                putChar(std.unicode.replacement_character);
+                continue;
+            };
+            if (length == 1) {
+                putChar(byte);
+                continue;
+            }
+        }
+
+        partial[partial_len] = byte;
+        partial_len += 1;
+
+        const length = std.unicode.utf8ByteSequenceLength(partial[0]) catch unreachable;
+        if (partial_len < length) {
+            continue;
+        }
+
+        const codepoint = std.unicode.utf8Decode(partial[0..length]) catch std.unicode.replacement_character;
+        partial_len = 0;
+        putChar(codepoint);
+    }
+
+    return bytes.len;
+}
+
+fn putChar(codepoint: u21) void {
+    switch (codepoint) {
+        '\n' => return newLine(),
+        '\r' => {
+            column = 0;
+            return;
+        },
+        '\t' => {
+            const next = (column / TAB_WIDTH + 1) * TAB_WIDTH;
+            while (column < next and column < columns) {
+                drawGlyph(' ');
+                column += 1;
+            }
+            if (column == columns) {
+                newLine();
+            }
+            return;
+        },
+        0x08 => {
+            column -|= 1;
+            return;
+        },
+        else => {},
+    }
+
+    if (column == columns) {
+        newLine();
+    }
+    drawGlyph(codepoint);
+    column += 1;
+}
+
+fn newLine() void {
+    column = 0;
+    if (row + 1 < rows) {
+        row += 1;
+        return;
+    }
+
+    for (targets[0..target_count]) |target| {
+        scroll(target);
+    }
+}
+
+fn scroll(target: *Display) void {
+    const pixels = target.surface();
+    const line_size = font.height * target.pitch();
+    const text_size = rows * line_size;
+
+    std.mem.copyForwards(u8, pixels[0 .. text_size - line_size], pixels[line_size..text_size]);
+    fillRows(target, (rows - 1) * font.height, rows * font.height);
+}
+
+fn fillRows(target: *Display, first: usize, end: usize) void {
+    const pixels = target.surface();
+    const pixel_format = target.pixelFormat();
+    const value = pixel_format.encode(background);
+
+    for (first..end) |y| {
+        const line = pixels[y * target.pitch() ..];
+        for (0..target.width()) |x| {
+            pixel_format.store(line[x * pixel_format.bytes_per_pixel ..].ptr, value);
+        }
+    }
+}
+
+fn drawGlyph(codepoint: u21) void {
+    const glyph = font.bitmap(codepoint);
+    const left = column * font.width;
+    const top = row * font.height;
+
+    for (targets[0..target_count]) |target| {
+        const pixels = target.surface();
+        const pixel_format = target.pixelFormat();
+        const fg = pixel_format.encode(foreground);
+        const bg = pixel_format.encode(background);
+
+        for (0..font.height) |y| {
+            const bits = glyph[y * font.bytes_per_row ..][0..font.bytes_per_row];
+            const line = pixels[(top + y) * target.pitch() ..];
+            for (0..font.width) |x| {
+                const set = bits[x / 8] & (@as(u8, 0x80) >> @intCast(x % 8)) != 0;
+                const pixel = line[(left + x) * pixel_format.bytes_per_pixel ..];
+                pixel_format.store(pixel.ptr, if (set) fg else bg);
+            }
+        }
+    }
+}
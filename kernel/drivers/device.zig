@@ -0,0 +1,89 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+// NOTE:
+// Drivers register a `Device` once the hardware is up, which keeps the list
+// in initialization order. Suspending walks it backwards so a device goes
+// down before anything it depends on, resuming walks it forwards. A device
+// that fails to suspend aborts the transition and everything already
+// suspended is resumed again. Until there is a real S3 path the `suspend`
+// shell command drives the same sequence with the machine left running,
+// which is enough to exercise the quiesce and restore logic of drivers.
+// Bus drivers embed a `Device` in every function they bind and get back to
+// the function from the callbacks with `@fieldParentPtr`.
+
+pub const PowerState = enum {
+    active,
+    suspended,
+};
+
+pub const Device = struct {
+    name: []const u8,
+    /// Stops the device and saves whatever `on_resume` needs. Must leave
+    /// the device working when it fails.
+    on_suspend: ?*const fn (device: *Device) Error!void = null,
+    /// Brings the device back to the state it was suspended in.
+    on_resume: ?*const fn (device: *Device) void = null,
+    state: PowerState = .active,
+    next: ?*Device = null,
+    previous: ?*Device = null,
+};
+
+var first: ?*Device = null;
+var last: ?*Device = null;
+
+/// Appends `device`, devices must be registered in the order they were
+/// brought up.
+pub fn register(device: *Device) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    device.next = null;
+    device.previous = last;
+    if (last) |tail| {
+        tail.next = device;
+    } else {
+        first = device;
+    }
+    last = device;
+}
+
+/// Head of the list in registration order, follow `next` for the rest.
+pub fn firstDevice() ?*const Device {
+    return first;
+}
+
+fn resumeFrom(start: ?*Device) void {
+    var device = start;
+    while (device) |current| : (device = current.next) {
+        if (current.state != .suspended) {
+            continue;
+        }
+        if (current.on_resume) |on_resume| {
+            on_resume(current);
+        }
+        current.state = .active;
+    }
+}
+
+/// Suspends every device, last registered first.
+pub fn suspendAll() Error!void {
+    var device = last;
+    while (device) |current| : (device = current.previous) {
+        if (current.on_suspend) |on_suspend| {
+            on_suspend(current) catch |err| {
+                log.warn("Device {s} failed to suspend: {s}", .{ current.name, @errorName(err) });
+                resumeFrom(current.next);
+                return err;
+            };
+        }
+        current.state = .suspended;
+    }
+}
+
+/// Resumes every suspended device, first registered first.
+pub fn resumeAll() void {
+    resumeFrom(first);
+}
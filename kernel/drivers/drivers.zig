@@ -0,0 +1,12 @@
+pub const device = @import("device.zig");
+pub const Device = device.Device;
+pub const console = @import("console.zig");
+pub const e1000 = @import("e1000.zig");
+pub const framebuffer = @import("framebuffer.zig");
+pub const pci = @import("pci.zig");
+pub const ps2_keyboard = @import("ps2_keyboard.zig");
+pub const rtc = @import("rtc.zig");
+pub const rtc_tests = @import("rtc_tests.zig");
+pub const serial = @import("serial.zig");
+pub const virtio = @import("virtio.zig");
+pub const virtio_net = @import("virtio_net.zig");
@@ -0,0 +1,523 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const memory = @import("kernel").memory;
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log.scoped(.e1000);
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+const net = @import("kernel").net;
+const shutdown = @import("kernel").power.shutdown;
+
+const pci = @import("pci.zig");
+
+// NOTE:
+// Intel 8254x (e1000) and 82574 (e1000e) network controllers, the cards
+// QEMU emulates by default on the `pc` and `q35` machines. Both understand
+// the legacy descriptor format, which is all that is used here: a receive
+// and a transmit ring of 16 byte descriptors in DMA memory, each pointing
+// at a fixed 2 KiB buffer. The card owns the receive descriptors from head
+// to tail and sets a descriptor's done bit once it filled the buffer; the
+// driver hands the buffer back by moving the tail past it. Transmit works
+// the other way around, the driver fills descriptors at the tail and the
+// card marks them done once the frame left. The card is registered as a
+// `NetDevice`.
+//
+// Receive, transmit completion and link changes all raise the same INTx
+// interrupt, the cause register tells them apart and clears on read. Other
+// devices may share the line, a cause of zero means the card didn't raise
+// it. The MAC comes from the EEPROM, falling back to the receive address
+// the firmware left in the card.
+
+const VENDOR_ID = 0x8086;
+const DEVICE_82540EM = 0x100e;
+const DEVICE_82545EM = 0x100f;
+const DEVICE_82574L = 0x10d3;
+
+// registers
+const CTRL = 0x0000;
+const STATUS = 0x0008;
+const EERD = 0x0014;
+const ICR = 0x00c0;
+const IMS = 0x00d0;
+const IMC = 0x00d8;
+const RCTL = 0x0100;
+const TCTL = 0x0400;
+const TIPG = 0x0410;
+const RDBAL = 0x2800;
+const RDBAH = 0x2804;
+const RDLEN = 0x2808;
+const RDH = 0x2810;
+const RDT = 0x2818;
+const TDBAL = 0x3800;
+const TDBAH = 0x3804;
+const TDLEN = 0x3808;
+const TDH = 0x3810;
+const TDT = 0x3818;
+const MTA = 0x5200;
+const RAL = 0x5400;
+const RAH = 0x5404;
+
+const CTRL_ASDE = 1 << 5;
+const CTRL_SLU = 1 << 6;
+const CTRL_RST = 1 << 26;
+
+const STATUS_LU = 1 << 1;
+
+const EERD_START = 1 << 0;
+
+const INTERRUPT_TXDW = 1 << 0;
+const INTERRUPT_LSC = 1 << 2;
+const INTERRUPT_RXDMT0 = 1 << 4;
+const INTERRUPT_RXO = 1 << 6;
+const INTERRUPT_RXT0 = 1 << 7;
+const INTERRUPTS = INTERRUPT_TXDW | INTERRUPT_LSC | INTERRUPT_RXDMT0 | INTERRUPT_RXO | INTERRUPT_RXT0;
+
+const RCTL_EN = 1 << 1;
+const RCTL_BAM = 1 << 15;
+/// Strip the CRC, frames are handed up without it.
+const RCTL_SECRC = 1 << 26;
+
+const TCTL_EN = 1 << 1;
+const TCTL_PSP = 1 << 3;
+const TCTL_CT = 0x10 << 4;
+const TCTL_COLD = 0x40 << 12;
+
+/// Inter packet gap the manuals recommend for copper.
+const TIPG_DEFAULT = 10 | 8 << 10 | 6 << 20;
+
+const RAH_AV = 1 << 31;
+
+const RECEIVE_DONE = 1 << 0;
+const RECEIVE_END_OF_PACKET = 1 << 1;
+
+const TRANSMIT_END_OF_PACKET = 1 << 0;
+const TRANSMIT_INSERT_FCS = 1 << 1;
+const TRANSMIT_REPORT_STATUS = 1 << 3;
+const TRANSMIT_DONE = 1 << 0;
+
+/// Ring lengths have to be a multiple of 128 bytes, 8 descriptors.
+const RECEIVE_DESCRIPTORS = 32;
+const TRANSMIT_DESCRIPTORS = 32;
+const BUFFER_SIZE = 2048;
+
+/// How long a reset or an EEPROM read may take.
+const TIMEOUT_US = 10_000;
+
+const MTU = 1500;
+
+const ReceiveDescriptor = extern struct {
+    address: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+};
+
+const TransmitDescriptor = extern struct {
+    address: u64,
+    length: u16,
+    checksum_offset: u8,
+    command: u8,
+    status: u8,
+    checksum_start: u8,
+    special: u16,
+};
+
+const Nic = struct {
+    device: *pci.Device,
+    base: usize,
+    mac: [6]u8,
+    irq: ?arch.irq.Line,
+    receive_ring: memory.DmaBuffer,
+    transmit_ring: memory.DmaBuffer,
+    receive_buffers: memory.DmaBuffer,
+    transmit_buffers: memory.DmaBuffer,
+    /// Next receive descriptor the card will fill.
+    receive_next: u16 = 0,
+    /// Next free transmit descriptor, and the oldest one still in flight.
+    transmit_tail: u16 = 0,
+    transmit_clean: u16 = 0,
+    transmit_lock: SpinLock = SpinLock.init(),
+    /// Set while the card is stopped for a sleep state.
+    suspended: bool = false,
+
+    fn read(self: *const Nic, register: usize) u32 {
+        return @as(*volatile u32, @ptrFromInt(self.base + register)).*;
+    }
+
+    fn write(self: *const Nic, register: usize, value: u32) void {
+        @as(*volatile u32, @ptrFromInt(self.base + register)).* = value;
+    }
+
+    fn receiveDescriptor(self: *Nic, index: u16) *volatile ReceiveDescriptor {
+        return &self.receive_ring.slice(ReceiveDescriptor)[index];
+    }
+
+    fn transmitDescriptor(self: *Nic, index: u16) *volatile TransmitDescriptor {
+        return &self.transmit_ring.slice(TransmitDescriptor)[index];
+    }
+};
+
+var nic: Nic = undefined;
+var present = false;
+var interrupt_handler = arch.irq.SharedHandler{ .func = handle };
+
+var stop_on_shutdown = shutdown.Hook{
+    .name = "e1000",
+    .run = stopOnShutdown,
+};
+
+var net_device = net.NetDevice{
+    .mac = undefined,
+    .mtu = MTU,
+    .transmit = transmit,
+    .link_up = isLinkUp,
+};
+
+var driver = pci.Driver{
+    .name = "e1000",
+    .matches = &.{
+        pci.Match.ids(VENDOR_ID, DEVICE_82540EM),
+        pci.Match.ids(VENDOR_ID, DEVICE_82545EM),
+        pci.Match.ids(VENDOR_ID, DEVICE_82574L),
+    },
+    .probe = probe,
+    .on_suspend = suspendCard,
+    .on_resume = resumeCard,
+};
+
+/// Registers the driver, the card is set up when PCI enumeration finds
+/// one.
+pub fn init() Error!void {
+    pci.register(&driver);
+}
+
+fn probe(device: *pci.Device) Error!void {
+    if (present) {
+        log.warn("Only one e1000 is supported, ignoring {}", .{device.address});
+        return error.Unsupported;
+    }
+
+    var receive_ring = try memory.DmaBuffer.alloc(RECEIVE_DESCRIPTORS * @sizeOf(ReceiveDescriptor));
+    errdefer receive_ring.deinit();
+    var transmit_ring = try memory.DmaBuffer.alloc(TRANSMIT_DESCRIPTORS * @sizeOf(TransmitDescriptor));
+    errdefer transmit_ring.deinit();
+    var receive_buffers = try memory.DmaBuffer.alloc(RECEIVE_DESCRIPTORS * BUFFER_SIZE);
+    errdefer receive_buffers.deinit();
+    var transmit_buffers = try memory.DmaBuffer.alloc(TRANSMIT_DESCRIPTORS * BUFFER_SIZE);
+    errdefer transmit_buffers.deinit();
+
+    nic = .{
+        .device = device,
+        .base = try device.mapBar(0),
+        .mac = undefined,
+        .irq = device.legacyIrq(),
+        .receive_ring = receive_ring,
+        .transmit_ring = transmit_ring,
+        .receive_buffers = receive_buffers,
+        .transmit_buffers = transmit_buffers,
+    };
+
+    try reset();
+    nic.mac = try readMac(device);
+
+    setupFilter();
+    setupReceive();
+    setupTransmit();
+
+    if (nic.irq) |line| {
+        arch.irq.installShared(line, &interrupt_handler) catch |err| {
+            log.warn("Failed to install the interrupt of {}: {s}", .{ device.address, @errorName(err) });
+            nic.irq = null;
+        };
+    }
+    if (nic.irq != null) {
+        nic.write(IMS, INTERRUPTS);
+    } else {
+        log.warn("{} has no routed interrupt, receiving by polling", .{device.address});
+        net_device.poll = poll;
+    }
+    device.enable(pci.COMMAND_BUS_MASTER);
+    present = true;
+
+    net_device.mac = nic.mac;
+    net.device.register(&net_device);
+    shutdown.register(&stop_on_shutdown);
+    log.info("e1000 at {} is {s}: link {s}", .{
+        device.address,
+        net_device.name(),
+        if (net_device.isLinkUp()) "up" else "down",
+    });
+}
+
+/// Resets the card, masks its interrupts and brings the link up.
+fn reset() Error!void {
+    nic.write(IMC, 0xffff_ffff);
+    nic.write(CTRL, nic.read(CTRL) | CTRL_RST);
+    // the reset bit reads back set for a few microseconds
+    time.delayUs(1);
+    var waited: usize = 0;
+    while (nic.read(CTRL) & CTRL_RST != 0) : (waited += 1) {
+        if (waited == TIMEOUT_US) {
+            return error.Timeout;
+        }
+        time.delayUs(1);
+    }
+
+    nic.write(IMC, 0xffff_ffff);
+    _ = nic.read(ICR);
+    nic.write(CTRL, nic.read(CTRL) | CTRL_SLU | CTRL_ASDE);
+}
+
+/// Reads a word of the EEPROM. The 82574 moved the done bit and the
+/// address in EERD.
+fn readEeprom(device: *const pci.Device, word: u8) Error!u16 {
+    const modern = device.device_id == DEVICE_82574L;
+    const address_shift: u5 = if (modern) 2 else 8;
+    const done: u32 = if (modern) 1 << 1 else 1 << 4;
+
+    nic.write(EERD, @as(u32, word) << address_shift | EERD_START);
+    var waited: usize = 0;
+    while (waited < TIMEOUT_US) : (waited += 1) {
+        const value = nic.read(EERD);
+        if (value & done != 0) {
+            return @truncate(value >> 16);
+        }
+        time.delayUs(1);
+    }
+    return error.Timeout;
+}
+
+fn readMac(device: *const pci.Device) Error![6]u8 {
+    var mac: [6]u8 = undefined;
+    if (readEeprom(device, 0)) |first| {
+        std.mem.writeInt(u16, mac[0..2], first, .little);
+        std.mem.writeInt(u16, mac[2..4], try readEeprom(device, 1), .little);
+        std.mem.writeInt(u16, mac[4..6], try readEeprom(device, 2), .little);
+        return mac;
+    } else |_| {}
+
+    // cards without an EEPROM get their address from the firmware
+    const high = nic.read(RAH);
+    if (high & RAH_AV == 0) {
+        return error.NotFound;
+    }
+    std.mem.writeInt(u32, mac[0..4], nic.read(RAL), .little);
+    std.mem.writeInt(u16, mac[4..6], @truncate(high), .little);
+    return mac;
+}
+
+/// Lets only the card's own address and broadcasts through.
+fn setupFilter() void {
+    nic.write(RAL, std.mem.readInt(u32, nic.mac[0..4], .little));
+    nic.write(RAH, @as(u32, std.mem.readInt(u16, nic.mac[4..6], .little)) | RAH_AV);
+    for (0..128) |index| {
+        nic.write(MTA + index * 4, 0);
+    }
+}
+
+fn setupReceive() void {
+    nic.receive_next = 0;
+    for (0..RECEIVE_DESCRIPTORS) |index| {
+        nic.receiveDescriptor(@intCast(index)).* = .{
+            .address = nic.receive_buffers.busAddress(index * BUFFER_SIZE),
+            .length = 0,
+            .checksum = 0,
+            .status = 0,
+            .errors = 0,
+            .special = 0,
+        };
+    }
+    nic.receive_ring.syncForDevice();
+
+    nic.write(RDBAL, @truncate(nic.receive_ring.bus_address));
+    nic.write(RDBAH, @truncate(nic.receive_ring.bus_address >> 32));
+    nic.write(RDLEN, RECEIVE_DESCRIPTORS * @sizeOf(ReceiveDescriptor));
+    nic.write(RDH, 0);
+    // the card owns every descriptor but one, a full ring would look empty
+    nic.write(RDT, RECEIVE_DESCRIPTORS - 1);
+    // buffer size bits left at zero select 2048 bytes
+    nic.write(RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+}
+
+fn setupTransmit() void {
+    nic.transmit_tail = 0;
+    nic.transmit_clean = 0;
+    for (0..TRANSMIT_DESCRIPTORS) |index| {
+        nic.transmitDescriptor(@intCast(index)).* = .{
+            .address = nic.transmit_buffers.busAddress(index * BUFFER_SIZE),
+            .length = 0,
+            .checksum_offset = 0,
+            .command = 0,
+            .status = TRANSMIT_DONE,
+            .checksum_start = 0,
+            .special = 0,
+        };
+    }
+    nic.transmit_ring.syncForDevice();
+
+    nic.write(TDBAL, @truncate(nic.transmit_ring.bus_address));
+    nic.write(TDBAH, @truncate(nic.transmit_ring.bus_address >> 32));
+    nic.write(TDLEN, TRANSMIT_DESCRIPTORS * @sizeOf(TransmitDescriptor));
+    nic.write(TDH, 0);
+    nic.write(TDT, 0);
+    nic.write(TIPG, TIPG_DEFAULT);
+    nic.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+}
+
+fn suspendCard(device: *pci.Device) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    // frames the card still held are lost, resuming starts the rings over
+    nic.write(IMC, 0xffff_ffff);
+    nic.write(RCTL, 0);
+    nic.write(TCTL, 0);
+    device.disable(pci.COMMAND_BUS_MASTER);
+    nic.suspended = true;
+}
+
+fn resumeCard(device: *pci.Device) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    // after a real sleep state the card comes back with nothing set up
+    reset() catch |err| {
+        log.warn("{s} failed to resume: {s}", .{ net_device.name(), @errorName(err) });
+        return;
+    };
+    setupFilter();
+    setupReceive();
+    setupTransmit();
+    if (nic.irq != null) {
+        nic.write(IMS, INTERRUPTS);
+    }
+    device.enable(pci.COMMAND_BUS_MASTER);
+    nic.suspended = false;
+}
+
+fn stopOnShutdown() void {
+    // nothing may be written into the rings once their memory is reused
+    reset() catch |err| {
+        log.warn("Failed to reset {}: {s}", .{ nic.device.address, @errorName(err) });
+    };
+    nic.device.disable(pci.COMMAND_BUS_MASTER);
+}
+
+fn handle(_: *arch.irq.SharedHandler) void {
+    // reading the cause also clears it, nothing set means the interrupt
+    // came from another device on the line
+    const cause = nic.read(ICR);
+    if (cause == 0) {
+        return;
+    }
+    service(cause);
+}
+
+fn poll(_: *net.NetDevice) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    service(nic.read(ICR));
+}
+
+fn service(cause: u32) void {
+    if (nic.suspended) {
+        return;
+    }
+    if (cause & INTERRUPT_LSC != 0) {
+        log.info("{s} link is {s}", .{ net_device.name(), if (net_device.isLinkUp()) "up" else "down" });
+    }
+    if (cause & INTERRUPT_RXO != 0) {
+        net_device.receiveError();
+    }
+
+    receiveFrames();
+
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+    reclaimTransmitted();
+}
+
+fn receiveFrames() void {
+    nic.receive_ring.syncForCpu();
+
+    var received = false;
+    while (true) : (nic.receive_next = (nic.receive_next + 1) % RECEIVE_DESCRIPTORS) {
+        const descriptor = nic.receiveDescriptor(nic.receive_next);
+        if (descriptor.status & RECEIVE_DONE == 0) {
+            break;
+        }
+
+        // frames never span buffers, a missing end of packet means the
+        // card got something larger than the MTU
+        const length = descriptor.length;
+        if (descriptor.status & RECEIVE_END_OF_PACKET == 0 or descriptor.errors != 0 or length > BUFFER_SIZE) {
+            net_device.receiveError();
+        } else {
+            const buffer = nic.receive_buffers.bytes()[@as(usize, nic.receive_next) * BUFFER_SIZE ..];
+            net_device.receive(buffer[0..length]);
+        }
+
+        descriptor.status = 0;
+        received = true;
+    }
+
+    if (received) {
+        // the descriptor before the next one to fill is the last one free
+        nic.receive_ring.syncForDevice();
+        nic.write(RDT, (nic.receive_next + RECEIVE_DESCRIPTORS - 1) % RECEIVE_DESCRIPTORS);
+    }
+}
+
+/// Frees the descriptors the card is done with. The transmit lock must be
+/// held.
+fn reclaimTransmitted() void {
+    nic.transmit_ring.syncForCpu();
+
+    while (nic.transmit_clean != nic.transmit_tail) {
+        if (nic.transmitDescriptor(nic.transmit_clean).status & TRANSMIT_DONE == 0) {
+            break;
+        }
+        nic.transmit_clean = (nic.transmit_clean + 1) % TRANSMIT_DESCRIPTORS;
+    }
+}
+
+fn transmit(_: *net.NetDevice, frame: *const memory.SgList) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    if (nic.suspended) {
+        return error.Timeout;
+    }
+    reclaimTransmitted();
+    // one descriptor stays unused so a full ring doesn't look empty
+    const next = (nic.transmit_tail + 1) % TRANSMIT_DESCRIPTORS;
+    if (next == nic.transmit_clean) {
+        return error.OutOfMemory;
+    }
+
+    const index = nic.transmit_tail;
+    const buffer = nic.transmit_buffers.bytes()[@as(usize, index) * BUFFER_SIZE ..][0..BUFFER_SIZE];
+    const length = frame.copyTo(0, buffer);
+
+    const descriptor = nic.transmitDescriptor(index);
+    descriptor.length = @intCast(length);
+    descriptor.command = TRANSMIT_END_OF_PACKET | TRANSMIT_INSERT_FCS | TRANSMIT_REPORT_STATUS;
+    descriptor.status = 0;
+
+    nic.transmit_tail = next;
+    nic.transmit_ring.syncForDevice();
+    nic.write(TDT, next);
+}
+
+fn isLinkUp(_: *net.NetDevice) bool {
+    return nic.read(STATUS) & STATUS_LU != 0;
+}
@@ -0,0 +1,303 @@
+const std = @import("std");
+const limine = @import("limine");
+const arch = @import("kernel").arch;
+const boot = @import("kernel").boot;
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+const devfs = @import("kernel").fs.devfs;
+
+const device = @import("device.zig");
+
+// NOTE:
+// Every framebuffer Limine reports becomes a `Display`, the first one is the
+// primary display that gets drawn on unless asked otherwise. Blanking
+// applies to all of them. Framebuffers whose pixel format can't be drawn
+// in, see `PixelFormat.of`, are skipped.
+
+pub const MAX_DISPLAYS = 4;
+
+pub const Display = struct {
+    framebuffer: *limine.Framebuffer,
+    format: PixelFormat,
+    /// Screen contents while blanked, allocated up front since blanking
+    /// happens from interrupt context.
+    shadow: []u8,
+    blanked: bool = false,
+
+    const Self = @This();
+
+    pub fn width(self: *const Self) usize {
+        return self.framebuffer.width;
+    }
+
+    pub fn height(self: *const Self) usize {
+        return self.framebuffer.height;
+    }
+
+    pub fn pitch(self: *const Self) usize {
+        return self.framebuffer.pitch;
+    }
+
+    pub fn pixelFormat(self: *const Self) PixelFormat {
+        return self.format;
+    }
+
+    fn pixels(self: *const Self) []u8 {
+        return self.framebuffer.address[0 .. self.framebuffer.pitch * self.framebuffer.height];
+    }
+
+    /// Where drawing goes: the screen, or the saved copy while it is blanked
+    /// so nothing drawn in the meantime is lost. Blanking happens from
+    /// interrupt context, keep interrupts off for as long as the slice is
+    /// in use.
+    pub fn surface(self: *const Self) []u8 {
+        return if (self.blanked) self.shadow else self.pixels();
+    }
+
+    fn blank(self: *Self) void {
+        if (self.blanked) {
+            return;
+        }
+
+        @memcpy(self.shadow, self.pixels());
+        @memset(self.pixels(), 0);
+        self.blanked = true;
+    }
+
+    fn unblank(self: *Self) void {
+        if (!self.blanked) {
+            return;
+        }
+
+        @memcpy(self.pixels(), self.shadow);
+        self.blanked = false;
+    }
+};
+
+var displays: [MAX_DISPLAYS]Display = undefined;
+var display_count: usize = 0;
+
+const NODE_NAMES = [MAX_DISPLAYS][]const u8{ "fb0", "fb1", "fb2", "fb3" };
+var nodes: [MAX_DISPLAYS]devfs.DeviceNode = undefined;
+
+pub fn init() Error!void {
+    const response = boot.framebuffer_request.response orelse return error.NotFound;
+    if (response.framebuffer_count < 1) {
+        return error.NotFound;
+    }
+
+    for (response.framebuffers()) |framebuffer| {
+        if (display_count == MAX_DISPLAYS) {
+            log.warn("Ignoring framebuffers past the first {}", .{MAX_DISPLAYS});
+            break;
+        }
+
+        const pixel_format = PixelFormat.of(framebuffer) catch {
+            log.warn("Ignoring a framebuffer with {} bpp, red {}@{}, green {}@{}, blue {}@{}", .{
+                framebuffer.bpp,
+                framebuffer.red_mask_size,
+                framebuffer.red_mask_shift,
+                framebuffer.green_mask_size,
+                framebuffer.green_mask_shift,
+                framebuffer.blue_mask_size,
+                framebuffer.blue_mask_shift,
+            });
+            continue;
+        };
+
+        const virtual = @intFromPtr(framebuffer.address);
+        const size = framebuffer.pitch * framebuffer.height;
+        _ = arch.mtrr.checkRange("Framebuffer", .framebuffer, memory.virtualToPhysical(virtual), size, virtual);
+
+        const shadow = try memory.allocator().alloc(u8, size);
+        displays[display_count] = .{ .framebuffer = framebuffer, .format = pixel_format, .shadow = shadow };
+
+        log.info("Framebuffer {}: {}x{}, {} bpp, red {}@{}, green {}@{}, blue {}@{}", .{
+            display_count,
+            framebuffer.width,
+            framebuffer.height,
+            framebuffer.bpp,
+            pixel_format.red_size,
+            pixel_format.red_shift,
+            pixel_format.green_size,
+            pixel_format.green_shift,
+            pixel_format.blue_size,
+            pixel_format.blue_shift,
+        });
+        nodes[display_count] = .{
+            .name = NODE_NAMES[display_count],
+            .kind = .block,
+            .index = display_count,
+            .read = readNode,
+            .write = writeNode,
+            .size = nodeSize,
+        };
+        try devfs.register(&nodes[display_count]);
+        display_count += 1;
+    }
+
+    if (display_count == 0) {
+        return error.Unsupported;
+    }
+
+    device.register(&framebuffer_device);
+}
+
+/// `/dev/fbN` is the display's memory, `pitch` bytes per row.
+fn readNode(node: *const devfs.DeviceNode, offset: usize, buffer: []u8) Error!usize {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const surface = displays[node.index].surface();
+    if (offset >= surface.len) {
+        return 0;
+    }
+    const length = @min(buffer.len, surface.len - offset);
+    @memcpy(buffer[0..length], surface[offset..][0..length]);
+    return length;
+}
+
+fn writeNode(node: *const devfs.DeviceNode, offset: usize, bytes: []const u8) Error!usize {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const surface = displays[node.index].surface();
+    if (offset >= surface.len) {
+        return error.InvalidArgument;
+    }
+    const length = @min(bytes.len, surface.len - offset);
+    @memcpy(surface[offset..][0..length], bytes[0..length]);
+    return length;
+}
+
+fn nodeSize(node: *const devfs.DeviceNode) usize {
+    return displays[node.index].pixels().len;
+}
+
+var framebuffer_device = device.Device{
+    .name = "framebuffer",
+    .on_suspend = suspendDisplays,
+    .on_resume = resumeDisplays,
+};
+
+fn suspendDisplays(_: *device.Device) Error!void {
+    blank();
+}
+
+fn resumeDisplays(_: *device.Device) void {
+    unblank();
+}
+
+/// Every display found by `init`, the primary one first.
+pub fn all() []Display {
+    return displays[0..display_count];
+}
+
+pub fn get(index: usize) ?*Display {
+    if (index >= display_count) {
+        return null;
+    }
+    return &displays[index];
+}
+
+pub fn primary() ?*Display {
+    return get(0);
+}
+
+/// Saves what is on every screen and turns them black.
+pub fn blank() void {
+    for (all()) |*display| {
+        display.blank();
+    }
+}
+
+/// Puts back what was on screen before `blank`.
+pub fn unblank() void {
+    for (all()) |*display| {
+        display.unblank();
+    }
+}
+
+pub const Color = struct {
+    r: u8,
+    g: u8,
+    b: u8,
+
+    pub const black = Color{ .r = 0, .g = 0, .b = 0 };
+    pub const white = Color{ .r = 0xff, .g = 0xff, .b = 0xff };
+};
+
+/// How a pixel is laid out in memory, from the channel masks the firmware
+/// reports. Anything from 15 to 32 bits per pixel, in any channel order.
+pub const PixelFormat = struct {
+    bytes_per_pixel: u8,
+    red_shift: u8,
+    red_size: u8,
+    green_shift: u8,
+    green_size: u8,
+    blue_shift: u8,
+    blue_size: u8,
+
+    const Self = @This();
+
+    /// `error.Unsupported` for pixels outside 15 to 32 bits and channels that
+    /// don't fit in them.
+    pub fn of(framebuffer: *const limine.Framebuffer) Error!Self {
+        const bpp = framebuffer.bpp;
+        if (bpp < 15 or bpp > 32) {
+            return error.Unsupported;
+        }
+        const channels = .{
+            .{ framebuffer.red_mask_size, framebuffer.red_mask_shift },
+            .{ framebuffer.green_mask_size, framebuffer.green_mask_shift },
+            .{ framebuffer.blue_mask_size, framebuffer.blue_mask_shift },
+        };
+        inline for (channels) |mask| {
+            if (@as(u16, mask[0]) + mask[1] > bpp) {
+                return error.Unsupported;
+            }
+        }
+
+        return .{
+            .bytes_per_pixel = @intCast(std.math.divCeil(u16, bpp, 8) catch unreachable),
+            .red_shift = framebuffer.red_mask_shift,
+            .red_size = framebuffer.red_mask_size,
+            .green_shift = framebuffer.green_mask_shift,
+            .green_size = framebuffer.green_mask_size,
+            .blue_shift = framebuffer.blue_mask_shift,
+            .blue_size = framebuffer.blue_mask_size,
+        };
+    }
+
+    /// Scales the 8-bit `value` to `size` bits, repeating it in channels
+    /// wider than 8 bits so full intensity stays all ones. `of` made sure
+    /// the channel fits 32 bits.
+    fn channel(value: u8, size: u8, shift: u8) u32 {
+        if (size == 0) {
+            return 0;
+        }
+        // e.g. value >> 3 for 5 bits, value << 2 | value >> 6 for 10
+        var repeated: u64 = 0;
+        var bits: u8 = 0;
+        while (bits < size) : (bits += 8) {
+            repeated = repeated << 8 | value;
+        }
+        const scaled: u32 = @intCast(repeated >> @intCast(bits - size));
+        return scaled << @intCast(shift);
+    }
+
+    /// The raw pixel value for `color`.
+    pub fn encode(self: Self, color: Color) u32 {
+        return channel(color.r, self.red_size, self.red_shift) |
+            channel(color.g, self.green_size, self.green_shift) |
+            channel(color.b, self.blue_size, self.blue_shift);
+    }
+
+    /// Stores an encoded pixel at `pixel`, which need not be aligned.
+    pub fn store(self: Self, pixel: [*]u8, value: u32) void {
+        for (0..self.bytes_per_pixel) |i| {
+            pixel[i] = @truncate(value >> @intCast(i * 8));
+        }
+    }
+};
@@ -0,0 +1,673 @@
+const std = @import("std");
+const acpi = @import("kernel").acpi;
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log.scoped(.pci);
+const Error = @import("kernel").Error;
+
+const device_list = @import("device.zig");
+
+// NOTE:
+// Configuration space is reached through the legacy mechanism: the address
+// of a dword goes to `CONFIG_ADDRESS` and the dword itself is read from or
+// written to `CONFIG_DATA`. The two accesses must not be interleaved with
+// another pair, so they happen with interrupts off. Every bus, device and
+// function is probed, which finds devices behind bridges the firmware set
+// up without having to follow the bridges ourselves; a function that
+// reads back vendor 0xffff doesn't exist. Enumeration only records what it
+// finds, BARs keep the addresses the firmware assigned. PCIe's memory
+// mapped configuration space (ECAM) would reach the extended registers
+// past 256 bytes, nothing needs them yet.
+//
+// Drivers `register` a `Driver` with the IDs or class codes they handle and
+// get their `probe` called with every matching function that no driver has
+// claimed yet, whether enumeration already ran or runs later. Registration
+// and probing happen from init tasks on the boot CPU, there is no locking.
+// A bound function joins the device list, so suspending and resuming reach
+// its driver in the order functions were bound.
+// Interrupts are the legacy INTx pins. With the PICs they arrive on the IRQ
+// the firmware wrote into the interrupt line register. With the I/O APIC
+// the GSI comes from the host bridge's _PRT for functions on bus 0, as far
+// as the AML code can read it: hard-wired entries, or entries naming a link
+// device whose _CRS is a constant, which is how QEMU's q35 hands out GSIs.
+// Where that finds nothing, e.g. QEMU's pc whose _PRT is a method, the pin
+// is taken to arrive on the interrupt line register's ISA IRQ, moved by the
+// MADT's interrupt source overrides like any other. Only a function without
+// a usable line register has no known route, its driver polls until there
+// is MSI.
+
+const CONFIG_ADDRESS = 0xcf8;
+const CONFIG_DATA = 0xcfc;
+
+const VENDOR_ID = 0x00;
+const DEVICE_ID = 0x02;
+pub const COMMAND = 0x04;
+const STATUS = 0x06;
+const REVISION = 0x08;
+const HEADER_TYPE = 0x0e;
+const BAR0 = 0x10;
+const SECONDARY_BUS = 0x19;
+const CAPABILITIES = 0x34;
+const INTERRUPT_LINE = 0x3c;
+const INTERRUPT_PIN = 0x3d;
+
+pub const COMMAND_IO = 1 << 0;
+pub const COMMAND_MEMORY = 1 << 1;
+pub const COMMAND_BUS_MASTER = 1 << 2;
+pub const COMMAND_INTERRUPT_DISABLE = 1 << 10;
+
+const STATUS_CAPABILITIES = 1 << 4;
+
+pub const CAPABILITY_MSI = 0x05;
+pub const CAPABILITY_VENDOR = 0x09;
+pub const CAPABILITY_MSIX = 0x11;
+
+const HEADER_MULTIFUNCTION = 0x80;
+const HEADER_KIND = 0x7f;
+const HEADER_GENERAL = 0x00;
+const HEADER_BRIDGE = 0x01;
+
+const NO_DEVICE = 0xffff;
+const MAX_DEVICES = 64;
+
+/// Location of a function in configuration space.
+pub const Address = struct {
+    bus: u8,
+    device: u5,
+    function: u3,
+
+    fn configAddress(self: Address, offset: u8) u32 {
+        return 0x8000_0000 |
+            @as(u32, self.bus) << 16 |
+            @as(u32, self.device) << 11 |
+            @as(u32, self.function) << 8 |
+            (offset & 0xfc);
+    }
+
+    pub fn read32(self: Address, offset: u8) u32 {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        arch.cpu.writeLong(CONFIG_ADDRESS, self.configAddress(offset));
+        return arch.cpu.readLong(CONFIG_DATA);
+    }
+
+    pub fn read16(self: Address, offset: u8) u16 {
+        const shift: u5 = @intCast((offset & 2) * 8);
+        return @truncate(self.read32(offset) >> shift);
+    }
+
+    pub fn read8(self: Address, offset: u8) u8 {
+        const shift: u5 = @intCast((offset & 3) * 8);
+        return @truncate(self.read32(offset) >> shift);
+    }
+
+    pub fn write32(self: Address, offset: u8, value: u32) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        arch.cpu.writeLong(CONFIG_ADDRESS, self.configAddress(offset));
+        arch.cpu.writeLong(CONFIG_DATA, value);
+    }
+
+    /// Writes only the word at `offset`. Going through the dword would
+    /// write the status register back too, clearing its error bits.
+    pub fn write16(self: Address, offset: u8, value: u16) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        arch.cpu.writeLong(CONFIG_ADDRESS, self.configAddress(offset));
+        arch.cpu.writeWord(@as(u16, CONFIG_DATA) + (offset & 2), value);
+    }
+
+    pub fn format(self: Address, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+        try writer.print("{x:0>2}:{x:0>2}.{}", .{ self.bus, @as(u8, self.device), @as(u8, self.function) });
+    }
+};
+
+pub const Bar = struct {
+    kind: Kind,
+    address: u64,
+    size: u64,
+    prefetchable: bool = false,
+
+    pub const Kind = enum { io, memory32, memory64 };
+};
+
+pub const Device = struct {
+    address: Address,
+    vendor_id: u16,
+    device_id: u16,
+    class: u8,
+    subclass: u8,
+    prog_if: u8,
+    revision: u8,
+    header_type: u8,
+    /// Bars in use, the upper half of a 64-bit BAR is left null.
+    bars: [6]?Bar = [_]?Bar{null} ** 6,
+    /// Bus behind a PCI-to-PCI bridge.
+    secondary_bus: ?u8 = null,
+    /// Legacy IRQ the firmware routed INTx to, 0xff when unknown.
+    interrupt_line: u8,
+    /// INTA# to INTD# as 1 to 4, 0 for none.
+    interrupt_pin: u8,
+    /// The driver that claimed the function.
+    driver: ?*const Driver = null,
+    /// Entry in the device list once a driver claimed the function.
+    power: device_list.Device = .{ .name = "" },
+
+    pub fn className(self: *const Device) []const u8 {
+        return classNameOf(self.class, self.subclass);
+    }
+
+    /// Sets `flags` (`COMMAND_*`) in the command register.
+    pub fn enable(self: *const Device, flags: u16) void {
+        self.address.write16(COMMAND, self.address.read16(COMMAND) | flags);
+    }
+
+    /// Clears `flags` (`COMMAND_*`) in the command register.
+    pub fn disable(self: *const Device, flags: u16) void {
+        self.address.write16(COMMAND, self.address.read16(COMMAND) & ~flags);
+    }
+
+    /// Maps memory BAR `index` uncached, turns on memory decoding and
+    /// returns the virtual address of the registers.
+    pub fn mapBar(self: *const Device, index: usize) Error!usize {
+        const bar = self.bars[index] orelse return error.NotFound;
+        if (bar.kind == .io) {
+            return error.InvalidArgument;
+        }
+
+        const virtual = try arch.paging.mapMmio(bar.address, bar.size);
+        const name = if (self.driver) |driver| driver.name else "PCI BAR";
+        _ = arch.mtrr.checkRange(name, .mmio, bar.address, bar.size, virtual);
+        self.enable(COMMAND_MEMORY);
+        return virtual;
+    }
+
+    /// First port of I/O BAR `index`, with I/O decoding turned on.
+    pub fn ioBar(self: *const Device, index: usize) Error!u16 {
+        const bar = self.bars[index] orelse return error.NotFound;
+        if (bar.kind != .io) {
+            return error.InvalidArgument;
+        }
+        self.enable(COMMAND_IO);
+        return @intCast(bar.address);
+    }
+
+    /// The capability list, empty when the function has none.
+    pub fn capabilities(self: *const Device) CapabilityIterator {
+        const has_list = self.address.read16(STATUS) & STATUS_CAPABILITIES != 0;
+        return .{
+            .address = self.address,
+            .offset = if (has_list) self.address.read8(CAPABILITIES) & 0xfc else 0,
+        };
+    }
+
+    /// Where INTx arrives, null when the function has no interrupt pin or
+    /// its route is unknown. PCI interrupts are level triggered and active
+    /// low unless the firmware says otherwise, and may be shared with other
+    /// functions.
+    pub fn irqRoute(self: *const Device) ?IrqRoute {
+        if (self.interrupt_pin == 0 or self.interrupt_pin > 4) {
+            return null;
+        }
+
+        if (arch.pic.active) {
+            if (self.interrupt_line >= arch.pic.IRQ_COUNT) {
+                return null;
+            }
+            return .{ .line = .{ .gsi = self.interrupt_line, .active_low = true, .level_triggered = true }, .source = .pic };
+        }
+
+        // behind a bridge the pin is swizzled onto one of the bridge's,
+        // which needs the bridge's own _PRT or the root's
+        if (self.address.bus == 0) {
+            var routes = acpi.aml.pciRoutes();
+            while (routes.next()) |route| {
+                if (!isHostBridgeTable(route.table) or route.device != self.address.device or route.pin != self.interrupt_pin - 1) {
+                    continue;
+                }
+                const link = route.source orelse {
+                    return .{ .line = .{ .gsi = route.source_index, .active_low = true, .level_triggered = true }, .source = .prt };
+                };
+                // links whose _CRS is a method can't be read, another
+                // table may still have a usable entry
+                if (acpi.aml.linkInterrupt(link, route.source_index)) |interrupt| {
+                    return .{
+                        .line = .{ .gsi = interrupt.gsi, .active_low = interrupt.active_low, .level_triggered = interrupt.level_triggered },
+                        .source = .link,
+                    };
+                }
+            }
+        }
+
+        // the firmware routed the pin to the ISA IRQ it wrote into the
+        // interrupt line register, which reaches the I/O APIC like any
+        // other ISA IRQ
+        if (self.interrupt_line >= arch.pic.IRQ_COUNT) {
+            return null;
+        }
+        var line = arch.irq.Line{ .gsi = self.interrupt_line, .active_low = true, .level_triggered = true };
+        for (acpi.madt.overrides()) |entry| {
+            if (entry.source == self.interrupt_line) {
+                // bus default means the PCI defaults here
+                line.gsi = entry.gsi;
+                line.active_low = entry.polarity != .active_high;
+                line.level_triggered = entry.trigger != .edge;
+            }
+        }
+        return .{ .line = line, .source = .interrupt_line };
+    }
+
+    /// `irqRoute`, logging which route was taken. For drivers about to
+    /// install a handler.
+    pub fn legacyIrq(self: *const Device) ?arch.irq.Line {
+        if (self.interrupt_pin == 0) {
+            return null;
+        }
+        const pin = 'A' + self.interrupt_pin - 1;
+        const route = self.irqRoute() orelse {
+            log.info("{} INT{c}# has no known route", .{ self.address, pin });
+            return null;
+        };
+        log.info("{} INT{c}# on {s} {} from {s}", .{ self.address, pin, if (route.source == .pic) "IRQ" else "GSI", route.line.gsi, route.source.description() });
+        return route.line;
+    }
+};
+
+/// Where `irqRoute` found a function's interrupt.
+pub const IrqSource = enum {
+    /// The interrupt line register, while the PICs are in use.
+    pic,
+    /// A hard-wired _PRT entry.
+    prt,
+    /// The `_CRS` of the link device a _PRT entry names.
+    link,
+    /// The interrupt line register, through the MADT's overrides.
+    interrupt_line,
+
+    pub fn description(self: IrqSource) []const u8 {
+        return switch (self) {
+            .pic => "the interrupt line",
+            .prt => "the _PRT",
+            .link => "a _PRT link device",
+            .interrupt_line => "the interrupt line and the MADT",
+        };
+    }
+};
+
+pub const IrqRoute = struct {
+    line: arch.irq.Line,
+    source: IrqSource,
+};
+
+/// Whether `table` is a routing table of a host bridge, such as
+/// `\_SB_.<bridge>._PRT`. Those of PCI-to-PCI bridges sit further down.
+fn isHostBridgeTable(table: acpi.aml.Path) bool {
+    const segments = table.slice();
+    return segments.len == 3 and std.mem.eql(u8, &segments[0], "_SB_");
+}
+
+pub const Capability = struct {
+    id: u8,
+    /// Where the capability starts in configuration space.
+    offset: u8,
+};
+
+/// Walks the capability list. Gives up after as many entries as fit in
+/// configuration space, in case the list loops.
+pub const CapabilityIterator = struct {
+    address: Address,
+    offset: u8,
+    remaining: u8 = 48,
+
+    pub fn next(self: *CapabilityIterator) ?Capability {
+        if (self.offset == 0 or self.remaining == 0) {
+            return null;
+        }
+        self.remaining -= 1;
+
+        const capability = Capability{ .id = self.address.read8(self.offset), .offset = self.offset };
+        self.offset = self.address.read8(self.offset + 1) & 0xfc;
+        return capability;
+    }
+};
+
+/// Functions a driver handles, every field that is set has to match.
+pub const Match = struct {
+    vendor_id: ?u16 = null,
+    device_id: ?u16 = null,
+    class: ?u8 = null,
+    subclass: ?u8 = null,
+    prog_if: ?u8 = null,
+
+    pub fn ids(vendor_id: u16, device_id: u16) Match {
+        return .{ .vendor_id = vendor_id, .device_id = device_id };
+    }
+
+    pub fn classCode(class: u8, subclass: u8) Match {
+        return .{ .class = class, .subclass = subclass };
+    }
+
+    fn matches(self: Match, device: *const Device) bool {
+        return fieldMatches(u16, self.vendor_id, device.vendor_id) and
+            fieldMatches(u16, self.device_id, device.device_id) and
+            fieldMatches(u8, self.class, device.class) and
+            fieldMatches(u8, self.subclass, device.subclass) and
+            fieldMatches(u8, self.prog_if, device.prog_if);
+    }
+
+    fn fieldMatches(comptime T: type, wanted: ?T, actual: T) bool {
+        return if (wanted) |value| value == actual else true;
+    }
+};
+
+pub const Driver = struct {
+    name: []const u8,
+    matches: []const Match,
+    /// Takes over `device`. Failing leaves it for other drivers, and must
+    /// leave it the way it was found.
+    probe: *const fn (device: *Device) Error!void,
+    /// Stops `device` and saves whatever `on_resume` needs. Must leave the
+    /// device working when it fails.
+    on_suspend: ?*const fn (device: *Device) Error!void = null,
+    /// Brings `device` back to the state it was suspended in.
+    on_resume: ?*const fn (device: *Device) void = null,
+    next: ?*Driver = null,
+};
+
+var devices: [MAX_DEVICES]Device = undefined;
+var device_count: usize = 0;
+var enumerated = false;
+
+var first_driver: ?*Driver = null;
+var last_driver: ?*Driver = null;
+
+/// Scans every bus for functions and logs what it finds.
+pub fn init() Error!void {
+    device_count = 0;
+    for (0..256) |bus| {
+        for (0..32) |slot| {
+            scanSlot(@intCast(bus), @intCast(slot));
+        }
+    }
+
+    if (device_count == 0) {
+        return error.NotFound;
+    }
+    for (all()) |*device| {
+        logDevice(device);
+    }
+
+    enumerated = true;
+    var driver = first_driver;
+    while (driver) |current| : (driver = current.next) {
+        bindDriver(current);
+    }
+}
+
+/// Adds `driver` and probes the functions it matches. Drivers registered
+/// first get the first pick.
+pub fn register(driver: *Driver) void {
+    driver.next = null;
+    if (last_driver) |tail| {
+        tail.next = driver;
+    } else {
+        first_driver = driver;
+    }
+    last_driver = driver;
+
+    if (enumerated) {
+        bindDriver(driver);
+    }
+}
+
+fn bindDriver(driver: *Driver) void {
+    for (all()) |*device| {
+        if (device.driver != null) {
+            continue;
+        }
+        for (driver.matches) |match| {
+            if (!match.matches(device)) {
+                continue;
+            }
+
+            // set before probing so that `mapBar` can name the driver
+            device.driver = driver;
+            driver.probe(device) catch |err| {
+                device.driver = null;
+                log.warn("{s} failed to take {}: {s}", .{ driver.name, device.address, @errorName(err) });
+                break;
+            };
+            log.info("{} bound to {s}", .{ device.address, driver.name });
+            device.power = .{
+                .name = driver.name,
+                .on_suspend = suspendFunction,
+                .on_resume = resumeFunction,
+            };
+            device_list.register(&device.power);
+            break;
+        }
+    }
+}
+
+fn suspendFunction(power: *device_list.Device) Error!void {
+    const function: *Device = @fieldParentPtr("power", power);
+    const on_suspend = function.driver.?.on_suspend orelse return;
+    try on_suspend(function);
+}
+
+fn resumeFunction(power: *device_list.Device) void {
+    const function: *Device = @fieldParentPtr("power", power);
+    const on_resume = function.driver.?.on_resume orelse return;
+    on_resume(function);
+}
+
+/// Every function found at boot, in bus order.
+pub fn all() []Device {
+    return devices[0..device_count];
+}
+
+/// The first function with the given IDs.
+pub fn find(vendor_id: u16, device_id: u16) ?*Device {
+    for (all()) |*device| {
+        if (device.vendor_id == vendor_id and device.device_id == device_id) {
+            return device;
+        }
+    }
+    return null;
+}
+
+fn scanSlot(bus: u8, slot: u5) void {
+    const first = Address{ .bus = bus, .device = slot, .function = 0 };
+    if (first.read16(VENDOR_ID) == NO_DEVICE) {
+        return;
+    }
+    scanFunction(first);
+
+    if (first.read8(HEADER_TYPE) & HEADER_MULTIFUNCTION == 0) {
+        return;
+    }
+    for (1..8) |function| {
+        const address = Address{ .bus = bus, .device = slot, .function = @intCast(function) };
+        if (address.read16(VENDOR_ID) != NO_DEVICE) {
+            scanFunction(address);
+        }
+    }
+}
+
+fn scanFunction(address: Address) void {
+    if (device_count == MAX_DEVICES) {
+        log.warn("More than {} PCI functions, ignoring {}", .{ MAX_DEVICES, address });
+        return;
+    }
+
+    const class_register = address.read32(REVISION);
+    const header_type = address.read8(HEADER_TYPE);
+    var device = Device{
+        .address = address,
+        .vendor_id = address.read16(VENDOR_ID),
+        .device_id = address.read16(DEVICE_ID),
+        .class = @truncate(class_register >> 24),
+        .subclass = @truncate(class_register >> 16),
+        .prog_if = @truncate(class_register >> 8),
+        .revision = @truncate(class_register),
+        .header_type = header_type,
+        .interrupt_line = address.read8(INTERRUPT_LINE),
+        .interrupt_pin = address.read8(INTERRUPT_PIN),
+    };
+
+    const bar_count: usize = switch (header_type & HEADER_KIND) {
+        HEADER_GENERAL => 6,
+        HEADER_BRIDGE => 2,
+        else => 0,
+    };
+    if (header_type & HEADER_KIND == HEADER_BRIDGE) {
+        device.secondary_bus = address.read8(SECONDARY_BUS);
+    }
+    readBars(&device, bar_count);
+
+    devices[device_count] = device;
+    device_count += 1;
+}
+
+/// Decodes the BARs and sizes them by writing all ones and reading back
+/// which bits stick. Decoding is off meanwhile, so the device doesn't
+/// answer at the bogus address for a moment.
+fn readBars(device: *Device, count: usize) void {
+    const address = device.address;
+    const command = address.read16(COMMAND);
+    address.write16(COMMAND, command & ~@as(u16, COMMAND_IO | COMMAND_MEMORY));
+    defer address.write16(COMMAND, command);
+
+    var index: usize = 0;
+    while (index < count) : (index += 1) {
+        const offset: u8 = @intCast(BAR0 + index * 4);
+        const low = address.read32(offset);
+        address.write32(offset, 0xffff_ffff);
+        const low_mask = address.read32(offset);
+        address.write32(offset, low);
+
+        if (low & 1 != 0) {
+            const mask = low_mask & 0xffff_fffc;
+            if (mask != 0) {
+                device.bars[index] = .{ .kind = .io, .address = low & 0xffff_fffc, .size = (~mask & 0xffff) + 1 };
+            }
+            continue;
+        }
+
+        const prefetchable = low & 0x8 != 0;
+        if ((low >> 1) & 0x3 == 0x2 and index + 1 < count) {
+            const high_offset = offset + 4;
+            const high = address.read32(high_offset);
+            address.write32(high_offset, 0xffff_ffff);
+            const high_mask = address.read32(high_offset);
+            address.write32(high_offset, high);
+
+            const mask = @as(u64, high_mask) << 32 | (low_mask & 0xffff_fff0);
+            if (mask != 0) {
+                device.bars[index] = .{
+                    .kind = .memory64,
+                    .address = @as(u64, high) << 32 | (low & 0xffff_fff0),
+                    .size = ~mask +% 1,
+                    .prefetchable = prefetchable,
+                };
+            }
+            // the upper half is not a BAR of its own
+            index += 1;
+            continue;
+        }
+
+        const mask = low_mask & 0xffff_fff0;
+        if (mask != 0) {
+            device.bars[index] = .{
+                .kind = .memory32,
+                .address = low & 0xffff_fff0,
+                .size = @as(u32, ~mask) + 1,
+                .prefetchable = prefetchable,
+            };
+        }
+    }
+}
+
+fn logDevice(device: *const Device) void {
+    if (device.secondary_bus) |bus| {
+        log.info("{} {x:0>4}:{x:0>4} {s} to bus {x:0>2}", .{ device.address, device.vendor_id, device.device_id, device.className(), bus });
+    } else {
+        log.info("{} {x:0>4}:{x:0>4} {s}", .{ device.address, device.vendor_id, device.device_id, device.className() });
+    }
+    for (device.bars, 0..) |maybe_bar, index| {
+        const bar = maybe_bar orelse continue;
+        log.debug("  BAR{} {s} 0x{x} +0x{x}{s}", .{ index, @tagName(bar.kind), bar.address, bar.size, if (bar.prefetchable) " prefetchable" else "" });
+    }
+}
+
+/// Lists every function with its BARs and interrupt routing.
+pub fn dump(writer: anytype) !void {
+    for (all()) |*device| {
+        try writer.print("{} {x:0>4}:{x:0>4} class {x:0>2}{x:0>2}{x:0>2} rev {x:0>2} {s}\n", .{
+            device.address,
+            device.vendor_id,
+            device.device_id,
+            device.class,
+            device.subclass,
+            device.prog_if,
+            device.revision,
+            device.className(),
+        });
+        if (device.driver) |driver| {
+            try writer.print("  driver {s}\n", .{driver.name});
+        }
+        if (device.secondary_bus) |bus| {
+            try writer.print("  bridge to bus {x:0>2}\n", .{bus});
+        }
+        if (device.interrupt_pin != 0) {
+            const pin = 'A' + device.interrupt_pin - 1;
+            if (device.irqRoute()) |route| {
+                try writer.print("  INT{c}# on {s} {} from {s}\n", .{ pin, if (route.source == .pic) "IRQ" else "GSI", route.line.gsi, route.source.description() });
+            } else {
+                try writer.print("  INT{c}# not routed\n", .{pin});
+            }
+        }
+        for (device.bars, 0..) |maybe_bar, index| {
+            const bar = maybe_bar orelse continue;
+            try writer.print("  BAR{} {s:<8} 0x{x:0>8} +0x{x}{s}\n", .{ index, @tagName(bar.kind), bar.address, bar.size, if (bar.prefetchable) " prefetchable" else "" });
+        }
+    }
+}
+
+fn classNameOf(class: u8, subclass: u8) []const u8 {
+    return switch (class) {
+        0x00 => "Unclassified device",
+        0x01 => switch (subclass) {
+            0x01 => "IDE controller",
+            0x06 => "SATA controller",
+            0x08 => "NVMe controller",
+            else => "Storage controller",
+        },
+        0x02 => switch (subclass) {
+            0x00 => "Ethernet controller",
+            else => "Network controller",
+        },
+        0x03 => switch (subclass) {
+            0x00 => "VGA controller",
+            else => "Display controller",
+        },
+        0x04 => "Multimedia controller",
+        0x05 => "Memory controller",
+        0x06 => switch (subclass) {
+            0x00 => "Host bridge",
+            0x01 => "ISA bridge",
+            0x04 => "PCI bridge",
+            else => "Bridge",
+        },
+        0x07 => "Communication controller",
+        0x08 => "System peripheral",
+        0x0c => switch (subclass) {
+            0x03 => "USB controller",
+            0x05 => "SMBus controller",
+            else => "Serial bus controller",
+        },
+        else => "Unknown device",
+    };
+}
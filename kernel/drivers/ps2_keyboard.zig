@@ -0,0 +1,291 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+const Key = @import("kernel").input.Key;
+const power = @import("kernel").power;
+
+// NOTE:
+// The keyboard is left in scan code set 2 with the 8042 translating it to
+// set 1, which every controller supports. Set 1 codes are one byte per make
+// (`0x80` set for the break), with an `0xe0` prefix for the keys added by
+// the extended keyboard. The interrupt handler decodes them and pushes
+// `RawEvent`s into a ring buffer that kernel code drains with `next`.
+
+const DATA_PORT = 0x60;
+const STATUS_PORT = 0x64;
+const COMMAND_PORT = 0x64;
+
+const STATUS_OUTPUT_FULL = 1 << 0;
+const STATUS_INPUT_FULL = 1 << 1;
+
+const COMMAND_READ_CONFIG = 0x20;
+const COMMAND_WRITE_CONFIG = 0x60;
+const COMMAND_DISABLE_SECOND_PORT = 0xa7;
+const COMMAND_SELF_TEST = 0xaa;
+const COMMAND_DISABLE_FIRST_PORT = 0xad;
+const COMMAND_ENABLE_FIRST_PORT = 0xae;
+
+const CONFIG_FIRST_IRQ = 1 << 0;
+const CONFIG_SECOND_IRQ = 1 << 1;
+const CONFIG_TRANSLATION = 1 << 6;
+
+const SELF_TEST_PASSED = 0x55;
+
+const KEYBOARD_ENABLE_SCANNING = 0xf4;
+const KEYBOARD_ACK = 0xfa;
+
+const IRQ = 1;
+
+/// How many times to poll the status register before giving up.
+const TIMEOUT = 100_000;
+
+pub const RawEvent = struct {
+    key: Key,
+    pressed: bool,
+};
+
+const set1 = blk: {
+    var table = [_]?Key{null} ** 128;
+    const codes = .{
+        .{ 0x01, .escape },
+        .{ 0x02, .@"1" },
+        .{ 0x03, .@"2" },
+        .{ 0x04, .@"3" },
+        .{ 0x05, .@"4" },
+        .{ 0x06, .@"5" },
+        .{ 0x07, .@"6" },
+        .{ 0x08, .@"7" },
+        .{ 0x09, .@"8" },
+        .{ 0x0a, .@"9" },
+        .{ 0x0b, .@"0" },
+        .{ 0x0c, .minus },
+        .{ 0x0d, .equal },
+        .{ 0x0e, .backspace },
+        .{ 0x0f, .tab },
+        .{ 0x10, .q },
+        .{ 0x11, .w },
+        .{ 0x12, .e },
+        .{ 0x13, .r },
+        .{ 0x14, .t },
+        .{ 0x15, .y },
+        .{ 0x16, .u },
+        .{ 0x17, .i },
+        .{ 0x18, .o },
+        .{ 0x19, .p },
+        .{ 0x1a, .left_bracket },
+        .{ 0x1b, .right_bracket },
+        .{ 0x1c, .enter },
+        .{ 0x1d, .left_ctrl },
+        .{ 0x1e, .a },
+        .{ 0x1f, .s },
+        .{ 0x20, .d },
+        .{ 0x21, .f },
+        .{ 0x22, .g },
+        .{ 0x23, .h },
+        .{ 0x24, .j },
+        .{ 0x25, .k },
+        .{ 0x26, .l },
+        .{ 0x27, .semicolon },
+        .{ 0x28, .apostrophe },
+        .{ 0x29, .grave },
+        .{ 0x2a, .left_shift },
+        .{ 0x2b, .backslash },
+        .{ 0x2c, .z },
+        .{ 0x2d, .x },
+        .{ 0x2e, .c },
+        .{ 0x2f, .v },
+        .{ 0x30, .b },
+        .{ 0x31, .n },
+        .{ 0x32, .m },
+        .{ 0x33, .comma },
+        .{ 0x34, .period },
+        .{ 0x35, .slash },
+        .{ 0x36, .right_shift },
+        .{ 0x38, .left_alt },
+        .{ 0x39, .space },
+        .{ 0x3a, .caps_lock },
+        .{ 0x3b, .f1 },
+        .{ 0x3c, .f2 },
+        .{ 0x3d, .f3 },
+        .{ 0x3e, .f4 },
+        .{ 0x3f, .f5 },
+        .{ 0x40, .f6 },
+        .{ 0x41, .f7 },
+        .{ 0x42, .f8 },
+        .{ 0x43, .f9 },
+        .{ 0x44, .f10 },
+        .{ 0x45, .num_lock },
+        .{ 0x46, .scroll_lock },
+        .{ 0x56, .intl_backslash },
+        .{ 0x57, .f11 },
+        .{ 0x58, .f12 },
+    };
+    for (codes) |code| {
+        table[code[0]] = code[1];
+    }
+    break :blk table;
+};
+
+fn extended(code: u7) ?Key {
+    return switch (code) {
+        0x1c => .enter,
+        0x1d => .right_ctrl,
+        0x38 => .right_alt,
+        0x47 => .home,
+        0x48 => .up,
+        0x49 => .page_up,
+        0x4b => .left,
+        0x4d => .right,
+        0x4f => .end,
+        0x50 => .down,
+        0x51 => .page_down,
+        0x52 => .insert,
+        0x53 => .delete,
+        else => null,
+    };
+}
+
+const QUEUE_SIZE = 64;
+
+// single producer (the interrupt handler), single consumer
+var queue: [QUEUE_SIZE]RawEvent = undefined;
+var head = std.atomic.Value(usize).init(0);
+var tail = std.atomic.Value(usize).init(0);
+var dropped: usize = 0;
+
+var prefixed = false;
+/// Bytes of the pause key sequence still to be swallowed.
+var skip: u8 = 0;
+
+fn waitInput() Error!void {
+    for (0..TIMEOUT) |_| {
+        if (arch.cpu.readByte(STATUS_PORT) & STATUS_INPUT_FULL == 0) {
+            return;
+        }
+        std.atomic.spinLoopHint();
+    }
+    return error.Timeout;
+}
+
+fn waitOutput() Error!void {
+    for (0..TIMEOUT) |_| {
+        if (arch.cpu.readByte(STATUS_PORT) & STATUS_OUTPUT_FULL != 0) {
+            return;
+        }
+        std.atomic.spinLoopHint();
+    }
+    return error.Timeout;
+}
+
+fn command(byte: u8) Error!void {
+    try waitInput();
+    arch.cpu.writeByte(COMMAND_PORT, byte);
+}
+
+fn writeData(byte: u8) Error!void {
+    try waitInput();
+    arch.cpu.writeByte(DATA_PORT, byte);
+}
+
+fn readData() Error!u8 {
+    try waitOutput();
+    return arch.cpu.readByte(DATA_PORT);
+}
+
+pub fn init() Error!void {
+    try command(COMMAND_DISABLE_FIRST_PORT);
+    try command(COMMAND_DISABLE_SECOND_PORT);
+
+    // throw away whatever was left in the output buffer
+    while (arch.cpu.readByte(STATUS_PORT) & STATUS_OUTPUT_FULL != 0) {
+        _ = arch.cpu.readByte(DATA_PORT);
+    }
+
+    try command(COMMAND_READ_CONFIG);
+    var config = try readData();
+    config &= ~@as(u8, CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ);
+    config |= CONFIG_TRANSLATION;
+    try command(COMMAND_WRITE_CONFIG);
+    try writeData(config);
+
+    try command(COMMAND_SELF_TEST);
+    if (try readData() != SELF_TEST_PASSED) {
+        return error.Corrupted;
+    }
+    // the self test may reset the configuration
+    try command(COMMAND_WRITE_CONFIG);
+    try writeData(config);
+
+    try command(COMMAND_ENABLE_FIRST_PORT);
+    try writeData(KEYBOARD_ENABLE_SCANNING);
+    if (try readData() != KEYBOARD_ACK) {
+        return error.NotFound;
+    }
+
+    try arch.irq.installIsa(IRQ, handle);
+
+    try command(COMMAND_WRITE_CONFIG);
+    try writeData(config | CONFIG_FIRST_IRQ);
+
+    log.info("PS/2 keyboard ready", .{});
+}
+
+fn push(event: RawEvent) void {
+    const position = head.load(.monotonic);
+    if (position - tail.load(.acquire) == QUEUE_SIZE) {
+        dropped += 1;
+        return;
+    }
+
+    queue[position % QUEUE_SIZE] = event;
+    head.store(position + 1, .release);
+}
+
+fn decode(byte: u8) void {
+    if (skip != 0) {
+        skip -= 1;
+        return;
+    }
+
+    switch (byte) {
+        0xe0 => {
+            prefixed = true;
+            return;
+        },
+        // pause sends e1 1d 45 e1 9d c5 and has no break code
+        0xe1 => {
+            skip = 5;
+            return;
+        },
+        else => {},
+    }
+
+    const code: u7 = @truncate(byte);
+    const key = if (prefixed) extended(code) else set1[code];
+    prefixed = false;
+
+    if (key) |k| {
+        power.activity();
+        push(.{ .key = k, .pressed = byte & 0x80 == 0 });
+    }
+}
+
+fn handle(_: *arch.idt.InterruptContext) void {
+    while (arch.cpu.readByte(STATUS_PORT) & STATUS_OUTPUT_FULL != 0) {
+        decode(arch.cpu.readByte(DATA_PORT));
+    }
+    arch.irq.acknowledgeIsa(IRQ);
+}
+
+/// Takes the oldest key press or release off the queue.
+pub fn next() ?RawEvent {
+    const position = tail.load(.monotonic);
+    if (position == head.load(.acquire)) {
+        return null;
+    }
+
+    const event = queue[position % QUEUE_SIZE];
+    tail.store(position + 1, .release);
+    return event;
+}
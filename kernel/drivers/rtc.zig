@@ -0,0 +1,191 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const acpi = @import("kernel").acpi;
+const wall_clock = @import("kernel").time.wall_clock;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+// NOTE:
+// The CMOS real-time clock keeps the date while the machine is off, which
+// is all it is used for: it is read once at boot to set the wall clock,
+// and from then on the wall clock follows the monotonic clock (and SNTP,
+// when configured). The RTC updates its registers once a second and they
+// are garbage while the update-in-progress flag is set, so they are read
+// after it clears and again until two reads agree. Depending on status
+// register B the values are BCD or binary and the hour is 12 or 24 hour
+// based. The century has no standard register, the FADT names one if the
+// firmware keeps it, otherwise (or when it holds garbage) the year is taken
+// to be in the 2000s. The RTC is assumed to run on UTC.
+
+const INDEX_PORT = 0x70;
+const DATA_PORT = 0x71;
+/// Set in the index for the access, NMIs mid-access could leave the RTC in
+/// an undefined state. The bit stays latched, so the index is written again
+/// without it afterwards.
+const NMI_DISABLE = 1 << 7;
+
+const REGISTER_SECONDS = 0x00;
+const REGISTER_MINUTES = 0x02;
+const REGISTER_HOURS = 0x04;
+const REGISTER_DAY = 0x07;
+const REGISTER_MONTH = 0x08;
+const REGISTER_YEAR = 0x09;
+const REGISTER_STATUS_A = 0x0a;
+const REGISTER_STATUS_B = 0x0b;
+
+const STATUS_A_UPDATE_IN_PROGRESS = 1 << 7;
+const STATUS_B_24_HOUR = 1 << 1;
+const STATUS_B_BINARY = 1 << 2;
+const HOUR_PM = 1 << 7;
+
+/// How many times to poll or reread before giving up, an update takes
+/// under 2ms.
+const MAX_ATTEMPTS = 100_000;
+
+/// The registers as the RTC holds them, before decoding.
+pub const Registers = struct {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    /// Null without a century register.
+    century: ?u8,
+};
+
+pub const DateTime = struct {
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+
+    /// Seconds since the Unix epoch.
+    pub fn toUnix(self: DateTime) i64 {
+        // days from civil, shifting the year to start in March so the leap
+        // day comes last
+        const year: i64 = @as(i64, self.year) - @intFromBool(self.month <= 2);
+        const era = @divFloor(year, 400);
+        const year_of_era = year - era * 400;
+        const month: i64 = self.month;
+        const day_of_year = @divFloor(153 * (if (month > 2) month - 3 else month + 9) + 2, 5) + self.day - 1;
+        const day_of_era = year_of_era * 365 + @divFloor(year_of_era, 4) - @divFloor(year_of_era, 100) + day_of_year;
+        const days = era * 146097 + day_of_era - 719468;
+        return days * std.time.s_per_day + @as(i64, self.hour) * std.time.s_per_hour + @as(i64, self.minute) * std.time.s_per_min + self.second;
+    }
+};
+
+fn isBcd(value: u8) bool {
+    return value >> 4 <= 9 and value & 0xf <= 9;
+}
+
+fn fromBcd(value: u8) u8 {
+    return (value >> 4) * 10 + (value & 0xf);
+}
+
+fn decodeValue(value: u8, binary: bool) u8 {
+    return if (binary) value else fromBcd(value);
+}
+
+/// Turns raw registers into a date, following status register B's format.
+pub fn decode(registers: Registers, status_b: u8) Error!DateTime {
+    const binary = status_b & STATUS_B_BINARY != 0;
+    const pm = status_b & STATUS_B_24_HOUR == 0 and registers.hours & HOUR_PM != 0;
+    var hour = decodeValue(registers.hours & ~@as(u8, HOUR_PM), binary);
+    if (status_b & STATUS_B_24_HOUR == 0) {
+        // 12 am is midnight and 12 pm noon
+        hour = if (pm) hour % 12 + 12 else hour % 12;
+    }
+
+    const year = decodeValue(registers.year, binary);
+    var century: u16 = 20;
+    if (registers.century) |value| {
+        // some firmware leaves the register unset, 0xff and the like
+        const decoded = decodeValue(value, binary);
+        if ((binary or isBcd(value)) and decoded <= 99) {
+            century = decoded;
+        } else {
+            log.warn("RTC century register holds {x:0>2}, assuming the 2000s", .{value});
+        }
+    }
+    const date = DateTime{
+        .year = century * 100 + year,
+        .month = decodeValue(registers.month, binary),
+        .day = decodeValue(registers.day, binary),
+        .hour = hour,
+        .minute = decodeValue(registers.minutes, binary),
+        .second = decodeValue(registers.seconds, binary),
+    };
+
+    if (date.month < 1 or date.month > 12 or date.day < 1 or date.day > 31 or
+        date.hour > 23 or date.minute > 59 or date.second > 59 or year > 99)
+    {
+        return error.Corrupted;
+    }
+    return date;
+}
+
+fn readRegister(register: u8) u8 {
+    arch.cpu.writeByte(INDEX_PORT, NMI_DISABLE | register);
+    const value = arch.cpu.readByte(DATA_PORT);
+    arch.cpu.writeByte(INDEX_PORT, register);
+    return value;
+}
+
+fn updateInProgress() bool {
+    return readRegister(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0;
+}
+
+fn readOnce(century_register: ?u8) Error!Registers {
+    var attempts: usize = 0;
+    while (updateInProgress()) : (attempts += 1) {
+        if (attempts == MAX_ATTEMPTS) {
+            return error.Timeout;
+        }
+        std.atomic.spinLoopHint();
+    }
+    return .{
+        .seconds = readRegister(REGISTER_SECONDS),
+        .minutes = readRegister(REGISTER_MINUTES),
+        .hours = readRegister(REGISTER_HOURS),
+        .day = readRegister(REGISTER_DAY),
+        .month = readRegister(REGISTER_MONTH),
+        .year = readRegister(REGISTER_YEAR),
+        .century = if (century_register) |register| readRegister(register) else null,
+    };
+}
+
+/// Reads the current date and time.
+pub fn read() Error!DateTime {
+    const century_register = acpi.rtcCenturyRegister();
+
+    const registers = blk: {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        // an update can start right after the flag was checked, only two
+        // matching reads in a row are known to be from the same second
+        var previous = try readOnce(century_register);
+        for (0..MAX_ATTEMPTS) |_| {
+            const current = try readOnce(century_register);
+            if (std.meta.eql(current, previous)) {
+                break :blk current;
+            }
+            previous = current;
+        }
+        return error.Timeout;
+    };
+    return decode(registers, readRegister(REGISTER_STATUS_B));
+}
+
+/// Sets the wall clock from the RTC, unless something better already did.
+pub fn init() Error!void {
+    const date = try read();
+    log.info("RTC reads {}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2}", .{ date.year, date.month, date.day, date.hour, date.minute, date.second });
+
+    if (!wall_clock.isSynchronized()) {
+        wall_clock.set(date.toUnix() * std.time.ns_per_s);
+    }
+}
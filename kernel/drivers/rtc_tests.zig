@@ -0,0 +1,69 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const rtc = @import("rtc.zig");
+
+const STATUS_B_24_HOUR = 1 << 1;
+const STATUS_B_BINARY = 1 << 2;
+
+fn unixTime() !void {
+    const cases = .{
+        .{ rtc.DateTime{ .year = 1970, .month = 1, .day = 1, .hour = 0, .minute = 0, .second = 0 }, 0 },
+        .{ rtc.DateTime{ .year = 2000, .month = 2, .day = 29, .hour = 12, .minute = 0, .second = 0 }, 951_825_600 },
+        .{ rtc.DateTime{ .year = 2024, .month = 1, .day = 1, .hour = 0, .minute = 0, .second = 0 }, 1_704_067_200 },
+        .{ rtc.DateTime{ .year = 2038, .month = 1, .day = 19, .hour = 3, .minute = 14, .second = 8 }, 2_147_483_648 },
+    };
+    inline for (cases) |case| {
+        if (case[0].toUnix() != case[1]) {
+            return error.WrongUnixTime;
+        }
+    }
+}
+
+fn registers() !void {
+    // 2024-03-09 21:07:45 in BCD with a 12 hour clock and a century register
+    const bcd = rtc.Registers{ .seconds = 0x45, .minutes = 0x07, .hours = 0x80 | 0x09, .day = 0x09, .month = 0x03, .year = 0x24, .century = 0x20 };
+    const date = try rtc.decode(bcd, 0);
+    if (!std.meta.eql(date, .{ .year = 2024, .month = 3, .day = 9, .hour = 21, .minute = 7, .second = 45 })) {
+        return error.WrongBcdDate;
+    }
+
+    // 12 am is midnight, 12 pm noon
+    var midnight = bcd;
+    midnight.hours = 0x12;
+    if ((try rtc.decode(midnight, 0)).hour != 0) {
+        return error.WrongMidnight;
+    }
+    var noon = bcd;
+    noon.hours = 0x80 | 0x12;
+    if ((try rtc.decode(noon, 0)).hour != 12) {
+        return error.WrongNoon;
+    }
+
+    // binary, 24 hour, no century register
+    const binary = rtc.Registers{ .seconds = 59, .minutes = 59, .hours = 23, .day = 31, .month = 12, .year = 99, .century = null };
+    const end = try rtc.decode(binary, STATUS_B_24_HOUR | STATUS_B_BINARY);
+    if (!std.meta.eql(end, .{ .year = 2099, .month = 12, .day = 31, .hour = 23, .minute = 59, .second = 59 })) {
+        return error.WrongBinaryDate;
+    }
+
+    var garbage = binary;
+    garbage.month = 13;
+    if (rtc.decode(garbage, STATUS_B_24_HOUR | STATUS_B_BINARY)) |_| {
+        return error.AcceptedGarbage;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+
+    // an unset century register in a BCD clock falls back to the 2000s
+    var no_century = bcd;
+    no_century.century = 0xff;
+    if ((try rtc.decode(no_century, 0)).year != 2024) {
+        return error.WrongUnsetCentury;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "rtc.unix_time", .func = unixTime },
+    .{ .name = "rtc.registers", .func = registers },
+};
@@ -0,0 +1,340 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const cmdline = @import("kernel").cmdline;
+const Error = @import("kernel").Error;
+const log = @import("kernel").utils.log;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+const devfs = @import("kernel").fs.devfs;
+
+const device = @import("device.zig");
+
+// NOTE:
+// COM1 is set up by the firmware (or QEMU). Until `init` installs the
+// interrupt handler the log is written out a byte at a time. Afterwards it
+// goes into `queue`, which the transmitter empty interrupt drains a FIFO's
+// worth at a time, and `policy` decides what gives when the log outpaces the
+// UART. The receive side is polled since nothing reads the port often enough
+// to be worth an interrupt.
+//
+// Under `drop_debug` a debug line is held back until its last piece arrives
+// and then queued or dropped as a whole, so a full queue never cuts one
+// short. Once the kernel panics nothing takes the lock any more, whoever
+// holds it may be what panicked: `panicWrite` writes out what is queued
+// and everything after it polled.
+
+pub const COM1 = 0x3f8;
+
+const INTERRUPT_ENABLE = 1;
+const INTERRUPT_IDENTIFICATION = 2;
+const FIFO_CONTROL = 2;
+const MODEM_CONTROL = 4;
+const LINE_STATUS = 5;
+
+const ENABLE_TRANSMIT_EMPTY = 1 << 1;
+const FIFO_ENABLE = 1 << 0;
+const FIFO_TRIGGER_14 = 0xc0;
+const FIFOS_ENABLED = 0xc0;
+/// Gates the UART's interrupt line on PCs.
+const OUT2 = 1 << 3;
+const DATA_READY = 1 << 0;
+const TRANSMIT_EMPTY = 1 << 5;
+
+const IRQ = 4;
+const QUEUE_SIZE = 16 * 1024;
+
+pub const Policy = enum {
+    /// Wait for the UART to make room.
+    block,
+    /// Throw away the oldest queued bytes.
+    drop_oldest,
+    /// Throw away debug lines that don't fit, wait for everything else.
+    drop_debug,
+};
+
+pub var policy: Policy = .drop_debug;
+
+/// Longest debug line held back under `drop_debug`, longer ones are dropped.
+const MAX_DEBUG_LINE = 512;
+
+var queue: [QUEUE_SIZE]u8 = undefined;
+var head: usize = 0;
+var tail: usize = 0;
+
+var dropped: usize = 0;
+var reported: usize = 0;
+var at_line_start = true;
+
+/// The debug line being held back, see `holdDebug`.
+var debug_line: [MAX_DEBUG_LINE]u8 = undefined;
+var debug_length: usize = 0;
+/// Set while the rest of a debug line too long to hold is thrown away.
+var discarding = false;
+
+var fifo_size: usize = 1;
+var queueing = false;
+var panicking = std.atomic.Value(bool).init(false);
+var lock = SpinLock.init();
+
+var sink = log.Sink{ .name = "serial", .write = write };
+
+/// Sends the log to COM1, written out polled until `init`. Called before
+/// anything is logged, `serial=off` on the kernel command line leaves the
+/// sink switched off.
+pub fn registerSink() void {
+    sink.enabled = cmdline.getOr(bool, "serial", true);
+    log.register(&sink);
+}
+
+/// Sets up the interrupt driven queue, unless `serial=off` is on the kernel
+/// command line. `serial.policy=<policy>` picks the `policy`.
+pub fn init() Error!void {
+    if (!cmdline.getOr(bool, "serial", true)) {
+        return;
+    }
+    policy = cmdline.getOr(Policy, "serial.policy", policy);
+
+    arch.cpu.writeByte(COM1 + FIFO_CONTROL, FIFO_ENABLE | FIFO_TRIGGER_14);
+    if (arch.cpu.readByte(COM1 + INTERRUPT_IDENTIFICATION) & FIFOS_ENABLED == FIFOS_ENABLED) {
+        fifo_size = 16;
+    }
+
+    try arch.irq.installIsa(IRQ, handle);
+    arch.cpu.writeByte(COM1 + MODEM_CONTROL, arch.cpu.readByte(COM1 + MODEM_CONTROL) | OUT2);
+
+    setQueueing(true);
+    arch.cpu.writeByte(COM1 + INTERRUPT_ENABLE, ENABLE_TRANSMIT_EMPTY);
+    device.register(&serial_device);
+    try devfs.register(&serial_node);
+}
+
+var serial_device = device.Device{
+    .name = "serial",
+    .on_suspend = quiesce,
+    .on_resume = restore,
+};
+
+var serial_node = devfs.DeviceNode{
+    .name = "ttyS0",
+    .kind = .character,
+    .read = readNode,
+    .write = writeNode,
+};
+
+/// Whatever has been received so far, never waits.
+fn readNode(_: *const devfs.DeviceNode, _: usize, buffer: []u8) Error!usize {
+    for (buffer, 0..) |*byte, index| {
+        byte.* = readByte() orelse return index;
+    }
+    return buffer.len;
+}
+
+fn writeNode(_: *const devfs.DeviceNode, _: usize, bytes: []const u8) Error!usize {
+    write(bytes, .normal);
+    return bytes.len;
+}
+
+/// Drains the queue and goes back to polled output.
+fn quiesce(_: *device.Device) Error!void {
+    flush();
+    setQueueing(false);
+    arch.cpu.writeByte(COM1 + INTERRUPT_ENABLE, 0);
+}
+
+fn restore(_: *device.Device) void {
+    setQueueing(true);
+    arch.cpu.writeByte(COM1 + INTERRUPT_ENABLE, ENABLE_TRANSMIT_EMPTY);
+}
+
+fn setQueueing(enabled: bool) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    queueing = enabled;
+}
+
+fn transmitEmpty() bool {
+    return arch.cpu.readByte(COM1 + LINE_STATUS) & TRANSMIT_EMPTY != 0;
+}
+
+fn pollByte(byte: u8) void {
+    while (!transmitEmpty()) {
+        std.atomic.spinLoopHint();
+    }
+    arch.cpu.writeByte(COM1, byte);
+}
+
+fn free() usize {
+    return QUEUE_SIZE - (head - tail);
+}
+
+/// Hands queued bytes to the UART for as long as it takes them.
+fn drain() void {
+    while (tail != head and transmitEmpty()) {
+        for (0..fifo_size) |_| {
+            if (tail == head) {
+                break;
+            }
+            arch.cpu.writeByte(COM1, queue[tail % QUEUE_SIZE]);
+            tail += 1;
+        }
+    }
+}
+
+fn enqueue(bytes: []const u8) void {
+    for (bytes) |byte| {
+        if (free() == 0) {
+            if (policy == .drop_oldest) {
+                tail += 1;
+                dropped += 1;
+            } else {
+                // interrupts are off, the queue has to be drained by hand
+                pollByte(queue[tail % QUEUE_SIZE]);
+                tail += 1;
+            }
+        }
+        queue[head % QUEUE_SIZE] = byte;
+        head += 1;
+    }
+
+    if (bytes.len != 0) {
+        at_line_start = bytes[bytes.len - 1] == '\n';
+    }
+}
+
+/// Collects the pieces of debug lines and queues each line once it is
+/// complete, if it fits.
+fn holdDebug(bytes: []const u8) void {
+    var rest = bytes;
+    while (rest.len != 0) {
+        const length = if (std.mem.indexOfScalar(u8, rest, '\n')) |newline| newline + 1 else rest.len;
+        const piece = rest[0..length];
+        rest = rest[length..];
+
+        if (!discarding and debug_length + piece.len > debug_line.len) {
+            dropped += debug_length;
+            debug_length = 0;
+            discarding = true;
+        }
+        if (discarding) {
+            dropped += piece.len;
+        } else {
+            @memcpy(debug_line[debug_length..][0..piece.len], piece);
+            debug_length += piece.len;
+        }
+
+        if (piece[piece.len - 1] != '\n') {
+            continue;
+        }
+        if (!discarding) {
+            if (debug_length <= free()) {
+                reportDropped();
+                enqueue(debug_line[0..debug_length]);
+            } else {
+                dropped += debug_length;
+            }
+        }
+        debug_length = 0;
+        discarding = false;
+    }
+}
+
+fn reportDropped() void {
+    if (dropped == reported or !at_line_start) {
+        return;
+    }
+
+    var buffer: [64]u8 = undefined;
+    const message = std.fmt.bufPrint(&buffer, "[serial: dropped {} bytes]\n", .{dropped - reported}) catch unreachable;
+    reported = dropped;
+    enqueue(message);
+}
+
+/// Queues `bytes` for transmission, or writes them out right away before
+/// `init`. May be called with interrupts disabled.
+pub fn write(bytes: []const u8, priority: log.Priority) void {
+    if (panicking.load(.acquire)) {
+        return panicWrite(bytes);
+    }
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    if (!queueing) {
+        for (bytes) |byte| {
+            pollByte(byte);
+        }
+        return;
+    }
+
+    if (priority == .debug and policy == .drop_debug) {
+        holdDebug(bytes);
+    } else {
+        reportDropped();
+        enqueue(bytes);
+    }
+    drain();
+}
+
+/// Writes out what is queued and then `bytes`, polled and without taking
+/// the lock. For the panic path, every later write goes this way too.
+pub fn panicWrite(bytes: []const u8) void {
+    arch.cpu.disableInterrupts();
+    panicking.store(true, .release);
+
+    while (tail != head) {
+        pollByte(queue[tail % QUEUE_SIZE]);
+        tail += 1;
+    }
+    for (bytes) |byte| {
+        pollByte(byte);
+    }
+}
+
+/// Writes out everything queued, for when the interrupt may never come
+/// (panics, exiting QEMU).
+pub fn flush() void {
+    if (panicking.load(.acquire)) {
+        return panicWrite("");
+    }
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    while (tail != head) {
+        pollByte(queue[tail % QUEUE_SIZE]);
+        tail += 1;
+    }
+}
+
+/// Bytes thrown away because the queue was full.
+pub fn droppedBytes() usize {
+    return @atomicLoad(usize, &dropped, .monotonic);
+}
+
+fn handle(_: *arch.idt.InterruptContext) void {
+    // reading the identification register acknowledges the interrupt
+    _ = arch.cpu.readByte(COM1 + INTERRUPT_IDENTIFICATION);
+
+    lock.acquire();
+    drain();
+    lock.release();
+
+    arch.irq.acknowledgeIsa(IRQ);
+}
+
+/// The next byte received on COM1, if there is one.
+pub fn readByte() ?u8 {
+    if (arch.cpu.readByte(COM1 + LINE_STATUS) & DATA_READY == 0) {
+        return null;
+    }
+    return arch.cpu.readByte(COM1);
+}
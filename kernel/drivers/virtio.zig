@@ -0,0 +1,375 @@
+const std = @import("std");
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const pci = @import("pci.zig");
+
+// NOTE:
+// The virtio 1.0 PCI transport, shared by every virtio driver. Vendor
+// capabilities point at the register blocks inside the BARs: the common
+// configuration (feature negotiation, device status and queue setup), the
+// notification area the driver writes a queue's index to, the ISR status
+// read in the interrupt handler and the device specific configuration.
+// Legacy devices, which only have an I/O BAR, are not supported.
+//
+// Queues are split virtqueues living in one physically contiguous DMA
+// buffer: descriptors, then the ring the driver makes buffers available
+// on, then the ring the device returns them on. The queue tracks which
+// descriptors are free but not what they point at, buffers are the
+// driver's business.
+
+pub const VENDOR_ID = 0x1af4;
+
+/// Device types, the modern PCI device ID is 0x1040 plus the type.
+pub const DeviceType = enum(u16) {
+    network = 1,
+    block = 2,
+    console = 3,
+    entropy = 4,
+    gpu = 16,
+    input = 18,
+};
+
+// offsets into the common configuration
+const DEVICE_FEATURE_SELECT = 0x00;
+const DEVICE_FEATURE = 0x04;
+const DRIVER_FEATURE_SELECT = 0x08;
+const DRIVER_FEATURE = 0x0c;
+const DEVICE_STATUS = 0x14;
+const CONFIG_GENERATION = 0x15;
+const QUEUE_SELECT = 0x16;
+const QUEUE_SIZE = 0x18;
+const QUEUE_ENABLE = 0x1c;
+const QUEUE_NOTIFY_OFF = 0x1e;
+const QUEUE_DESCRIPTORS = 0x20;
+const QUEUE_DRIVER = 0x28;
+const QUEUE_DEVICE = 0x30;
+
+const CAP_COMMON = 1;
+const CAP_NOTIFY = 2;
+const CAP_ISR = 3;
+const CAP_DEVICE = 4;
+
+const STATUS_ACKNOWLEDGE = 1;
+const STATUS_DRIVER = 2;
+const STATUS_DRIVER_OK = 4;
+const STATUS_FEATURES_OK = 8;
+const STATUS_FAILED = 0x80;
+
+pub const FEATURE_VERSION_1 = 1 << 32;
+
+pub const DESCRIPTOR_NEXT = 1;
+pub const DESCRIPTOR_WRITE = 2;
+
+/// Most descriptors used per queue, the free descriptors are a `u64`.
+pub const MAX_QUEUE_SIZE = 64;
+
+/// The modern PCI device ID for `kind`.
+pub fn deviceId(kind: DeviceType) u16 {
+    return 0x1040 + @intFromEnum(kind);
+}
+
+/// A register block inside a mapped BAR.
+pub const Registers = struct {
+    base: usize,
+
+    pub fn read(self: Registers, comptime T: type, offset: usize) T {
+        return @as(*volatile T, @ptrFromInt(self.base + offset)).*;
+    }
+
+    pub fn write(self: Registers, comptime T: type, offset: usize, value: T) void {
+        @as(*volatile T, @ptrFromInt(self.base + offset)).* = value;
+    }
+};
+
+/// Why the device interrupted, reading it also deasserts the interrupt.
+pub const InterruptStatus = packed struct(u8) {
+    queue: bool,
+    config: bool,
+    _: u6,
+};
+
+pub const Descriptor = extern struct {
+    address: u64,
+    length: u32,
+    flags: u16,
+    next: u16,
+};
+
+pub const UsedElement = extern struct {
+    /// Head of the descriptor chain the device is done with.
+    id: u32,
+    /// Bytes the device wrote into the chain.
+    length: u32,
+};
+
+pub const Virtqueue = struct {
+    index: u16,
+    size: u16,
+    rings: memory.DmaBuffer,
+    /// Offsets of the available and used rings in `rings`.
+    available_offset: usize,
+    used_offset: usize,
+    notify: *volatile u16,
+    /// Our copy of the available ring's index.
+    next_available: u16 = 0,
+    /// Used ring index up to which elements have been taken.
+    last_used: u16 = 0,
+    /// Descriptors handed out by `allocDescriptor`.
+    busy: u64 = 0,
+
+    pub fn deinit(self: *Virtqueue) void {
+        self.rings.deinit();
+    }
+
+    fn at(self: *Virtqueue, comptime T: type, offset: usize) *volatile T {
+        return @ptrCast(@alignCast(&self.rings.pages[offset]));
+    }
+
+    pub fn descriptor(self: *Virtqueue, index: u16) *volatile Descriptor {
+        std.debug.assert(index < self.size);
+        return self.at(Descriptor, @as(usize, index) * @sizeOf(Descriptor));
+    }
+
+    /// A descriptor nobody uses, for drivers that don't tie descriptors
+    /// to fixed buffers.
+    pub fn allocDescriptor(self: *Virtqueue) ?u16 {
+        const free = ~self.busy & (std.math.shl(u64, 1, self.size) -% 1);
+        if (free == 0) {
+            return null;
+        }
+        const index: u16 = @ctz(free);
+        self.busy |= @as(u64, 1) << @intCast(index);
+        return index;
+    }
+
+    pub fn freeDescriptor(self: *Virtqueue, index: u16) void {
+        self.busy &= ~(@as(u64, 1) << @intCast(index));
+    }
+
+    /// Hands the chain starting at descriptor `head` to the device, which
+    /// only looks at it after `kick`.
+    pub fn offer(self: *Virtqueue, head: u16) void {
+        const slot = self.next_available % self.size;
+        self.at(u16, self.available_offset + 4 + 2 * @as(usize, slot)).* = head;
+        self.next_available +%= 1;
+        // the ring entry has to be visible before the index that covers it
+        self.rings.syncForDevice();
+        self.at(u16, self.available_offset + 2).* = self.next_available;
+    }
+
+    pub fn kick(self: *Virtqueue) void {
+        self.rings.syncForDevice();
+        self.notify.* = self.index;
+    }
+
+    /// The next chain the device is done with.
+    pub fn takeUsed(self: *Virtqueue) ?UsedElement {
+        if (self.at(u16, self.used_offset + 2).* == self.last_used) {
+            return null;
+        }
+        self.rings.syncForCpu();
+
+        const slot = self.last_used % self.size;
+        const element = self.at(UsedElement, self.used_offset + 4 + @as(usize, slot) * @sizeOf(UsedElement)).*;
+        self.last_used +%= 1;
+        return element;
+    }
+};
+
+pub const Transport = struct {
+    device: *pci.Device,
+    common: Registers,
+    notify: Registers,
+    notify_multiplier: u32,
+    isr: Registers,
+    /// The device specific configuration, null when the device has none.
+    config: ?Registers,
+    /// What `negotiate` settled on.
+    features: u64 = 0,
+
+    /// Finds the register blocks the vendor capabilities point at, then
+    /// resets the device and tells it a driver is looking at it.
+    pub fn init(device: *pci.Device) Error!Transport {
+        var common: ?Registers = null;
+        var notify: ?Registers = null;
+        var notify_multiplier: u32 = 0;
+        var isr: ?Registers = null;
+        var config: ?Registers = null;
+        var mapped: [6]?usize = .{null} ** 6;
+
+        var capabilities = device.capabilities();
+        while (capabilities.next()) |capability| {
+            if (capability.id != pci.CAPABILITY_VENDOR) {
+                continue;
+            }
+            const kind = device.address.read8(capability.offset + 3);
+            const bar = device.address.read8(capability.offset + 4);
+            const offset = device.address.read32(capability.offset + 8);
+            if (bar >= 6 or kind < CAP_COMMON or kind > CAP_DEVICE) {
+                continue;
+            }
+
+            if (mapped[bar] == null) {
+                mapped[bar] = try device.mapBar(bar);
+            }
+            const registers = Registers{ .base = mapped[bar].? + offset };
+
+            // the first capability of each kind is the preferred one
+            switch (kind) {
+                CAP_COMMON => common = common orelse registers,
+                CAP_NOTIFY => if (notify == null) {
+                    notify = registers;
+                    notify_multiplier = device.address.read32(capability.offset + 16);
+                },
+                CAP_ISR => isr = isr orelse registers,
+                CAP_DEVICE => config = config orelse registers,
+                else => unreachable,
+            }
+        }
+
+        // a legacy-only device has none of the capabilities
+        var transport = Transport{
+            .device = device,
+            .common = common orelse return error.Unsupported,
+            .notify = notify orelse return error.Unsupported,
+            .notify_multiplier = notify_multiplier,
+            .isr = isr orelse return error.Unsupported,
+            .config = config,
+        };
+        transport.restart();
+        return transport;
+    }
+
+    /// Resets the device and tells it a driver is looking at it, the first
+    /// steps of setting it up.
+    pub fn restart(self: *const Transport) void {
+        self.reset();
+        self.setStatus(STATUS_ACKNOWLEDGE);
+        self.setStatus(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    }
+
+    fn setStatus(self: *const Transport, status: u8) void {
+        self.common.write(u8, DEVICE_STATUS, status);
+    }
+
+    /// Stops the device, it forgets its queues and features.
+    pub fn reset(self: *const Transport) void {
+        self.setStatus(0);
+        while (self.common.read(u8, DEVICE_STATUS) != 0) {
+            std.atomic.spinLoopHint();
+        }
+    }
+
+    /// Tells the device the driver gave up on it.
+    pub fn fail(self: *const Transport) void {
+        self.setStatus(self.common.read(u8, DEVICE_STATUS) | STATUS_FAILED);
+    }
+
+    /// Accepts the features in `wanted` the device offers and checks the
+    /// device is happy with them. VERSION_1 is always required.
+    pub fn negotiate(self: *Transport, wanted: u64) Error!u64 {
+        self.common.write(u32, DEVICE_FEATURE_SELECT, 0);
+        const low = self.common.read(u32, DEVICE_FEATURE);
+        self.common.write(u32, DEVICE_FEATURE_SELECT, 1);
+        const high = self.common.read(u32, DEVICE_FEATURE);
+        const offered = @as(u64, high) << 32 | low;
+
+        if (offered & FEATURE_VERSION_1 == 0) {
+            return error.Unsupported;
+        }
+        const accepted = offered & (wanted | FEATURE_VERSION_1);
+
+        self.common.write(u32, DRIVER_FEATURE_SELECT, 0);
+        self.common.write(u32, DRIVER_FEATURE, @truncate(accepted));
+        self.common.write(u32, DRIVER_FEATURE_SELECT, 1);
+        self.common.write(u32, DRIVER_FEATURE, @truncate(accepted >> 32));
+
+        self.setStatus(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+        if (self.common.read(u8, DEVICE_STATUS) & STATUS_FEATURES_OK == 0) {
+            return error.Unsupported;
+        }
+        self.features = accepted;
+        return accepted;
+    }
+
+    pub fn hasFeature(self: *const Transport, feature: u64) bool {
+        return self.features & feature != 0;
+    }
+
+    /// Allocates queue `index` with up to `MAX_QUEUE_SIZE` descriptors and
+    /// tells the device where it lives. The queue is used once enabled.
+    pub fn setupQueue(self: *const Transport, index: u16) Error!Virtqueue {
+        self.common.write(u16, QUEUE_SELECT, index);
+        const size = @min(self.common.read(u16, QUEUE_SIZE), MAX_QUEUE_SIZE);
+        if (size == 0) {
+            return error.NotFound;
+        }
+        self.common.write(u16, QUEUE_SIZE, size);
+
+        const available_offset = @as(usize, size) * @sizeOf(Descriptor);
+        const used_offset = std.mem.alignForward(usize, available_offset + 6 + 2 * @as(usize, size), 4);
+        const rings = try memory.DmaBuffer.alloc(used_offset + 6 + @as(usize, size) * @sizeOf(UsedElement));
+
+        const notify_offset = @as(usize, self.common.read(u16, QUEUE_NOTIFY_OFF)) * self.notify_multiplier;
+        const queue = Virtqueue{
+            .index = index,
+            .size = size,
+            .rings = rings,
+            .available_offset = available_offset,
+            .used_offset = used_offset,
+            .notify = @ptrFromInt(self.notify.base + notify_offset),
+        };
+        self.writeQueueAddresses(&queue);
+        return queue;
+    }
+
+    /// Hands `queue` to the device again after a reset made it forget the
+    /// queue. The rings start over empty, the caller offers its buffers
+    /// again before enabling the queue.
+    pub fn restoreQueue(self: *const Transport, queue: *Virtqueue) void {
+        @memset(queue.rings.bytes(), 0);
+        queue.next_available = 0;
+        queue.last_used = 0;
+        queue.busy = 0;
+
+        self.common.write(u16, QUEUE_SELECT, queue.index);
+        self.common.write(u16, QUEUE_SIZE, queue.size);
+        self.writeQueueAddresses(queue);
+    }
+
+    /// Tells the device where the rings of the selected queue live.
+    fn writeQueueAddresses(self: *const Transport, queue: *const Virtqueue) void {
+        self.common.write(u64, QUEUE_DESCRIPTORS, queue.rings.busAddress(0));
+        self.common.write(u64, QUEUE_DRIVER, queue.rings.busAddress(queue.available_offset));
+        self.common.write(u64, QUEUE_DEVICE, queue.rings.busAddress(queue.used_offset));
+    }
+
+    pub fn enableQueue(self: *const Transport, queue: *const Virtqueue) void {
+        self.common.write(u16, QUEUE_SELECT, queue.index);
+        self.common.write(u16, QUEUE_ENABLE, 1);
+    }
+
+    /// Lets the device go, after the queues are enabled.
+    pub fn ready(self: *const Transport) void {
+        self.device.enable(pci.COMMAND_BUS_MASTER);
+        self.setStatus(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK);
+    }
+
+    pub fn interruptStatus(self: *const Transport) InterruptStatus {
+        return @bitCast(self.isr.read(u8, 0));
+    }
+
+    /// Reads the device configuration, retrying while the device changes
+    /// it underneath.
+    pub fn readConfig(self: *const Transport, comptime T: type, offset: usize) T {
+        const config = self.config orelse return std.mem.zeroes(T);
+        while (true) {
+            const generation = self.common.read(u8, CONFIG_GENERATION);
+            const value = config.read(T, offset);
+            if (self.common.read(u8, CONFIG_GENERATION) == generation) {
+                return value;
+            }
+        }
+    }
+};
@@ -0,0 +1,309 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log.scoped(.virtio);
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+const net = @import("kernel").net;
+const shutdown = @import("kernel").power.shutdown;
+
+const pci = @import("pci.zig");
+const virtio = @import("virtio.zig");
+
+// NOTE:
+// A virtio network device, registered as a `NetDevice`, with one receive
+// and one transmit queue. Every descriptor owns a fixed buffer large
+// enough for a full frame plus the virtio-net header, so neither direction
+// ever chains descriptors or allocates. Received frames are handed to the
+// stack from the interrupt handler and their buffers go straight back to
+// the device. Sending copies the frame into a free transmit buffer, which
+// is reclaimed once the device reports it sent. Checksum offload,
+// segmentation and mergeable receive buffers are not negotiated.
+//
+// The interrupt is the INTx line, which other devices may share, or none
+// when its route is unknown and the stack polls instead.
+
+const DEVICE_ID_TRANSITIONAL = 0x1000;
+
+const FEATURE_MAC = 1 << 5;
+const FEATURE_STATUS = 1 << 16;
+const FEATURES = FEATURE_MAC | FEATURE_STATUS;
+
+// offsets into the device configuration
+const CONFIG_MAC = 0x00;
+const CONFIG_STATUS = 0x06;
+const CONFIG_LINK_UP = 1;
+
+const RECEIVE_QUEUE = 0;
+const TRANSMIT_QUEUE = 1;
+
+/// Every packet starts with this header, all zeroes when nothing is
+/// offloaded.
+const HEADER_SIZE = 12;
+const BUFFER_SIZE = 2048;
+
+const MTU = 1500;
+
+const Nic = struct {
+    transport: virtio.Transport,
+    mac: [6]u8,
+    irq: ?arch.irq.Line,
+    receive: virtio.Virtqueue,
+    transmit: virtio.Virtqueue,
+    receive_buffers: memory.DmaBuffer,
+    transmit_buffers: memory.DmaBuffer,
+    transmit_lock: SpinLock = SpinLock.init(),
+    /// Set while the device is reset for a sleep state.
+    suspended: bool = false,
+};
+
+var nic: Nic = undefined;
+var present = false;
+var interrupt_handler = arch.irq.SharedHandler{ .func = handle };
+
+var stop_on_shutdown = shutdown.Hook{
+    .name = "virtio-net",
+    .run = stopOnShutdown,
+};
+
+var net_device = net.NetDevice{
+    .mac = undefined,
+    .mtu = MTU,
+    .transmit = transmit,
+    .link_up = isLinkUp,
+};
+
+var driver = pci.Driver{
+    .name = "virtio-net",
+    .matches = &.{
+        pci.Match.ids(virtio.VENDOR_ID, virtio.deviceId(.network)),
+        pci.Match.ids(virtio.VENDOR_ID, DEVICE_ID_TRANSITIONAL),
+    },
+    .probe = probe,
+    .on_suspend = suspendDevice,
+    .on_resume = resumeDevice,
+};
+
+/// Registers the driver, the device is set up when PCI enumeration finds
+/// one.
+pub fn init() Error!void {
+    pci.register(&driver);
+}
+
+fn probe(device: *pci.Device) Error!void {
+    if (present) {
+        log.warn("Only one virtio-net device is supported, ignoring {}", .{device.address});
+        return error.Unsupported;
+    }
+
+    var transport = try virtio.Transport.init(device);
+    errdefer transport.fail();
+    _ = try transport.negotiate(FEATURES);
+
+    var receive = try transport.setupQueue(RECEIVE_QUEUE);
+    errdefer receive.deinit();
+    var transmit = try transport.setupQueue(TRANSMIT_QUEUE);
+    errdefer transmit.deinit();
+
+    var receive_buffers = try memory.DmaBuffer.alloc(@as(usize, receive.size) * BUFFER_SIZE);
+    errdefer receive_buffers.deinit();
+    var transmit_buffers = try memory.DmaBuffer.alloc(@as(usize, transmit.size) * BUFFER_SIZE);
+    errdefer transmit_buffers.deinit();
+
+    nic = .{
+        .transport = transport,
+        .mac = readMac(&transport),
+        .irq = device.legacyIrq(),
+        .receive = receive,
+        .transmit = transmit,
+        .receive_buffers = receive_buffers,
+        .transmit_buffers = transmit_buffers,
+    };
+
+    fillQueues();
+
+    if (nic.irq) |line| {
+        arch.irq.installShared(line, &interrupt_handler) catch |err| {
+            log.warn("Failed to install the interrupt of {}: {s}", .{ device.address, @errorName(err) });
+            nic.irq = null;
+        };
+    }
+    if (nic.irq == null) {
+        log.warn("{} has no routed interrupt, receiving by polling", .{device.address});
+        net_device.poll = poll;
+    }
+
+    start();
+    present = true;
+
+    net_device.mac = nic.mac;
+    net.device.register(&net_device);
+    shutdown.register(&stop_on_shutdown);
+    log.info("virtio-net at {} is {s}: link {s}, {} receive and {} transmit buffers", .{
+        device.address,
+        net_device.name(),
+        if (net_device.isLinkUp()) "up" else "down",
+        nic.receive.size,
+        nic.transmit.size,
+    });
+}
+
+/// Ties every descriptor to its buffer, every receive buffer starts out
+/// with the device.
+fn fillQueues() void {
+    for (0..nic.receive.size) |index| {
+        nic.receive.descriptor(@intCast(index)).* = .{
+            .address = nic.receive_buffers.busAddress(index * BUFFER_SIZE),
+            .length = BUFFER_SIZE,
+            .flags = virtio.DESCRIPTOR_WRITE,
+            .next = 0,
+        };
+        nic.receive.offer(@intCast(index));
+    }
+    for (0..nic.transmit.size) |index| {
+        nic.transmit.descriptor(@intCast(index)).address = nic.transmit_buffers.busAddress(index * BUFFER_SIZE);
+    }
+}
+
+/// Enables the queues and lets the device go.
+fn start() void {
+    nic.transport.enableQueue(&nic.receive);
+    nic.transport.enableQueue(&nic.transmit);
+    nic.transport.ready();
+    nic.receive.kick();
+}
+
+fn readMac(transport: *const virtio.Transport) [6]u8 {
+    // QEMU's default, locally administered
+    var mac = [6]u8{ 0x52, 0x54, 0x00, 0x12, 0x34, 0x56 };
+    if (transport.hasFeature(FEATURE_MAC)) {
+        for (&mac, 0..) |*byte, index| {
+            byte.* = transport.readConfig(u8, CONFIG_MAC + index);
+        }
+    }
+    return mac;
+}
+
+fn suspendDevice(device: *pci.Device) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    // the device forgets its queues, frames it held are lost
+    nic.transport.reset();
+    device.disable(pci.COMMAND_BUS_MASTER);
+    nic.suspended = true;
+}
+
+fn resumeDevice(_: *pci.Device) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    nic.transport.restart();
+    _ = nic.transport.negotiate(FEATURES) catch |err| {
+        log.warn("{s} failed to resume: {s}", .{ net_device.name(), @errorName(err) });
+        nic.transport.fail();
+        return;
+    };
+    nic.transport.restoreQueue(&nic.receive);
+    nic.transport.restoreQueue(&nic.transmit);
+    fillQueues();
+    start();
+    nic.suspended = false;
+}
+
+fn stopOnShutdown() void {
+    // the device forgets its queues, nothing may be written into the
+    // buffers once their memory is reused
+    nic.transport.reset();
+    nic.transport.device.disable(pci.COMMAND_BUS_MASTER);
+}
+
+fn handle(_: *arch.irq.SharedHandler) void {
+    // reading the status acknowledges it, nothing set means the interrupt
+    // came from another device on the line
+    const status = nic.transport.interruptStatus();
+    if (!status.queue and !status.config) {
+        return;
+    }
+    service(status);
+}
+
+fn poll(_: *net.NetDevice) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    service(nic.transport.interruptStatus());
+}
+
+fn service(status: virtio.InterruptStatus) void {
+    if (nic.suspended) {
+        return;
+    }
+    if (status.config) {
+        log.info("{s} link is {s}", .{ net_device.name(), if (net_device.isLinkUp()) "up" else "down" });
+    }
+
+    var received = false;
+    while (nic.receive.takeUsed()) |element| {
+        const index: u16 = @intCast(element.id);
+        const buffer = nic.receive_buffers.bytes()[@as(usize, index) * BUFFER_SIZE ..][0..BUFFER_SIZE];
+        if (element.length > HEADER_SIZE and element.length <= BUFFER_SIZE) {
+            net_device.receive(buffer[HEADER_SIZE..element.length]);
+        } else {
+            net_device.receiveError();
+        }
+        nic.receive.offer(index);
+        received = true;
+    }
+    if (received) {
+        nic.receive.kick();
+    }
+
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+    reclaimTransmitted();
+}
+
+/// Frees the transmit buffers the device is done with. The transmit lock
+/// must be held.
+fn reclaimTransmitted() void {
+    while (nic.transmit.takeUsed()) |element| {
+        nic.transmit.freeDescriptor(@intCast(element.id));
+    }
+}
+
+fn transmit(_: *net.NetDevice, frame: *const memory.SgList) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    nic.transmit_lock.acquire();
+    defer nic.transmit_lock.release();
+
+    if (nic.suspended) {
+        return error.Timeout;
+    }
+    reclaimTransmitted();
+    // every buffer is still in flight
+    const index = nic.transmit.allocDescriptor() orelse return error.OutOfMemory;
+
+    const buffer = nic.transmit_buffers.bytes()[@as(usize, index) * BUFFER_SIZE ..][0..BUFFER_SIZE];
+    @memset(buffer[0..HEADER_SIZE], 0);
+    const length = frame.copyTo(0, buffer[HEADER_SIZE..]);
+
+    const descriptor = nic.transmit.descriptor(index);
+    descriptor.length = @intCast(HEADER_SIZE + length);
+    descriptor.flags = 0;
+
+    nic.transmit.offer(index);
+    nic.transmit.kick();
+}
+
+fn isLinkUp(_: *net.NetDevice) bool {
+    if (!nic.transport.hasFeature(FEATURE_STATUS)) {
+        return true;
+    }
+    return nic.transport.readConfig(u16, CONFIG_STATUS) & CONFIG_LINK_UP != 0;
+}
@@ -0,0 +1,23 @@
+/// The errors kernel subsystems report to their callers. Initialization code
+/// returns these instead of panicking so that the caller can decide whether
+/// to propagate the failure or to log it and carry on without the subsystem.
+pub const Error = error{
+    /// Not enough physical memory, heap or static storage.
+    OutOfMemory,
+    /// The hardware or firmware lacks a required feature.
+    Unsupported,
+    /// A firmware table, device or resource could not be found.
+    NotFound,
+    /// A firmware table or on-disk structure failed validation.
+    Corrupted,
+    /// The caller passed a value outside the accepted range.
+    InvalidArgument,
+    /// A device did not respond in time.
+    Timeout,
+    /// Something with that name or at that place is already there.
+    AlreadyExists,
+    /// A virtual address to be mapped already has a translation.
+    AlreadyMapped,
+    /// The other end of a connection refused or reset it.
+    ConnectionReset,
+};
@@ -0,0 +1,235 @@
+const std = @import("std");
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+
+// NOTE:
+// Filesystems read and write whole blocks through a `BlockCache` instead of
+// going to the driver every time. Cached blocks are keyed by device and LBA
+// and kept on a list from most to least recently used; once the cache is
+// full the least recently used block makes room, after being written back
+// if it is dirty. Writes only reach the device on eviction or `sync`, so
+// anything that must survive a power cut has to be synced. Device I/O
+// happens with the cache's lock held, which is fine while drivers poll and
+// there is a single CPU. Drivers get each transfer as an `SgList`, so a
+// block can go straight to its device in pieces; the cache hands over one
+// piece per block.
+
+pub const BlockDevice = struct {
+    name: []const u8,
+    block_size: usize = 512,
+    block_count: u64,
+    /// Fills `buffer` from the blocks starting at `lba`, its length is a
+    /// multiple of the block size.
+    read: *const fn (device: *BlockDevice, lba: u64, buffer: *const memory.SgList) Error!void,
+    /// Writes `data` to the blocks starting at `lba`, its length is a
+    /// multiple of the block size.
+    write: *const fn (device: *BlockDevice, lba: u64, data: *const memory.SgList) Error!void,
+};
+
+const Key = struct {
+    device: *BlockDevice,
+    lba: u64,
+};
+
+const Entry = struct {
+    key: Key,
+    data: []u8,
+    dirty: bool = false,
+    /// Towards the most recently used end of the list.
+    newer: ?*Entry = null,
+    older: ?*Entry = null,
+};
+
+pub const Stats = struct {
+    hits: usize = 0,
+    misses: usize = 0,
+    write_backs: usize = 0,
+    cached_blocks: usize = 0,
+    dirty_blocks: usize = 0,
+};
+
+pub const BlockCache = struct {
+    allocator: std.mem.Allocator,
+    /// Most blocks kept at once.
+    capacity: usize,
+    entries: std.AutoHashMapUnmanaged(Key, *Entry) = .{},
+    newest: ?*Entry = null,
+    oldest: ?*Entry = null,
+    stats: Stats = .{},
+    lock: SpinLock = SpinLock.init(),
+
+    const Self = @This();
+
+    pub fn init(allocator: std.mem.Allocator, capacity: usize) Self {
+        std.debug.assert(capacity > 0);
+        return .{ .allocator = allocator, .capacity = capacity };
+    }
+
+    /// Frees every block, dirty ones are lost unless synced first.
+    pub fn deinit(self: *Self) void {
+        while (self.oldest) |entry| {
+            self.drop(entry);
+        }
+        self.entries.deinit(self.allocator);
+    }
+
+    /// Reads block `lba` of `device` into `buffer`, which holds exactly one
+    /// block.
+    pub fn read(self: *Self, device: *BlockDevice, lba: u64, buffer: []u8) Error!void {
+        try checkAccess(device, lba, buffer.len);
+
+        self.lock.acquire();
+        defer self.lock.release();
+
+        const entry = try self.lookup(device, lba, true);
+        @memcpy(buffer, entry.data);
+    }
+
+    /// Replaces block `lba` of `device` with `bytes`, which hold exactly
+    /// one block. The device sees the write on eviction or `sync`.
+    pub fn write(self: *Self, device: *BlockDevice, lba: u64, bytes: []const u8) Error!void {
+        try checkAccess(device, lba, bytes.len);
+
+        self.lock.acquire();
+        defer self.lock.release();
+
+        // the whole block is overwritten, no point in reading it first
+        const entry = try self.lookup(device, lba, false);
+        @memcpy(entry.data, bytes);
+        entry.dirty = true;
+    }
+
+    /// Writes every dirty block back. Stops at the first failure, the
+    /// blocks not written yet stay dirty.
+    pub fn sync(self: *Self) Error!void {
+        self.lock.acquire();
+        defer self.lock.release();
+
+        var entry = self.oldest;
+        while (entry) |current| : (entry = current.newer) {
+            try self.writeBack(current);
+        }
+    }
+
+    /// Writes back and forgets every block of `device`, e.g. before the
+    /// device goes away.
+    pub fn flushDevice(self: *Self, device: *BlockDevice) Error!void {
+        self.lock.acquire();
+        defer self.lock.release();
+
+        var entry = self.oldest;
+        while (entry) |current| {
+            entry = current.newer;
+            if (current.key.device != device) {
+                continue;
+            }
+            try self.writeBack(current);
+            self.drop(current);
+        }
+    }
+
+    pub fn statistics(self: *Self) Stats {
+        self.lock.acquire();
+        defer self.lock.release();
+
+        var result = self.stats;
+        result.cached_blocks = self.entries.count();
+        var entry = self.oldest;
+        while (entry) |current| : (entry = current.newer) {
+            if (current.dirty) {
+                result.dirty_blocks += 1;
+            }
+        }
+        return result;
+    }
+
+    /// The cached block, loaded from the device with `fill` when missing,
+    /// and marked as the most recently used.
+    fn lookup(self: *Self, device: *BlockDevice, lba: u64, fill: bool) Error!*Entry {
+        const key = Key{ .device = device, .lba = lba };
+        if (self.entries.get(key)) |entry| {
+            self.stats.hits += 1;
+            self.unlink(entry);
+            self.pushNewest(entry);
+            return entry;
+        }
+        self.stats.misses += 1;
+
+        if (self.entries.count() >= self.capacity) {
+            try self.evict();
+        }
+
+        const entry = try self.allocator.create(Entry);
+        errdefer self.allocator.destroy(entry);
+        entry.* = .{ .key = key, .data = try self.allocator.alloc(u8, device.block_size) };
+        errdefer self.allocator.free(entry.data);
+
+        if (fill) {
+            var storage: [1]memory.IoVec = undefined;
+            var buffer = memory.SgList.init(&storage);
+            try buffer.append(entry.data);
+            try device.read(device, lba, &buffer);
+        }
+        try self.entries.put(self.allocator, key, entry);
+        self.pushNewest(entry);
+        return entry;
+    }
+
+    fn evict(self: *Self) Error!void {
+        const victim = self.oldest orelse return;
+        try self.writeBack(victim);
+        self.drop(victim);
+    }
+
+    fn writeBack(self: *Self, entry: *Entry) Error!void {
+        if (!entry.dirty) {
+            return;
+        }
+        var storage: [1]memory.IoVec = undefined;
+        var data = memory.SgList.init(&storage);
+        try data.append(entry.data);
+        try entry.key.device.write(entry.key.device, entry.key.lba, &data);
+        entry.dirty = false;
+        self.stats.write_backs += 1;
+    }
+
+    fn drop(self: *Self, entry: *Entry) void {
+        self.unlink(entry);
+        _ = self.entries.remove(entry.key);
+        self.allocator.free(entry.data);
+        self.allocator.destroy(entry);
+    }
+
+    fn pushNewest(self: *Self, entry: *Entry) void {
+        entry.newer = null;
+        entry.older = self.newest;
+        if (self.newest) |newest| {
+            newest.newer = entry;
+        } else {
+            self.oldest = entry;
+        }
+        self.newest = entry;
+    }
+
+    fn unlink(self: *Self, entry: *Entry) void {
+        if (entry.newer) |newer| {
+            newer.older = entry.older;
+        } else {
+            self.newest = entry.older;
+        }
+        if (entry.older) |older| {
+            older.newer = entry.newer;
+        } else {
+            self.oldest = entry.newer;
+        }
+        entry.newer = null;
+        entry.older = null;
+    }
+};
+
+fn checkAccess(device: *const BlockDevice, lba: u64, length: usize) Error!void {
+    if (lba >= device.block_count or length != device.block_size) {
+        return error.InvalidArgument;
+    }
+}
@@ -0,0 +1,127 @@
+const std = @import("std");
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+const testdev = @import("kernel").utils.testdev;
+
+const block_cache = @import("block_cache.zig");
+
+const BLOCK_SIZE = 512;
+const BLOCK_COUNT = 8;
+
+/// A device in memory that counts how often the cache really reaches it.
+const RamDevice = struct {
+    device: block_cache.BlockDevice = .{
+        .name = "ram",
+        .block_size = BLOCK_SIZE,
+        .block_count = BLOCK_COUNT,
+        .read = read,
+        .write = write,
+    },
+    blocks: [BLOCK_COUNT][BLOCK_SIZE]u8 = undefined,
+    reads: usize = 0,
+    writes: usize = 0,
+
+    fn of(device: *block_cache.BlockDevice) *RamDevice {
+        return @fieldParentPtr("device", device);
+    }
+
+    fn read(device: *block_cache.BlockDevice, lba: u64, buffer: *const memory.SgList) Error!void {
+        const self = of(device);
+        self.reads += 1;
+        _ = buffer.copyFrom(0, std.mem.sliceAsBytes(self.blocks[lba..])[0..buffer.totalLength()]);
+    }
+
+    fn write(device: *block_cache.BlockDevice, lba: u64, data: *const memory.SgList) Error!void {
+        const self = of(device);
+        self.writes += 1;
+        _ = data.copyTo(0, std.mem.sliceAsBytes(self.blocks[lba..])[0..data.totalLength()]);
+    }
+};
+
+fn fillBlocks(ram: *RamDevice) void {
+    for (&ram.blocks, 0..) |*block, index| {
+        @memset(block, @intCast(index));
+    }
+}
+
+fn cachedReads() !void {
+    var ram = RamDevice{};
+    fillBlocks(&ram);
+    var cache = block_cache.BlockCache.init(memory.allocator(), 4);
+    defer cache.deinit();
+
+    var buffer: [BLOCK_SIZE]u8 = undefined;
+    for (0..3) |_| {
+        try cache.read(&ram.device, 5, &buffer);
+    }
+    if (ram.reads != 1 or buffer[0] != 5) {
+        return error.NotCached;
+    }
+
+    if (cache.read(&ram.device, BLOCK_COUNT, &buffer)) |_| {
+        return error.ReadPastEnd;
+    } else |err| if (err != error.InvalidArgument) {
+        return err;
+    }
+}
+
+fn writeBack() !void {
+    var ram = RamDevice{};
+    fillBlocks(&ram);
+    var cache = block_cache.BlockCache.init(memory.allocator(), 2);
+    defer cache.deinit();
+
+    const data = [_]u8{0xaa} ** BLOCK_SIZE;
+    try cache.write(&ram.device, 0, &data);
+    if (ram.writes != 0 or ram.reads != 0) {
+        return error.WroteThrough;
+    }
+
+    // touching two other blocks pushes the dirty one out
+    var buffer: [BLOCK_SIZE]u8 = undefined;
+    try cache.read(&ram.device, 1, &buffer);
+    try cache.read(&ram.device, 2, &buffer);
+    if (ram.writes != 1 or ram.blocks[0][0] != 0xaa) {
+        return error.NotWrittenOnEviction;
+    }
+
+    try cache.write(&ram.device, 3, &data);
+    try cache.sync();
+    if (ram.writes != 2 or ram.blocks[3][0] != 0xaa) {
+        return error.NotSynced;
+    }
+    if (cache.statistics().dirty_blocks != 0) {
+        return error.StillDirty;
+    }
+}
+
+/// The least recently used block goes first, not the oldest one loaded.
+fn lruOrder() !void {
+    var ram = RamDevice{};
+    fillBlocks(&ram);
+    var cache = block_cache.BlockCache.init(memory.allocator(), 2);
+    defer cache.deinit();
+
+    var buffer: [BLOCK_SIZE]u8 = undefined;
+    try cache.read(&ram.device, 0, &buffer);
+    try cache.read(&ram.device, 1, &buffer);
+    try cache.read(&ram.device, 0, &buffer);
+    // evicts 1, block 0 was used more recently
+    try cache.read(&ram.device, 2, &buffer);
+
+    const before = ram.reads;
+    try cache.read(&ram.device, 0, &buffer);
+    if (ram.reads != before) {
+        return error.EvictedRecentBlock;
+    }
+    try cache.read(&ram.device, 1, &buffer);
+    if (ram.reads != before + 1) {
+        return error.KeptStaleBlock;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "block_cache.cached_reads", .func = cachedReads },
+    .{ .name = "block_cache.write_back", .func = writeBack },
+    .{ .name = "block_cache.lru_order", .func = lruOrder },
+};
@@ -0,0 +1,176 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const Error = @import("kernel").Error;
+
+const vfs = @import("vfs.zig");
+
+// NOTE:
+// `/dev` is a single flat directory of the device nodes drivers registered,
+// nothing is stored besides the nodes themselves. A driver owns its
+// `DeviceNode` and the callbacks that back it: character devices ignore the
+// offset, block devices treat it as a byte offset into the device. Nodes
+// can be registered before `/dev` is mounted and are never unregistered, so
+// the `vfs.Node`s handed out stay valid. Entries can't be created or
+// removed through the filesystem.
+
+pub const DeviceNode = struct {
+    /// Name under `/dev`, e.g. "ttyS0".
+    name: []const u8,
+    kind: enum { character, block },
+    /// Passed back to the callbacks, e.g. which of several displays.
+    index: usize = 0,
+    read: ?*const fn (node: *const DeviceNode, offset: usize, buffer: []u8) Error!usize = null,
+    write: ?*const fn (node: *const DeviceNode, offset: usize, bytes: []const u8) Error!usize = null,
+    /// Size in bytes of a block device.
+    size: ?*const fn (node: *const DeviceNode) usize = null,
+    next: ?*DeviceNode = null,
+
+    fn vfsKind(self: *const DeviceNode) vfs.Kind {
+        return switch (self.kind) {
+            .character => .character_device,
+            .block => .block_device,
+        };
+    }
+};
+
+var first: ?*DeviceNode = null;
+var last: ?*DeviceNode = null;
+var count: usize = 0;
+
+/// Adds `node` under `/dev`, fails if the name is taken.
+pub fn register(node: *DeviceNode) Error!void {
+    if (!vfs.isValidName(node.name)) {
+        return error.InvalidArgument;
+    }
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    if (find(node.name) != null) {
+        return error.AlreadyExists;
+    }
+
+    node.next = null;
+    if (last) |tail| {
+        tail.next = node;
+    } else {
+        first = node;
+    }
+    last = node;
+    count += 1;
+}
+
+fn find(name: []const u8) ?*DeviceNode {
+    var node = first;
+    while (node) |current| : (node = current.next) {
+        if (std.mem.eql(u8, current.name, name)) {
+            return current;
+        }
+    }
+    return null;
+}
+
+/// The directory to mount at `/dev`.
+pub fn root() vfs.Node {
+    // the root has no state of its own, any pointer will do
+    return .{ .ptr = &first, .vtable = &root_vtable };
+}
+
+fn unsupportedCreate(_: *anyopaque, _: []const u8, _: vfs.Kind) Error!vfs.Node {
+    return error.Unsupported;
+}
+
+fn unsupportedRemove(_: *anyopaque, _: []const u8) Error!void {
+    return error.Unsupported;
+}
+
+fn notAFile(_: *anyopaque, _: usize, _: []u8) Error!usize {
+    return error.InvalidArgument;
+}
+
+fn notAFileWrite(_: *anyopaque, _: usize, _: []const u8) Error!usize {
+    return error.InvalidArgument;
+}
+
+fn notAFileTruncate(_: *anyopaque, _: usize) Error!void {
+    return error.InvalidArgument;
+}
+
+fn notADirectory(_: *anyopaque, _: []const u8) Error!vfs.Node {
+    return error.InvalidArgument;
+}
+
+fn notADirectoryRead(_: *anyopaque, _: usize) Error!?vfs.DirEntry {
+    return error.InvalidArgument;
+}
+
+const root_vtable = vfs.Node.VTable{
+    .stat = rootStat,
+    .lookup = rootLookup,
+    .create = unsupportedCreate,
+    .remove = unsupportedRemove,
+    .read = notAFile,
+    .write = notAFileWrite,
+    .truncate = notAFileTruncate,
+    .readDir = rootReadDir,
+};
+
+fn rootStat(_: *anyopaque) vfs.Stat {
+    return .{ .kind = .directory, .size = count };
+}
+
+fn rootLookup(_: *anyopaque, name: []const u8) Error!vfs.Node {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const node = find(name) orelse return error.NotFound;
+    return .{ .ptr = node, .vtable = &device_vtable };
+}
+
+fn rootReadDir(_: *anyopaque, index: usize) Error!?vfs.DirEntry {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    var node = first;
+    var position: usize = 0;
+    while (node) |current| : (node = current.next) {
+        if (position == index) {
+            return .{ .name = current.name, .kind = current.vfsKind() };
+        }
+        position += 1;
+    }
+    return null;
+}
+
+const device_vtable = vfs.Node.VTable{
+    .stat = deviceStat,
+    .lookup = notADirectory,
+    .create = unsupportedCreate,
+    .remove = unsupportedRemove,
+    .read = deviceRead,
+    .write = deviceWrite,
+    .truncate = notAFileTruncate,
+    .readDir = notADirectoryRead,
+};
+
+fn nodeOf(ptr: *anyopaque) *const DeviceNode {
+    return @ptrCast(@alignCast(ptr));
+}
+
+fn deviceStat(ptr: *anyopaque) vfs.Stat {
+    const node = nodeOf(ptr);
+    const bytes = if (node.size) |measure| measure(node) else 0;
+    return .{ .kind = node.vfsKind(), .size = bytes };
+}
+
+fn deviceRead(ptr: *anyopaque, offset: usize, buffer: []u8) Error!usize {
+    const node = nodeOf(ptr);
+    const read = node.read orelse return error.Unsupported;
+    return read(node, offset, buffer);
+}
+
+fn deviceWrite(ptr: *anyopaque, offset: usize, bytes: []const u8) Error!usize {
+    const node = nodeOf(ptr);
+    const write = node.write orelse return error.Unsupported;
+    return write(node, offset, bytes);
+}
@@ -0,0 +1,124 @@
+const std = @import("std");
+const Error = @import("kernel").Error;
+const testdev = @import("kernel").utils.testdev;
+
+const devfs = @import("devfs.zig");
+const vfs = @import("vfs.zig");
+
+const DISK_SIZE = 16;
+
+// devfs never lets go of a node, so these stay registered after the test
+var disk: [DISK_SIZE]u8 = [_]u8{0} ** DISK_SIZE;
+var disk_node = devfs.DeviceNode{
+    .name = "testdisk",
+    .kind = .block,
+    .read = readDisk,
+    .write = writeDisk,
+    .size = diskSize,
+};
+var sink_node = devfs.DeviceNode{
+    .name = "testsink",
+    .kind = .character,
+    .index = 3,
+    .write = writeSink,
+};
+var sink_bytes: usize = 0;
+
+fn readDisk(_: *const devfs.DeviceNode, offset: usize, buffer: []u8) Error!usize {
+    if (offset >= DISK_SIZE) {
+        return 0;
+    }
+    const count = @min(buffer.len, DISK_SIZE - offset);
+    @memcpy(buffer[0..count], disk[offset..][0..count]);
+    return count;
+}
+
+fn writeDisk(_: *const devfs.DeviceNode, offset: usize, bytes: []const u8) Error!usize {
+    if (offset >= DISK_SIZE) {
+        return error.InvalidArgument;
+    }
+    const count = @min(bytes.len, DISK_SIZE - offset);
+    @memcpy(disk[offset..][0..count], bytes[0..count]);
+    return count;
+}
+
+fn diskSize(_: *const devfs.DeviceNode) usize {
+    return DISK_SIZE;
+}
+
+fn writeSink(node: *const devfs.DeviceNode, _: usize, bytes: []const u8) Error!usize {
+    if (node.index != 3) {
+        return error.InvalidArgument;
+    }
+    sink_bytes += bytes.len;
+    return bytes.len;
+}
+
+fn lookup() !void {
+    try devfs.register(&disk_node);
+    try devfs.register(&sink_node);
+
+    var duplicate = devfs.DeviceNode{ .name = "testdisk", .kind = .character };
+    if (devfs.register(&duplicate)) |_| {
+        return error.RegisteredTwice;
+    } else |err| if (err != error.AlreadyExists) {
+        return err;
+    }
+
+    const disk_file = try vfs.resolve("/dev/testdisk");
+    const disk_stat = disk_file.stat();
+    if (disk_stat.kind != .block_device or disk_stat.size != DISK_SIZE) {
+        return error.WrongDiskStat;
+    }
+    const sink_file = try vfs.resolve("/dev/testsink");
+    const sink_stat = sink_file.stat();
+    if (sink_stat.kind != .character_device or sink_stat.size != 0) {
+        return error.WrongSinkStat;
+    }
+
+    if (vfs.resolve("/dev/testnone")) |_| {
+        return error.FoundMissingNode;
+    } else |err| if (err != error.NotFound) {
+        return err;
+    }
+    if (vfs.create("/dev/testnew", .file)) |_| {
+        return error.CreatedNode;
+    } else |err| if (err != error.Unsupported) {
+        return err;
+    }
+}
+
+fn dispatch() !void {
+    const disk_file = try vfs.resolve("/dev/testdisk");
+
+    // the offset goes to the device untouched
+    if (try disk_file.write(4, "abcd") != 4) {
+        return error.ShortWrite;
+    }
+    if (!std.mem.eql(u8, disk[4..8], "abcd")) {
+        return error.WriteNotDispatched;
+    }
+    var buffer: [8]u8 = undefined;
+    if (try disk_file.read(6, &buffer) != 8 or !std.mem.eql(u8, buffer[0..2], "cd")) {
+        return error.ReadNotDispatched;
+    }
+    if (try disk_file.read(DISK_SIZE, &buffer) != 0) {
+        return error.ReadPastEnd;
+    }
+
+    // a missing callback is unsupported, not a crash
+    const sink_file = try vfs.resolve("/dev/testsink");
+    if (try sink_file.write(0, "hello") != 5 or sink_bytes != 5) {
+        return error.SinkNotDispatched;
+    }
+    if (sink_file.read(0, &buffer)) |_| {
+        return error.ReadWithoutCallback;
+    } else |err| if (err != error.Unsupported) {
+        return err;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "devfs.lookup", .func = lookup },
+    .{ .name = "devfs.dispatch", .func = dispatch },
+};
@@ -0,0 +1,54 @@
+const memory = @import("kernel").memory;
+const shutdown = @import("kernel").power.shutdown;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+pub const vfs = @import("vfs.zig");
+pub const tmpfs = @import("tmpfs.zig");
+pub const tmpfs_tests = @import("tmpfs_tests.zig");
+pub const devfs = @import("devfs.zig");
+pub const devfs_tests = @import("devfs_tests.zig");
+pub const block_cache = @import("block_cache.zig");
+pub const block_cache_tests = @import("block_cache_tests.zig");
+pub const BlockDevice = block_cache.BlockDevice;
+pub const initramfs = @import("initramfs.zig");
+pub const ustar = @import("ustar.zig");
+pub const ustar_tests = @import("ustar_tests.zig");
+
+/// Blocks the cache holds before it starts evicting, 128 KiB of 512 byte
+/// sectors.
+const CACHED_BLOCKS = 256;
+
+/// The cache every block device is read and written through.
+pub var cache: block_cache.BlockCache = undefined;
+
+var tmp: tmpfs.Tmpfs = undefined;
+
+var sync_on_shutdown = shutdown.Hook{
+    .name = "block cache",
+    .run = syncOnShutdown,
+};
+
+/// Mounts the filesystems that need nothing but the heap and sets up the
+/// block cache.
+pub fn init() Error!void {
+    cache = block_cache.BlockCache.init(memory.allocator(), CACHED_BLOCKS);
+    shutdown.register(&sync_on_shutdown);
+
+    try vfs.mount("/dev", devfs.root());
+
+    try tmp.init(memory.allocator());
+    errdefer tmp.deinit();
+    try vfs.mount("/tmp", tmp.root());
+}
+
+/// Writes every dirty cached block to its device.
+pub fn sync() Error!void {
+    try cache.sync();
+}
+
+fn syncOnShutdown() void {
+    sync() catch |err| {
+        log.warn("Failed to write back cached blocks: {s}", .{@errorName(err)});
+    };
+}
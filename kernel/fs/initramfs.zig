@@ -0,0 +1,58 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const ustar = @import("ustar.zig");
+
+// NOTE:
+// The initramfs is a ustar archive `build.zig` packs from the `initramfs`
+// directory, loaded by Limine as the module whose command line is
+// `initramfs` (see `limine.cfg`). It sits in memory the memory map marks as
+// kernel and modules, which the PMM never hands out, so the slice stays
+// valid for as long as the kernel runs and is never copied. Its contents
+// are read with `ustar.zig`.
+
+const NAME = "initramfs";
+
+var image: ?[]const u8 = null;
+
+fn isInitramfs(module: anytype) bool {
+    if (std.mem.eql(u8, std.mem.sliceTo(module.cmdline, 0), NAME)) {
+        return true;
+    }
+    // fall back to the file name for configs that don't set a command line
+    const path = std.mem.sliceTo(module.path, 0);
+    const name = path[(std.mem.lastIndexOfScalar(u8, path, '/') orelse 0)..];
+    return std.mem.indexOf(u8, name, NAME) != null;
+}
+
+pub fn init() Error!void {
+    const response = boot.module_request.response orelse return error.NotFound;
+    for (response.modules()) |module| {
+        if (!isInitramfs(module)) {
+            continue;
+        }
+
+        image = module.address[0..module.size];
+        log.info("Initramfs at 0x{x} ({} KiB)", .{ @intFromPtr(module.address), module.size / 1024 });
+        return;
+    }
+    return error.NotFound;
+}
+
+/// The raw archive, null when there is no initramfs.
+pub fn bytes() ?[]const u8 {
+    return image;
+}
+
+/// The initramfs as an archive to look files up in.
+pub fn archive() ?ustar.Archive {
+    return ustar.Archive.init(image orelse return null);
+}
+
+/// The contents of the file at `path` inside the initramfs.
+pub fn readFile(path: []const u8) Error![]const u8 {
+    const files = archive() orelse return error.NotFound;
+    return files.readFile(path);
+}
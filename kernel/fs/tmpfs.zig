@@ -0,0 +1,232 @@
+const std = @import("std");
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+
+const vfs = @import("vfs.zig");
+
+// NOTE:
+// Everything lives on the heap: an inode per entry, a directory keeps its
+// children in an array in creation order and a file its contents in one
+// growable buffer. One lock covers the whole filesystem, the operations are
+// short and none of them sleeps. Removing an entry frees its inode right
+// away, so a `vfs.Node` for it must not be used afterwards.
+
+const Inode = struct {
+    filesystem: *Tmpfs,
+    kind: vfs.Kind,
+    name: []u8,
+    /// Contents of a file.
+    data: std.ArrayListUnmanaged(u8) = .{},
+    /// Entries of a directory.
+    children: std.ArrayListUnmanaged(*Inode) = .{},
+
+    fn node(self: *Inode) vfs.Node {
+        return .{ .ptr = self, .vtable = &vtable };
+    }
+
+    fn find(self: *Inode, name: []const u8) ?usize {
+        for (self.children.items, 0..) |child, index| {
+            if (std.mem.eql(u8, child.name, name)) {
+                return index;
+            }
+        }
+        return null;
+    }
+};
+
+const vtable = vfs.Node.VTable{
+    .stat = stat,
+    .lookup = lookup,
+    .create = create,
+    .remove = remove,
+    .read = read,
+    .write = write,
+    .truncate = truncate,
+    .readDir = readDir,
+};
+
+pub const Tmpfs = struct {
+    allocator: std.mem.Allocator,
+    root_inode: *Inode,
+    lock: SpinLock = SpinLock.init(),
+
+    const Self = @This();
+
+    /// Sets up an empty filesystem in place, `self` must not move after.
+    pub fn init(self: *Self, allocator: std.mem.Allocator) Error!void {
+        self.* = .{ .allocator = allocator, .root_inode = undefined };
+        self.root_inode = try self.newInode("", .directory);
+    }
+
+    /// Frees every entry, nodes handed out become invalid.
+    pub fn deinit(self: *Self) void {
+        self.freeInode(self.root_inode);
+    }
+
+    pub fn root(self: *Self) vfs.Node {
+        return self.root_inode.node();
+    }
+
+    fn newInode(self: *Self, name: []const u8, kind: vfs.Kind) Error!*Inode {
+        const inode = try self.allocator.create(Inode);
+        errdefer self.allocator.destroy(inode);
+        inode.* = .{
+            .filesystem = self,
+            .kind = kind,
+            .name = try self.allocator.dupe(u8, name),
+        };
+        return inode;
+    }
+
+    fn freeInode(self: *Self, inode: *Inode) void {
+        for (inode.children.items) |child| {
+            self.freeInode(child);
+        }
+        inode.children.deinit(self.allocator);
+        inode.data.deinit(self.allocator);
+        self.allocator.free(inode.name);
+        self.allocator.destroy(inode);
+    }
+};
+
+fn inodeOf(ptr: *anyopaque) *Inode {
+    return @ptrCast(@alignCast(ptr));
+}
+
+fn stat(ptr: *anyopaque) vfs.Stat {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    return .{
+        .kind = inode.kind,
+        .size = if (inode.kind == .directory) inode.children.items.len else inode.data.items.len,
+    };
+}
+
+fn lookup(ptr: *anyopaque, name: []const u8) Error!vfs.Node {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    if (inode.kind != .directory) {
+        return error.InvalidArgument;
+    }
+    const index = inode.find(name) orelse return error.NotFound;
+    return inode.children.items[index].node();
+}
+
+fn create(ptr: *anyopaque, name: []const u8, kind: vfs.Kind) Error!vfs.Node {
+    const inode = inodeOf(ptr);
+    const filesystem = inode.filesystem;
+    filesystem.lock.acquire();
+    defer filesystem.lock.release();
+
+    if (inode.kind != .directory) {
+        return error.InvalidArgument;
+    }
+    // device nodes only exist in devfs
+    if (kind != .file and kind != .directory) {
+        return error.Unsupported;
+    }
+    if (inode.find(name) != null) {
+        return error.AlreadyExists;
+    }
+
+    const child = try filesystem.newInode(name, kind);
+    inode.children.append(filesystem.allocator, child) catch |err| {
+        filesystem.freeInode(child);
+        return err;
+    };
+    return child.node();
+}
+
+fn remove(ptr: *anyopaque, name: []const u8) Error!void {
+    const inode = inodeOf(ptr);
+    const filesystem = inode.filesystem;
+    filesystem.lock.acquire();
+    defer filesystem.lock.release();
+
+    if (inode.kind != .directory) {
+        return error.InvalidArgument;
+    }
+    const index = inode.find(name) orelse return error.NotFound;
+    const child = inode.children.items[index];
+    if (child.children.items.len != 0) {
+        return error.InvalidArgument;
+    }
+
+    _ = inode.children.orderedRemove(index);
+    filesystem.freeInode(child);
+}
+
+fn read(ptr: *anyopaque, offset: usize, buffer: []u8) Error!usize {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    if (inode.kind != .file) {
+        return error.InvalidArgument;
+    }
+    const data = inode.data.items;
+    if (offset >= data.len) {
+        return 0;
+    }
+
+    const count = @min(buffer.len, data.len - offset);
+    @memcpy(buffer[0..count], data[offset..][0..count]);
+    return count;
+}
+
+/// Grows or shrinks a file, new bytes are zeroed. The lock must be held.
+fn resizeLocked(inode: *Inode, length: usize) Error!void {
+    const old_length = inode.data.items.len;
+    try inode.data.resize(inode.filesystem.allocator, length);
+    if (length > old_length) {
+        @memset(inode.data.items[old_length..], 0);
+    }
+}
+
+fn write(ptr: *anyopaque, offset: usize, bytes: []const u8) Error!usize {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    if (inode.kind != .file) {
+        return error.InvalidArgument;
+    }
+    const end = std.math.add(usize, offset, bytes.len) catch return error.InvalidArgument;
+    if (end > inode.data.items.len) {
+        try resizeLocked(inode, end);
+    }
+
+    @memcpy(inode.data.items[offset..end], bytes);
+    return bytes.len;
+}
+
+fn truncate(ptr: *anyopaque, length: usize) Error!void {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    if (inode.kind != .file) {
+        return error.InvalidArgument;
+    }
+    try resizeLocked(inode, length);
+}
+
+fn readDir(ptr: *anyopaque, index: usize) Error!?vfs.DirEntry {
+    const inode = inodeOf(ptr);
+    inode.filesystem.lock.acquire();
+    defer inode.filesystem.lock.release();
+
+    if (inode.kind != .directory) {
+        return error.InvalidArgument;
+    }
+    if (index >= inode.children.items.len) {
+        return null;
+    }
+
+    const child = inode.children.items[index];
+    return .{ .name = child.name, .kind = child.kind };
+}
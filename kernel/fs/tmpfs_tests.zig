@@ -0,0 +1,114 @@
+const std = @import("std");
+const memory = @import("kernel").memory;
+const testdev = @import("kernel").utils.testdev;
+
+const tmpfs = @import("tmpfs.zig");
+const vfs = @import("vfs.zig");
+
+fn expectContents(file: vfs.Node, expected: []const u8) !void {
+    var buffer: [64]u8 = undefined;
+    const count = try file.read(0, &buffer);
+    if (!std.mem.eql(u8, buffer[0..count], expected)) {
+        return error.WrongContents;
+    }
+}
+
+fn files() !void {
+    var filesystem: tmpfs.Tmpfs = undefined;
+    try filesystem.init(memory.allocator());
+    defer filesystem.deinit();
+
+    const file = try filesystem.root().create("notes", .file);
+    _ = try file.write(0, "hello");
+    _ = try file.append(" world");
+    try expectContents(file, "hello world");
+
+    // overwriting in the middle keeps the rest
+    _ = try file.write(0, "HELLO");
+    try expectContents(file, "HELLO world");
+
+    try file.truncate(5);
+    try expectContents(file, "HELLO");
+
+    // growing fills with zeros, and so does a write past the end
+    try file.truncate(7);
+    _ = try file.write(9, "!");
+    try expectContents(file, "HELLO\x00\x00\x00\x00!");
+
+    var buffer: [4]u8 = undefined;
+    if (try file.read(10, &buffer) != 0) {
+        return error.ReadPastEnd;
+    }
+
+    if (filesystem.root().create("notes", .file)) |_| {
+        return error.CreatedTwice;
+    } else |err| if (err != error.AlreadyExists) {
+        return err;
+    }
+}
+
+fn directories() !void {
+    var filesystem: tmpfs.Tmpfs = undefined;
+    try filesystem.init(memory.allocator());
+    defer filesystem.deinit();
+
+    const root = filesystem.root();
+    const etc = try root.create("etc", .directory);
+    _ = try etc.create("hostname", .file);
+    _ = try root.create("var", .directory);
+
+    if (root.stat().size != 2) {
+        return error.WrongEntryCount;
+    }
+    const first = try root.readDir(0) orelse return error.MissingEntry;
+    const second = try root.readDir(1) orelse return error.MissingEntry;
+    if (!std.mem.eql(u8, first.name, "etc") or !std.mem.eql(u8, second.name, "var") or second.kind != .directory) {
+        return error.WrongEntries;
+    }
+    if (try root.readDir(2) != null) {
+        return error.ExtraEntry;
+    }
+
+    // a directory has to be empty before it goes
+    if (root.remove("etc")) |_| {
+        return error.RemovedNonEmpty;
+    } else |err| if (err != error.InvalidArgument) {
+        return err;
+    }
+    try etc.remove("hostname");
+    try root.remove("etc");
+
+    if (root.lookup("etc")) |_| {
+        return error.StillThere;
+    } else |err| if (err != error.NotFound) {
+        return err;
+    }
+    if (root.create("a/b", .file)) |_| {
+        return error.AcceptedSlash;
+    } else |err| if (err != error.InvalidArgument) {
+        return err;
+    }
+}
+
+fn mountedPaths() !void {
+    // `fs.init` mounts the shared instance before the tests run
+    _ = try vfs.create("/tmp/tmpfs_tests", .directory);
+    defer vfs.remove("/tmp/tmpfs_tests") catch {};
+
+    const file = try vfs.create("/tmp/tmpfs_tests/file", .file);
+    defer vfs.remove("/tmp/tmpfs_tests/file") catch {};
+    _ = try file.write(0, "data");
+
+    try expectContents(try vfs.resolve("/tmp//tmpfs_tests/./file"), "data");
+    if (vfs.resolve("/nowhere")) |_| {
+        return error.ResolvedOutsideMounts;
+    } else |err| if (err != error.NotFound) {
+        return err;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "tmpfs.files", .func = files },
+    .{ .name = "tmpfs.directories", .func = directories },
+    .{ .name = "tmpfs.mounted_paths", .func = mountedPaths },
+};
@@ -0,0 +1,205 @@
+const std = @import("std");
+const kassert = @import("kernel").utils.kassert;
+const Error = @import("kernel").Error;
+
+// NOTE:
+// A ustar archive is a sequence of 512 byte headers, each followed by the
+// file's data padded to a multiple of 512 bytes, and ends with two zeroed
+// blocks. Numbers are octal ASCII. Paths longer than 100 bytes are split
+// into `prefix` and `name`. Entries are read in place, nothing is copied,
+// so the returned slices point into the archive and live as long as it.
+// Leading "./" and "/" are ignored on both sides of a lookup, that is how
+// `tar -C dir .` names everything.
+
+pub const BLOCK_SIZE = 512;
+
+const Header = extern struct {
+    name: [100]u8,
+    mode: [8]u8,
+    uid: [8]u8,
+    gid: [8]u8,
+    size: [12]u8,
+    mtime: [12]u8,
+    checksum: [8]u8,
+    typeflag: u8,
+    linkname: [100]u8,
+    magic: [6]u8,
+    version: [2]u8,
+    uname: [32]u8,
+    gname: [32]u8,
+    devmajor: [8]u8,
+    devminor: [8]u8,
+    prefix: [155]u8,
+    __padding: [12]u8,
+};
+
+comptime {
+    kassert.comptimeAssert(@sizeOf(Header) == BLOCK_SIZE, "Header is {} bytes, not one block", .{@sizeOf(Header)});
+}
+
+pub const Kind = enum {
+    file,
+    directory,
+    symlink,
+    /// Hard links, devices, FIFOs and extensions, listed but not read.
+    other,
+};
+
+pub const Entry = struct {
+    /// The part of the path before `name`, usually empty.
+    prefix: []const u8,
+    name: []const u8,
+    kind: Kind,
+    mode: u32,
+    /// The file's contents, empty for everything else.
+    data: []const u8,
+    /// Target of a symlink.
+    link: []const u8,
+
+    const Self = @This();
+
+    /// Whether the entry is called `path`, ignoring leading "./" and "/"
+    /// and a directory's trailing "/".
+    pub fn matches(self: *const Self, path: []const u8) bool {
+        const wanted = normalize(path);
+        const prefix = normalize(self.prefix);
+        const name = if (prefix.len == 0) normalize(self.name) else std.mem.trimRight(u8, self.name, "/");
+
+        if (prefix.len == 0) {
+            return std.mem.eql(u8, name, wanted);
+        }
+        return wanted.len == prefix.len + 1 + name.len and
+            std.mem.startsWith(u8, wanted, prefix) and
+            wanted[prefix.len] == '/' and
+            std.mem.endsWith(u8, wanted, name);
+    }
+
+    /// Writes the full path of the entry.
+    pub fn format(self: Self, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+        const prefix = normalize(self.prefix);
+        if (prefix.len != 0) {
+            try writer.print("{s}/{s}", .{ prefix, self.name });
+        } else {
+            try writer.writeAll(normalize(self.name));
+        }
+    }
+};
+
+fn normalize(path: []const u8) []const u8 {
+    var result = path;
+    while (true) {
+        if (std.mem.startsWith(u8, result, "./")) {
+            result = result[2..];
+        } else if (std.mem.startsWith(u8, result, "/")) {
+            result = result[1..];
+        } else {
+            return std.mem.trimRight(u8, result, "/");
+        }
+    }
+}
+
+fn field(bytes: []const u8) []const u8 {
+    return std.mem.sliceTo(bytes, 0);
+}
+
+fn octal(bytes: []const u8) Error!u64 {
+    const text = std.mem.trim(u8, field(bytes), " ");
+    if (text.len == 0) {
+        return 0;
+    }
+    return std.fmt.parseInt(u64, text, 8) catch error.Corrupted;
+}
+
+fn checksumMatches(block: *const [BLOCK_SIZE]u8, header: *align(1) const Header) Error!bool {
+    // the checksum field itself counts as spaces
+    var sum: u64 = 8 * ' ';
+    for (block, 0..) |byte, index| {
+        if (index < @offsetOf(Header, "checksum") or index >= @offsetOf(Header, "typeflag")) {
+            sum += byte;
+        }
+    }
+    return sum == try octal(&header.checksum);
+}
+
+pub const Iterator = struct {
+    bytes: []const u8,
+    offset: usize = 0,
+
+    /// The next entry, null at the end of the archive. Fails on a header
+    /// that doesn't check out or data running past the end.
+    pub fn next(self: *Iterator) Error!?Entry {
+        if (self.offset + BLOCK_SIZE > self.bytes.len) {
+            return null;
+        }
+
+        const block = self.bytes[self.offset..][0..BLOCK_SIZE];
+        if (std.mem.allEqual(u8, block, 0)) {
+            return null;
+        }
+
+        const header: *align(1) const Header = @ptrCast(block);
+        if (!std.mem.startsWith(u8, &header.magic, "ustar")) {
+            return error.Corrupted;
+        }
+        if (!try checksumMatches(block, header)) {
+            return error.Corrupted;
+        }
+
+        const size = try octal(&header.size);
+        const data_start = self.offset + BLOCK_SIZE;
+        if (size > self.bytes.len - data_start) {
+            return error.Corrupted;
+        }
+        self.offset = data_start + std.mem.alignForward(usize, size, BLOCK_SIZE);
+
+        const kind: Kind = switch (header.typeflag) {
+            0, '0', '7' => .file,
+            '5' => .directory,
+            '2' => .symlink,
+            else => .other,
+        };
+
+        return .{
+            .prefix = field(&header.prefix),
+            .name = field(&header.name),
+            .kind = kind,
+            .mode = @truncate(try octal(&header.mode)),
+            .data = if (kind == .file) self.bytes[data_start..][0..size] else "",
+            .link = field(&header.linkname),
+        };
+    }
+};
+
+pub const Archive = struct {
+    bytes: []const u8,
+
+    const Self = @This();
+
+    pub fn init(bytes: []const u8) Self {
+        return .{ .bytes = bytes };
+    }
+
+    pub fn iterator(self: Self) Iterator {
+        return .{ .bytes = self.bytes };
+    }
+
+    /// Looks up the entry called `path`.
+    pub fn find(self: Self, path: []const u8) Error!?Entry {
+        var entries = self.iterator();
+        while (try entries.next()) |entry| {
+            if (entry.matches(path)) {
+                return entry;
+            }
+        }
+        return null;
+    }
+
+    /// The contents of the regular file at `path`.
+    pub fn readFile(self: Self, path: []const u8) Error![]const u8 {
+        const entry = try self.find(path) orelse return error.NotFound;
+        if (entry.kind != .file) {
+            return error.InvalidArgument;
+        }
+        return entry.data;
+    }
+};
@@ -0,0 +1,91 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const ustar = @import("ustar.zig");
+
+const BLOCK_SIZE = ustar.BLOCK_SIZE;
+
+/// Writes a header for `name` at the start of `block` the way tar does.
+fn writeHeader(block: *[BLOCK_SIZE]u8, prefix: []const u8, name: []const u8, typeflag: u8, size: usize) void {
+    @memset(block, 0);
+    @memcpy(block[0..name.len], name);
+    _ = std.fmt.bufPrint(block[100..108], "{o:0>7}", .{0o644}) catch unreachable;
+    _ = std.fmt.bufPrint(block[124..136], "{o:0>11}", .{size}) catch unreachable;
+    block[156] = typeflag;
+    @memcpy(block[257..263], "ustar\x00");
+    @memcpy(block[263..265], "00");
+    @memcpy(block[345..][0..prefix.len], prefix);
+
+    @memset(block[148..156], ' ');
+    var sum: u32 = 0;
+    for (block) |byte| {
+        sum += byte;
+    }
+    _ = std.fmt.bufPrint(block[148..156], "{o:0>6}\x00", .{sum}) catch unreachable;
+}
+
+fn buildArchive(buffer: *[6 * BLOCK_SIZE]u8) []const u8 {
+    @memset(buffer, 0);
+    writeHeader(buffer[0..BLOCK_SIZE], "", "./etc/", '5', 0);
+    writeHeader(buffer[BLOCK_SIZE..][0..BLOCK_SIZE], "", "./etc/hostname", '0', 7);
+    @memcpy(buffer[2 * BLOCK_SIZE ..][0..7], "reason\n");
+    writeHeader(buffer[3 * BLOCK_SIZE ..][0..BLOCK_SIZE], "usr/share", "motd", '0', 3);
+    @memcpy(buffer[4 * BLOCK_SIZE ..][0..3], "hi\n");
+    // the last block stays zeroed and ends the archive
+    return buffer;
+}
+
+fn iterateEntries() !void {
+    var buffer: [6 * BLOCK_SIZE]u8 = undefined;
+    const archive = ustar.Archive.init(buildArchive(&buffer));
+
+    var count: usize = 0;
+    var entries = archive.iterator();
+    while (try entries.next()) |_| {
+        count += 1;
+    }
+    if (count != 3) {
+        return error.WrongCount;
+    }
+}
+
+fn findFiles() !void {
+    var buffer: [6 * BLOCK_SIZE]u8 = undefined;
+    const archive = ustar.Archive.init(buildArchive(&buffer));
+
+    if (!std.mem.eql(u8, try archive.readFile("/etc/hostname"), "reason\n")) {
+        return error.Mismatch;
+    }
+    if (!std.mem.eql(u8, try archive.readFile("usr/share/motd"), "hi\n")) {
+        return error.Mismatch;
+    }
+
+    const directory = try archive.find("etc") orelse return error.Missing;
+    if (directory.kind != .directory) {
+        return error.WrongKind;
+    }
+    if (archive.readFile("etc")) |_| {
+        return error.ReadDirectory;
+    } else |_| {}
+    if (try archive.find("etc/missing") != null) {
+        return error.Phantom;
+    }
+}
+
+fn rejectCorruption() !void {
+    var buffer: [6 * BLOCK_SIZE]u8 = undefined;
+    _ = buildArchive(&buffer);
+    buffer[BLOCK_SIZE + 4] ^= 0xff;
+
+    var entries = ustar.Archive.init(&buffer).iterator();
+    _ = try entries.next();
+    if (entries.next()) |_| {
+        return error.Accepted;
+    } else |_| {}
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "ustar.iterate_entries", .func = iterateEntries },
+    .{ .name = "ustar.find_files", .func = findFiles },
+    .{ .name = "ustar.reject_corruption", .func = rejectCorruption },
+};
@@ -0,0 +1,216 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log.scoped(.vfs);
+const Error = @import("kernel").Error;
+
+// NOTE:
+// A filesystem hands out `Node`s, a pointer to its own inode plus a table of
+// operations, the same shape as `std.mem.Allocator`. Nodes are borrowed: they
+// stay valid until the entry is removed or the filesystem goes away, nothing
+// is reference counted yet. Filesystems are mounted at an absolute path and
+// a lookup picks the longest mount point that prefixes the path, then walks
+// the remaining components with `lookup`. There is no root filesystem, paths
+// outside every mount point are not found.
+
+pub const Kind = enum {
+    file,
+    directory,
+    character_device,
+    block_device,
+};
+
+pub const Stat = struct {
+    kind: Kind,
+    /// Bytes in a file or block device, entries in a directory.
+    size: usize,
+};
+
+pub const DirEntry = struct {
+    /// Owned by the filesystem, valid as long as the entry exists.
+    name: []const u8,
+    kind: Kind,
+};
+
+pub const Node = struct {
+    ptr: *anyopaque,
+    vtable: *const VTable,
+
+    const Self = @This();
+
+    /// Operations that make no sense for a node's kind fail with
+    /// `InvalidArgument`, ones a filesystem doesn't do with `Unsupported`.
+    pub const VTable = struct {
+        stat: *const fn (ptr: *anyopaque) Stat,
+        lookup: *const fn (ptr: *anyopaque, name: []const u8) Error!Node,
+        create: *const fn (ptr: *anyopaque, name: []const u8, kind: Kind) Error!Node,
+        remove: *const fn (ptr: *anyopaque, name: []const u8) Error!void,
+        read: *const fn (ptr: *anyopaque, offset: usize, buffer: []u8) Error!usize,
+        write: *const fn (ptr: *anyopaque, offset: usize, bytes: []const u8) Error!usize,
+        truncate: *const fn (ptr: *anyopaque, length: usize) Error!void,
+        readDir: *const fn (ptr: *anyopaque, index: usize) Error!?DirEntry,
+    };
+
+    pub fn stat(self: Self) Stat {
+        return self.vtable.stat(self.ptr);
+    }
+
+    /// The entry called `name` in this directory.
+    pub fn lookup(self: Self, name: []const u8) Error!Node {
+        return self.vtable.lookup(self.ptr, name);
+    }
+
+    /// Adds an empty entry called `name` to this directory.
+    pub fn create(self: Self, name: []const u8, kind: Kind) Error!Node {
+        if (!isValidName(name)) {
+            return error.InvalidArgument;
+        }
+        return self.vtable.create(self.ptr, name, kind);
+    }
+
+    /// Removes the entry called `name`, directories have to be empty.
+    pub fn remove(self: Self, name: []const u8) Error!void {
+        return self.vtable.remove(self.ptr, name);
+    }
+
+    /// Reads from `offset` into `buffer`, returns how much was read, 0 at
+    /// the end of the file.
+    pub fn read(self: Self, offset: usize, buffer: []u8) Error!usize {
+        return self.vtable.read(self.ptr, offset, buffer);
+    }
+
+    /// Writes `bytes` at `offset`, a gap past the end reads back as zeros.
+    pub fn write(self: Self, offset: usize, bytes: []const u8) Error!usize {
+        return self.vtable.write(self.ptr, offset, bytes);
+    }
+
+    pub fn append(self: Self, bytes: []const u8) Error!usize {
+        return self.write(self.stat().size, bytes);
+    }
+
+    /// Cuts the file to `length` bytes or zero-extends it.
+    pub fn truncate(self: Self, length: usize) Error!void {
+        return self.vtable.truncate(self.ptr, length);
+    }
+
+    /// The entry at `index` of this directory, null past the last one.
+    pub fn readDir(self: Self, index: usize) Error!?DirEntry {
+        return self.vtable.readDir(self.ptr, index);
+    }
+};
+
+/// Whether `name` can be a single path component.
+pub fn isValidName(name: []const u8) bool {
+    return name.len != 0 and
+        !std.mem.eql(u8, name, ".") and
+        !std.mem.eql(u8, name, "..") and
+        std.mem.indexOfScalar(u8, name, '/') == null;
+}
+
+const MAX_MOUNTS = 8;
+const MAX_MOUNT_PATH = 32;
+
+const Mount = struct {
+    path_buffer: [MAX_MOUNT_PATH]u8 = undefined,
+    path_length: usize = 0,
+    root: Node,
+
+    fn path(self: *const Mount) []const u8 {
+        return self.path_buffer[0..self.path_length];
+    }
+};
+
+var mounts: [MAX_MOUNTS]Mount = undefined;
+var mount_count: usize = 0;
+
+fn trimPath(path: []const u8) []const u8 {
+    const trimmed = std.mem.trimRight(u8, path, "/");
+    return if (trimmed.len == 0) "/" else trimmed;
+}
+
+/// Makes `root` reachable under the absolute `path`.
+pub fn mount(path: []const u8, root: Node) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const target = trimPath(path);
+    if (target[0] != '/' or target.len > MAX_MOUNT_PATH) {
+        return error.InvalidArgument;
+    }
+    if (mount_count == MAX_MOUNTS) {
+        return error.OutOfMemory;
+    }
+    for (mounts[0..mount_count]) |*existing| {
+        if (std.mem.eql(u8, existing.path(), target)) {
+            return error.AlreadyExists;
+        }
+    }
+
+    var entry = Mount{ .root = root };
+    @memcpy(entry.path_buffer[0..target.len], target);
+    entry.path_length = target.len;
+    mounts[mount_count] = entry;
+    mount_count += 1;
+
+    log.info("Mounted {s}", .{target});
+}
+
+/// The mount point `path` lies under and the rest of the path.
+fn findMount(path: []const u8) Error!struct { Node, []const u8 } {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    var best: ?*const Mount = null;
+    for (mounts[0..mount_count]) |*candidate| {
+        const prefix = candidate.path();
+        const under = std.mem.eql(u8, prefix, "/") or
+            (std.mem.startsWith(u8, path, prefix) and (path.len == prefix.len or path[prefix.len] == '/'));
+        if (under and (best == null or prefix.len > best.?.path_length)) {
+            best = candidate;
+        }
+    }
+
+    const found = best orelse return error.NotFound;
+    const rest = if (std.mem.eql(u8, found.path(), "/")) path else path[found.path_length..];
+    return .{ found.root, rest };
+}
+
+/// The node at the absolute `path`.
+pub fn resolve(path: []const u8) Error!Node {
+    if (path.len == 0 or path[0] != '/') {
+        return error.InvalidArgument;
+    }
+
+    const start, const rest = try findMount(path);
+    var node = start;
+    var components = std.mem.tokenizeScalar(u8, rest, '/');
+    while (components.next()) |component| {
+        if (std.mem.eql(u8, component, ".")) {
+            continue;
+        }
+        // `..` would have to cross mount points, not needed so far
+        if (std.mem.eql(u8, component, "..")) {
+            return error.Unsupported;
+        }
+        node = try node.lookup(component);
+    }
+    return node;
+}
+
+fn splitParent(path: []const u8) Error!struct { []const u8, []const u8 } {
+    const trimmed = trimPath(path);
+    const slash = std.mem.lastIndexOfScalar(u8, trimmed, '/') orelse return error.InvalidArgument;
+    const parent = if (slash == 0) "/" else trimmed[0..slash];
+    return .{ parent, trimmed[slash + 1 ..] };
+}
+
+/// Creates an empty file or directory at `path`, its parent has to exist.
+pub fn create(path: []const u8, kind: Kind) Error!Node {
+    const parent, const name = try splitParent(path);
+    return (try resolve(parent)).create(name, kind);
+}
+
+/// Removes the file or empty directory at `path`.
+pub fn remove(path: []const u8) Error!void {
+    const parent, const name = try splitParent(path);
+    return (try resolve(parent)).remove(name);
+}
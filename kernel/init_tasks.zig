@@ -0,0 +1,190 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log;
+
+// NOTE:
+// Boot is a list of tasks, each naming the tasks it needs. The order is
+// worked out at compile time: a task runs once everything it depends on
+// has, ties going to the one declared first, and an unknown name or a cycle
+// fails the build. A task that fails is logged and its dependents are
+// skipped, the rest of boot carries on without them. Only a critical task
+// failing stops boot. Stages are run one after another, so a later stage
+// may rely on everything in the earlier ones without naming it. Every run
+// is recorded with its outcome and duration for the `inittasks` command.
+
+pub const Task = struct {
+    name: []const u8,
+    depends_on: []const []const u8 = &.{},
+    /// Boot cannot go on without this task.
+    critical: bool = false,
+    /// Logged along with the error when the task fails.
+    failure: []const u8,
+    run: *const fn () anyerror!void,
+};
+
+pub const Options = struct {
+    depends_on: []const []const u8 = &.{},
+    critical: bool = false,
+    failure: ?[]const u8 = null,
+};
+
+/// A task running `func`, which may return nothing, a value that is
+/// ignored or an error union.
+pub fn task(comptime name: []const u8, comptime options: Options, comptime func: anytype) Task {
+    const Wrapper = struct {
+        fn run() anyerror!void {
+            const Return = @typeInfo(@TypeOf(func)).Fn.return_type.?;
+            if (@typeInfo(Return) == .ErrorUnion) {
+                _ = try func();
+            } else {
+                _ = func();
+            }
+        }
+    };
+
+    return .{
+        .name = name,
+        .depends_on = options.depends_on,
+        .critical = options.critical,
+        .failure = options.failure orelse "Failed to initialize " ++ name,
+        .run = Wrapper.run,
+    };
+}
+
+pub const Status = enum {
+    done,
+    failed,
+    /// A dependency failed or was skipped itself.
+    skipped,
+};
+
+pub const Record = struct {
+    name: []const u8,
+    status: Status,
+    err: ?anyerror = null,
+    /// TSC cycles the task took, the clock may not be calibrated yet when
+    /// it runs.
+    cycles: u64 = 0,
+};
+
+const MAX_RECORDS = 64;
+
+var records: [MAX_RECORDS]Record = undefined;
+var record_count: usize = 0;
+
+fn indexOf(comptime tasks: []const Task, comptime name: []const u8) usize {
+    for (tasks, 0..) |candidate, index| {
+        if (std.mem.eql(u8, candidate.name, name)) {
+            return index;
+        }
+    }
+    @compileError("unknown init task " ++ name);
+}
+
+/// The order `tasks` run in, as indices into it.
+fn order(comptime tasks: []const Task) [tasks.len]usize {
+    comptime {
+        @setEvalBranchQuota(100 * tasks.len * tasks.len + 1000);
+
+        var result: [tasks.len]usize = undefined;
+        var placed = [_]bool{false} ** tasks.len;
+
+        for (0..tasks.len) |position| {
+            const next = for (tasks, 0..) |candidate, index| {
+                if (placed[index]) {
+                    continue;
+                }
+                const ready = for (candidate.depends_on) |dependency| {
+                    if (!placed[indexOf(tasks, dependency)]) {
+                        break false;
+                    }
+                } else true;
+                if (ready) {
+                    break index;
+                }
+            } else @compileError("init tasks depend on each other in a cycle");
+
+            placed[next] = true;
+            result[position] = next;
+        }
+        return result;
+    }
+}
+
+fn record(entry: Record) void {
+    if (record_count < MAX_RECORDS) {
+        records[record_count] = entry;
+        record_count += 1;
+    }
+}
+
+/// The first dependency of `current` that did not come up.
+fn blockedBy(comptime tasks: []const Task, comptime current: Task, statuses: []const Status) ?[]const u8 {
+    inline for (current.depends_on) |dependency| {
+        if (statuses[comptime indexOf(tasks, dependency)] != .done) {
+            return dependency;
+        }
+    }
+    return null;
+}
+
+/// Runs a stage of tasks, returns false when a critical one failed.
+pub fn run(comptime tasks: []const Task) bool {
+    const sequence = comptime order(tasks);
+    // dependencies always run first, so nothing is read before it is set
+    var statuses = [_]Status{.skipped} ** tasks.len;
+
+    inline for (sequence) |index| {
+        const current = tasks[index];
+
+        if (blockedBy(tasks, current, &statuses)) |dependency| {
+            log.warn("Skipping {s}, {s} did not come up", .{ current.name, dependency });
+            statuses[index] = .skipped;
+            record(.{ .name = current.name, .status = .skipped });
+        } else {
+            const start = arch.tsc.read();
+            const result = current.run();
+            const cycles = arch.tsc.read() - start;
+
+            if (result) |_| {
+                statuses[index] = .done;
+                record(.{ .name = current.name, .status = .done, .cycles = cycles });
+            } else |err| {
+                statuses[index] = .failed;
+                record(.{ .name = current.name, .status = .failed, .err = err, .cycles = cycles });
+
+                if (current.critical) {
+                    log.write("FATAL: {s}: {s}", .{ current.failure, @errorName(err) });
+                    return false;
+                }
+                log.warn("{s}: {s}", .{ current.failure, @errorName(err) });
+            }
+        }
+    }
+    return true;
+}
+
+/// Every task run so far, in the order they ran.
+pub fn all() []const Record {
+    return records[0..record_count];
+}
+
+/// Writes every task with its outcome and how long it took.
+pub fn dump(writer: anytype) !void {
+    const frequency = time.tscFrequency();
+    for (all()) |entry| {
+        try writer.print("  {s:<16} {s:<8}", .{ entry.name, @tagName(entry.status) });
+        if (entry.status != .skipped) {
+            if (frequency != 0) {
+                try writer.print(" {:>8} us", .{entry.cycles * std.time.us_per_s / frequency});
+            } else {
+                try writer.print(" {:>8} cycles", .{entry.cycles});
+            }
+        }
+        if (entry.err) |err| {
+            try writer.print(" {s}", .{@errorName(err)});
+        }
+        try writer.writeAll("\n");
+    }
+}
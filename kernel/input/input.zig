@@ -0,0 +1,4 @@
+pub const Key = @import("key.zig").Key;
+pub const keymap = @import("keymap.zig");
+pub const keyboard = @import("keyboard.zig");
+pub const KeyEvent = keyboard.KeyEvent;
@@ -0,0 +1,98 @@
+/// A physical key, named after what it is labelled with on a US keyboard.
+/// Keyboard drivers report these, what a key produces is up to the keymap.
+pub const Key = enum(u8) {
+    escape,
+    f1,
+    f2,
+    f3,
+    f4,
+    f5,
+    f6,
+    f7,
+    f8,
+    f9,
+    f10,
+    f11,
+    f12,
+
+    grave,
+    @"1",
+    @"2",
+    @"3",
+    @"4",
+    @"5",
+    @"6",
+    @"7",
+    @"8",
+    @"9",
+    @"0",
+    minus,
+    equal,
+    backspace,
+
+    tab,
+    q,
+    w,
+    e,
+    r,
+    t,
+    y,
+    u,
+    i,
+    o,
+    p,
+    left_bracket,
+    right_bracket,
+    backslash,
+
+    caps_lock,
+    a,
+    s,
+    d,
+    f,
+    g,
+    h,
+    j,
+    k,
+    l,
+    semicolon,
+    apostrophe,
+    enter,
+
+    left_shift,
+    /// The extra key next to left shift on ISO keyboards.
+    intl_backslash,
+    z,
+    x,
+    c,
+    v,
+    b,
+    n,
+    m,
+    comma,
+    period,
+    slash,
+    right_shift,
+
+    left_ctrl,
+    left_alt,
+    space,
+    right_alt,
+    right_ctrl,
+
+    insert,
+    delete,
+    home,
+    end,
+    page_up,
+    page_down,
+    up,
+    down,
+    left,
+    right,
+
+    num_lock,
+    scroll_lock,
+
+    pub const count = @typeInfo(Key).Enum.fields.len;
+};
@@ -0,0 +1,54 @@
+const ps2_keyboard = @import("kernel").drivers.ps2_keyboard;
+
+const Key = @import("key.zig").Key;
+const keymap = @import("keymap.zig");
+
+// NOTE:
+// Turns the raw presses and releases the keyboard driver queues into
+// `KeyEvent`s: the modifier state is tracked here and presses are run
+// through the active keymap and dead-key composition. Consumers, the debug
+// shell's line editor for now, poll `next` until it returns null.
+
+pub const KeyEvent = struct {
+    key: Key,
+    pressed: bool,
+    /// State after this event was applied.
+    modifiers: keymap.Modifiers,
+    /// What the key typed, empty for releases, modifiers, pending dead keys
+    /// and ctrl/alt chords.
+    text: keymap.Output,
+};
+
+var modifiers = keymap.Modifiers{};
+var composer = keymap.Composer{};
+
+fn update(key: Key, pressed: bool) void {
+    switch (key) {
+        .left_shift, .right_shift => modifiers.shift = pressed,
+        .left_ctrl, .right_ctrl => modifiers.ctrl = pressed,
+        .left_alt => modifiers.alt = pressed,
+        .right_alt => modifiers.alt_gr = pressed,
+        .caps_lock => if (pressed) {
+            modifiers.caps_lock = !modifiers.caps_lock;
+        },
+        else => {},
+    }
+}
+
+/// Takes the next key event, null once the queue is drained.
+pub fn next() ?KeyEvent {
+    const raw = ps2_keyboard.next() orelse return null;
+    update(raw.key, raw.pressed);
+
+    var event = KeyEvent{
+        .key = raw.key,
+        .pressed = raw.pressed,
+        .modifiers = modifiers,
+        .text = .{},
+    };
+
+    if (raw.pressed and !modifiers.ctrl and !modifiers.alt) {
+        event.text = composer.feed(keymap.translate(raw.key, modifiers));
+    }
+    return event;
+}
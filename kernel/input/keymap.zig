@@ -0,0 +1,257 @@
+const std = @import("std");
+const cmdline = @import("kernel").cmdline;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+const Key = @import("key.zig").Key;
+
+// NOTE:
+// A layout maps every key to what it produces alone, with shift and with
+// AltGr, as Unicode code points (0 for nothing). Dead keys produce a
+// combining accent (U+0300-U+036F); `Composer` holds on to it and merges it
+// with the next character into the precomposed letter, or emits the accent
+// on its own when there is none.
+
+pub const Entry = struct {
+    normal: u21 = 0,
+    shifted: u21 = 0,
+    alt_gr: u21 = 0,
+};
+
+pub const Layout = struct {
+    name: []const u8,
+    entries: [Key.count]Entry,
+};
+
+pub const Modifiers = struct {
+    shift: bool = false,
+    ctrl: bool = false,
+    alt: bool = false,
+    alt_gr: bool = false,
+    caps_lock: bool = false,
+};
+
+const DEAD_GRAVE = 0x300;
+const DEAD_ACUTE = 0x301;
+const DEAD_CIRCUMFLEX = 0x302;
+
+const Mapping = struct { Key, u21, u21, u21 };
+
+fn build(comptime name: []const u8, comptime mappings: []const Mapping) Layout {
+    @setEvalBranchQuota(10_000);
+
+    var entries = [_]Entry{.{}} ** Key.count;
+    for ("abcdefghijklmnopqrstuvwxyz") |letter| {
+        const key = std.meta.stringToEnum(Key, &[_]u8{letter}).?;
+        entries[@intFromEnum(key)] = .{ .normal = letter, .shifted = std.ascii.toUpper(letter) };
+    }
+
+    entries[@intFromEnum(Key.escape)] = .{ .normal = 0x1b, .shifted = 0x1b };
+    entries[@intFromEnum(Key.backspace)] = .{ .normal = 0x08, .shifted = 0x08 };
+    entries[@intFromEnum(Key.tab)] = .{ .normal = '\t', .shifted = '\t' };
+    entries[@intFromEnum(Key.enter)] = .{ .normal = '\n', .shifted = '\n' };
+    entries[@intFromEnum(Key.space)] = .{ .normal = ' ', .shifted = ' ' };
+
+    for (mappings) |mapping| {
+        entries[@intFromEnum(mapping[0])] = .{ .normal = mapping[1], .shifted = mapping[2], .alt_gr = mapping[3] };
+    }
+
+    return .{ .name = name, .entries = entries };
+}
+
+const us_symbols = [_]Mapping{
+    .{ .grave, '`', '~', 0 },
+    .{ .@"1", '1', '!', 0 },
+    .{ .@"2", '2', '@', 0 },
+    .{ .@"3", '3', '#', 0 },
+    .{ .@"4", '4', '$', 0 },
+    .{ .@"5", '5', '%', 0 },
+    .{ .@"6", '6', '^', 0 },
+    .{ .@"7", '7', '&', 0 },
+    .{ .@"8", '8', '*', 0 },
+    .{ .@"9", '9', '(', 0 },
+    .{ .@"0", '0', ')', 0 },
+    .{ .minus, '-', '_', 0 },
+    .{ .equal, '=', '+', 0 },
+    .{ .left_bracket, '[', '{', 0 },
+    .{ .right_bracket, ']', '}', 0 },
+    .{ .backslash, '\\', '|', 0 },
+    .{ .semicolon, ';', ':', 0 },
+    .{ .apostrophe, '\'', '"', 0 },
+    .{ .intl_backslash, '\\', '|', 0 },
+    .{ .comma, ',', '<', 0 },
+    .{ .period, '.', '>', 0 },
+    .{ .slash, '/', '?', 0 },
+};
+
+pub const us = build("us", &us_symbols);
+
+pub const uk = build("uk", &(us_symbols ++ [_]Mapping{
+    .{ .grave, '`', '¬', '¦' },
+    .{ .@"2", '2', '"', 0 },
+    .{ .@"3", '3', '£', 0 },
+    .{ .@"4", '4', '$', '€' },
+    .{ .apostrophe, '\'', '@', 0 },
+    .{ .backslash, '#', '~', 0 },
+    .{ .intl_backslash, '\\', '|', 0 },
+}));
+
+pub const de = build("de", &[_]Mapping{
+    .{ .grave, DEAD_CIRCUMFLEX, '°', 0 },
+    .{ .@"1", '1', '!', 0 },
+    .{ .@"2", '2', '"', '²' },
+    .{ .@"3", '3', '§', '³' },
+    .{ .@"4", '4', '$', 0 },
+    .{ .@"5", '5', '%', 0 },
+    .{ .@"6", '6', '&', 0 },
+    .{ .@"7", '7', '/', '{' },
+    .{ .@"8", '8', '(', '[' },
+    .{ .@"9", '9', ')', ']' },
+    .{ .@"0", '0', '=', '}' },
+    .{ .minus, 'ß', '?', '\\' },
+    .{ .equal, DEAD_ACUTE, DEAD_GRAVE, 0 },
+    .{ .q, 'q', 'Q', '@' },
+    .{ .e, 'e', 'E', '€' },
+    .{ .y, 'z', 'Z', 0 },
+    .{ .z, 'y', 'Y', 0 },
+    .{ .m, 'm', 'M', 'µ' },
+    .{ .left_bracket, 'ü', 'Ü', 0 },
+    .{ .right_bracket, '+', '*', '~' },
+    .{ .backslash, '#', '\'', 0 },
+    .{ .semicolon, 'ö', 'Ö', 0 },
+    .{ .apostrophe, 'ä', 'Ä', 0 },
+    .{ .intl_backslash, '<', '>', '|' },
+    .{ .comma, ',', ';', 0 },
+    .{ .period, '.', ':', 0 },
+    .{ .slash, '-', '_', 0 },
+});
+
+pub const layouts = [_]*const Layout{ &us, &uk, &de };
+
+var current: *const Layout = &us;
+
+pub fn active() *const Layout {
+    return current;
+}
+
+/// Switches to the layout called `name`, e.g. "de".
+pub fn select(name: []const u8) Error!void {
+    for (layouts) |layout| {
+        if (std.mem.eql(u8, layout.name, name)) {
+            current = layout;
+            return;
+        }
+    }
+    return error.NotFound;
+}
+
+/// Selects the layout named by `keymap=` on the kernel command line.
+pub fn init() void {
+    const name = cmdline.getOr([]const u8, "keymap", "");
+    if (name.len == 0) {
+        return;
+    }
+    select(name) catch {
+        log.warn("Unknown keymap {s}, keeping {s}", .{ name, current.name });
+    };
+}
+
+/// Lowercase letters with an uppercase form in Latin-1. Not ß, which has
+/// none, nor ÿ, whose uppercase is U+0178.
+fn isLowercase(codepoint: u21) bool {
+    return (codepoint >= 'a' and codepoint <= 'z') or
+        (codepoint >= 0xe0 and codepoint <= 0xfe and codepoint != 0xf7);
+}
+
+/// What `key` produces under `modifiers` in the active layout, 0 for
+/// nothing. Dead keys come out as combining accents, see `Composer`.
+pub fn translate(key: Key, modifiers: Modifiers) u21 {
+    const entry = current.entries[@intFromEnum(key)];
+    if (modifiers.alt_gr) {
+        return entry.alt_gr;
+    }
+
+    // caps lock only shifts letters
+    const shift = modifiers.shift != (modifiers.caps_lock and isLowercase(entry.normal) and entry.shifted != entry.normal);
+    return if (shift) entry.shifted else entry.normal;
+}
+
+pub fn isDead(codepoint: u21) bool {
+    return codepoint >= 0x300 and codepoint <= 0x36f;
+}
+
+fn spacing(accent: u21) u21 {
+    return switch (accent) {
+        DEAD_GRAVE => '`',
+        DEAD_ACUTE => '´',
+        DEAD_CIRCUMFLEX => '^',
+        else => accent,
+    };
+}
+
+fn compose(accent: u21, base: u21) ?u21 {
+    const bases = "aeiouAEIOU";
+    const composed: []const u21 = switch (accent) {
+        DEAD_GRAVE => &.{ 'à', 'è', 'ì', 'ò', 'ù', 'À', 'È', 'Ì', 'Ò', 'Ù' },
+        DEAD_ACUTE => &.{ 'á', 'é', 'í', 'ó', 'ú', 'Á', 'É', 'Í', 'Ó', 'Ú' },
+        DEAD_CIRCUMFLEX => &.{ 'â', 'ê', 'î', 'ô', 'û', 'Â', 'Ê', 'Î', 'Ô', 'Û' },
+        else => return null,
+    };
+
+    if (base > 0x7f) {
+        return null;
+    }
+    const index = std.mem.indexOfScalar(u8, bases, @intCast(base)) orelse return null;
+    return composed[index];
+}
+
+pub const Output = struct {
+    buffer: [2]u21 = undefined,
+    len: usize = 0,
+
+    fn push(self: *Output, codepoint: u21) void {
+        self.buffer[self.len] = codepoint;
+        self.len += 1;
+    }
+
+    pub fn slice(self: *const Output) []const u21 {
+        return self.buffer[0..self.len];
+    }
+};
+
+/// Applies dead keys to a stream of translated characters.
+pub const Composer = struct {
+    pending: u21 = 0,
+
+    const Self = @This();
+
+    pub fn feed(self: *Self, codepoint: u21) Output {
+        var output = Output{};
+        if (codepoint == 0) {
+            return output;
+        }
+
+        if (self.pending == 0) {
+            if (isDead(codepoint)) {
+                self.pending = codepoint;
+            } else {
+                output.push(codepoint);
+            }
+            return output;
+        }
+
+        const accent = self.pending;
+        self.pending = 0;
+
+        if (codepoint == ' ') {
+            output.push(spacing(accent));
+        } else if (compose(accent, codepoint)) |composed| {
+            output.push(composed);
+        } else {
+            // a second dead key or a letter without that accent
+            output.push(spacing(accent));
+            output.push(if (isDead(codepoint)) spacing(codepoint) else codepoint);
+        }
+        return output;
+    }
+};
@@ -0,0 +1,142 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const Error = @import("kernel").Error;
+
+// NOTE:
+// Where the parts of the loaded kernel are. The section bounds come from
+// symbols `linker.ld` defines, so they are runtime addresses and already
+// include the slide. The slide and the physical load address come from the
+// kernel address request, anything that has to undo the relocation (the
+// symbolizer, page table checks) goes through here.
+
+/// Link-time base address of the kernel, see `linker.ld`.
+pub const LINK_BASE = 0xffffffff80000000;
+
+// Defined in `linker.ld`.
+extern const __kernel_start: u8;
+extern const __kernel_end: u8;
+extern const __text_start: u8;
+extern const __text_end: u8;
+extern const __rodata_start: u8;
+extern const __rodata_end: u8;
+extern const __data_start: u8;
+extern const __data_end: u8;
+extern const __bss_start: u8;
+extern const __bss_end: u8;
+
+pub const Flags = struct {
+    write: bool,
+    execute: bool,
+};
+
+pub const Kind = enum {
+    text,
+    rodata,
+    data,
+    bss,
+};
+
+pub const Section = struct {
+    kind: Kind,
+    start: usize,
+    end: usize,
+    /// Permissions the section is loaded with, see the PHDRS in `linker.ld`.
+    flags: Flags,
+
+    const Self = @This();
+
+    pub fn size(self: Self) usize {
+        return self.end - self.start;
+    }
+
+    pub fn contains(self: Self, address: usize) bool {
+        return address >= self.start and address < self.end;
+    }
+
+    pub fn bytes(self: Self) []const u8 {
+        const start: [*]const u8 = @ptrFromInt(self.start);
+        return start[0..self.size()];
+    }
+};
+
+pub fn section(kind: Kind) Section {
+    return switch (kind) {
+        .text => .{
+            .kind = kind,
+            .start = @intFromPtr(&__text_start),
+            .end = @intFromPtr(&__text_end),
+            .flags = .{ .write = false, .execute = true },
+        },
+        .rodata => .{
+            .kind = kind,
+            .start = @intFromPtr(&__rodata_start),
+            .end = @intFromPtr(&__rodata_end),
+            .flags = .{ .write = false, .execute = false },
+        },
+        .data => .{
+            .kind = kind,
+            .start = @intFromPtr(&__data_start),
+            .end = @intFromPtr(&__data_end),
+            .flags = .{ .write = true, .execute = false },
+        },
+        .bss => .{
+            .kind = kind,
+            .start = @intFromPtr(&__bss_start),
+            .end = @intFromPtr(&__bss_end),
+            .flags = .{ .write = true, .execute = false },
+        },
+    };
+}
+
+/// Every section, in address order.
+pub fn sections() [std.meta.fields(Kind).len]Section {
+    var result: [std.meta.fields(Kind).len]Section = undefined;
+    for (&result, std.enums.values(Kind)) |*entry, kind| {
+        entry.* = section(kind);
+    }
+    return result;
+}
+
+pub fn start() usize {
+    return @intFromPtr(&__kernel_start);
+}
+
+pub fn end() usize {
+    return @intFromPtr(&__kernel_end);
+}
+
+/// Whether `address` lies anywhere inside the loaded kernel.
+pub fn contains(address: usize) bool {
+    return address >= start() and address < end();
+}
+
+/// How far the kernel was moved from where it was linked.
+pub fn slide() usize {
+    const response = boot.kernel_address_request.response orelse return 0;
+    return response.virtual_base -% LINK_BASE;
+}
+
+/// Translates a runtime address inside the kernel to the physical address
+/// Limine loaded it at.
+pub fn physicalAddress(address: usize) ?usize {
+    const response = boot.kernel_address_request.response orelse return null;
+    if (!contains(address)) {
+        return null;
+    }
+    return response.physical_base + (address - response.virtual_base);
+}
+
+/// An absolute pointer into the kernel. It only holds the runtime address
+/// once the relocations were applied, the code around it is position
+/// independent and works either way.
+var relocation_probe: *const u8 = &__kernel_start;
+
+/// Checks that the kernel was relocated to where it runs. Limine relocates
+/// PIE kernels itself when it slides them, a stale absolute pointer means
+/// that did not happen and every function pointer and vtable is off.
+pub fn checkRelocations() Error!void {
+    const probe: *volatile *const u8 = &relocation_probe;
+    if (@intFromPtr(probe.*) != start()) {
+        return error.Corrupted;
+    }
+}
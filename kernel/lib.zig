@@ -1,4 +1,20 @@
 const builtin = @import("builtin");
 
+pub const Error = @import("error.zig").Error;
+
+pub const boot = @import("boot.zig");
+pub const kernel_image = @import("kernel_image.zig");
+pub const cmdline = @import("cmdline.zig");
+pub const init_tasks = @import("init_tasks.zig");
 pub const utils = @import("utils/utils.zig");
 pub const arch = @import("arch/arch.zig");
+pub const acpi = @import("acpi/acpi.zig");
+pub const memory = @import("memory/memory.zig");
+pub const fs = @import("fs/fs.zig");
+pub const time = @import("time/time.zig");
+pub const input = @import("input/input.zig");
+pub const drivers = @import("drivers/drivers.zig");
+pub const net = @import("net/net.zig");
+pub const power = @import("power/power.zig");
+pub const shell = @import("shell/shell.zig");
+pub const tests = @import("tests.zig");
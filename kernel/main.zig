@@ -1,45 +1,131 @@
 const arch = @import("kernel").arch;
+const boot = @import("kernel").boot;
+const kernel_image = @import("kernel").kernel_image;
+const init_tasks = @import("kernel").init_tasks;
 const log = @import("kernel").utils.log;
+const backtrace = @import("kernel").utils.backtrace;
+const memory = @import("kernel").memory;
+const fs = @import("kernel").fs;
+const acpi = @import("kernel").acpi;
+const time = @import("kernel").time;
+const drivers = @import("kernel").drivers;
+const input = @import("kernel").input;
+const net = @import("kernel").net;
+const power = @import("kernel").power;
+const shell = @import("kernel").shell;
+const testdev = @import("kernel").utils.testdev;
+const selftest = @import("kernel").utils.selftest;
+const tests = @import("kernel").tests;
 
-const limine = @import("limine");
 const std = @import("std");
 const builtin_panic = @import("std").builtin.panic;
 
-pub export var framebuffer_request: limine.FramebufferRequest = .{};
-pub export var base_revision: limine.BaseRevision = .{ .revision = 2 };
+pub const std_options: std.Options = .{
+    .logFn = log.stdLogFn,
+    // filtering happens at runtime in `log`, see `log.setLevel`
+    .log_level = .debug,
+};
+
+const task = init_tasks.task;
+
+/// Everything that has to be up before interrupts are enabled.
+const early_tasks = [_]init_tasks.Task{
+    task("arch", .{ .critical = true, .failure = "failed to initialize the CPU" }, arch.init),
+    task("memory", .{ .depends_on = &.{"arch"}, .critical = true, .failure = "failed to initialize memory" }, memory.init),
+    task("filesystems", .{ .depends_on = &.{"memory"}, .failure = "Failed to mount /dev and /tmp" }, fs.init),
+    task("initramfs", .{ .depends_on = &.{"memory"}, .failure = "No initramfs" }, fs.initramfs.init),
+    task("global_pages", .{ .depends_on = &.{"memory"} }, arch.paging.enableGlobalPages),
+    task("page_table_view", .{ .depends_on = &.{"global_pages"}, .failure = "No page table view" }, arch.page_table_view.init),
+    task("mapping_check", .{ .depends_on = &.{"page_table_view"} }, arch.sanity.checkMappings),
+    task("acpi", .{ .depends_on = &.{"memory"}, .failure = "Failed to read the ACPI tables" }, acpi.init),
+    task("pci", .{ .depends_on = &.{"arch"}, .failure = "No PCI devices found" }, drivers.pci.init),
+    task("interrupts", .{ .depends_on = &.{"acpi"}, .failure = "Failed to set up the interrupt controllers" }, arch.initInterrupts),
+    task("time", .{ .depends_on = &.{"interrupts"}, .failure = "Failed to calibrate the TSC, delays are unavailable" }, time.init),
+    task("rtc", .{ .depends_on = &.{ "acpi", "time" }, .failure = "No RTC, the wall clock stays unset" }, drivers.rtc.init),
+    task("keymap", .{}, input.keymap.init),
+    task("ps2_keyboard", .{ .depends_on = &.{ "interrupts", "keymap" }, .failure = "No PS/2 keyboard" }, drivers.ps2_keyboard.init),
+    task("serial", .{ .depends_on = &.{"interrupts"}, .failure = "Serial output stays polled" }, drivers.serial.init),
+    task("virtio_net", .{ .depends_on = &.{ "pci", "interrupts" } }, drivers.virtio_net.init),
+    task("e1000", .{ .depends_on = &.{ "pci", "interrupts", "time" } }, drivers.e1000.init),
+    task("loopback", .{}, net.loopback.init),
+    task("net", .{ .depends_on = &.{ "virtio_net", "e1000", "loopback", "time" }, .failure = "No networking" }, net.init),
+};
+
+/// Everything that runs with interrupts enabled, after the tests would.
+const late_tasks = [_]init_tasks.Task{
+    task("framebuffer", .{ .failure = "No framebuffer available, continuing without one" }, drivers.framebuffer.init),
+    task("console", .{ .depends_on = &.{"framebuffer"}, .failure = "No text console" }, drivers.console.init),
+    task("power", .{}, power.init),
+};
 
 inline fn done() noreturn {
+    // nothing may be left to drain the log once we halt here
+    drivers.serial.flush();
     while (true) {
         asm volatile ("hlt");
     }
 }
 
-pub fn panic(message: []const u8, _: ?*std.builtin.StackTrace, _: ?usize) noreturn {
+var panicking = std.atomic.Value(bool).init(false);
+
+pub fn panic(message: []const u8, _: ?*std.builtin.StackTrace, return_address: ?usize) noreturn {
+    // whoever holds the serial lock may be what panicked, from here on the
+    // log goes out polled without it
+    drivers.serial.panicWrite("");
+
+    // e.g. an assertion tripped by the panic path itself
+    if (panicking.swap(true, .acquire)) {
+        log.write("FATAL: panicked while panicking: {s}", .{message});
+        done();
+    }
+
+    // the heap may be what broke, everything from here on formats on the
+    // stack and allocating trips an assertion
+    memory.forbidAllocations();
     log.write("FATAL: {s}", .{message});
+    arch.cpuinfo.dump();
+    backtrace.dump(return_address orelse @returnAddress());
 
     done();
 }
 
 export fn _start() callconv(.C) noreturn {
-    if (!base_revision.is_supported()) {
+    if (!boot.base_revision.is_supported()) {
         done();
     }
 
-    arch.init();
+    // Leave the stack Limine gave us for one the kernel owns, its bounds are
+    // known and it is what the TSS hands out as the boot thread's RSP0.
+    arch.cpu.switchStack(arch.stack.boot.top(), &kernelMain);
+}
+
+fn kernelMain() callconv(.C) noreturn {
+    drivers.serial.registerSink();
+    log.init();
 
-    if (framebuffer_request.response) |framebuffer_response| {
-        if (framebuffer_response.framebuffer_count < 1) {
-            done();
-        }
-        const framebuffer = framebuffer_response.framebuffers()[0];
+    kernel_image.checkRelocations() catch {
+        log.write("FATAL: the kernel was not relocated to 0x{x}", .{kernel_image.start()});
+        done();
+    };
+    log.info("Kernel at 0x{x}, slid by 0x{x}", .{ kernel_image.start(), kernel_image.slide() });
 
-        for (0..100) |i| {
-            const pixel_offset = i * framebuffer.pitch + i * 4;
-            @as(*u32, @ptrCast(@alignCast(framebuffer.address + pixel_offset))).* = 0xFFFFFFFF;
-        }
+    if (!init_tasks.run(&early_tasks)) {
+        done();
     }
 
-    asm volatile ("int $0x99");
+    // every interrupt source is either masked or has a handler by now
+    arch.cpu.enableInterrupts();
 
-    done();
+    if (testdev.enabled) {
+        testdev.run(&tests.all);
+    }
+
+    _ = init_tasks.run(&late_tasks);
+    drivers.console.write("ReasonOS", .{});
+
+    if (selftest.requested()) {
+        _ = selftest.run(&tests.selftest);
+    }
+
+    shell.run();
 }
@@ -0,0 +1,70 @@
+const std = @import("std");
+const memory = @import("memory.zig");
+const pmm = @import("pmm.zig");
+const Error = @import("kernel").Error;
+
+// NOTE:
+// Without an IOMMU a device sees physical memory as is, so the bus address
+// of a buffer is its physical address. x86 keeps DMA coherent with the
+// caches, the sync calls only have to order the CPU's accesses against the
+// doorbell write or status read that hands the buffer over; they are there
+// so drivers are written as if that were not a given.
+
+/// Physically contiguous, page aligned memory shared with a device. Owned by
+/// whoever allocated it, `deinit` hands it back.
+pub const DmaBuffer = struct {
+    pages: []align(pmm.PAGE_SIZE) u8,
+    /// Address the device uses to reach `pages`.
+    bus_address: u64,
+    /// Bytes asked for, `pages` is rounded up to whole pages.
+    len: usize,
+
+    const Self = @This();
+
+    /// Allocates a zeroed buffer of at least `size` bytes.
+    pub fn alloc(size: usize) Error!Self {
+        if (size == 0) {
+            return error.InvalidArgument;
+        }
+
+        const pages = try memory.allocPagesExact(std.math.divCeil(usize, size, pmm.PAGE_SIZE) catch unreachable);
+        return .{
+            .pages = pages,
+            .bus_address = memory.physicalAddress(pages),
+            .len = size,
+        };
+    }
+
+    pub fn deinit(self: *Self) void {
+        memory.freePagesExact(self.pages);
+        self.* = undefined;
+    }
+
+    pub fn bytes(self: Self) []u8 {
+        return self.pages[0..self.len];
+    }
+
+    /// The buffer viewed as an array of `T`, e.g. descriptors of a ring.
+    pub fn slice(self: Self, comptime T: type) []T {
+        std.debug.assert(@alignOf(T) <= pmm.PAGE_SIZE);
+        return std.mem.bytesAsSlice(T, self.pages[0 .. self.len / @sizeOf(T) * @sizeOf(T)]);
+    }
+
+    /// Bus address of the byte at `offset`.
+    pub fn busAddress(self: Self, offset: usize) u64 {
+        std.debug.assert(offset < self.pages.len);
+        return self.bus_address + offset;
+    }
+
+    /// Makes the CPU's writes visible to the device. Call before telling the
+    /// device to look at the buffer.
+    pub fn syncForDevice(_: Self) void {
+        @fence(.seq_cst);
+    }
+
+    /// Makes the device's writes visible to the CPU. Call after the device
+    /// reported it is done with the buffer.
+    pub fn syncForCpu(_: Self) void {
+        @fence(.seq_cst);
+    }
+};
@@ -0,0 +1,79 @@
+const std = @import("std");
+
+/// A bump allocator over a static region, usable before the real heap is
+/// up. Only the most recent allocation can be resized or freed, everything
+/// else stays allocated until the region is retired.
+pub const BumpAllocator = struct {
+    region: []u8,
+    used: usize = 0,
+    retired: bool = false,
+
+    const Self = @This();
+
+    pub fn init(region: []u8) Self {
+        return .{ .region = region };
+    }
+
+    pub fn allocator(self: *Self) std.mem.Allocator {
+        return .{
+            .ptr = self,
+            .vtable = &.{
+                .alloc = alloc,
+                .resize = resize,
+                .free = free,
+            },
+        };
+    }
+
+    pub fn owns(self: *const Self, buf: []const u8) bool {
+        const start = @intFromPtr(self.region.ptr);
+        const address = @intFromPtr(buf.ptr);
+        return address >= start and address + buf.len <= start + self.region.len;
+    }
+
+    fn isLast(self: *const Self, buf: []const u8) bool {
+        return @intFromPtr(buf.ptr) + buf.len == @intFromPtr(self.region.ptr) + self.used;
+    }
+
+    fn alloc(ctx: *anyopaque, len: usize, ptr_align: u8, _: usize) ?[*]u8 {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        if (self.retired) {
+            return null;
+        }
+
+        const alignment = @as(usize, 1) << @intCast(ptr_align);
+        const base = @intFromPtr(self.region.ptr);
+        const start = std.mem.alignForward(usize, base + self.used, alignment) - base;
+        if (start + len > self.region.len) {
+            return null;
+        }
+
+        self.used = start + len;
+        return self.region.ptr + start;
+    }
+
+    fn resize(ctx: *anyopaque, buf: []u8, _: u8, new_len: usize, _: usize) bool {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        if (new_len <= buf.len and !self.isLast(buf)) {
+            return true;
+        }
+        if (self.retired or !self.isLast(buf)) {
+            return false;
+        }
+
+        const start = @intFromPtr(buf.ptr) - @intFromPtr(self.region.ptr);
+        if (start + new_len > self.region.len) {
+            return false;
+        }
+
+        self.used = start + new_len;
+        return true;
+    }
+
+    fn free(ctx: *anyopaque, buf: []u8, _: u8, _: usize) void {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        if (!self.retired and self.isLast(buf)) {
+            self.used -= buf.len;
+        }
+    }
+};
@@ -0,0 +1,653 @@
+const std = @import("std");
+const options = @import("build_options");
+const log = @import("kernel").utils.log.scoped(.heap);
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+
+const memory = @import("memory.zig");
+const pmm = @import("pmm.zig");
+
+// NOTE:
+// The heap carves blocks out of chunks of contiguous frames taken from the
+// PMM. Every block starts with a header recording its own size and the size
+// of the block physically before it, so freeing a block can merge it with
+// both neighbours. Free blocks are kept in buckets by size class (powers of
+// two), with a bitmask of the non-empty buckets: any block in a bucket above
+// the request's own class fits, so only that one bucket is ever scanned.
+//
+// Which of the fitting blocks gets used is up to the placement policy,
+// `-Dheap-policy` picks the one the kernel boots with and `setPolicy`
+// switches it at runtime so tests can compare them on the same workload.
+//
+// Allocations of `LARGE_THRESHOLD` bytes or more never touch the blocks at
+// all: they get their own run of frames from the PMM, which goes straight
+// back on free. Otherwise a single huge buffer would grow the heap by a chunk
+// that is later only ever reused piecemeal. Whether a buffer is large is
+// decided by its length alone, so `resize` refuses to move a buffer across
+// the threshold and callers fall back to allocate, copy and free.
+
+const ALIGNMENT = 16;
+
+/// Smallest amount of memory the heap grows by.
+const CHUNK_SIZE = 1024 * 1024;
+
+/// Allocations at least this large bypass the blocks, see above.
+const LARGE_THRESHOLD = CHUNK_SIZE / 2;
+
+const BUCKET_COUNT = 48;
+const MIN_CLASS = std.math.log2_int(usize, MIN_BLOCK_SIZE);
+
+const Chunk = extern struct {
+    next: ?*Chunk,
+    size: usize,
+
+    fn first(self: *Chunk) *Block {
+        return @ptrFromInt(@intFromPtr(self) + @sizeOf(Chunk));
+    }
+};
+
+const Block = extern struct {
+    // NOTE: sizes are multiples of `ALIGNMENT`, the low bits hold the flags
+    tagged_size: usize,
+    previous_size: usize,
+
+    const FREE = 1;
+    const LAST = 2;
+    const FLAGS = ALIGNMENT - 1;
+
+    fn size(self: *const Block) usize {
+        return self.tagged_size & ~@as(usize, FLAGS);
+    }
+
+    fn isFree(self: *const Block) bool {
+        return self.tagged_size & FREE != 0;
+    }
+
+    fn isLast(self: *const Block) bool {
+        return self.tagged_size & LAST != 0;
+    }
+
+    fn set(self: *Block, new_size: usize, is_free: bool, is_last: bool) void {
+        std.debug.assert(new_size % ALIGNMENT == 0);
+        self.tagged_size = new_size | @as(usize, if (is_free) FREE else 0) | @as(usize, if (is_last) LAST else 0);
+    }
+
+    fn next(self: *Block) ?*Block {
+        if (self.isLast()) {
+            return null;
+        }
+        return @ptrFromInt(@intFromPtr(self) + self.size());
+    }
+
+    fn previous(self: *Block) ?*Block {
+        if (self.previous_size == 0) {
+            return null;
+        }
+        return @ptrFromInt(@intFromPtr(self) - self.previous_size);
+    }
+
+    fn payload(self: *Block) [*]u8 {
+        return @as([*]u8, @ptrCast(self)) + @sizeOf(Block);
+    }
+
+    fn fromPayload(ptr: [*]u8) *Block {
+        return @ptrCast(@alignCast(ptr - @sizeOf(Block)));
+    }
+
+    fn links(self: *Block) *Links {
+        return @ptrCast(@alignCast(self.payload()));
+    }
+};
+
+/// Lives in the payload of free blocks.
+const Links = extern struct {
+    next: ?*Block,
+    previous: ?*Block,
+};
+
+const MIN_BLOCK_SIZE = @sizeOf(Block) + @sizeOf(Links);
+
+var buckets = [_]?*Block{null} ** BUCKET_COUNT;
+var non_empty: u64 = 0;
+var chunks: ?*Chunk = null;
+
+pub const Policy = enum {
+    /// The first block found that fits.
+    first_fit,
+    /// Like first fit, but every bucket resumes where its last search ended.
+    next_fit,
+    /// The smallest block that fits.
+    best_fit,
+};
+
+var policy = std.meta.stringToEnum(Policy, @tagName(options.heap_policy)).?;
+
+/// Where the next search in each bucket starts under `Policy.next_fit`.
+var rovers = [_]?*Block{null} ** BUCKET_COUNT;
+
+var allocations: usize = 0;
+var blocks_scanned: usize = 0;
+var free_bytes: usize = 0;
+
+var large_allocations: usize = 0;
+var large_bytes: usize = 0;
+
+/// How often the fragmentation history is sampled, in allocations.
+const SAMPLE_INTERVAL = 256;
+
+pub const Sample = struct {
+    allocations: usize,
+    largest_free_block: usize,
+    fragmentation: usize,
+};
+
+var samples: [64]Sample = undefined;
+var sample_count: usize = 0;
+
+var lock = SpinLock.init();
+
+pub const Stats = struct {
+    total_bytes: usize,
+    free_bytes: usize,
+    largest_free_block: usize,
+    free_blocks: usize,
+    /// Share of free memory that cannot serve an allocation as large as the
+    /// largest free block, in percent.
+    fragmentation: usize,
+    /// Average number of free blocks looked at per allocation, times 100.
+    average_scan: usize,
+    /// Live allocations that bypassed the blocks and the frames they hold.
+    large_allocations: usize,
+    large_bytes: usize,
+};
+
+fn bucketIndex(block_size: usize) u6 {
+    const class = std.math.log2_int(usize, block_size);
+    return @intCast(@min(class - MIN_CLASS, BUCKET_COUNT - 1));
+}
+
+fn insert(block: *Block) void {
+    const index = bucketIndex(block.size());
+    const links = block.links();
+    links.* = .{ .next = buckets[index], .previous = null };
+    if (buckets[index]) |head| {
+        head.links().previous = block;
+    }
+    buckets[index] = block;
+    non_empty |= @as(u64, 1) << index;
+    free_bytes += block.size();
+}
+
+fn remove(block: *Block) void {
+    const index = bucketIndex(block.size());
+    const links = block.links();
+    if (rovers[index] == block) {
+        rovers[index] = links.next;
+    }
+    if (links.previous) |previous| {
+        previous.links().next = links.next;
+    } else {
+        buckets[index] = links.next;
+    }
+    if (links.next) |next| {
+        next.links().previous = links.previous;
+    }
+    if (buckets[index] == null) {
+        non_empty &= ~(@as(u64, 1) << index);
+    }
+    free_bytes -= block.size();
+}
+
+/// Picks a block of at least `block_size` bytes out of `bucket` according to
+/// the current policy.
+fn search(index: u6, block_size: usize) ?*Block {
+    const head = buckets[index] orelse return null;
+    const start = if (policy == .next_fit) rovers[index] orelse head else head;
+
+    var best: ?*Block = null;
+    var candidate: ?*Block = start;
+    while (candidate) |block| {
+        blocks_scanned += 1;
+        if (block.size() >= block_size) {
+            if (policy != .best_fit) {
+                rovers[index] = block.links().next;
+                return block;
+            }
+            if (best == null or block.size() < best.?.size()) {
+                best = block;
+            }
+            if (block.size() == block_size) {
+                break;
+            }
+        }
+
+        // next fit wraps around to the blocks before the rover
+        candidate = block.links().next orelse if (start != head) head else null;
+        if (candidate == start) {
+            break;
+        }
+    }
+
+    return best;
+}
+
+fn find(block_size: usize) ?*Block {
+    const index = bucketIndex(block_size);
+
+    // blocks in the request's own class may still be too small
+    if (search(index, block_size)) |block| {
+        return block;
+    }
+
+    // but any block in a larger one fits
+    const larger = non_empty & ~((@as(u64, 2) << index) - 1);
+    if (larger == 0) {
+        return null;
+    }
+    return search(@intCast(@ctz(larger)), block_size);
+}
+
+fn largestFreeBlock() usize {
+    if (non_empty == 0) {
+        return 0;
+    }
+
+    var largest: usize = 0;
+    var candidate = buckets[63 - @clz(non_empty)];
+    while (candidate) |block| : (candidate = block.links().next) {
+        largest = @max(largest, block.size());
+    }
+    return largest;
+}
+
+fn fragmentation(largest: usize) usize {
+    if (free_bytes == 0) {
+        return 0;
+    }
+    return 100 - largest * 100 / free_bytes;
+}
+
+fn sample() void {
+    if (sample_count == samples.len) {
+        std.mem.copyForwards(Sample, samples[0 .. samples.len - 1], samples[1..]);
+        sample_count -= 1;
+    }
+
+    const largest = largestFreeBlock();
+    samples[sample_count] = .{
+        .allocations = allocations,
+        .largest_free_block = largest,
+        .fragmentation = fragmentation(largest),
+    };
+    sample_count += 1;
+}
+
+/// Marks `block` free, merges it with free neighbours and files the result.
+fn release(block: *Block) void {
+    var current = block;
+    var last = block.isLast();
+    var size = block.size();
+
+    if (current.next()) |next| {
+        if (next.isFree()) {
+            remove(next);
+            size += next.size();
+            last = next.isLast();
+        }
+    }
+    if (current.previous()) |previous| {
+        if (previous.isFree()) {
+            remove(previous);
+            size += previous.size();
+            current = previous;
+        }
+    }
+
+    current.set(size, true, last);
+    if (current.next()) |next| {
+        next.previous_size = size;
+    }
+    insert(current);
+}
+
+/// Shrinks an allocated block to `block_size`, releasing the tail if it is
+/// large enough to be a block of its own.
+fn split(block: *Block, block_size: usize) void {
+    const remainder = block.size() - block_size;
+    if (remainder < MIN_BLOCK_SIZE) {
+        return;
+    }
+
+    const last = block.isLast();
+    block.set(block_size, false, false);
+
+    const rest: *Block = @ptrFromInt(@intFromPtr(block) + block_size);
+    rest.set(remainder, false, last);
+    rest.previous_size = block_size;
+    if (rest.next()) |next| {
+        next.previous_size = remainder;
+    }
+    release(rest);
+}
+
+fn grow(block_size: usize) Error!void {
+    const size = std.mem.alignForward(usize, @max(CHUNK_SIZE, block_size + @sizeOf(Chunk)), pmm.PAGE_SIZE);
+    const physical = try pmm.allocPagesFor(size / pmm.PAGE_SIZE, .heap);
+
+    const chunk: *Chunk = @ptrFromInt(memory.physicalToVirtual(physical));
+    chunk.* = .{ .next = chunks, .size = size };
+    chunks = chunk;
+
+    const block = chunk.first();
+    block.set(size - @sizeOf(Chunk), true, true);
+    block.previous_size = 0;
+    insert(block);
+}
+
+fn blockSize(len: usize) usize {
+    return @max(std.mem.alignForward(usize, len + @sizeOf(Block), ALIGNMENT), MIN_BLOCK_SIZE);
+}
+
+pub fn init() Error!void {
+    try grow(CHUNK_SIZE);
+}
+
+pub fn allocator() std.mem.Allocator {
+    return .{
+        .ptr = undefined,
+        .vtable = &.{
+            .alloc = alloc,
+            .resize = resize,
+            .free = free,
+        },
+    };
+}
+
+fn isLarge(len: usize) bool {
+    return len >= LARGE_THRESHOLD;
+}
+
+fn pageCount(len: usize) usize {
+    return std.mem.alignForward(usize, len, pmm.PAGE_SIZE) / pmm.PAGE_SIZE;
+}
+
+fn allocLarge(len: usize, alignment: usize) ?[*]u8 {
+    // runs of frames are only ever page aligned
+    if (alignment > pmm.PAGE_SIZE) {
+        return null;
+    }
+
+    const pages = pageCount(len);
+    const physical = pmm.allocPagesFor(pages, .heap) catch return null;
+
+    lock.acquire();
+    defer lock.release();
+
+    large_allocations += 1;
+    large_bytes += pages * pmm.PAGE_SIZE;
+    return @ptrFromInt(memory.physicalToVirtual(physical));
+}
+
+fn freeLarge(buf: []u8) void {
+    const pages = pageCount(buf.len);
+    pmm.freePages(memory.virtualToPhysical(@intFromPtr(buf.ptr)), pages);
+
+    lock.acquire();
+    defer lock.release();
+
+    large_allocations -= 1;
+    large_bytes -= pages * pmm.PAGE_SIZE;
+}
+
+fn alloc(_: *anyopaque, len: usize, ptr_align: u8, _: usize) ?[*]u8 {
+    const alignment = @as(usize, 1) << @intCast(ptr_align);
+    if (isLarge(len)) {
+        return allocLarge(len, alignment);
+    }
+
+    const size = blockSize(len);
+    // over-aligned requests need room to cut a free block off the front
+    const search_size = if (alignment > ALIGNMENT) size + alignment + MIN_BLOCK_SIZE else size;
+
+    lock.acquire();
+    defer lock.release();
+
+    allocations += 1;
+    if (allocations % SAMPLE_INTERVAL == 0) {
+        sample();
+    }
+
+    const found = find(search_size) orelse blk: {
+        grow(search_size) catch return null;
+        break :blk find(search_size).?;
+    };
+    remove(found);
+    found.set(found.size(), false, found.isLast());
+
+    var block = found;
+    if (alignment > ALIGNMENT) {
+        const start = @intFromPtr(block.payload());
+        var aligned = std.mem.alignForward(usize, start, alignment);
+        while (aligned != start and aligned - start < MIN_BLOCK_SIZE) {
+            aligned += alignment;
+        }
+
+        if (aligned != start) {
+            const padding = aligned - start;
+            const last = block.isLast();
+
+            const moved: *Block = @ptrFromInt(@intFromPtr(block) + padding);
+            moved.set(block.size() - padding, false, last);
+            moved.previous_size = padding;
+            if (moved.next()) |next| {
+                next.previous_size = moved.size();
+            }
+
+            block.set(padding, false, false);
+            release(block);
+            block = moved;
+        }
+    }
+
+    split(block, size);
+    return block.payload();
+}
+
+fn resize(_: *anyopaque, buf: []u8, _: u8, new_len: usize, _: usize) bool {
+    if (isLarge(buf.len) != isLarge(new_len)) {
+        return false;
+    }
+    if (isLarge(buf.len)) {
+        return pageCount(buf.len) == pageCount(new_len);
+    }
+
+    const block = Block.fromPayload(buf.ptr);
+    const size = blockSize(new_len);
+
+    lock.acquire();
+    defer lock.release();
+
+    if (size <= block.size()) {
+        split(block, size);
+        return true;
+    }
+
+    const next = block.next() orelse return false;
+    if (!next.isFree() or block.size() + next.size() < size) {
+        return false;
+    }
+
+    remove(next);
+    block.set(block.size() + next.size(), false, next.isLast());
+    if (block.next()) |after| {
+        after.previous_size = block.size();
+    }
+    split(block, size);
+    return true;
+}
+
+fn free(_: *anyopaque, buf: []u8, _: u8, _: usize) void {
+    if (isLarge(buf.len)) {
+        return freeLarge(buf);
+    }
+
+    lock.acquire();
+    defer lock.release();
+
+    release(Block.fromPayload(buf.ptr));
+}
+
+pub fn stats() Stats {
+    lock.acquire();
+    defer lock.release();
+
+    var result = std.mem.zeroes(Stats);
+
+    var chunk = chunks;
+    while (chunk) |current| : (chunk = current.next) {
+        result.total_bytes += current.size;
+
+        var block: ?*Block = current.first();
+        while (block) |b| : (block = b.next()) {
+            if (b.isFree()) {
+                result.free_bytes += b.size();
+                result.free_blocks += 1;
+                result.largest_free_block = @max(result.largest_free_block, b.size());
+            }
+        }
+    }
+
+    result.fragmentation = fragmentation(result.largest_free_block);
+    if (allocations != 0) {
+        result.average_scan = blocks_scanned * 100 / allocations;
+    }
+    result.large_allocations = large_allocations;
+    result.large_bytes = large_bytes;
+
+    return result;
+}
+
+/// Walks every chunk and checks that the blocks tile it, that their headers
+/// agree with each other and with the free lists.
+pub fn verify() Error!void {
+    lock.acquire();
+    defer lock.release();
+
+    var counted_free: usize = 0;
+
+    var chunk = chunks;
+    while (chunk) |current| : (chunk = current.next) {
+        var covered: usize = @sizeOf(Chunk);
+        var previous_size: usize = 0;
+        var previous_free = false;
+
+        var block: ?*Block = current.first();
+        while (block) |b| : (block = b.next()) {
+            if (b.size() < MIN_BLOCK_SIZE or b.previous_size != previous_size) {
+                return error.Corrupted;
+            }
+            // neighbouring free blocks are always merged
+            if (b.isFree() and previous_free) {
+                return error.Corrupted;
+            }
+            if (b.isFree()) {
+                counted_free += b.size();
+            }
+
+            covered += b.size();
+            previous_size = b.size();
+            previous_free = b.isFree();
+        }
+
+        if (covered != current.size) {
+            return error.Corrupted;
+        }
+    }
+
+    var listed_free: usize = 0;
+    for (buckets, 0..) |head, index| {
+        var candidate = head;
+        while (candidate) |b| : (candidate = b.links().next) {
+            if (!b.isFree() or bucketIndex(b.size()) != index) {
+                return error.Corrupted;
+            }
+            listed_free += b.size();
+        }
+    }
+
+    if (counted_free != free_bytes or listed_free != free_bytes) {
+        return error.Corrupted;
+    }
+}
+
+/// Writes every chunk with how much of it is handed out. Large
+/// allocations aren't kept in a list, only their totals are known.
+pub fn dumpChunks(writer: anytype) !void {
+    lock.acquire();
+    defer lock.release();
+
+    var chunk = chunks;
+    while (chunk) |current| : (chunk = current.next) {
+        var used_blocks: usize = 0;
+        var used_bytes: usize = 0;
+
+        var block: ?*Block = current.first();
+        while (block) |b| : (block = b.next()) {
+            if (!b.isFree()) {
+                used_blocks += 1;
+                used_bytes += b.size();
+            }
+        }
+
+        try writer.print("  chunk 0x{x} {:>6} KiB, {} blocks holding {} KiB\n", .{
+            @intFromPtr(current),
+            current.size / 1024,
+            used_blocks,
+            used_bytes / 1024,
+        });
+    }
+    try writer.print("  {} large allocations holding {} KiB\n", .{ large_allocations, large_bytes / 1024 });
+}
+
+pub fn setPolicy(new_policy: Policy) void {
+    lock.acquire();
+    defer lock.release();
+
+    policy = new_policy;
+    rovers = [_]?*Block{null} ** BUCKET_COUNT;
+}
+
+/// Copies the most recent fragmentation samples, oldest first, into
+/// `buffer` and returns how many there were.
+pub fn history(buffer: []Sample) usize {
+    lock.acquire();
+    defer lock.release();
+
+    const count = @min(buffer.len, sample_count);
+    @memcpy(buffer[0..count], samples[sample_count - count .. sample_count]);
+    return count;
+}
+
+pub fn dumpStats() void {
+    const s = stats();
+    log.info("Heap ({s}): {} KiB total, {} KiB free in {} blocks, largest {} KiB, {}% fragmented, {}.{:0>2} blocks scanned per allocation", .{
+        @tagName(policy),
+        s.total_bytes / 1024,
+        s.free_bytes / 1024,
+        s.free_blocks,
+        s.largest_free_block / 1024,
+        s.fragmentation,
+        s.average_scan / 100,
+        s.average_scan % 100,
+    });
+    log.info("  {} large allocations holding {} KiB", .{ s.large_allocations, s.large_bytes / 1024 });
+
+    var buffer: [samples.len]Sample = undefined;
+    for (buffer[0..history(&buffer)]) |entry| {
+        log.info("  after {} allocations: largest free block {} KiB, {}% fragmented", .{
+            entry.allocations,
+            entry.largest_free_block / 1024,
+            entry.fragmentation,
+        });
+    }
+}
@@ -0,0 +1,114 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const heap = @import("heap.zig");
+const memory = @import("memory.zig");
+const pmm = @import("pmm.zig");
+
+const SIZES = [_]usize{ 1, 24, 100, 1000, 5000 };
+
+const MIN_ALIGNMENT = 3;
+const MAX_ALIGNMENT = 12;
+
+fn allocAligned(len: usize, log2_alignment: u8) ![]u8 {
+    const ptr = heap.allocator().rawAlloc(len, log2_alignment, @returnAddress()) orelse return error.OutOfMemory;
+    if (@intFromPtr(ptr) & ((@as(usize, 1) << @intCast(log2_alignment)) - 1) != 0) {
+        return error.Misaligned;
+    }
+    @memset(ptr[0..len], 0xa5);
+    return ptr[0..len];
+}
+
+fn freeAligned(buf: []u8, log2_alignment: u8) void {
+    heap.allocator().rawFree(buf, log2_alignment, @returnAddress());
+}
+
+fn usedBytes() usize {
+    const stats = heap.stats();
+    return stats.total_bytes - stats.free_bytes;
+}
+
+fn alignmentMatrix() !void {
+    const before = usedBytes();
+
+    for (MIN_ALIGNMENT..MAX_ALIGNMENT + 1) |log2_alignment| {
+        for (SIZES) |size| {
+            const alignment: u8 = @intCast(log2_alignment);
+
+            // unaligned neighbours make the aligned ones start at odd places
+            const first = try allocAligned(size, alignment);
+            const filler = try allocAligned(7, 0);
+            const second = try allocAligned(size, alignment);
+
+            for (first) |byte| {
+                if (byte != 0xa5) {
+                    return error.Overwritten;
+                }
+            }
+
+            freeAligned(first, alignment);
+            freeAligned(filler, 0);
+            freeAligned(second, alignment);
+            try heap.verify();
+        }
+    }
+
+    if (usedBytes() != before) {
+        return error.Leaked;
+    }
+}
+
+fn alignedReuse() !void {
+    const before = usedBytes();
+
+    // freed aligned blocks must go back on the free lists and be found again
+    for (0..100) |i| {
+        const alignment: u8 = @intCast(MIN_ALIGNMENT + i % (MAX_ALIGNMENT - MIN_ALIGNMENT + 1));
+        const buf = try allocAligned(64 + i, alignment);
+        freeAligned(buf, alignment);
+        try heap.verify();
+    }
+
+    if (usedBytes() != before) {
+        return error.Leaked;
+    }
+}
+
+fn pagesExact() !void {
+    for (1..9) |count| {
+        const pages = try memory.allocPagesExact(count);
+        defer memory.freePagesExact(pages);
+
+        if (pages.len != count * pmm.PAGE_SIZE or memory.physicalAddress(pages) % pmm.PAGE_SIZE != 0) {
+            return error.Misaligned;
+        }
+        if (memory.physicalToVirtual(memory.physicalAddress(pages)) != @intFromPtr(pages.ptr)) {
+            return error.WrongPhysicalAddress;
+        }
+    }
+    try heap.verify();
+}
+
+fn quotaAccounting() !void {
+    var account = memory.quota.Account{ .name = "heap_tests" };
+    const previous = memory.quota.enter(&account);
+    defer memory.quota.leave(previous);
+
+    const buffer = try memory.allocator().alloc(u8, 100);
+    if (account.allocated != 100) {
+        memory.allocator().free(buffer);
+        return error.NotCharged;
+    }
+    memory.allocator().free(buffer);
+
+    if (account.allocated != 0 or account.peak != 100) {
+        return error.NotCredited;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "heap.alignment_matrix", .func = alignmentMatrix },
+    .{ .name = "heap.aligned_reuse", .func = alignedReuse },
+    .{ .name = "heap.pages_exact", .func = pagesExact },
+    .{ .name = "heap.quota_accounting", .func = quotaAccounting },
+};
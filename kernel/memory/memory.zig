@@ -0,0 +1,168 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+const fault_injection = @import("kernel").utils.fault_injection;
+const kassert = @import("kernel").utils.kassert;
+const cmdline = @import("kernel").cmdline;
+
+const BumpAllocator = @import("early.zig").BumpAllocator;
+
+pub const pmm = @import("pmm.zig");
+pub const pmm_tests = @import("pmm_tests.zig");
+pub const heap = @import("heap.zig");
+pub const heap_tests = @import("heap_tests.zig");
+pub const dma = @import("dma.zig");
+pub const DmaBuffer = dma.DmaBuffer;
+pub const sg_list = @import("sg_list.zig");
+pub const sg_list_tests = @import("sg_list_tests.zig");
+pub const quota = @import("quota.zig");
+pub const SgList = sg_list.SgList;
+pub const IoVec = sg_list.IoVec;
+
+// NOTE:
+// Everything allocates through `allocator()`, which forwards to the early
+// bump allocator until `handoff` installs the real heap. Memory handed out
+// before the handoff stays valid forever; freeing it afterwards is silently
+// ignored instead of reaching a heap that never owned it.
+var early_region: [256 * 1024]u8 align(4096) = undefined;
+var early = BumpAllocator.init(&early_region);
+
+var real_heap: ?std.mem.Allocator = null;
+
+var hhdm_offset: usize = 0;
+
+var alloc_site = fault_injection.Site{ .name = "memory.alloc" };
+
+/// Nesting depth of windows in which nothing may allocate. Nothing does
+/// before `init`, the log and the panic path format on the stack.
+var forbidden_depth: usize = 1;
+
+pub fn init() Error!void {
+    allowAllocations();
+
+    const hhdm = boot.hhdm_request.response orelse return error.NotFound;
+    hhdm_offset = hhdm.offset;
+
+    try pmm.init();
+    try heap.init();
+    handoff(heap.allocator());
+
+    quota.boot_account.limit = cmdline.getOr(usize, "heap.quota", 0);
+}
+
+/// Translates a physical address into its alias in the higher half direct
+/// map Limine sets up.
+pub fn physicalToVirtual(physical: usize) usize {
+    kassert.debug(hhdm_offset != 0, "HHDM used before memory.init", .{});
+    return physical + hhdm_offset;
+}
+
+/// Inverse of `physicalToVirtual`, only valid for addresses in the higher
+/// half direct map.
+pub fn virtualToPhysical(virtual: usize) usize {
+    kassert.debug(hhdm_offset != 0 and virtual >= hhdm_offset, "0x{x} is not in the HHDM", .{virtual});
+    return virtual - hhdm_offset;
+}
+
+/// Allocates `count` zeroed pages from the heap that are page aligned and
+/// physically contiguous, so that drivers can hand `physicalAddress` of them
+/// to a device (e.g. for descriptor rings) without going to the PMM.
+pub fn allocPagesExact(count: usize) Error![]align(pmm.PAGE_SIZE) u8 {
+    std.debug.assert(real_heap != null);
+
+    const pages = try allocator().alignedAlloc(u8, pmm.PAGE_SIZE, count * pmm.PAGE_SIZE);
+    @memset(pages, 0);
+    return pages;
+}
+
+pub fn freePagesExact(pages: []align(pmm.PAGE_SIZE) u8) void {
+    allocator().free(pages);
+}
+
+/// Physical address of memory handed out by the heap, which is always
+/// reached through the higher half direct map. Memory from before the
+/// handoff lives in the kernel image and has no such alias.
+pub fn physicalAddress(buf: []const u8) usize {
+    std.debug.assert(!early.owns(buf));
+    return virtualToPhysical(@intFromPtr(buf.ptr));
+}
+
+pub fn allocator() std.mem.Allocator {
+    return .{
+        .ptr = undefined,
+        .vtable = &.{
+            .alloc = alloc,
+            .resize = resize,
+            .free = free,
+        },
+    };
+}
+
+/// Retires the early allocator and routes all further allocations to `real`.
+pub fn handoff(real: std.mem.Allocator) void {
+    std.debug.assert(real_heap == null);
+
+    early.retired = true;
+    real_heap = real;
+
+    log.info("Early allocator retired with {} KiB in use", .{early.used / 1024});
+}
+
+/// Opens a window in which allocating is a bug, caught by an assertion in
+/// builds with runtime safety. Windows nest, each one is closed with
+/// `allowAllocations`.
+pub fn forbidAllocations() void {
+    _ = @atomicRmw(usize, &forbidden_depth, .Add, 1, .monotonic);
+}
+
+pub fn allowAllocations() void {
+    _ = @atomicRmw(usize, &forbidden_depth, .Sub, 1, .monotonic);
+}
+
+fn checkAllowed(len: usize, ret_addr: usize) void {
+    kassert.debug(@atomicLoad(usize, &forbidden_depth, .monotonic) == 0, "allocation of {} bytes from 0x{x} where nothing may allocate", .{ len, ret_addr });
+}
+
+fn current() std.mem.Allocator {
+    return real_heap orelse early.allocator();
+}
+
+fn alloc(_: *anyopaque, len: usize, ptr_align: u8, ret_addr: usize) ?[*]u8 {
+    checkAllowed(len, ret_addr);
+    if (fault_injection.shouldFail(&alloc_site)) {
+        return null;
+    }
+    const result = current().rawAlloc(len, ptr_align, ret_addr) orelse return null;
+    // TLS, and with it the quota accounts, only exists by the handoff
+    if (real_heap != null) {
+        quota.charge(len);
+    }
+    return result;
+}
+
+fn resize(_: *anyopaque, buf: []u8, buf_align: u8, new_len: usize, ret_addr: usize) bool {
+    if (new_len > buf.len) {
+        checkAllowed(new_len - buf.len, ret_addr);
+    }
+    if (early.owns(buf)) {
+        return early.allocator().rawResize(buf, buf_align, new_len, ret_addr);
+    }
+    if (!current().rawResize(buf, buf_align, new_len, ret_addr)) {
+        return false;
+    }
+    if (new_len > buf.len) {
+        quota.charge(new_len - buf.len);
+    } else {
+        quota.credit(buf.len - new_len);
+    }
+    return true;
+}
+
+fn free(_: *anyopaque, buf: []u8, buf_align: u8, ret_addr: usize) void {
+    if (early.owns(buf)) {
+        return early.allocator().rawFree(buf, buf_align, ret_addr);
+    }
+    current().rawFree(buf, buf_align, ret_addr);
+    quota.credit(buf.len);
+}
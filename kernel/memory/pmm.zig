@@ -0,0 +1,361 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const log = @import("kernel").utils.log.scoped(.pmm);
+const Error = @import("kernel").Error;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+const kassert = @import("kernel").utils.kassert;
+const options = @import("build_options");
+
+const memory = @import("memory.zig");
+
+pub const PAGE_SIZE = 4096;
+
+// NOTE:
+// Physical memory is tracked with one bit per frame, set while the frame is
+// in use. The bitmap covers everything up to the end of the highest usable
+// region and lives in the first usable region large enough to hold it,
+// reached through the higher half direct map.
+var bitmap: []u8 = &.{};
+var total_frames: usize = 0;
+var usable_frames: usize = 0;
+var used_frames: usize = 0;
+
+/// Who allocated a frame, recorded with `-Dframe-owners=true`.
+pub const Owner = enum(u8) {
+    free = 0,
+    unknown,
+    /// The PMM's own bitmap and owner table.
+    pmm,
+    heap,
+    page_tables,
+    tests,
+};
+
+/// One `Owner` per frame, empty unless frame owners are recorded. Lives
+/// right after the bitmap.
+var owners: []Owner = &.{};
+
+/// Where the bitmap and owner table live, never to be freed.
+var storage_base: usize = 0;
+var storage_size: usize = 0;
+
+/// Frame after the last allocation, so that scans don't start over on the
+/// frames that are most likely still taken.
+var search_start: usize = 0;
+
+/// Below this share of free frames (in percent) memory counts as low.
+const LOW_MEMORY_PERCENT = 5;
+
+var low_memory = false;
+var low_memory_warnings: usize = 0;
+
+var lock = SpinLock.init();
+
+pub const Stats = struct {
+    usable_frames: usize,
+    used_frames: usize,
+    /// How often free memory dropped below the low memory mark.
+    low_memory_warnings: usize,
+};
+
+/// Tracks whether free memory crossed the low memory mark, returns true
+/// when it just dropped below. Called with the lock held.
+fn updateLowMemory() bool {
+    const is_low = (usable_frames - used_frames) * 100 < usable_frames * LOW_MEMORY_PERCENT;
+    defer low_memory = is_low;
+
+    if (is_low and !low_memory) {
+        low_memory_warnings += 1;
+        return true;
+    }
+    return false;
+}
+
+fn warnLowMemory() void {
+    const current = stats();
+    log.warn("Low on memory: {} of {} frames left", .{ current.usable_frames - current.used_frames, current.usable_frames });
+}
+
+fn isUsed(frame: usize) bool {
+    return bitmap[frame / 8] & (@as(u8, 1) << @intCast(frame % 8)) != 0;
+}
+
+fn setUsed(frame: usize, used: bool) void {
+    const mask = @as(u8, 1) << @intCast(frame % 8);
+    if (used) {
+        bitmap[frame / 8] |= mask;
+    } else {
+        bitmap[frame / 8] &= ~mask;
+    }
+}
+
+fn setOwner(frame: usize, owner: Owner) void {
+    if (options.frame_owners) {
+        owners[frame] = owner;
+    }
+}
+
+pub fn init() Error!void {
+    const response = boot.memory_map_request.response orelse return error.NotFound;
+    const entries = response.entries();
+
+    var highest: usize = 0;
+    for (entries) |entry| {
+        if (entry.kind == .usable) {
+            highest = @max(highest, entry.base + entry.length);
+        }
+    }
+
+    total_frames = highest / PAGE_SIZE;
+    const bitmap_bytes = std.mem.alignForward(usize, (total_frames + 7) / 8, PAGE_SIZE);
+    const owners_bytes = if (options.frame_owners) std.mem.alignForward(usize, total_frames, PAGE_SIZE) else 0;
+    const bitmap_size = bitmap_bytes + owners_bytes;
+
+    const home = for (entries) |entry| {
+        if (entry.kind == .usable and entry.length >= bitmap_size) {
+            break entry.base;
+        }
+    } else return error.OutOfMemory;
+
+    const storage: [*]u8 = @ptrFromInt(memory.physicalToVirtual(home));
+    bitmap = storage[0..bitmap_bytes];
+    @memset(bitmap, 0xFF);
+    if (options.frame_owners) {
+        owners = @as([*]Owner, @ptrCast(storage + bitmap_bytes))[0..total_frames];
+        @memset(owners, .unknown);
+    }
+
+    for (entries) |entry| {
+        if (entry.kind != .usable) {
+            continue;
+        }
+
+        const first = std.mem.alignForward(usize, entry.base, PAGE_SIZE) / PAGE_SIZE;
+        const end = (entry.base + entry.length) / PAGE_SIZE;
+        for (first..end) |frame| {
+            setUsed(frame, false);
+            setOwner(frame, .free);
+        }
+        usable_frames += end - first;
+    }
+
+    storage_base = home;
+    storage_size = bitmap_size;
+    for (home / PAGE_SIZE..(home + bitmap_size) / PAGE_SIZE) |frame| {
+        setUsed(frame, true);
+        setOwner(frame, .pmm);
+    }
+    used_frames = bitmap_size / PAGE_SIZE;
+
+    log.info("Tracking {} MiB of usable memory", .{usable_frames * PAGE_SIZE / (1024 * 1024)});
+}
+
+/// Allocates `count` physically contiguous frames and returns the physical
+/// address of the first one.
+pub fn allocPages(count: usize) Error!usize {
+    return allocPagesFor(count, .unknown);
+}
+
+/// Like `allocPages`, recording `owner` as the one the frames belong to.
+pub fn allocPagesFor(count: usize, owner: Owner) Error!usize {
+    std.debug.assert(count > 0);
+
+    var became_low = false;
+    defer if (became_low) warnLowMemory();
+
+    lock.acquire();
+    defer lock.release();
+
+    var run: usize = 0;
+    for (0..total_frames) |i| {
+        const frame = (search_start + i) % total_frames;
+        // runs of free frames cannot wrap around the end of memory
+        if (frame == 0) {
+            run = 0;
+        }
+        if (isUsed(frame)) {
+            run = 0;
+            continue;
+        }
+
+        run += 1;
+        if (run == count) {
+            const first = frame + 1 - count;
+            for (first..frame + 1) |taken| {
+                setUsed(taken, true);
+                setOwner(taken, owner);
+            }
+            used_frames += count;
+            search_start = frame + 1;
+            became_low = updateLowMemory();
+            return first * PAGE_SIZE;
+        }
+    }
+
+    return error.OutOfMemory;
+}
+
+/// Returns `count` frames starting at `physical` to the allocator. The
+/// frames must have come from `allocPages`: freeing frames outside of
+/// usable memory or ones that are already free is a bug, it panics in debug
+/// builds and is ignored otherwise.
+pub fn freePages(physical: usize, count: usize) void {
+    kassert.that(physical % PAGE_SIZE == 0, "freeing unaligned frame 0x{x}", .{physical});
+
+    tryFreePages(physical, count) catch |err| {
+        const problem = switch (err) {
+            error.NotFound => "already free",
+            else => "not usable memory",
+        };
+        kassert.debug(false, "freeing frames 0x{x}-0x{x}: {s}", .{ physical, physical + count * PAGE_SIZE, problem });
+        log.warn("Ignoring free of frames 0x{x}-0x{x}: {s}", .{ physical, physical + count * PAGE_SIZE, problem });
+    };
+}
+
+/// Like `freePages`, but a bad range is reported instead of being treated
+/// as a bug: `error.InvalidArgument` when the frames are not all inside one
+/// usable memory map entry (reserved, ACPI, framebuffer and kernel memory
+/// are not) or hold the bitmap, `error.NotFound` when any of them is
+/// already free. Nothing is freed then.
+pub fn tryFreePages(physical: usize, count: usize) Error!void {
+    if (physical % PAGE_SIZE != 0 or count == 0) {
+        return error.InvalidArgument;
+    }
+
+    lock.acquire();
+    defer lock.release();
+
+    const first = physical / PAGE_SIZE;
+    if (count > total_frames or first > total_frames - count or !isAllocatable(physical, count * PAGE_SIZE)) {
+        return error.InvalidArgument;
+    }
+    for (first..first + count) |frame| {
+        if (!isUsed(frame)) {
+            return error.NotFound;
+        }
+    }
+
+    for (first..first + count) |frame| {
+        setUsed(frame, false);
+        setOwner(frame, .free);
+    }
+    used_frames -= count;
+    _ = updateLowMemory();
+}
+
+/// Whether `length` bytes at `physical` lie inside a single usable memory
+/// map entry, clear of the bitmap.
+fn isAllocatable(physical: usize, length: usize) bool {
+    const end = physical + length;
+    if (physical < storage_base + storage_size and end > storage_base) {
+        return false;
+    }
+
+    const response = boot.memory_map_request.response orelse return false;
+    for (response.entries()) |entry| {
+        if (entry.kind == .usable and physical >= entry.base and end <= entry.base + entry.length) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Who allocated the frame at `physical`, null unless frame owners are
+/// recorded.
+pub fn ownerOf(physical: usize) ?Owner {
+    const frame = physical / PAGE_SIZE;
+    if (!options.frame_owners or frame >= total_frames) {
+        return null;
+    }
+    return owners[frame];
+}
+
+/// How many frames every owner holds, null unless frame owners are
+/// recorded.
+pub fn ownerCounts() ?std.enums.EnumArray(Owner, usize) {
+    if (!options.frame_owners) {
+        return null;
+    }
+
+    lock.acquire();
+    defer lock.release();
+
+    var counts = std.enums.EnumArray(Owner, usize).initFill(0);
+    for (owners) |frame_owner| {
+        counts.getPtr(frame_owner).* += 1;
+    }
+    return counts;
+}
+
+/// Characters in the utilization bar of a region.
+const BAR_WIDTH = 32;
+
+/// Fills `bar` with one character per slice of the frames `first..end`:
+/// '.' when all of them are free, '#' when all are used, '+' in between.
+/// Returns how many frames are used. Called with the lock held.
+fn usage(first: usize, end: usize, bar: *[BAR_WIDTH]u8) usize {
+    var used: usize = 0;
+    const count = end - first;
+    for (bar, 0..) |*cell, index| {
+        const slice_start = first + count * index / BAR_WIDTH;
+        const slice_end = first + count * (index + 1) / BAR_WIDTH;
+
+        var slice_used: usize = 0;
+        for (slice_start..slice_end) |frame| {
+            if (isUsed(frame)) {
+                slice_used += 1;
+            }
+        }
+        used += slice_used;
+
+        cell.* = if (slice_end == slice_start)
+            ' '
+        else if (slice_used == 0)
+            '.'
+        else if (slice_used == slice_end - slice_start)
+            '#'
+        else
+            '+';
+    }
+    return used;
+}
+
+/// Writes every memory map entry with its type and size, and for usable
+/// memory how many frames are used and free along with a utilization bar.
+pub fn dump(writer: anytype) !void {
+    const response = boot.memory_map_request.response orelse return error.NotFound;
+
+    for (response.entries()) |entry| {
+        try writer.print("  0x{x:0>12}-0x{x:0>12} {s:<22} {:>9} KiB", .{
+            entry.base,
+            entry.base + entry.length,
+            @tagName(entry.kind),
+            entry.length / 1024,
+        });
+        if (entry.kind != .usable) {
+            try writer.writeAll("\n");
+            continue;
+        }
+
+        const first = std.mem.alignForward(usize, entry.base, PAGE_SIZE) / PAGE_SIZE;
+        const end = (entry.base + entry.length) / PAGE_SIZE;
+        var bar: [BAR_WIDTH]u8 = undefined;
+        const used = blk: {
+            lock.acquire();
+            defer lock.release();
+            break :blk usage(first, end, &bar);
+        };
+        try writer.print(" {:>7} used {:>7} free [{s}]\n", .{ used, end - first - used, &bar });
+    }
+}
+
+pub fn stats() Stats {
+    lock.acquire();
+    defer lock.release();
+
+    return .{
+        .usable_frames = usable_frames,
+        .used_frames = used_frames,
+        .low_memory_warnings = low_memory_warnings,
+    };
+}
@@ -0,0 +1,150 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const testdev = @import("kernel").utils.testdev;
+
+const heap = @import("heap.zig");
+const memory = @import("memory.zig");
+const pmm = @import("pmm.zig");
+
+/// Large enough to bypass the heap's chunks and go to the PMM.
+const LARGE_ALLOCATION = 1024 * 1024;
+
+/// Takes every free frame, chaining them through their first word so that
+/// nothing else has to be allocated to remember them. Returns the last one.
+fn takeEverything() ?usize {
+    var last: ?usize = null;
+    while (pmm.allocPagesFor(1, .tests)) |physical| {
+        const link: *?usize = @ptrFromInt(memory.physicalToVirtual(physical));
+        link.* = last;
+        last = physical;
+    } else |_| {}
+    return last;
+}
+
+fn giveBack(last: ?usize) void {
+    var current = last;
+    while (current) |physical| {
+        const link: *?usize = @ptrFromInt(memory.physicalToVirtual(physical));
+        current = link.*;
+        pmm.freePages(physical, 1);
+    }
+}
+
+fn exhaustion() !void {
+    const before = pmm.stats();
+
+    const taken = takeEverything();
+    const exhausted = pmm.stats();
+    if (exhausted.used_frames != exhausted.usable_frames) {
+        giveBack(taken);
+        return error.FramesLeft;
+    }
+
+    // failures have to come back as errors, not as a crash or a bogus frame
+    const pages_failed = if (pmm.allocPagesFor(1, .tests)) |physical| blk: {
+        pmm.freePages(physical, 1);
+        break :blk false;
+    } else |err| err == error.OutOfMemory;
+
+    const large_failed = if (memory.allocator().alloc(u8, LARGE_ALLOCATION)) |buffer| blk: {
+        memory.allocator().free(buffer);
+        break :blk false;
+    } else |err| err == error.OutOfMemory;
+
+    giveBack(taken);
+
+    if (!pages_failed or !large_failed) {
+        return error.AllocatedFromNothing;
+    }
+    if (exhausted.low_memory_warnings == before.low_memory_warnings) {
+        return error.NoLowMemoryWarning;
+    }
+
+    const after = pmm.stats();
+    if (after.used_frames != before.used_frames) {
+        return error.Leaked;
+    }
+
+    // and the system has to work again once memory is back
+    const buffer = try memory.allocator().alloc(u8, LARGE_ALLOCATION);
+    memory.allocator().free(buffer);
+    try heap.verify();
+}
+
+fn ownerTags() !void {
+    const physical = try pmm.allocPagesFor(2, .tests);
+    const before = pmm.stats().used_frames;
+    pmm.freePages(physical, 2);
+
+    if (pmm.stats().used_frames != before - 2) {
+        return error.WrongCount;
+    }
+    // only recorded with -Dframe-owners=true
+    if (pmm.ownerOf(physical)) |owner| {
+        if (owner != .free) {
+            return error.StillOwned;
+        }
+    }
+
+    const again = try pmm.allocPagesFor(1, .tests);
+    defer pmm.freePages(again, 1);
+    if (pmm.ownerOf(again)) |owner| {
+        if (owner != .tests) {
+            return error.WrongOwner;
+        }
+    }
+}
+
+fn freeReserved() !void {
+    const response = boot.memory_map_request.response orelse return error.NoMemoryMap;
+    const before = pmm.stats().used_frames;
+
+    for (response.entries()) |entry| {
+        if (entry.kind == .usable) {
+            continue;
+        }
+        const first = std.mem.alignForward(usize, entry.base, pmm.PAGE_SIZE);
+        if (first + pmm.PAGE_SIZE > entry.base + entry.length) {
+            continue;
+        }
+
+        pmm.tryFreePages(first, 1) catch |err| {
+            if (err == error.InvalidArgument) {
+                continue;
+            }
+            return err;
+        };
+        return error.FreedReserved;
+    }
+
+    if (pmm.stats().used_frames != before) {
+        return error.WrongCount;
+    }
+}
+
+fn doubleFree() !void {
+    const physical = try pmm.allocPagesFor(2, .tests);
+    pmm.freePages(physical + pmm.PAGE_SIZE, 1);
+    const before = pmm.stats().used_frames;
+
+    // one of the two frames is still taken, neither may be freed
+    pmm.tryFreePages(physical, 2) catch |err| {
+        const after = pmm.stats().used_frames;
+        pmm.freePages(physical, 1);
+        if (err != error.NotFound) {
+            return err;
+        }
+        if (after != before) {
+            return error.WrongCount;
+        }
+        return;
+    };
+    return error.FreedTwice;
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "pmm.exhaustion", .func = exhaustion },
+    .{ .name = "pmm.owner_tags", .func = ownerTags },
+    .{ .name = "pmm.free_reserved", .func = freeReserved },
+    .{ .name = "pmm.double_free", .func = doubleFree },
+};
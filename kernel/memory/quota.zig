@@ -0,0 +1,61 @@
+const std = @import("std");
+const kassert = @import("kernel").utils.kassert;
+
+// NOTE:
+// Heap usage is charged to the `Account` of whoever is running. The account
+// pointer is `threadlocal`, so once there are threads each one's TLS block
+// carries its own and switching threads switches accounts for free; until
+// then everything runs on the boot thread and lands on `boot_account`
+// unless code opts into another one with `enter`. Memory is credited to the
+// account that frees it, so a buffer handed from one thread to another
+// moves its cost along. In builds with runtime safety going over an
+// account's `limit` panics, which points at the culprit with a backtrace
+// instead of the heap running dry somewhere else much later.
+
+pub const Account = struct {
+    name: []const u8,
+    /// Heap bytes currently charged.
+    allocated: usize = 0,
+    /// Most bytes ever charged at once.
+    peak: usize = 0,
+    /// Bytes past which to panic, 0 for no limit.
+    limit: usize = 0,
+};
+
+pub var boot_account = Account{ .name = "boot" };
+
+threadlocal var current_account: ?*Account = null;
+
+/// The account allocations are charged to right now.
+pub fn current() *Account {
+    return current_account orelse &boot_account;
+}
+
+/// Charges allocations to `account` until `leave` is called with the
+/// returned previous account.
+pub fn enter(account: *Account) *Account {
+    const previous = current();
+    current_account = account;
+    return previous;
+}
+
+pub fn leave(previous: *Account) void {
+    current_account = previous;
+}
+
+pub fn charge(bytes: usize) void {
+    const account = current();
+    account.allocated += bytes;
+    account.peak = @max(account.peak, account.allocated);
+
+    kassert.debug(account.limit == 0 or account.allocated <= account.limit, "{s} went over its heap quota: {} of {} bytes", .{
+        account.name,
+        account.allocated,
+        account.limit,
+    });
+}
+
+pub fn credit(bytes: usize) void {
+    const account = current();
+    account.allocated -|= bytes;
+}
@@ -0,0 +1,184 @@
+const std = @import("std");
+const kernel_image = @import("kernel").kernel_image;
+const Error = @import("kernel").Error;
+
+const memory = @import("memory.zig");
+
+// NOTE:
+// An `SgList` describes one logical transfer spread over buffers that stay
+// where they are. Each layer adds its own pieces (a header, the caller's
+// data) instead of copying everything into a buffer of its own, and at the
+// bottom `segments` turns the list into the physical ranges a device
+// scatters into or gathers from. The list never allocates, its storage
+// comes from whoever builds it. Outgoing packets are lists too: every
+// protocol puts its header in front of the pieces it was given, so the data
+// reaches the device where the caller left it, see `NetDevice.send`.
+
+pub const IoVec = struct {
+    base: [*]u8,
+    len: usize,
+
+    const Self = @This();
+
+    pub fn of(buffer: []u8) Self {
+        return .{ .base = buffer.ptr, .len = buffer.len };
+    }
+
+    pub fn slice(self: Self) []u8 {
+        return self.base[0..self.len];
+    }
+};
+
+/// A physically contiguous piece of a transfer, as a device sees it.
+pub const Segment = struct {
+    physical: u64,
+    len: usize,
+};
+
+pub const SgList = struct {
+    vecs: []IoVec,
+    len: usize = 0,
+
+    const Self = @This();
+
+    pub fn init(storage: []IoVec) Self {
+        return .{ .vecs = storage };
+    }
+
+    pub fn append(self: *Self, buffer: []u8) Error!void {
+        if (buffer.len == 0) {
+            return;
+        }
+        if (self.len == self.vecs.len) {
+            return error.OutOfMemory;
+        }
+
+        self.vecs[self.len] = IoVec.of(buffer);
+        self.len += 1;
+    }
+
+    /// Appends a buffer the list is only read from, such as data being
+    /// gathered for a device. Nothing may copy into the list afterwards.
+    pub fn appendConst(self: *Self, buffer: []const u8) Error!void {
+        return self.append(@constCast(buffer));
+    }
+
+    /// Appends every buffer of `other`, e.g. to put a header in front of
+    /// the pieces the layer above already has.
+    pub fn appendList(self: *Self, other: *const Self) Error!void {
+        for (other.items()) |vec| {
+            try self.append(vec.slice());
+        }
+    }
+
+    pub fn items(self: *const Self) []const IoVec {
+        return self.vecs[0..self.len];
+    }
+
+    /// Total number of bytes described by the list.
+    pub fn totalLength(self: *const Self) usize {
+        var total: usize = 0;
+        for (self.items()) |vec| {
+            total += vec.len;
+        }
+        return total;
+    }
+
+    /// Copies from `source` into the list starting `offset` bytes in.
+    /// Returns how many bytes fit.
+    pub fn copyFrom(self: *const Self, offset: usize, source: []const u8) usize {
+        var copied: usize = 0;
+        var cursor = Cursor.init(self, offset);
+        while (copied < source.len) {
+            const chunk = cursor.next(source.len - copied) orelse break;
+            @memcpy(chunk, source[copied..][0..chunk.len]);
+            copied += chunk.len;
+        }
+        return copied;
+    }
+
+    /// Copies out of the list starting `offset` bytes in into
+    /// `destination`. Returns how many bytes were there.
+    pub fn copyTo(self: *const Self, offset: usize, destination: []u8) usize {
+        var copied: usize = 0;
+        var cursor = Cursor.init(self, offset);
+        while (copied < destination.len) {
+            const chunk = cursor.next(destination.len - copied) orelse break;
+            @memcpy(destination[copied..][0..chunk.len], chunk);
+            copied += chunk.len;
+        }
+        return copied;
+    }
+
+    /// Translates the list into physical segments, merging neighbours that
+    /// are physically adjacent. Fails when `out` is too small.
+    pub fn segments(self: *const Self, out: []Segment) Error![]Segment {
+        var count: usize = 0;
+        for (self.items()) |vec| {
+            // the higher half direct map is physically contiguous, the
+            // kernel image too, so only pages of other mappings would have
+            // to be split
+            const physical = physicalAddress(@intFromPtr(vec.base)) orelse return error.InvalidArgument;
+
+            if (count != 0 and out[count - 1].physical + out[count - 1].len == physical) {
+                out[count - 1].len += vec.len;
+                continue;
+            }
+            if (count == out.len) {
+                return error.OutOfMemory;
+            }
+            out[count] = .{ .physical = physical, .len = vec.len };
+            count += 1;
+        }
+        return out[0..count];
+    }
+};
+
+fn physicalAddress(virtual: usize) ?u64 {
+    if (kernel_image.physicalAddress(virtual)) |physical| {
+        return physical;
+    }
+    if (virtual >= memory.physicalToVirtual(0)) {
+        return memory.virtualToPhysical(virtual);
+    }
+    return null;
+}
+
+/// Walks an `SgList` a chunk at a time.
+pub const Cursor = struct {
+    list: *const SgList,
+    index: usize = 0,
+    offset: usize = 0,
+
+    const Self = @This();
+
+    /// Starts `skip` bytes into the list.
+    pub fn init(list: *const SgList, skip: usize) Self {
+        var self = Self{ .list = list };
+        var remaining = skip;
+        while (self.index < list.len and remaining >= list.vecs[self.index].len) {
+            remaining -= list.vecs[self.index].len;
+            self.index += 1;
+        }
+        self.offset = remaining;
+        return self;
+    }
+
+    /// The next up to `max` contiguous bytes, null at the end of the list.
+    pub fn next(self: *Self, max: usize) ?[]u8 {
+        if (self.index == self.list.len or max == 0) {
+            return null;
+        }
+
+        const vec = self.list.vecs[self.index];
+        const len = @min(max, vec.len - self.offset);
+        const chunk = vec.slice()[self.offset..][0..len];
+
+        self.offset += len;
+        if (self.offset == vec.len) {
+            self.index += 1;
+            self.offset = 0;
+        }
+        return chunk;
+    }
+};
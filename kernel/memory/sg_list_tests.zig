@@ -0,0 +1,67 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const memory = @import("memory.zig");
+const sg_list = @import("sg_list.zig");
+
+const IoVec = sg_list.IoVec;
+const SgList = sg_list.SgList;
+const Segment = sg_list.Segment;
+
+fn copyAcrossVecs() !void {
+    var first: [3]u8 = undefined;
+    var second: [5]u8 = undefined;
+    var third: [4]u8 = undefined;
+
+    var storage: [3]IoVec = undefined;
+    var list = SgList.init(&storage);
+    try list.append(&first);
+    try list.append(&second);
+    try list.append(&third);
+
+    if (list.totalLength() != 12) {
+        return error.WrongLength;
+    }
+
+    // starts inside the first vec and ends inside the last one
+    if (list.copyFrom(2, "abcdefgh") != 8) {
+        return error.ShortCopy;
+    }
+
+    var out: [8]u8 = undefined;
+    if (list.copyTo(2, &out) != 8 or !std.mem.eql(u8, &out, "abcdefgh")) {
+        return error.Mismatch;
+    }
+    if (!std.mem.eql(u8, &second, "bcdef")) {
+        return error.Mismatch;
+    }
+
+    // running off the end copies what fits
+    if (list.copyFrom(10, "xyz") != 2) {
+        return error.OverlongCopy;
+    }
+}
+
+fn mergedSegments() !void {
+    const pages = try memory.allocPagesExact(2);
+    defer memory.freePagesExact(pages);
+
+    var storage: [2]IoVec = undefined;
+    var list = SgList.init(&storage);
+    try list.append(pages[0..100]);
+    try list.append(pages[100..]);
+
+    var out: [2]Segment = undefined;
+    const segments = try list.segments(&out);
+    if (segments.len != 1) {
+        return error.NotMerged;
+    }
+    if (segments[0].physical != memory.physicalAddress(pages) or segments[0].len != pages.len) {
+        return error.WrongSegment;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "sg_list.copy_across_vecs", .func = copyAcrossVecs },
+    .{ .name = "sg_list.merged_segments", .func = mergedSegments },
+};
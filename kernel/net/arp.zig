@@ -0,0 +1,397 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log.scoped(.net);
+const Error = @import("kernel").Error;
+
+const device = @import("device.zig");
+const NetDevice = device.NetDevice;
+const ethernet = @import("ethernet.zig");
+const interface = @import("interface.zig");
+
+// NOTE:
+// Resolves IPv4 addresses to MACs on Ethernet devices. Mappings are cached
+// per device for five minutes. A miss creates an incomplete entry and
+// broadcasts a request, repeated every second until a reply arrives or
+// three went unanswered. Packets sent to an address that is still being
+// resolved wait in a handful of shared slots, the oldest one giving way
+// when they run out, and go out once the reply comes in.
+//
+// Like every receiver, replies to us and requests for our address both
+// refresh the sender's entry, and requests for our address are answered.
+// Requests between other hosts only refresh entries that already exist so
+// that broadcasts on a busy segment don't flush the cache. Loopback needs
+// no resolution, frames on it go to the zero address.
+
+const PACKET_SIZE = 28;
+const HARDWARE_ETHERNET = 1;
+
+const CACHE_SIZE = 32;
+const ENTRY_LIFETIME_NS = 5 * 60 * std.time.ns_per_s;
+const RETRY_INTERVAL_NS = std.time.ns_per_s;
+const MAX_REQUESTS = 3;
+const MAX_PENDING = 4;
+
+pub const Operation = enum(u16) {
+    request = 1,
+    reply = 2,
+    _,
+};
+
+pub const Packet = struct {
+    operation: Operation,
+    sender_mac: [6]u8,
+    sender_address: [4]u8,
+    target_mac: [6]u8,
+    target_address: [4]u8,
+};
+
+/// Accepts Ethernet/IPv4 packets only.
+pub fn parse(bytes: []const u8) Error!Packet {
+    if (bytes.len < PACKET_SIZE) {
+        return error.Corrupted;
+    }
+    if (std.mem.readInt(u16, bytes[0..2], .big) != HARDWARE_ETHERNET or
+        std.mem.readInt(u16, bytes[2..4], .big) != @intFromEnum(ethernet.EtherType.ipv4) or
+        bytes[4] != 6 or bytes[5] != 4)
+    {
+        return error.Unsupported;
+    }
+    return .{
+        .operation = @enumFromInt(std.mem.readInt(u16, bytes[6..8], .big)),
+        .sender_mac = bytes[8..14].*,
+        .sender_address = bytes[14..18].*,
+        .target_mac = bytes[18..24].*,
+        .target_address = bytes[24..28].*,
+    };
+}
+
+pub fn encode(packet: Packet) [PACKET_SIZE]u8 {
+    var bytes: [PACKET_SIZE]u8 = undefined;
+    std.mem.writeInt(u16, bytes[0..2], HARDWARE_ETHERNET, .big);
+    std.mem.writeInt(u16, bytes[2..4], @intFromEnum(ethernet.EtherType.ipv4), .big);
+    bytes[4] = 6;
+    bytes[5] = 4;
+    std.mem.writeInt(u16, bytes[6..8], @intFromEnum(packet.operation), .big);
+    bytes[8..14].* = packet.sender_mac;
+    bytes[14..18].* = packet.sender_address;
+    bytes[18..24].* = packet.target_mac;
+    bytes[24..28].* = packet.target_address;
+    return bytes;
+}
+
+pub const State = enum {
+    free,
+    /// A request is out, `mac` means nothing yet.
+    incomplete,
+    resolved,
+};
+
+pub const Entry = struct {
+    state: State = .free,
+    device: ?*NetDevice = null,
+    address: [4]u8 = .{ 0, 0, 0, 0 },
+    mac: [6]u8 = .{ 0, 0, 0, 0, 0, 0 },
+    /// When the mapping was learned, or the last request went out.
+    updated_ns: u64 = 0,
+    requests: u8 = 0,
+};
+
+/// Fixed size, the least recently updated entry makes room for a new one.
+/// Times are passed in so the cache can be tested without a clock.
+pub const Cache = struct {
+    entries: [CACHE_SIZE]Entry = [_]Entry{.{}} ** CACHE_SIZE,
+
+    const Self = @This();
+
+    pub fn find(self: *Self, net_device: *const NetDevice, address: [4]u8) ?*Entry {
+        for (&self.entries) |*entry| {
+            if (entry.state != .free and entry.device == net_device and std.mem.eql(u8, &entry.address, &address)) {
+                return entry;
+            }
+        }
+        return null;
+    }
+
+    /// The MAC of `address` if it is known and not stale.
+    pub fn get(self: *Self, net_device: *const NetDevice, address: [4]u8, now_ns: u64) ?[6]u8 {
+        const entry = self.find(net_device, address) orelse return null;
+        if (entry.state != .resolved or now_ns -| entry.updated_ns >= ENTRY_LIFETIME_NS) {
+            return null;
+        }
+        return entry.mac;
+    }
+
+    /// Refreshes the entry for `address` if there is one, returns whether
+    /// there was.
+    pub fn update(self: *Self, net_device: *NetDevice, address: [4]u8, mac: [6]u8, now_ns: u64) bool {
+        const entry = self.find(net_device, address) orelse return false;
+        entry.state = .resolved;
+        entry.mac = mac;
+        entry.updated_ns = now_ns;
+        entry.requests = 0;
+        return true;
+    }
+
+    pub fn insert(self: *Self, net_device: *NetDevice, address: [4]u8, mac: [6]u8, now_ns: u64) void {
+        if (self.update(net_device, address, mac, now_ns)) {
+            return;
+        }
+        self.claim(net_device, address).* = .{
+            .state = .resolved,
+            .device = net_device,
+            .address = address,
+            .mac = mac,
+            .updated_ns = now_ns,
+        };
+    }
+
+    /// A slot for a new entry: a free one, or the least recently updated.
+    pub fn claim(self: *Self, net_device: *NetDevice, address: [4]u8) *Entry {
+        var oldest = &self.entries[0];
+        for (&self.entries) |*entry| {
+            if (entry.state == .free) {
+                oldest = entry;
+                break;
+            }
+            if (entry.updated_ns < oldest.updated_ns) {
+                oldest = entry;
+            }
+        }
+        oldest.* = .{ .state = .incomplete, .device = net_device, .address = address };
+        return oldest;
+    }
+
+    /// Frees resolved entries that have gone stale.
+    pub fn expire(self: *Self, now_ns: u64) void {
+        for (&self.entries) |*entry| {
+            if (entry.state == .resolved and now_ns -| entry.updated_ns >= ENTRY_LIFETIME_NS) {
+                entry.* = .{};
+            }
+        }
+    }
+
+    /// Forgets every entry of `net_device`, or all of them.
+    pub fn flush(self: *Self, net_device: ?*const NetDevice) void {
+        for (&self.entries) |*entry| {
+            if (net_device == null or entry.device == net_device) {
+                entry.* = .{};
+            }
+        }
+    }
+};
+
+/// A packet waiting for its next hop to be resolved.
+const Pending = struct {
+    device: ?*NetDevice = null,
+    address: [4]u8 = undefined,
+    ether_type: ethernet.EtherType = undefined,
+    queued_ns: u64 = 0,
+    length: usize = 0,
+    data: [ethernet.MAX_FRAME_SIZE - ethernet.HEADER_SIZE]u8 = undefined,
+};
+
+var cache = Cache{};
+var pending = [_]Pending{.{}} ** MAX_PENDING;
+var retry_timer = time.Timer{};
+
+var protocol = ethernet.Protocol{
+    .ether_type = .arp,
+    .handler = handle,
+};
+
+pub fn init() void {
+    ethernet.registerProtocol(&protocol);
+    retry_timer.periodic(RETRY_INTERVAL_NS, age);
+}
+
+/// Sends `payload` to the host with address `next_hop` on `net_device`,
+/// resolving its MAC first if need be. This is how IP gets its packets on
+/// the wire.
+pub fn send(net_device: *NetDevice, next_hop: [4]u8, ether_type: ethernet.EtherType, payload: *const memory.SgList) Error!void {
+    if (payload.totalLength() > net_device.mtu) {
+        return error.InvalidArgument;
+    }
+    if (net_device.kind == .loopback) {
+        return ethernet.send(net_device, .{ 0, 0, 0, 0, 0, 0 }, ether_type, payload);
+    }
+    if (std.mem.eql(u8, &next_hop, &.{ 255, 255, 255, 255 })) {
+        return ethernet.send(net_device, ethernet.BROADCAST, ether_type, payload);
+    }
+
+    const mac = blk: {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        if (cache.get(net_device, next_hop, time.nowNs())) |known| {
+            break :blk known;
+        }
+        try startResolving(net_device, next_hop);
+        queue(net_device, next_hop, ether_type, payload);
+        return;
+    };
+    try ethernet.send(net_device, mac, ether_type, payload);
+}
+
+/// The MAC of `address` on `net_device` if it is known. Starts resolving
+/// it otherwise, a later call may have the answer.
+pub fn resolve(net_device: *NetDevice, address: [4]u8) Error!?[6]u8 {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    if (cache.get(net_device, address, time.nowNs())) |mac| {
+        return mac;
+    }
+    try startResolving(net_device, address);
+    return null;
+}
+
+/// Interrupts must be disabled.
+fn startResolving(net_device: *NetDevice, address: [4]u8) Error!void {
+    const entry = cache.find(net_device, address) orelse cache.claim(net_device, address);
+    if (entry.state == .incomplete and entry.requests != 0) {
+        // already asked, the timer keeps asking
+        return;
+    }
+    entry.state = .incomplete;
+    try sendRequest(entry);
+}
+
+fn sendRequest(entry: *Entry) Error!void {
+    const net_device = entry.device.?;
+    // counted even when it can't go out, so the timer gives up eventually
+    entry.requests += 1;
+    entry.updated_ns = time.nowNs();
+
+    const our_address = interface.addressOf(net_device) orelse return error.NotFound;
+    const request = encode(.{
+        .operation = .request,
+        .sender_mac = net_device.mac,
+        .sender_address = our_address,
+        .target_mac = .{ 0, 0, 0, 0, 0, 0 },
+        .target_address = entry.address,
+    });
+    try sendBytes(net_device, ethernet.BROADCAST, .arp, &request);
+}
+
+/// `ethernet.send` for a payload in one piece.
+fn sendBytes(net_device: *NetDevice, destination: [6]u8, ether_type: ethernet.EtherType, bytes: []const u8) Error!void {
+    var vecs: [1]memory.IoVec = undefined;
+    var payload = memory.SgList.init(&vecs);
+    try payload.appendConst(bytes);
+    try ethernet.send(net_device, destination, ether_type, &payload);
+}
+
+/// Interrupts must be disabled.
+fn queue(net_device: *NetDevice, address: [4]u8, ether_type: ethernet.EtherType, payload: *const memory.SgList) void {
+    var slot = &pending[0];
+    for (&pending) |*candidate| {
+        if (candidate.device == null) {
+            slot = candidate;
+            break;
+        }
+        if (candidate.queued_ns < slot.queued_ns) {
+            slot = candidate;
+        }
+    }
+    slot.* = .{
+        .device = net_device,
+        .address = address,
+        .ether_type = ether_type,
+        .queued_ns = time.nowNs(),
+    };
+    slot.length = payload.copyTo(0, &slot.data);
+}
+
+/// Sends, or drops when `mac` is null, whatever waits for `address`.
+/// Interrupts must be disabled.
+fn flushPending(net_device: *NetDevice, address: [4]u8, mac: ?[6]u8) void {
+    for (&pending) |*slot| {
+        if (slot.device != net_device or !std.mem.eql(u8, &slot.address, &address)) {
+            continue;
+        }
+        if (mac) |destination| {
+            sendBytes(net_device, destination, slot.ether_type, slot.data[0..slot.length]) catch |err| {
+                log.debug("{s}: dropping a queued packet: {s}", .{ net_device.name(), @errorName(err) });
+            };
+        }
+        slot.* = .{};
+    }
+}
+
+fn handle(net_device: *NetDevice, _: *const ethernet.Header, payload: []const u8) void {
+    const packet = parse(payload) catch return;
+    const our_address = interface.addressOf(net_device) orelse return;
+    // probes announce nothing about the sender
+    if (std.mem.eql(u8, &packet.sender_address, &.{ 0, 0, 0, 0 })) {
+        return;
+    }
+
+    const now = time.nowNs();
+    const for_us = std.mem.eql(u8, &packet.target_address, &our_address);
+    var known = cache.update(net_device, packet.sender_address, packet.sender_mac, now);
+    if (!known and for_us) {
+        cache.insert(net_device, packet.sender_address, packet.sender_mac, now);
+        known = true;
+    }
+    if (known) {
+        flushPending(net_device, packet.sender_address, packet.sender_mac);
+    }
+
+    if (for_us and packet.operation == .request) {
+        const reply = encode(.{
+            .operation = .reply,
+            .sender_mac = net_device.mac,
+            .sender_address = our_address,
+            .target_mac = packet.sender_mac,
+            .target_address = packet.sender_address,
+        });
+        sendBytes(net_device, packet.sender_mac, .arp, &reply) catch |err| {
+            log.debug("{s}: failed to answer ARP: {s}", .{ net_device.name(), @errorName(err) });
+        };
+    }
+}
+
+/// Runs every second from the timer: repeats unanswered requests, gives up
+/// on addresses that stay silent and drops stale mappings.
+fn age(_: *time.Timer) void {
+    const now = time.nowNs();
+    cache.expire(now);
+
+    for (&cache.entries) |*entry| {
+        if (entry.state != .incomplete) {
+            continue;
+        }
+        if (entry.requests >= MAX_REQUESTS) {
+            log.debug("{s}: no ARP reply from {}", .{ entry.device.?.name(), interface.formatAddress(entry.address) });
+            flushPending(entry.device.?, entry.address, null);
+            entry.* = .{};
+            continue;
+        }
+        sendRequest(entry) catch {};
+    }
+}
+
+/// Lists the cache.
+pub fn dump(writer: anytype) !void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const now = time.nowNs();
+    for (cache.entries) |entry| {
+        switch (entry.state) {
+            .free => {},
+            .incomplete => try writer.print("{s:<6} {} (incomplete)\n", .{
+                entry.device.?.name(),
+                interface.formatAddress(entry.address),
+            }),
+            .resolved => try writer.print("{s:<6} {} {}, {}s left\n", .{
+                entry.device.?.name(),
+                interface.formatAddress(entry.address),
+                device.formatMac(entry.mac),
+                (ENTRY_LIFETIME_NS -| (now -| entry.updated_ns)) / std.time.ns_per_s,
+            }),
+        }
+    }
+}
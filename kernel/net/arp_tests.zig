@@ -0,0 +1,101 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+const Error = @import("kernel").Error;
+const SgList = @import("kernel").memory.SgList;
+
+const arp = @import("arp.zig");
+const ethernet = @import("ethernet.zig");
+const NetDevice = @import("device.zig").NetDevice;
+
+const MINUTE = 60 * std.time.ns_per_s;
+
+fn discard(_: *NetDevice, _: *const SgList) Error!void {}
+
+/// Never registered, the cache only compares the pointers.
+var first_device = NetDevice{ .mac = .{ 0x52, 0x54, 0, 0, 0, 1 }, .transmit = discard };
+var second_device = NetDevice{ .mac = .{ 0x52, 0x54, 0, 0, 0, 2 }, .transmit = discard };
+
+fn packets() !void {
+    const request = arp.Packet{
+        .operation = .request,
+        .sender_mac = .{ 0x52, 0x55, 0x0a, 0x00, 0x02, 0x02 },
+        .sender_address = .{ 10, 0, 2, 2 },
+        .target_mac = .{ 0, 0, 0, 0, 0, 0 },
+        .target_address = .{ 10, 0, 2, 15 },
+    };
+    const bytes = arp.encode(request);
+    if (!std.mem.eql(u8, bytes[0..8], &.{ 0, 1, 8, 0, 6, 4, 0, 1 })) {
+        return error.WrongHeader;
+    }
+    const parsed = try arp.parse(&bytes);
+    if (!std.meta.eql(parsed, request)) {
+        return error.RoundTrip;
+    }
+
+    // token ring hardware, and a truncated packet
+    var other = bytes;
+    other[1] = 6;
+    if (arp.parse(&other)) |_| {
+        return error.AcceptedHardware;
+    } else |err| if (err != error.Unsupported) {
+        return err;
+    }
+    if (arp.parse(bytes[0..20])) |_| {
+        return error.AcceptedTruncated;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+
+    var frame_buffer: [64]u8 = undefined;
+    const frame = try ethernet.build(&frame_buffer, .{
+        .destination = ethernet.BROADCAST,
+        .source = first_device.mac,
+        .ether_type = .arp,
+    }, &bytes);
+    const parsed_frame = try ethernet.parse(frame);
+    if (parsed_frame.header.ether_type != .arp or !ethernet.isBroadcast(parsed_frame.header.destination) or parsed_frame.payload.len != bytes.len) {
+        return error.WrongFrame;
+    }
+}
+
+fn cacheEntries() !void {
+    var cache = arp.Cache{};
+    const gateway = [4]u8{ 10, 0, 2, 2 };
+    const mac = [6]u8{ 0x52, 0x55, 0x0a, 0x00, 0x02, 0x02 };
+
+    cache.insert(&first_device, gateway, mac, MINUTE);
+    if (!std.mem.eql(u8, &(cache.get(&first_device, gateway, 2 * MINUTE) orelse return error.Missing), &mac)) {
+        return error.WrongMac;
+    }
+    // mappings are per device
+    if (cache.get(&second_device, gateway, 2 * MINUTE) != null) {
+        return error.LeakedAcrossDevices;
+    }
+    // an unknown sender doesn't get an entry from `update`
+    if (cache.update(&first_device, .{ 10, 0, 2, 3 }, mac, MINUTE)) {
+        return error.UpdatedMissing;
+    }
+
+    // stale after five minutes, and gone once expired
+    if (cache.get(&first_device, gateway, 6 * MINUTE) != null) {
+        return error.Stale;
+    }
+    cache.expire(6 * MINUTE);
+    if (cache.find(&first_device, gateway) != null) {
+        return error.NotExpired;
+    }
+
+    // a full cache evicts the least recently updated entry
+    for (0..32) |index| {
+        cache.insert(&first_device, .{ 10, 0, 3, @intCast(index) }, mac, MINUTE + index);
+    }
+    cache.insert(&first_device, gateway, mac, 2 * MINUTE);
+    if (cache.find(&first_device, .{ 10, 0, 3, 0 }) != null or cache.find(&first_device, .{ 10, 0, 3, 1 }) == null) {
+        return error.WrongEviction;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "arp.packets", .func = packets },
+    .{ .name = "arp.cache", .func = cacheEntries },
+};
@@ -0,0 +1,216 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log.scoped(.net);
+const Error = @import("kernel").Error;
+const SgList = @import("kernel").memory.SgList;
+
+// NOTE:
+// Everything that carries Ethernet frames, network cards and the loopback
+// device alike, registers a `NetDevice` and the protocol stack only ever
+// talks to those. A driver embeds one, fills in how to transmit and hands
+// every frame it receives to `receive`, which passes it on to whatever
+// handler the stack installed. Frames are Ethernet II frames without the
+// FCS in both directions, the loopback device included.
+//
+// Receiving happens in interrupt context and frames are only valid during
+// the handler call. A frame to transmit is an `SgList` of the pieces the
+// layers above built, which the driver gathers into its own buffer before
+// returning, so the caller's buffers can be reused right away. Transmitting
+// is safe from a receive handler.
+
+pub const ETHERNET_HEADER_SIZE = 14;
+
+pub const Kind = enum {
+    ethernet,
+    loopback,
+};
+
+pub const Stats = struct {
+    received: u64 = 0,
+    received_bytes: u64 = 0,
+    transmitted: u64 = 0,
+    transmitted_bytes: u64 = 0,
+    /// Frames the device got with errors or had to drop itself.
+    receive_errors: u64 = 0,
+    /// Frames that could not be queued, e.g. with the transmit ring full.
+    transmit_errors: u64 = 0,
+};
+
+pub const ReceiveHandler = *const fn (device: *NetDevice, frame: []const u8) void;
+
+const MAX_NAME = 8;
+
+pub const NetDevice = struct {
+    kind: Kind = .ethernet,
+    mac: [6]u8,
+    /// Largest payload after the Ethernet header.
+    mtu: usize = 1500,
+    /// Queues `frame` for sending, it is copied out of the list before
+    /// this returns.
+    transmit: *const fn (device: *NetDevice, frame: *const SgList) Error!void,
+    /// Null for devices whose link is always up.
+    link_up: ?*const fn (device: *NetDevice) bool = null,
+    /// Picks up received frames without waiting for the interrupt, for
+    /// devices that have none.
+    poll: ?*const fn (device: *NetDevice) void = null,
+
+    // filled in by `register`
+    name_buffer: [MAX_NAME]u8 = undefined,
+    name_length: usize = 0,
+    receive_handler: ?ReceiveHandler = null,
+    stats: Stats = .{},
+    next: ?*NetDevice = null,
+
+    const Self = @This();
+
+    /// `lo` for the loopback device, `eth0`, `eth1`... for the others in
+    /// the order they were registered.
+    pub fn name(self: *const Self) []const u8 {
+        return self.name_buffer[0..self.name_length];
+    }
+
+    pub fn maxFrameSize(self: *const Self) usize {
+        return self.mtu + ETHERNET_HEADER_SIZE;
+    }
+
+    /// Sends the frame `frame` describes, which starts with its Ethernet
+    /// header.
+    pub fn send(self: *Self, frame: *const SgList) Error!void {
+        const length = frame.totalLength();
+        if (length < ETHERNET_HEADER_SIZE or length > self.maxFrameSize()) {
+            return error.InvalidArgument;
+        }
+
+        self.transmit(self, frame) catch |err| {
+            _ = @atomicRmw(u64, &self.stats.transmit_errors, .Add, 1, .monotonic);
+            return err;
+        };
+        _ = @atomicRmw(u64, &self.stats.transmitted, .Add, 1, .monotonic);
+        _ = @atomicRmw(u64, &self.stats.transmitted_bytes, .Add, length, .monotonic);
+    }
+
+    /// Called by the driver, in interrupt context, for every frame it
+    /// received.
+    pub fn receive(self: *Self, frame: []const u8) void {
+        _ = @atomicRmw(u64, &self.stats.received, .Add, 1, .monotonic);
+        _ = @atomicRmw(u64, &self.stats.received_bytes, .Add, frame.len, .monotonic);
+        if (self.receive_handler) |handler| {
+            handler(self, frame);
+        }
+    }
+
+    /// Called by the driver for every frame it had to throw away.
+    pub fn receiveError(self: *Self) void {
+        _ = @atomicRmw(u64, &self.stats.receive_errors, .Add, 1, .monotonic);
+    }
+
+    /// Calls `handler` with every frame received from now on.
+    pub fn setReceiveHandler(self: *Self, handler: ?ReceiveHandler) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        self.receive_handler = handler;
+    }
+
+    pub fn isLinkUp(self: *Self) bool {
+        const link_up = self.link_up orelse return true;
+        return link_up(self);
+    }
+
+    pub fn statistics(self: *const Self) Stats {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        return self.stats;
+    }
+};
+
+var first: ?*NetDevice = null;
+var last: ?*NetDevice = null;
+var ethernet_count: usize = 0;
+
+/// Names `device` and makes it visible to the protocol stack.
+pub fn register(device: *NetDevice) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const device_name = switch (device.kind) {
+        .loopback => std.fmt.bufPrint(&device.name_buffer, "lo", .{}),
+        .ethernet => std.fmt.bufPrint(&device.name_buffer, "eth{}", .{ethernet_count}),
+    } catch unreachable;
+    device.name_length = device_name.len;
+    if (device.kind == .ethernet) {
+        ethernet_count += 1;
+    }
+
+    device.next = null;
+    if (last) |tail| {
+        tail.next = device;
+    } else {
+        first = device;
+    }
+    last = device;
+
+    log.info("{s}: MAC {}, MTU {}", .{ device.name(), formatMac(device.mac), device.mtu });
+}
+
+/// Head of the list in registration order, follow `next` for the rest.
+pub fn firstDevice() ?*NetDevice {
+    return first;
+}
+
+pub fn find(device_name: []const u8) ?*NetDevice {
+    var device = first;
+    while (device) |current| : (device = current.next) {
+        if (std.mem.eql(u8, current.name(), device_name)) {
+            return current;
+        }
+    }
+    return null;
+}
+
+/// Polls every device that has no interrupt.
+pub fn pollAll() void {
+    var device = first;
+    while (device) |current| : (device = current.next) {
+        if (current.poll) |poll| {
+            poll(current);
+        }
+    }
+}
+
+fn formatMacFn(mac: [6]u8, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+    for (mac, 0..) |byte, index| {
+        if (index != 0) {
+            try writer.writeByte(':');
+        }
+        try writer.print("{x:0>2}", .{byte});
+    }
+}
+
+/// Formats a MAC address the usual way, `52:54:00:12:34:56`.
+pub fn formatMac(mac: [6]u8) std.fmt.Formatter(formatMacFn) {
+    return .{ .data = mac };
+}
+
+/// Lists every device with its address, link state and counters.
+pub fn dump(writer: anytype) !void {
+    var device = first;
+    while (device) |current| : (device = current.next) {
+        const stats = current.statistics();
+        try writer.print("{s:<6} {} mtu {} link {s}\n", .{
+            current.name(),
+            formatMac(current.mac),
+            current.mtu,
+            if (current.isLinkUp()) "up" else "down",
+        });
+        try writer.print("       rx {} frames {} bytes {} errors, tx {} frames {} bytes {} errors\n", .{
+            stats.received,
+            stats.received_bytes,
+            stats.receive_errors,
+            stats.transmitted,
+            stats.transmitted_bytes,
+            stats.transmit_errors,
+        });
+    }
+}
@@ -0,0 +1,158 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log.scoped(.net);
+const Error = @import("kernel").Error;
+const memory = @import("kernel").memory;
+
+const device = @import("device.zig");
+const NetDevice = device.NetDevice;
+
+// NOTE:
+// Ethernet II framing. Every device's receive handler lands in `receive`,
+// which drops frames addressed to someone else and hands the payload to
+// the protocol registered for the frame's EtherType. IEEE 802.3 frames,
+// which carry a length where the EtherType would be, are dropped, nothing
+// speaks LLC here. Outgoing frames are `SgList`s of the header and the
+// pieces the protocols above put together, nothing copies them into one
+// buffer before the driver does.
+
+pub const HEADER_SIZE = device.ETHERNET_HEADER_SIZE;
+pub const MAX_FRAME_SIZE = 1514;
+/// Most pieces a frame is sent in: the Ethernet, IP and transport headers
+/// and the data.
+pub const MAX_FRAME_VECS = 4;
+
+pub const BROADCAST = [6]u8{ 0xff, 0xff, 0xff, 0xff, 0xff, 0xff };
+
+pub const EtherType = enum(u16) {
+    ipv4 = 0x0800,
+    arp = 0x0806,
+    ipv6 = 0x86dd,
+    _,
+};
+
+/// Values below this are 802.3 lengths, not EtherTypes.
+const MIN_ETHER_TYPE = 0x0600;
+
+pub const Header = struct {
+    destination: [6]u8,
+    source: [6]u8,
+    ether_type: EtherType,
+};
+
+pub const Frame = struct {
+    header: Header,
+    payload: []const u8,
+};
+
+pub fn parse(frame: []const u8) Error!Frame {
+    if (frame.len < HEADER_SIZE) {
+        return error.Corrupted;
+    }
+    const ether_type = std.mem.readInt(u16, frame[12..14], .big);
+    if (ether_type < MIN_ETHER_TYPE) {
+        return error.Unsupported;
+    }
+    return .{
+        .header = .{
+            .destination = frame[0..6].*,
+            .source = frame[6..12].*,
+            .ether_type = @enumFromInt(ether_type),
+        },
+        .payload = frame[HEADER_SIZE..],
+    };
+}
+
+/// Writes `header` and `payload` into `buffer`, returns the frame.
+pub fn build(buffer: []u8, header: Header, payload: []const u8) Error![]u8 {
+    const length = HEADER_SIZE + payload.len;
+    if (length > buffer.len) {
+        return error.InvalidArgument;
+    }
+    writeHeader(buffer[0..HEADER_SIZE], header);
+    @memcpy(buffer[HEADER_SIZE..length], payload);
+    return buffer[0..length];
+}
+
+fn writeHeader(buffer: *[HEADER_SIZE]u8, header: Header) void {
+    buffer[0..6].* = header.destination;
+    buffer[6..12].* = header.source;
+    std.mem.writeInt(u16, buffer[12..14], @intFromEnum(header.ether_type), .big);
+}
+
+pub fn isBroadcast(mac: [6]u8) bool {
+    return std.mem.eql(u8, &mac, &BROADCAST);
+}
+
+/// Group addresses have the lowest bit of the first byte set, broadcast
+/// included.
+pub fn isMulticast(mac: [6]u8) bool {
+    return mac[0] & 1 != 0;
+}
+
+/// Sends `payload` from `net_device` to `destination`.
+pub fn send(net_device: *NetDevice, destination: [6]u8, ether_type: EtherType, payload: *const memory.SgList) Error!void {
+    if (payload.totalLength() > net_device.mtu) {
+        return error.InvalidArgument;
+    }
+    var header: [HEADER_SIZE]u8 = undefined;
+    writeHeader(&header, .{
+        .destination = destination,
+        .source = net_device.mac,
+        .ether_type = ether_type,
+    });
+
+    // the payload stays where the protocol built it
+    var vecs: [MAX_FRAME_VECS]memory.IoVec = undefined;
+    var frame = memory.SgList.init(&vecs);
+    try frame.append(&header);
+    try frame.appendList(payload);
+    try net_device.send(&frame);
+}
+
+pub const Handler = *const fn (net_device: *NetDevice, header: *const Header, payload: []const u8) void;
+
+/// A protocol carried directly in Ethernet frames.
+pub const Protocol = struct {
+    ether_type: EtherType,
+    /// Called in interrupt context, the payload is only valid during the
+    /// call.
+    handler: Handler,
+    next: ?*Protocol = null,
+};
+
+var protocols: ?*Protocol = null;
+
+pub fn registerProtocol(protocol: *Protocol) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    protocol.next = protocols;
+    protocols = protocol;
+}
+
+/// The receive handler of every device.
+pub fn receive(net_device: *NetDevice, bytes: []const u8) void {
+    const frame = parse(bytes) catch |err| {
+        if (err == error.Corrupted) {
+            net_device.receiveError();
+        }
+        return;
+    };
+
+    // a card may pass on frames for other stations, loopback has no
+    // address of its own
+    const destination = frame.header.destination;
+    if (net_device.kind != .loopback and !isMulticast(destination) and !std.mem.eql(u8, &destination, &net_device.mac)) {
+        return;
+    }
+
+    var protocol = protocols;
+    while (protocol) |current| : (protocol = current.next) {
+        if (current.ether_type == frame.header.ether_type) {
+            current.handler(net_device, &frame.header, frame.payload);
+            return;
+        }
+    }
+    log.debug("{s}: dropping frame with EtherType 0x{x:0>4}", .{ net_device.name(), @intFromEnum(frame.header.ether_type) });
+}
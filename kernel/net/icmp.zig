@@ -0,0 +1,128 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log.scoped(.net);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const ipv4 = @import("ipv4.zig");
+const interface = @import("interface.zig");
+
+// NOTE:
+// Echo requests sent to one of our addresses are answered, broadcast ones
+// are not. The reply repeats the request's identifier, sequence number and
+// data and comes from the address that was pinged. `ping` sends requests
+// of its own and records the last matching reply, which is all the shell
+// needs to time a round trip. Errors other hosts report are only logged.
+
+const HEADER_SIZE = 8;
+/// Identifies our own echo requests among replies.
+const PING_IDENTIFIER = 0x5245;
+
+pub const Type = enum(u8) {
+    echo_reply = 0,
+    destination_unreachable = 3,
+    echo_request = 8,
+    time_exceeded = 11,
+    _,
+};
+
+pub const Reply = struct {
+    source: [4]u8,
+    sequence: u16,
+    ttl: u8,
+    received_ns: u64,
+};
+
+var receiver = ipv4.Receiver{
+    .protocol = .icmp,
+    .handler = handle,
+};
+
+var last_reply: ?Reply = null;
+
+pub fn init() void {
+    ipv4.registerReceiver(&receiver);
+}
+
+/// Builds an echo message with `data` into `buffer`.
+pub fn buildEcho(buffer: []u8, kind: Type, identifier: u16, sequence: u16, data: []const u8) Error![]u8 {
+    const length = HEADER_SIZE + data.len;
+    if (length > buffer.len) {
+        return error.InvalidArgument;
+    }
+    buffer[0] = @intFromEnum(kind);
+    buffer[1] = 0;
+    std.mem.writeInt(u16, buffer[2..4], 0, .big);
+    std.mem.writeInt(u16, buffer[4..6], identifier, .big);
+    std.mem.writeInt(u16, buffer[6..8], sequence, .big);
+    @memcpy(buffer[HEADER_SIZE..length], data);
+    std.mem.writeInt(u16, buffer[2..4], ipv4.checksum(buffer[0..length]), .big);
+    return buffer[0..length];
+}
+
+fn handle(packet: *const ipv4.Packet) void {
+    const message = packet.payload;
+    if (message.len < HEADER_SIZE or ipv4.checksum(message) != 0) {
+        packet.device.receiveError();
+        return;
+    }
+    const identifier = std.mem.readInt(u16, message[4..6], .big);
+    const sequence = std.mem.readInt(u16, message[6..8], .big);
+
+    switch (@as(Type, @enumFromInt(message[0]))) {
+        .echo_request => {
+            // answering broadcasts would let anyone amplify traffic
+            if (!interface.isLocal(packet.header.destination)) {
+                return;
+            }
+            var buffer: [1500]u8 = undefined;
+            const reply = buildEcho(&buffer, .echo_reply, identifier, sequence, message[HEADER_SIZE..]) catch return;
+            var vecs: [1]memory.IoVec = undefined;
+            var payload = memory.SgList.init(&vecs);
+            payload.append(reply) catch return;
+            ipv4.sendFrom(packet.header.destination, packet.header.source, .icmp, &payload) catch |err| {
+                log.debug("Failed to answer a ping from {}: {s}", .{ interface.formatAddress(packet.header.source), @errorName(err) });
+            };
+        },
+        .echo_reply => {
+            if (identifier != PING_IDENTIFIER) {
+                return;
+            }
+            last_reply = .{
+                .source = packet.header.source,
+                .sequence = sequence,
+                .ttl = packet.header.ttl,
+                .received_ns = time.nowNs(),
+            };
+        },
+        .destination_unreachable, .time_exceeded => |kind| {
+            log.debug("{s} from {}, code {}", .{ @tagName(kind), interface.formatAddress(packet.header.source), message[1] });
+        },
+        _ => {},
+    }
+}
+
+/// Sends an echo request with sequence number `sequence` and 56 bytes of
+/// data, like most pings.
+pub fn ping(destination: [4]u8, sequence: u16) Error!void {
+    var data: [56]u8 = undefined;
+    for (&data, 0..) |*byte, index| {
+        byte.* = @truncate(index);
+    }
+    var buffer: [HEADER_SIZE + data.len]u8 = undefined;
+    const request = try buildEcho(&buffer, .echo_request, PING_IDENTIFIER, sequence, &data);
+    var vecs: [1]memory.IoVec = undefined;
+    var payload = memory.SgList.init(&vecs);
+    try payload.append(request);
+    try ipv4.send(destination, .icmp, &payload);
+}
+
+/// The reply to our ping with sequence number `sequence`, if it came in.
+pub fn replyTo(sequence: u16) ?Reply {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const reply = last_reply orelse return null;
+    return if (reply.sequence == sequence) reply else null;
+}
@@ -0,0 +1,193 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const cmdline = @import("kernel").cmdline;
+const log = @import("kernel").utils.log.scoped(.net);
+const Error = @import("kernel").Error;
+
+const device = @import("device.zig");
+const NetDevice = device.NetDevice;
+const route = @import("route.zig");
+
+// NOTE:
+// The IPv4 address each device answers to, one per device, and the subnet
+// it is on. `ip.<device>=<address>[/<prefix>]` on the kernel command line
+// sets it, e.g. `ip.eth0=10.0.2.15/24` for QEMU's user networking, the
+// prefix defaulting to 24. The loopback device always gets 127.0.0.1/8. A
+// device without an address still receives but never answers ARP.
+// Configuring an address also routes its subnet through the device.
+
+const MAX_INTERFACES = 8;
+
+pub const LOOPBACK_ADDRESS = [4]u8{ 127, 0, 0, 1 };
+pub const BROADCAST = [4]u8{ 255, 255, 255, 255 };
+
+const DEFAULT_PREFIX_LENGTH = 24;
+
+pub const Interface = struct {
+    device: *NetDevice,
+    address: [4]u8,
+    prefix_length: u6,
+
+    /// The subnet's directed broadcast address.
+    pub fn broadcast(self: *const Interface) [4]u8 {
+        var address: [4]u8 = undefined;
+        std.mem.writeInt(u32, &address, std.mem.readInt(u32, &self.address, .big) | ~route.mask(self.prefix_length), .big);
+        return address;
+    }
+};
+
+var interfaces: [MAX_INTERFACES]Interface = undefined;
+var count: usize = 0;
+
+/// Gives `net_device` the address `address` on a subnet of
+/// `prefix_length` bits, replacing the one it had and its routes.
+pub fn configure(net_device: *NetDevice, address: [4]u8, prefix_length: u6) Error!void {
+    if (prefix_length > 32) {
+        return error.InvalidArgument;
+    }
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    const slot = for (interfaces[0..count]) |*interface| {
+        if (interface.device == net_device) {
+            break interface;
+        }
+    } else blk: {
+        if (count == MAX_INTERFACES) {
+            return error.OutOfMemory;
+        }
+        count += 1;
+        break :blk &interfaces[count - 1];
+    };
+    slot.* = .{ .device = net_device, .address = address, .prefix_length = prefix_length };
+
+    route.removeDevice(net_device);
+    try route.add(.{ .destination = address, .prefix_length = prefix_length, .device = net_device });
+}
+
+pub fn find(net_device: *const NetDevice) ?Interface {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (interfaces[0..count]) |interface| {
+        if (interface.device == net_device) {
+            return interface;
+        }
+    }
+    return null;
+}
+
+pub fn addressOf(net_device: *const NetDevice) ?[4]u8 {
+    const interface = find(net_device) orelse return null;
+    return interface.address;
+}
+
+/// Whether `address` is one of ours, on any device.
+pub fn isLocal(address: [4]u8) bool {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (interfaces[0..count]) |interface| {
+        if (std.mem.eql(u8, &interface.address, &address)) {
+            return true;
+        }
+    }
+    return false;
+}
+
+/// Whether a packet for `address` that came in on `net_device` is meant
+/// for us: one of our addresses, or a broadcast on that device's subnet.
+pub fn accepts(net_device: *const NetDevice, address: [4]u8) bool {
+    if (std.mem.eql(u8, &address, &BROADCAST)) {
+        return true;
+    }
+    // whatever goes out through loopback is local
+    if (net_device.kind == .loopback) {
+        return isLocal(address) or address[0] == LOOPBACK_ADDRESS[0];
+    }
+    const interface = find(net_device) orelse return false;
+    return std.mem.eql(u8, &interface.address, &address) or std.mem.eql(u8, &interface.broadcast(), &address);
+}
+
+/// Sets up the address of `net_device` from the command line.
+pub fn configureFromCommandLine(net_device: *NetDevice) void {
+    if (net_device.kind == .loopback) {
+        configure(net_device, LOOPBACK_ADDRESS, 8) catch {};
+        return;
+    }
+
+    var key_buffer: [16]u8 = undefined;
+    const key = std.fmt.bufPrint(&key_buffer, "ip.{s}", .{net_device.name()}) catch unreachable;
+    const text = cmdline.getOr([]const u8, key, "");
+    if (text.len == 0) {
+        return;
+    }
+    const address, const prefix_length = parseSubnet(text) catch {
+        log.warn("Ignoring malformed {s}", .{key});
+        return;
+    };
+    configure(net_device, address, prefix_length) catch |err| {
+        log.warn("{s} keeps no address: {s}", .{ net_device.name(), @errorName(err) });
+        return;
+    };
+    log.info("{s} has address {}/{}", .{ net_device.name(), formatAddress(address), prefix_length });
+}
+
+/// Adds the default route through `net.gateway` from the command line, on
+/// the device whose subnet the gateway is on.
+pub fn configureGateway() void {
+    const text = cmdline.getOr([]const u8, "net.gateway", "");
+    if (text.len == 0) {
+        return;
+    }
+    const gateway = parseAddress(text) catch {
+        log.warn("Ignoring malformed net.gateway", .{});
+        return;
+    };
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (interfaces[0..count]) |interface| {
+        if (interface.device.kind == .loopback or !route.contains(interface.address, interface.prefix_length, gateway)) {
+            continue;
+        }
+        route.add(.{ .destination = .{ 0, 0, 0, 0 }, .prefix_length = 0, .gateway = gateway, .device = interface.device }) catch |err| {
+            log.warn("No default route: {s}", .{@errorName(err)});
+        };
+        return;
+    }
+    log.warn("Gateway {} is on none of the configured subnets", .{formatAddress(gateway)});
+}
+
+/// Parses `<address>[/<prefix>]`.
+fn parseSubnet(text: []const u8) Error!struct { [4]u8, u6 } {
+    const slash = std.mem.indexOfScalar(u8, text, '/') orelse return .{ try parseAddress(text), DEFAULT_PREFIX_LENGTH };
+    const prefix_length = std.fmt.parseInt(u6, text[slash + 1 ..], 10) catch return error.InvalidArgument;
+    if (prefix_length > 32) {
+        return error.InvalidArgument;
+    }
+    return .{ try parseAddress(text[0..slash]), prefix_length };
+}
+
+/// Parses dotted decimal, `10.0.2.15`.
+pub fn parseAddress(text: []const u8) Error![4]u8 {
+    var address: [4]u8 = undefined;
+    var parts = std.mem.splitScalar(u8, text, '.');
+    for (&address) |*byte| {
+        const part = parts.next() orelse return error.InvalidArgument;
+        byte.* = std.fmt.parseInt(u8, part, 10) catch return error.InvalidArgument;
+    }
+    if (parts.next() != null) {
+        return error.InvalidArgument;
+    }
+    return address;
+}
+
+fn formatAddressFn(address: [4]u8, comptime _: []const u8, _: std.fmt.FormatOptions, writer: anytype) !void {
+    try writer.print("{}.{}.{}.{}", .{ address[0], address[1], address[2], address[3] });
+}
+
+pub fn formatAddress(address: [4]u8) std.fmt.Formatter(formatAddressFn) {
+    return .{ .data = address };
+}
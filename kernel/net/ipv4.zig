@@ -0,0 +1,266 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log.scoped(.net);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const device = @import("device.zig");
+const NetDevice = device.NetDevice;
+const ethernet = @import("ethernet.zig");
+const arp = @import("arp.zig");
+const interface = @import("interface.zig");
+const route = @import("route.zig");
+
+// NOTE:
+// IPv4 without fragmentation: outgoing packets have to fit the device's
+// MTU and are sent with Don't Fragment set, incoming fragments are dropped
+// instead of reassembled. Options are skipped on receive and never sent.
+// There is no forwarding either, a packet is delivered when it is for one
+// of our addresses or a broadcast and dropped otherwise. Packets for one
+// of our own addresses go out through the loopback device whichever
+// interface the address belongs to.
+
+pub const HEADER_SIZE = 20;
+const VERSION = 4;
+const DEFAULT_TTL = 64;
+
+const FLAG_DONT_FRAGMENT = 0x4000;
+const FLAG_MORE_FRAGMENTS = 0x2000;
+const FRAGMENT_OFFSET = 0x1fff;
+
+pub const Protocol = enum(u8) {
+    icmp = 1,
+    tcp = 6,
+    udp = 17,
+    _,
+};
+
+pub const Header = struct {
+    source: [4]u8,
+    destination: [4]u8,
+    protocol: Protocol,
+    ttl: u8 = DEFAULT_TTL,
+    identification: u16 = 0,
+};
+
+pub const Datagram = struct {
+    header: Header,
+    payload: []const u8,
+};
+
+/// A received packet, as handed to the protocol above.
+pub const Packet = struct {
+    device: *NetDevice,
+    header: Header,
+    payload: []const u8,
+};
+
+/// Adds `bytes` to a running ones' complement sum, as 16-bit big endian
+/// words. Pass the result through `finishChecksum`.
+pub fn sumWords(initial: u32, bytes: []const u8) u32 {
+    var sum = initial;
+    var index: usize = 0;
+    while (index + 1 < bytes.len) : (index += 2) {
+        sum += std.mem.readInt(u16, bytes[index..][0..2], .big);
+        // fold early, the sum never overflows
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    if (index < bytes.len) {
+        sum += @as(u32, bytes[index]) << 8;
+    }
+    return sum;
+}
+
+pub fn finishChecksum(sum: u32) u16 {
+    var folded = sum;
+    while (folded >> 16 != 0) {
+        folded = (folded & 0xffff) + (folded >> 16);
+    }
+    return ~@as(u16, @truncate(folded));
+}
+
+/// The Internet checksum of `bytes`. Over data that already carries its
+/// checksum the result is zero when it is intact.
+pub fn checksum(bytes: []const u8) u16 {
+    return finishChecksum(sumWords(0, bytes));
+}
+
+/// Sum of the pseudo header UDP and TCP checksums also cover.
+pub fn pseudoHeaderSum(source: [4]u8, destination: [4]u8, protocol: Protocol, length: usize) u32 {
+    var sum = sumWords(0, &source);
+    sum = sumWords(sum, &destination);
+    return sum + @intFromEnum(protocol) + @as(u32, @intCast(length));
+}
+
+pub fn parse(bytes: []const u8) Error!Datagram {
+    if (bytes.len < HEADER_SIZE or bytes[0] >> 4 != VERSION) {
+        return error.Corrupted;
+    }
+    const header_length = @as(usize, bytes[0] & 0xf) * 4;
+    const total_length = std.mem.readInt(u16, bytes[2..4], .big);
+    if (header_length < HEADER_SIZE or total_length < header_length or total_length > bytes.len) {
+        return error.Corrupted;
+    }
+    if (checksum(bytes[0..header_length]) != 0) {
+        return error.Corrupted;
+    }
+    const flags = std.mem.readInt(u16, bytes[6..8], .big);
+    if (flags & FLAG_MORE_FRAGMENTS != 0 or flags & FRAGMENT_OFFSET != 0) {
+        return error.Unsupported;
+    }
+
+    return .{
+        .header = .{
+            .source = bytes[12..16].*,
+            .destination = bytes[16..20].*,
+            .protocol = @enumFromInt(bytes[9]),
+            .ttl = bytes[8],
+            .identification = std.mem.readInt(u16, bytes[4..6], .big),
+        },
+        // Ethernet pads short frames, the total length says where it ends
+        .payload = bytes[header_length..total_length],
+    };
+}
+
+/// Writes the header and `payload` into `buffer`, returns the packet.
+pub fn build(buffer: []u8, header: Header, payload: []const u8) Error![]u8 {
+    const length = HEADER_SIZE + payload.len;
+    if (length > buffer.len) {
+        return error.InvalidArgument;
+    }
+    try writeHeader(buffer[0..HEADER_SIZE], header, payload.len);
+    @memcpy(buffer[HEADER_SIZE..length], payload);
+    return buffer[0..length];
+}
+
+/// Writes the header of a packet carrying `payload_length` bytes.
+fn writeHeader(buffer: *[HEADER_SIZE]u8, header: Header, payload_length: usize) Error!void {
+    const length = HEADER_SIZE + payload_length;
+    if (length > std.math.maxInt(u16)) {
+        return error.InvalidArgument;
+    }
+
+    buffer[0] = VERSION << 4 | HEADER_SIZE / 4;
+    buffer[1] = 0;
+    std.mem.writeInt(u16, buffer[2..4], @intCast(length), .big);
+    std.mem.writeInt(u16, buffer[4..6], header.identification, .big);
+    std.mem.writeInt(u16, buffer[6..8], FLAG_DONT_FRAGMENT, .big);
+    buffer[8] = header.ttl;
+    buffer[9] = @intFromEnum(header.protocol);
+    std.mem.writeInt(u16, buffer[10..12], 0, .big);
+    buffer[12..16].* = header.source;
+    buffer[16..20].* = header.destination;
+    std.mem.writeInt(u16, buffer[10..12], checksum(buffer), .big);
+}
+
+pub const Handler = *const fn (packet: *const Packet) void;
+
+/// A protocol carried in IPv4 packets.
+pub const Receiver = struct {
+    protocol: Protocol,
+    /// Called in interrupt context, the payload is only valid during the
+    /// call.
+    handler: Handler,
+    next: ?*Receiver = null,
+};
+
+var receivers: ?*Receiver = null;
+var next_identification = std.atomic.Value(u16).init(1);
+
+var ethernet_protocol = ethernet.Protocol{
+    .ether_type = .ipv4,
+    .handler = handle,
+};
+
+pub fn init() void {
+    ethernet.registerProtocol(&ethernet_protocol);
+}
+
+pub fn registerReceiver(receiver: *Receiver) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    receiver.next = receivers;
+    receivers = receiver;
+}
+
+/// Where a packet for `destination` goes: our own addresses loop back,
+/// everything else follows the routing table.
+pub fn routeTo(destination: [4]u8) ?route.Hop {
+    if (interface.isLocal(destination)) {
+        if (device.find("lo")) |loopback| {
+            return .{ .device = loopback, .next_hop = destination };
+        }
+    }
+    return route.lookup(destination);
+}
+
+/// Largest payload a packet to `destination` can carry.
+pub fn maxPayload(destination: [4]u8) ?usize {
+    const hop = routeTo(destination) orelse return null;
+    return hop.device.mtu - HEADER_SIZE;
+}
+
+/// Sends `payload` to `destination` from the address of the device the
+/// route goes through.
+pub fn send(destination: [4]u8, protocol: Protocol, payload: *const memory.SgList) Error!void {
+    const hop = routeTo(destination) orelse return error.NotFound;
+    const source = interface.addressOf(hop.device) orelse return error.NotFound;
+    return sendVia(hop, source, destination, protocol, payload);
+}
+
+/// Sends from `source`, which has to be one of ours, e.g. the address a
+/// request was sent to.
+pub fn sendFrom(source: [4]u8, destination: [4]u8, protocol: Protocol, payload: *const memory.SgList) Error!void {
+    const hop = routeTo(destination) orelse return error.NotFound;
+    return sendVia(hop, source, destination, protocol, payload);
+}
+
+fn sendVia(hop: route.Hop, source: [4]u8, destination: [4]u8, protocol: Protocol, payload: *const memory.SgList) Error!void {
+    const length = payload.totalLength();
+    if (HEADER_SIZE + length > hop.device.mtu) {
+        return error.InvalidArgument;
+    }
+
+    var header: [HEADER_SIZE]u8 = undefined;
+    try writeHeader(&header, .{
+        .source = source,
+        .destination = destination,
+        .protocol = protocol,
+        .identification = next_identification.fetchAdd(1, .monotonic),
+    }, length);
+
+    var vecs: [ethernet.MAX_FRAME_VECS]memory.IoVec = undefined;
+    var packet = memory.SgList.init(&vecs);
+    try packet.append(&header);
+    try packet.appendList(payload);
+    try arp.send(hop.device, hop.next_hop, .ipv4, &packet);
+}
+
+fn handle(net_device: *NetDevice, _: *const ethernet.Header, bytes: []const u8) void {
+    const datagram = parse(bytes) catch |err| {
+        if (err == error.Corrupted) {
+            net_device.receiveError();
+        } else {
+            log.debug("{s}: dropping a fragment", .{net_device.name()});
+        }
+        return;
+    };
+    if (!interface.accepts(net_device, datagram.header.destination)) {
+        return;
+    }
+
+    const packet = Packet{
+        .device = net_device,
+        .header = datagram.header,
+        .payload = datagram.payload,
+    };
+    var receiver = receivers;
+    while (receiver) |current| : (receiver = current.next) {
+        if (current.protocol == packet.header.protocol) {
+            current.handler(&packet);
+            return;
+        }
+    }
+    log.debug("{s}: no receiver for IP protocol {}", .{ net_device.name(), @intFromEnum(packet.header.protocol) });
+}
@@ -0,0 +1,111 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+const Error = @import("kernel").Error;
+const SgList = @import("kernel").memory.SgList;
+
+const ipv4 = @import("ipv4.zig");
+const icmp = @import("icmp.zig");
+const route = @import("route.zig");
+const NetDevice = @import("device.zig").NetDevice;
+
+fn discard(_: *NetDevice, _: *const SgList) Error!void {}
+
+/// Never registered, routes only compare the pointers.
+var lan = NetDevice{ .mac = .{ 0x52, 0x54, 0, 0, 0, 1 }, .transmit = discard };
+var vpn = NetDevice{ .mac = .{ 0x52, 0x54, 0, 0, 0, 2 }, .transmit = discard };
+
+fn checksums() !void {
+    // a UDP packet from 192.168.0.1 to 192.168.0.199, checksum 0xb861
+    const header = [_]u8{ 0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7 };
+    if (ipv4.checksum(&header) != 0xb861) {
+        return error.WrongChecksum;
+    }
+    // an odd length pads with a zero byte
+    if (ipv4.checksum(&.{ 0x01, 0x02, 0x03 }) != ~@as(u16, 0x0402)) {
+        return error.WrongOddChecksum;
+    }
+}
+
+fn packets() !void {
+    var buffer: [128]u8 = undefined;
+    var message_buffer: [64]u8 = undefined;
+    const message = try icmp.buildEcho(&message_buffer, .echo_request, 1, 2, "ping");
+    if (ipv4.checksum(message) != 0) {
+        return error.WrongIcmpChecksum;
+    }
+
+    const header = ipv4.Header{
+        .source = .{ 10, 0, 2, 15 },
+        .destination = .{ 10, 0, 2, 2 },
+        .protocol = .icmp,
+        .identification = 0x1234,
+    };
+    const packet = try ipv4.build(&buffer, header, message);
+    const datagram = try ipv4.parse(packet);
+    if (!std.meta.eql(datagram.header, header) or !std.mem.eql(u8, datagram.payload, message)) {
+        return error.RoundTrip;
+    }
+
+    // Ethernet padding after the packet is not payload
+    @memset(buffer[packet.len..][0..10], 0);
+    const padded = try ipv4.parse(buffer[0 .. packet.len + 10]);
+    if (padded.payload.len != message.len) {
+        return error.KeptPadding;
+    }
+
+    // a flipped bit, and a first fragment
+    buffer[15] ^= 1;
+    if (ipv4.parse(packet)) |_| {
+        return error.AcceptedCorrupted;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+    buffer[15] ^= 1;
+    buffer[6] = 0x20;
+    buffer[10] = 0;
+    buffer[11] = 0;
+    std.mem.writeInt(u16, buffer[10..12], ipv4.checksum(buffer[0..ipv4.HEADER_SIZE]), .big);
+    if (ipv4.parse(packet)) |_| {
+        return error.AcceptedFragment;
+    } else |err| if (err != error.Unsupported) {
+        return err;
+    }
+}
+
+fn routes() !void {
+    var table = route.Table{};
+    try table.add(.{ .destination = .{ 10, 0, 2, 15 }, .prefix_length = 24, .device = &lan });
+    try table.add(.{ .destination = .{ 10, 8, 0, 0 }, .prefix_length = 16, .gateway = .{ 10, 0, 2, 3 }, .device = &lan });
+    try table.add(.{ .destination = .{ 10, 8, 1, 0 }, .prefix_length = 24, .device = &vpn });
+    try table.add(.{ .destination = .{ 0, 0, 0, 0 }, .prefix_length = 0, .gateway = .{ 10, 0, 2, 2 }, .device = &lan });
+
+    // on link, the destination is its own next hop
+    const direct = table.lookup(.{ 10, 0, 2, 7 }) orelse return error.NoRoute;
+    if (direct.device != &lan or !std.mem.eql(u8, &direct.next_hop, &.{ 10, 0, 2, 7 })) {
+        return error.WrongDirectRoute;
+    }
+    // the longest prefix wins
+    const specific = table.lookup(.{ 10, 8, 1, 9 }) orelse return error.NoRoute;
+    if (specific.device != &vpn) {
+        return error.WrongSpecificRoute;
+    }
+    const via = table.lookup(.{ 10, 8, 2, 9 }) orelse return error.NoRoute;
+    if (!std.mem.eql(u8, &via.next_hop, &.{ 10, 0, 2, 3 })) {
+        return error.WrongGatewayRoute;
+    }
+    const default = table.lookup(.{ 1, 1, 1, 1 }) orelse return error.NoRoute;
+    if (!std.mem.eql(u8, &default.next_hop, &.{ 10, 0, 2, 2 })) {
+        return error.WrongDefaultRoute;
+    }
+
+    table.removeDevice(&lan);
+    if (table.lookup(.{ 1, 1, 1, 1 }) != null or table.lookup(.{ 10, 8, 1, 1 }) == null) {
+        return error.WrongRemoval;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "ipv4.checksums", .func = checksums },
+    .{ .name = "ipv4.packets", .func = packets },
+    .{ .name = "ipv4.routes", .func = routes },
+};
@@ -0,0 +1,76 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const Error = @import("kernel").Error;
+const SgList = @import("kernel").memory.SgList;
+
+const device = @import("device.zig");
+
+// NOTE:
+// Frames sent on `lo` come back in on `lo`. Handing them straight to the
+// receive handler from `transmit` would run the stack inside itself, with
+// whatever it held while sending, so they are queued and delivered from a
+// timer instead, in interrupt context like frames from a real card. Without
+// the tick they sit in the queue until the device is polled.
+
+const QUEUE_LENGTH = 16;
+const MTU = 1500;
+
+var queue: [QUEUE_LENGTH][MTU + device.ETHERNET_HEADER_SIZE]u8 = undefined;
+var lengths: [QUEUE_LENGTH]usize = undefined;
+var head: usize = 0;
+var count: usize = 0;
+
+var delivery = time.Timer{};
+
+var net_device = device.NetDevice{
+    .kind = .loopback,
+    .mac = .{0} ** 6,
+    .mtu = MTU,
+    .transmit = transmit,
+    .poll = poll,
+};
+
+pub fn init() Error!void {
+    device.register(&net_device);
+}
+
+fn transmit(_: *device.NetDevice, frame: *const SgList) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    if (count == QUEUE_LENGTH) {
+        return error.OutOfMemory;
+    }
+    const slot = (head + count) % QUEUE_LENGTH;
+    lengths[slot] = frame.copyTo(0, &queue[slot]);
+    count += 1;
+
+    if (!delivery.pending) {
+        delivery.oneShot(0, deliverQueued);
+    }
+}
+
+fn deliverQueued(_: *time.Timer) void {
+    deliver();
+}
+
+fn poll(_: *device.NetDevice) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    deliver();
+}
+
+/// Delivers the frames queued so far, the ones their handlers send are
+/// left for the next round.
+fn deliver() void {
+    var remaining = count;
+    while (remaining != 0) : (remaining -= 1) {
+        // the slot stays taken while its frame is handled, a reply queued
+        // from the handler lands behind it
+        net_device.receive(queue[head][0..lengths[head]]);
+        head = (head + 1) % QUEUE_LENGTH;
+        count -= 1;
+    }
+}
@@ -0,0 +1,35 @@
+const Error = @import("kernel").Error;
+
+pub const device = @import("device.zig");
+pub const NetDevice = device.NetDevice;
+pub const loopback = @import("loopback.zig");
+pub const ethernet = @import("ethernet.zig");
+pub const arp = @import("arp.zig");
+pub const arp_tests = @import("arp_tests.zig");
+pub const interface = @import("interface.zig");
+pub const route = @import("route.zig");
+pub const ipv4 = @import("ipv4.zig");
+pub const ipv4_tests = @import("ipv4_tests.zig");
+pub const icmp = @import("icmp.zig");
+pub const udp = @import("udp.zig");
+pub const udp_tests = @import("udp_tests.zig");
+pub const sntp_client = @import("sntp_client.zig");
+pub const tcp = @import("tcp.zig");
+pub const tcp_tests = @import("tcp_tests.zig");
+
+/// Attaches the protocol stack to every registered device.
+pub fn init() Error!void {
+    arp.init();
+    ipv4.init();
+    icmp.init();
+    udp.init();
+    tcp.init();
+
+    var net_device = device.firstDevice();
+    while (net_device) |current| : (net_device = current.next) {
+        interface.configureFromCommandLine(current);
+        current.setReceiveHandler(ethernet.receive);
+    }
+    interface.configureGateway();
+    sntp_client.init();
+}
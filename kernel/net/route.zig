@@ -0,0 +1,132 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const Error = @import("kernel").Error;
+
+const NetDevice = @import("device.zig").NetDevice;
+const interface = @import("interface.zig");
+
+// NOTE:
+// A static routing table. Configuring an interface adds the route to its
+// subnet, `net.gateway` on the command line adds the default route, and
+// nothing else changes the table yet. Lookups pick the most specific route
+// that covers the destination; a route without a gateway means the
+// destination is on the link and is its own next hop.
+
+const MAX_ROUTES = 16;
+
+pub const Route = struct {
+    destination: [4]u8,
+    prefix_length: u6,
+    /// Null for directly connected subnets.
+    gateway: ?[4]u8 = null,
+    device: *NetDevice,
+};
+
+/// Where to send a packet for some destination.
+pub const Hop = struct {
+    device: *NetDevice,
+    next_hop: [4]u8,
+};
+
+pub fn mask(prefix_length: u6) u32 {
+    std.debug.assert(prefix_length <= 32);
+    return if (prefix_length == 0) 0 else ~@as(u32, 0) << @intCast(32 - @as(u7, prefix_length));
+}
+
+/// Whether `address` lies in `network`/`prefix_length`.
+pub fn contains(network: [4]u8, prefix_length: u6, address: [4]u8) bool {
+    const bits = mask(prefix_length);
+    return std.mem.readInt(u32, &network, .big) & bits == std.mem.readInt(u32, &address, .big) & bits;
+}
+
+pub const Table = struct {
+    routes: [MAX_ROUTES]Route = undefined,
+    count: usize = 0,
+
+    const Self = @This();
+
+    /// Adds `route`, replacing the one for the same destination and prefix.
+    pub fn add(self: *Self, route: Route) Error!void {
+        if (route.prefix_length > 32) {
+            return error.InvalidArgument;
+        }
+        var normalized = route;
+        std.mem.writeInt(u32, &normalized.destination, std.mem.readInt(u32, &route.destination, .big) & mask(route.prefix_length), .big);
+
+        for (self.routes[0..self.count]) |*existing| {
+            if (existing.prefix_length == normalized.prefix_length and std.mem.eql(u8, &existing.destination, &normalized.destination)) {
+                existing.* = normalized;
+                return;
+            }
+        }
+        if (self.count == MAX_ROUTES) {
+            return error.OutOfMemory;
+        }
+        self.routes[self.count] = normalized;
+        self.count += 1;
+    }
+
+    /// Drops every route through `device`.
+    pub fn removeDevice(self: *Self, device: *const NetDevice) void {
+        var index: usize = 0;
+        while (index < self.count) {
+            if (self.routes[index].device == device) {
+                self.count -= 1;
+                self.routes[index] = self.routes[self.count];
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    pub fn lookup(self: *const Self, destination: [4]u8) ?Hop {
+        var best: ?*const Route = null;
+        for (self.routes[0..self.count]) |*route| {
+            if (!contains(route.destination, route.prefix_length, destination)) {
+                continue;
+            }
+            if (best == null or route.prefix_length > best.?.prefix_length) {
+                best = route;
+            }
+        }
+        const route = best orelse return null;
+        return .{ .device = route.device, .next_hop = route.gateway orelse destination };
+    }
+};
+
+var table = Table{};
+
+pub fn add(route: Route) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    return table.add(route);
+}
+
+pub fn removeDevice(device: *const NetDevice) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    table.removeDevice(device);
+}
+
+pub fn lookup(destination: [4]u8) ?Hop {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    return table.lookup(destination);
+}
+
+/// Lists the table.
+pub fn dump(writer: anytype) !void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (table.routes[0..table.count]) |route| {
+        try writer.print("{}/{} ", .{ interface.formatAddress(route.destination), route.prefix_length });
+        if (route.gateway) |gateway| {
+            try writer.print("via {} ", .{interface.formatAddress(gateway)});
+        }
+        try writer.print("dev {s}\n", .{route.device.name()});
+    }
+}
@@ -0,0 +1,80 @@
+const std = @import("std");
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log.scoped(.net);
+const sntp = time.sntp;
+const wall_clock = time.wall_clock;
+
+const udp = @import("udp.zig");
+const interface = @import("interface.zig");
+
+// NOTE:
+// Keeps the wall clock in step with the server `sntp.server` names on the
+// command line. A request goes out at boot and then every few seconds
+// until a reply sets the clock, after that once every `POLL_INTERVAL`
+// ticks of the retry timer. Past the first request everything happens in
+// interrupt context, from the timer and the socket's notification, so
+// nothing waits on the network.
+
+/// Time between requests while the clock isn't set.
+const RETRY_INTERVAL_NS = 4 * std.time.ns_per_s;
+/// Retry periods between requests once it is, about a minute.
+const POLL_INTERVAL = 16;
+
+var server: [4]u8 = undefined;
+var socket = udp.Socket{ .notify = receive };
+var timer = time.Timer{};
+/// The request a reply has to match, null when none is out.
+var sent: ?[sntp.PACKET_SIZE]u8 = null;
+var periods: u32 = 0;
+
+/// Starts asking the configured server, if there is one.
+pub fn init() void {
+    server = sntp.configuredServer() orelse return;
+    udp.bind(&socket, 0) catch |err| {
+        log.warn("No SNTP socket: {s}", .{@errorName(err)});
+        return;
+    };
+    query();
+    timer.periodic(RETRY_INTERVAL_NS, tick);
+}
+
+fn query() void {
+    const request = sntp.request(wall_clock.estimateNs());
+    sent = request;
+    socket.sendTo(server, sntp.PORT, &request) catch |err| {
+        log.debug("Failed to ask {} for the time: {s}", .{ interface.formatAddress(server), @errorName(err) });
+    };
+}
+
+fn tick(_: *time.Timer) void {
+    periods += 1;
+    if (!wall_clock.isSynchronized() or periods >= POLL_INTERVAL) {
+        periods = 0;
+        query();
+    }
+}
+
+fn receive(_: *udp.Socket) void {
+    var buffer: [sntp.PACKET_SIZE]u8 = undefined;
+    while (socket.pollFrom(&buffer)) |sender| {
+        const request = sent orelse continue;
+        if (!std.mem.eql(u8, &sender.address, &server) or sender.port != sntp.PORT) {
+            continue;
+        }
+        const sample = sntp.parseReply(buffer[0..sender.length], &request, wall_clock.estimateNs()) catch |err| {
+            log.debug("Ignoring a reply from {}: {s}", .{ interface.formatAddress(server), @errorName(err) });
+            continue;
+        };
+
+        sent = null;
+        const first = !wall_clock.isSynchronized();
+        wall_clock.correct(sample.offset_ns);
+        if (first) {
+            log.info("Wall clock set from {}, stratum {}, {}us round trip", .{
+                interface.formatAddress(server),
+                sample.stratum,
+                @divTrunc(sample.delay_ns, std.time.ns_per_us),
+            });
+        }
+    }
+}
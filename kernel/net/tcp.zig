@@ -0,0 +1,953 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log.scoped(.net);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const device = @import("device.zig");
+const ipv4 = @import("ipv4.zig");
+const interface = @import("interface.zig");
+
+// NOTE:
+// TCP with a fixed pool of connections and listeners. Segments are handled
+// in interrupt context and every change to a connection happens with
+// interrupts disabled; `connect`, `accept`, `read` and `write` wait for the
+// stack like `udp.Socket.recvFrom` does, halting between ticks.
+//
+// Each connection buffers 4 KiB each way. The receive window is whatever
+// is free of the receive buffer and only in-order data is taken, anything
+// past a gap is dropped and answered with a duplicate ACK so the sender
+// retransmits it. Sending is bounded by the peer's window and MSS, and a
+// single timer per connection retransmits from the first unacknowledged
+// byte (go-back-N) with exponential backoff, also probing zero windows.
+// The connection gives up after `MAX_RETRANSMISSIONS` tries in a row.
+//
+// `close` queues a FIN behind the data still to send and hands the
+// connection back; the stack finishes the close on its own and the slot is
+// reused once it reaches CLOSED. TIME-WAIT is a few seconds rather than
+// twice the four minute MSL, a kernel with eight slots can't hold on to
+// them for that long.
+
+pub const HEADER_SIZE = 20;
+/// With the only option we send, the MSS.
+const MAX_HEADER_SIZE = HEADER_SIZE + 4;
+/// Largest payload we send or advertise, an Ethernet MTU minus headers.
+pub const MAX_SEGMENT = 1500 - ipv4.HEADER_SIZE - HEADER_SIZE;
+/// What the peer gets when its SYN carries no MSS option.
+const DEFAULT_SEGMENT = 536;
+const OPTION_END = 0;
+const OPTION_NOP = 1;
+const OPTION_MSS = 2;
+
+const MAX_CONNECTIONS = 8;
+const MAX_LISTENERS = 4;
+/// Connections a listener holds that haven't been accepted yet.
+const BACKLOG = 4;
+const BUFFER_SIZE = 4096;
+
+const INITIAL_RTO_NS = std.time.ns_per_s;
+const MAX_RTO_NS = 16 * std.time.ns_per_s;
+const MAX_RETRANSMISSIONS = 6;
+const TIME_WAIT_NS = 5 * std.time.ns_per_s;
+
+const EPHEMERAL_FIRST = 49152;
+const EPHEMERAL_LAST = 65535;
+
+pub const Flags = packed struct(u8) {
+    fin: bool = false,
+    syn: bool = false,
+    rst: bool = false,
+    psh: bool = false,
+    ack: bool = false,
+    urg: bool = false,
+    ece: bool = false,
+    cwr: bool = false,
+};
+
+pub const Segment = struct {
+    source_port: u16,
+    destination_port: u16,
+    sequence: u32,
+    acknowledgment: u32 = 0,
+    flags: Flags,
+    window: u16 = 0,
+    /// The MSS option, only sent on SYNs.
+    mss: ?u16 = null,
+    payload: []const u8 = &.{},
+
+    /// Sequence space the segment takes up, SYN and FIN count as one.
+    pub fn length(self: *const Segment) u32 {
+        return @as(u32, @intCast(self.payload.len)) + @intFromBool(self.flags.syn) + @intFromBool(self.flags.fin);
+    }
+};
+
+/// Whether sequence number `a` comes before `b`, modulo wraparound.
+pub fn before(a: u32, b: u32) bool {
+    return @as(i32, @bitCast(a -% b)) < 0;
+}
+
+fn after(a: u32, b: u32) bool {
+    return before(b, a);
+}
+
+/// Parses a segment from `source` to `destination`. Options other than
+/// the MSS are skipped.
+pub fn parse(source: [4]u8, destination: [4]u8, bytes: []const u8) Error!Segment {
+    if (bytes.len < HEADER_SIZE) {
+        return error.Corrupted;
+    }
+    const header_length = @as(usize, bytes[12] >> 4) * 4;
+    if (header_length < HEADER_SIZE or header_length > bytes.len) {
+        return error.Corrupted;
+    }
+    if (ipv4.finishChecksum(ipv4.sumWords(ipv4.pseudoHeaderSum(source, destination, .tcp, bytes.len), bytes)) != 0) {
+        return error.Corrupted;
+    }
+
+    var segment = Segment{
+        .source_port = std.mem.readInt(u16, bytes[0..2], .big),
+        .destination_port = std.mem.readInt(u16, bytes[2..4], .big),
+        .sequence = std.mem.readInt(u32, bytes[4..8], .big),
+        .acknowledgment = std.mem.readInt(u32, bytes[8..12], .big),
+        .flags = @bitCast(bytes[13]),
+        .window = std.mem.readInt(u16, bytes[14..16], .big),
+        .payload = bytes[header_length..],
+    };
+
+    const options = bytes[HEADER_SIZE..header_length];
+    var index: usize = 0;
+    while (index < options.len) {
+        switch (options[index]) {
+            OPTION_END => break,
+            OPTION_NOP => index += 1,
+            else => |kind| {
+                if (index + 1 >= options.len or options[index + 1] < 2 or index + options[index + 1] > options.len) {
+                    return error.Corrupted;
+                }
+                if (kind == OPTION_MSS and options[index + 1] == 4) {
+                    segment.mss = std.mem.readInt(u16, options[index + 2 ..][0..2], .big);
+                }
+                index += options[index + 1];
+            },
+        }
+    }
+    return segment;
+}
+
+/// Writes `segment` from `source` to `destination` into `buffer`.
+pub fn build(buffer: []u8, source: [4]u8, destination: [4]u8, segment: Segment) Error![]u8 {
+    var header_buffer: [MAX_HEADER_SIZE]u8 = undefined;
+    const header = try writeHeader(&header_buffer, source, destination, segment);
+    const length = header.len + segment.payload.len;
+    if (length > buffer.len) {
+        return error.InvalidArgument;
+    }
+    @memcpy(buffer[0..header.len], header);
+    @memcpy(buffer[header.len..length], segment.payload);
+    return buffer[0..length];
+}
+
+/// Writes the header of `segment`, options included, with a checksum
+/// that covers its payload too. Returns the header.
+fn writeHeader(buffer: *[MAX_HEADER_SIZE]u8, source: [4]u8, destination: [4]u8, segment: Segment) Error![]u8 {
+    const header_length: usize = if (segment.mss != null) HEADER_SIZE + 4 else HEADER_SIZE;
+    const length = header_length + segment.payload.len;
+    if (length > std.math.maxInt(u16)) {
+        return error.InvalidArgument;
+    }
+
+    std.mem.writeInt(u16, buffer[0..2], segment.source_port, .big);
+    std.mem.writeInt(u16, buffer[2..4], segment.destination_port, .big);
+    std.mem.writeInt(u32, buffer[4..8], segment.sequence, .big);
+    std.mem.writeInt(u32, buffer[8..12], segment.acknowledgment, .big);
+    buffer[12] = @intCast((header_length / 4) << 4);
+    buffer[13] = @bitCast(segment.flags);
+    std.mem.writeInt(u16, buffer[14..16], segment.window, .big);
+    std.mem.writeInt(u16, buffer[16..18], 0, .big);
+    std.mem.writeInt(u16, buffer[18..20], 0, .big);
+    if (segment.mss) |mss| {
+        buffer[20] = OPTION_MSS;
+        buffer[21] = 4;
+        std.mem.writeInt(u16, buffer[22..24], mss, .big);
+    }
+
+    // the header's length is even, the payload's sum carries straight on
+    const header_sum = ipv4.sumWords(ipv4.pseudoHeaderSum(source, destination, .tcp, length), buffer[0..header_length]);
+    const sum = ipv4.sumWords(header_sum, segment.payload);
+    std.mem.writeInt(u16, buffer[16..18], ipv4.finishChecksum(sum), .big);
+    return buffer[0..header_length];
+}
+
+/// A byte queue, what connections buffer data in.
+pub const Ring = struct {
+    data: [BUFFER_SIZE]u8 = undefined,
+    start: usize = 0,
+    len: usize = 0,
+
+    const Self = @This();
+
+    pub fn free(self: *const Self) usize {
+        return BUFFER_SIZE - self.len;
+    }
+
+    /// Appends what fits of `bytes`, returns how much did.
+    pub fn write(self: *Self, bytes: []const u8) usize {
+        const length = @min(bytes.len, self.free());
+        for (bytes[0..length], 0..) |byte, index| {
+            self.data[(self.start + self.len + index) % BUFFER_SIZE] = byte;
+        }
+        self.len += length;
+        return length;
+    }
+
+    /// Copies the bytes from `offset` on into `out` without taking them.
+    pub fn peek(self: *const Self, offset: usize, out: []u8) usize {
+        const length = @min(out.len, self.len -| offset);
+        for (out[0..length], 0..) |*byte, index| {
+            byte.* = self.data[(self.start + offset + index) % BUFFER_SIZE];
+        }
+        return length;
+    }
+
+    pub fn discard(self: *Self, count: usize) void {
+        const length = @min(count, self.len);
+        self.start = (self.start + length) % BUFFER_SIZE;
+        self.len -= length;
+    }
+
+    pub fn read(self: *Self, out: []u8) usize {
+        const length = self.peek(0, out);
+        self.discard(length);
+        return length;
+    }
+};
+
+pub const State = enum {
+    closed,
+    syn_sent,
+    syn_received,
+    established,
+    fin_wait_1,
+    fin_wait_2,
+    close_wait,
+    closing,
+    last_ack,
+    time_wait,
+};
+
+pub const Connection = struct {
+    state: State = .closed,
+    /// Whether someone holds the connection, from `connect` or `accept`
+    /// until `close` or `abort`.
+    held: bool = false,
+    /// The listener of a connection that is yet to be accepted.
+    listener: ?*Listener = null,
+    /// Why the connection ended early, reported by the next call.
+    failure: ?Error = null,
+
+    local_address: [4]u8 = undefined,
+    local_port: u16 = 0,
+    remote_address: [4]u8 = undefined,
+    remote_port: u16 = 0,
+    /// Largest segment the peer takes.
+    mss: u16 = DEFAULT_SEGMENT,
+
+    initial_sequence: u32 = 0,
+    /// Oldest byte not acknowledged, the start of `transmit_buffer`.
+    send_unacknowledged: u32 = 0,
+    send_next: u32 = 0,
+    /// Highest `send_next` so far, retransmitting moves `send_next` back.
+    send_max: u32 = 0,
+    send_window: u32 = 0,
+    /// `close` was called, a FIN follows the data.
+    fin_queued: bool = false,
+    fin_sent: bool = false,
+
+    receive_next: u32 = 0,
+    /// The peer sent its FIN, there is nothing more to read.
+    peer_closed: bool = false,
+
+    transmit_buffer: Ring = .{},
+    receive_buffer: Ring = .{},
+
+    timer: time.Timer = .{},
+    rto_ns: u64 = INITIAL_RTO_NS,
+    retransmissions: u8 = 0,
+
+    const Self = @This();
+
+    fn isFree(self: *const Self) bool {
+        return self.state == .closed and !self.held and self.listener == null and !self.timer.pending;
+    }
+
+    fn open(self: *Self, local_address: [4]u8, local_port: u16, remote_address: [4]u8, remote_port: u16) void {
+        self.* = .{
+            .local_address = local_address,
+            .local_port = local_port,
+            .remote_address = remote_address,
+            .remote_port = remote_port,
+        };
+        self.initial_sequence = initialSequence();
+        self.send_unacknowledged = self.initial_sequence;
+        self.send_next = self.initial_sequence +% 1;
+        self.send_max = self.send_next;
+    }
+
+    fn matches(self: *const Self, local_port: u16, remote_address: [4]u8, remote_port: u16) bool {
+        return self.state != .closed and self.local_port == local_port and self.remote_port == remote_port and
+            std.mem.eql(u8, &self.remote_address, &remote_address);
+    }
+
+    fn receiveWindow(self: *const Self) u16 {
+        return @intCast(@min(self.receive_buffer.free(), std.math.maxInt(u16)));
+    }
+
+    fn sendSegment(self: *Self, flags: Flags, sequence: u32, payload: []const u8) void {
+        transmitSegment(self.local_address, self.remote_address, .{
+            .source_port = self.local_port,
+            .destination_port = self.remote_port,
+            .sequence = sequence,
+            .acknowledgment = if (flags.ack) self.receive_next else 0,
+            .flags = flags,
+            .window = self.receiveWindow(),
+            .mss = if (flags.syn) MAX_SEGMENT else null,
+            .payload = payload,
+        });
+    }
+
+    fn sendAck(self: *Self) void {
+        self.sendSegment(.{ .ack = true }, self.send_next, &.{});
+    }
+
+    fn armTimer(self: *Self) void {
+        if (!self.timer.pending) {
+            self.timer.oneShot(self.rto_ns, expired);
+        }
+    }
+
+    /// Ends the connection without telling the peer.
+    fn fail(self: *Self, err: Error) void {
+        self.failure = err;
+        self.state = .closed;
+        self.listener = null;
+        self.timer.cancel();
+    }
+
+    /// Sends whatever the window allows of the unsent data, and the FIN
+    /// once all of it is out. `probe` sends a byte into a zero window.
+    fn output(self: *Self, probe: bool) void {
+        switch (self.state) {
+            .established, .close_wait, .fin_wait_1, .closing, .last_ack => {},
+            else => return,
+        }
+        if (self.fin_sent) {
+            return;
+        }
+
+        var payload: [MAX_SEGMENT]u8 = undefined;
+        while (true) {
+            const in_flight = self.send_next -% self.send_unacknowledged;
+            const unsent = self.transmit_buffer.len - in_flight;
+            var window = self.send_window -| in_flight;
+            if (probe and window == 0 and in_flight == 0) {
+                window = 1;
+            }
+            const length = @min(unsent, window, self.mss);
+            if (length == 0) {
+                break;
+            }
+            _ = self.transmit_buffer.peek(in_flight, payload[0..length]);
+            self.sendSegment(.{ .ack = true, .psh = length == unsent }, self.send_next, payload[0..length]);
+            self.send_next +%= @intCast(length);
+        }
+
+        if (self.fin_queued and self.send_next -% self.send_unacknowledged == self.transmit_buffer.len) {
+            self.sendSegment(.{ .fin = true, .ack = true }, self.send_next, &.{});
+            self.send_next +%= 1;
+            self.fin_sent = true;
+            switch (self.state) {
+                .established => self.state = .fin_wait_1,
+                .close_wait => self.state = .last_ack,
+                else => {},
+            }
+        }
+        if (after(self.send_next, self.send_max)) {
+            self.send_max = self.send_next;
+        }
+        // waiting on an ACK, or on the window to open
+        if (self.send_next != self.send_unacknowledged or self.transmit_buffer.len != 0) {
+            self.armTimer();
+        }
+    }
+
+    /// Takes an ACK for `acknowledgment`, returns whether it covers our FIN.
+    fn acknowledge(self: *Self, acknowledgment: u32) bool {
+        const acked = acknowledgment -% self.send_unacknowledged;
+        const data = @min(acked, self.transmit_buffer.len);
+        self.transmit_buffer.discard(data);
+        self.send_unacknowledged = acknowledgment;
+        if (after(acknowledgment, self.send_next)) {
+            self.send_next = acknowledgment;
+        }
+
+        self.retransmissions = 0;
+        self.rto_ns = INITIAL_RTO_NS;
+        self.timer.cancel();
+        return self.fin_queued and acked > data;
+    }
+
+    fn enterTimeWait(self: *Self) void {
+        self.state = .time_wait;
+        self.timer.cancel();
+        self.timer.oneShot(TIME_WAIT_NS, expired);
+    }
+
+    fn input(self: *Self, segment: *const Segment) void {
+        if (self.state == .syn_sent) {
+            return self.inputSynSent(segment);
+        }
+
+        if (segment.flags.rst) {
+            // only an exact match, anything else could be a blind attack
+            if (segment.sequence == self.receive_next) {
+                self.fail(error.ConnectionReset);
+            }
+            return;
+        }
+        if (segment.flags.syn) {
+            // a retransmitted SYN, ours got lost
+            if (self.state == .syn_received and segment.sequence == self.receive_next -% 1) {
+                self.sendSegment(.{ .syn = true, .ack = true }, self.initial_sequence, &.{});
+            } else {
+                self.sendAck();
+            }
+            return;
+        }
+
+        var payload = segment.payload;
+        if (before(segment.sequence, self.receive_next)) {
+            const skip = self.receive_next -% segment.sequence;
+            if (skip >= segment.length()) {
+                // a duplicate, the ACK for it got lost
+                self.sendAck();
+                return;
+            }
+            payload = payload[@min(skip, payload.len)..];
+        } else if (segment.sequence != self.receive_next) {
+            self.sendAck();
+            return;
+        }
+        if (!segment.flags.ack) {
+            return;
+        }
+
+        if (self.state == .syn_received) {
+            if (segment.acknowledgment != self.send_next) {
+                transmitSegment(self.local_address, self.remote_address, .{
+                    .source_port = self.local_port,
+                    .destination_port = self.remote_port,
+                    .sequence = segment.acknowledgment,
+                    .flags = .{ .rst = true },
+                });
+                return;
+            }
+            self.state = .established;
+            self.send_unacknowledged = segment.acknowledgment;
+            self.retransmissions = 0;
+            self.rto_ns = INITIAL_RTO_NS;
+            self.timer.cancel();
+        }
+
+        if (after(segment.acknowledgment, self.send_max)) {
+            self.sendAck();
+            return;
+        }
+        if (!before(segment.acknowledgment, self.send_unacknowledged)) {
+            var fin_acked = false;
+            if (after(segment.acknowledgment, self.send_unacknowledged)) {
+                fin_acked = self.acknowledge(segment.acknowledgment);
+            }
+            self.send_window = segment.window;
+            if (fin_acked) {
+                switch (self.state) {
+                    .fin_wait_1 => self.state = .fin_wait_2,
+                    .closing => self.enterTimeWait(),
+                    .last_ack => {
+                        self.state = .closed;
+                        return;
+                    },
+                    else => {},
+                }
+            }
+        }
+
+        var taken: usize = 0;
+        switch (self.state) {
+            .established, .fin_wait_1, .fin_wait_2 => {
+                taken = self.receive_buffer.write(payload);
+                self.receive_next +%= @intCast(taken);
+            },
+            else => {},
+        }
+        if (segment.flags.fin and taken == payload.len) {
+            self.receive_next +%= 1;
+            self.peer_closed = true;
+            switch (self.state) {
+                .established => self.state = .close_wait,
+                .fin_wait_1 => self.state = .closing,
+                .fin_wait_2 => self.enterTimeWait(),
+                else => {},
+            }
+        }
+        if (payload.len != 0 or segment.flags.fin) {
+            self.sendAck();
+        }
+        self.output(false);
+    }
+
+    fn inputSynSent(self: *Self, segment: *const Segment) void {
+        if (segment.flags.ack and segment.acknowledgment != self.send_next) {
+            if (!segment.flags.rst) {
+                transmitSegment(self.local_address, self.remote_address, .{
+                    .source_port = self.local_port,
+                    .destination_port = self.remote_port,
+                    .sequence = segment.acknowledgment,
+                    .flags = .{ .rst = true },
+                });
+            }
+            return;
+        }
+        if (segment.flags.rst) {
+            if (segment.flags.ack) {
+                self.fail(error.ConnectionReset);
+            }
+            return;
+        }
+        // simultaneous opens aren't supported, wait for the SYN-ACK
+        if (!segment.flags.syn or !segment.flags.ack) {
+            return;
+        }
+
+        self.receive_next = segment.sequence +% 1;
+        self.send_unacknowledged = segment.acknowledgment;
+        self.send_window = segment.window;
+        self.mss = @min(segment.mss orelse DEFAULT_SEGMENT, MAX_SEGMENT);
+        self.state = .established;
+        self.retransmissions = 0;
+        self.timer.cancel();
+        self.sendAck();
+    }
+
+    fn isConnected(self: *Self) bool {
+        return self.state != .syn_sent;
+    }
+
+    fn canRead(self: *Self) bool {
+        return self.receive_buffer.len != 0 or self.peer_closed or self.state == .closed;
+    }
+
+    fn canWrite(self: *Self) bool {
+        return self.transmit_buffer.free() != 0 or (self.state != .established and self.state != .close_wait);
+    }
+
+    /// Reads what arrived into `buffer`, waiting up to `timeout_ns` for
+    /// something to. Zero means the peer closed its end.
+    pub fn read(self: *Self, buffer: []u8, timeout_ns: u64) Error!usize {
+        try waitUntil(timeout_ns, self, canRead);
+
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        const was_full = self.receive_buffer.free() < self.mss;
+        const length = self.receive_buffer.read(buffer);
+        if (length == 0) {
+            if (self.failure) |err| {
+                return err;
+            }
+            return 0;
+        }
+        // tell the peer the window opened up again
+        if (was_full and self.receive_buffer.free() >= self.mss and self.state != .closed) {
+            self.sendAck();
+        }
+        return length;
+    }
+
+    /// Queues all of `bytes`, waiting up to `timeout_ns` for room.
+    pub fn write(self: *Self, bytes: []const u8, timeout_ns: u64) Error!void {
+        const start = time.nowNs();
+        var written: usize = 0;
+        while (written < bytes.len) {
+            const elapsed = time.nowNs() - start;
+            if (elapsed >= timeout_ns) {
+                return error.Timeout;
+            }
+            try waitUntil(timeout_ns - elapsed, self, canWrite);
+
+            const guard = arch.interrupts.disable();
+            defer guard.restore();
+
+            if (self.failure) |err| {
+                return err;
+            }
+            if (self.fin_queued or (self.state != .established and self.state != .close_wait)) {
+                return error.InvalidArgument;
+            }
+            written += self.transmit_buffer.write(bytes[written..]);
+            self.output(false);
+        }
+    }
+
+    /// Closes our end once the data queued so far is sent, and gives the
+    /// connection back.
+    pub fn close(self: *Self) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        self.held = false;
+        switch (self.state) {
+            .syn_sent => {
+                self.state = .closed;
+                self.timer.cancel();
+            },
+            .established, .close_wait => {
+                self.fin_queued = true;
+                self.output(false);
+            },
+            else => {},
+        }
+    }
+
+    /// Resets the connection and gives it back.
+    pub fn abort(self: *Self) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        self.held = false;
+        switch (self.state) {
+            .closed, .syn_sent, .time_wait => {},
+            else => self.sendSegment(.{ .rst = true }, self.send_next, &.{}),
+        }
+        self.state = .closed;
+        self.timer.cancel();
+    }
+};
+
+pub const Listener = struct {
+    /// Zero while the slot is free.
+    port: u16 = 0,
+
+    const Self = @This();
+
+    fn backlog(self: *const Self) usize {
+        var count: usize = 0;
+        for (&connections) |*connection| {
+            if (connection.listener == self) {
+                count += 1;
+            }
+        }
+        return count;
+    }
+
+    /// A connection that finished its handshake and is yet to be accepted.
+    fn ready(self: *const Self) ?*Connection {
+        for (&connections) |*connection| {
+            if (connection.listener == self and connection.state != .syn_received) {
+                return connection;
+            }
+        }
+        return null;
+    }
+
+    fn hasConnection(self: *Self) bool {
+        return self.ready() != null;
+    }
+
+    fn incoming(self: *Self, packet: *const ipv4.Packet, segment: *const Segment) void {
+        if (self.backlog() == BACKLOG) {
+            // the peer retries its SYN, by then there may be room
+            return;
+        }
+        const connection = allocate() orelse return;
+        connection.open(packet.header.destination, self.port, packet.header.source, segment.source_port);
+        connection.listener = self;
+        connection.state = .syn_received;
+        connection.receive_next = segment.sequence +% 1;
+        connection.send_window = segment.window;
+        connection.mss = @min(segment.mss orelse DEFAULT_SEGMENT, MAX_SEGMENT);
+        connection.sendSegment(.{ .syn = true, .ack = true }, connection.initial_sequence, &.{});
+        connection.armTimer();
+    }
+
+    /// Waits up to `timeout_ns` for a connection and takes it.
+    pub fn accept(self: *Self, timeout_ns: u64) Error!*Connection {
+        try waitUntil(timeout_ns, self, hasConnection);
+
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        const connection = self.ready() orelse return error.Timeout;
+        connection.listener = null;
+        connection.held = true;
+        return connection;
+    }
+
+    /// Stops listening and resets the connections not accepted yet.
+    pub fn close(self: *Self) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        for (&connections) |*connection| {
+            if (connection.listener == self) {
+                connection.listener = null;
+                connection.abort();
+            }
+        }
+        self.port = 0;
+    }
+};
+
+var connections = [_]Connection{.{}} ** MAX_CONNECTIONS;
+var listeners = [_]Listener{.{}} ** MAX_LISTENERS;
+var next_ephemeral: u16 = EPHEMERAL_FIRST;
+var sequence_counter: u32 = 0;
+
+var receiver = ipv4.Receiver{
+    .protocol = .tcp,
+    .handler = handle,
+};
+
+pub fn init() void {
+    ipv4.registerReceiver(&receiver);
+}
+
+/// The clock in 4us steps, as RFC 793 suggests, plus a counter so that
+/// connections opened within one step differ.
+fn initialSequence() u32 {
+    sequence_counter +%= 64000;
+    return @as(u32, @truncate(time.nowNs() / 4000)) +% sequence_counter;
+}
+
+fn allocate() ?*Connection {
+    for (&connections) |*connection| {
+        if (connection.isFree()) {
+            return connection;
+        }
+    }
+    return null;
+}
+
+fn portInUse(port: u16) bool {
+    for (&listeners) |*listener| {
+        if (listener.port == port) {
+            return true;
+        }
+    }
+    for (&connections) |*connection| {
+        if (!connection.isFree() and connection.local_port == port) {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn ephemeralPort() ?u16 {
+    for (EPHEMERAL_FIRST..EPHEMERAL_LAST + 1) |_| {
+        const candidate = next_ephemeral;
+        next_ephemeral = if (candidate == EPHEMERAL_LAST) EPHEMERAL_FIRST else candidate + 1;
+        if (!portInUse(candidate)) {
+            return candidate;
+        }
+    }
+    return null;
+}
+
+/// Waits up to `timeout_ns` for `ready(context)`, which is checked with
+/// interrupts disabled.
+fn waitUntil(timeout_ns: u64, context: anytype, comptime ready: fn (@TypeOf(context)) bool) Error!void {
+    const start = time.nowNs();
+    while (true) {
+        device.pollAll();
+        {
+            const guard = arch.interrupts.disable();
+            defer guard.restore();
+
+            if (ready(context)) {
+                return;
+            }
+        }
+        if (time.nowNs() - start >= timeout_ns) {
+            return error.Timeout;
+        }
+        time.sleep(std.time.ns_per_ms);
+    }
+}
+
+/// Listens on `port` on every address.
+pub fn listen(port: u16) Error!*Listener {
+    if (port == 0) {
+        return error.InvalidArgument;
+    }
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    if (portInUse(port)) {
+        return error.AlreadyExists;
+    }
+    for (&listeners) |*listener| {
+        if (listener.port == 0) {
+            listener.port = port;
+            return listener;
+        }
+    }
+    return error.OutOfMemory;
+}
+
+/// Opens a connection to `port` on `address`, waiting up to `timeout_ns`
+/// for the handshake.
+pub fn connect(address: [4]u8, port: u16, timeout_ns: u64) Error!*Connection {
+    const hop = ipv4.routeTo(address) orelse return error.NotFound;
+    const source = interface.addressOf(hop.device) orelse return error.NotFound;
+
+    const connection = blk: {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        const slot = allocate() orelse return error.OutOfMemory;
+        const local_port = ephemeralPort() orelse return error.OutOfMemory;
+        slot.open(source, local_port, address, port);
+        slot.held = true;
+        slot.state = .syn_sent;
+        slot.sendSegment(.{ .syn = true }, slot.initial_sequence, &.{});
+        slot.armTimer();
+        break :blk slot;
+    };
+
+    waitUntil(timeout_ns, connection, Connection.isConnected) catch |err| {
+        connection.abort();
+        return err;
+    };
+    if (connection.failure) |err| {
+        connection.close();
+        return err;
+    }
+    return connection;
+}
+
+fn expired(timer: *time.Timer) void {
+    const connection: *Connection = @fieldParentPtr("timer", timer);
+    if (connection.state == .time_wait) {
+        connection.state = .closed;
+        return;
+    }
+
+    connection.retransmissions += 1;
+    if (connection.retransmissions > MAX_RETRANSMISSIONS) {
+        log.debug("Giving up on {}:{}", .{ interface.formatAddress(connection.remote_address), connection.remote_port });
+        connection.fail(error.Timeout);
+        return;
+    }
+    connection.rto_ns = @min(connection.rto_ns * 2, MAX_RTO_NS);
+
+    switch (connection.state) {
+        .syn_sent => {
+            connection.sendSegment(.{ .syn = true }, connection.initial_sequence, &.{});
+            connection.armTimer();
+        },
+        .syn_received => {
+            connection.sendSegment(.{ .syn = true, .ack = true }, connection.initial_sequence, &.{});
+            connection.armTimer();
+        },
+        else => {
+            connection.send_next = connection.send_unacknowledged;
+            connection.fin_sent = false;
+            connection.output(true);
+        },
+    }
+}
+
+fn transmitSegment(source: [4]u8, destination: [4]u8, segment: Segment) void {
+    var header_buffer: [MAX_HEADER_SIZE]u8 = undefined;
+    const header = writeHeader(&header_buffer, source, destination, segment) catch return;
+
+    var vecs: [2]memory.IoVec = undefined;
+    var bytes = memory.SgList.init(&vecs);
+    bytes.append(header) catch return;
+    bytes.appendConst(segment.payload) catch return;
+    ipv4.sendFrom(source, destination, .tcp, &bytes) catch |err| {
+        log.debug("Failed to send a segment to {}: {s}", .{ interface.formatAddress(destination), @errorName(err) });
+    };
+}
+
+/// Answers a segment no connection wants with a reset.
+fn refuse(packet: *const ipv4.Packet, segment: *const Segment) void {
+    if (segment.flags.rst or !interface.isLocal(packet.header.destination)) {
+        return;
+    }
+    var reply = Segment{
+        .source_port = segment.destination_port,
+        .destination_port = segment.source_port,
+        .sequence = 0,
+        .flags = .{ .rst = true },
+    };
+    if (segment.flags.ack) {
+        reply.sequence = segment.acknowledgment;
+    } else {
+        reply.flags.ack = true;
+        reply.acknowledgment = segment.sequence +% segment.length();
+    }
+    transmitSegment(packet.header.destination, packet.header.source, reply);
+}
+
+fn handle(packet: *const ipv4.Packet) void {
+    const segment = parse(packet.header.source, packet.header.destination, packet.payload) catch {
+        packet.device.receiveError();
+        return;
+    };
+
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (&connections) |*connection| {
+        if (connection.matches(segment.destination_port, packet.header.source, segment.source_port)) {
+            connection.input(&segment);
+            return;
+        }
+    }
+    if (segment.flags.syn and !segment.flags.ack and !segment.flags.rst) {
+        for (&listeners) |*listener| {
+            if (listener.port != 0 and listener.port == segment.destination_port) {
+                listener.incoming(packet, &segment);
+                return;
+            }
+        }
+    }
+    refuse(packet, &segment);
+}
+
+/// Lists the listeners and open connections.
+pub fn dump(writer: anytype) !void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    for (&listeners) |*listener| {
+        if (listener.port != 0) {
+            try writer.print("*:{} listen\n", .{listener.port});
+        }
+    }
+    for (&connections) |*connection| {
+        if (connection.state == .closed) {
+            continue;
+        }
+        try writer.print("{}:{} {}:{} {s}, {} bytes to send, {} to read\n", .{
+            interface.formatAddress(connection.local_address),
+            connection.local_port,
+            interface.formatAddress(connection.remote_address),
+            connection.remote_port,
+            @tagName(connection.state),
+            connection.transmit_buffer.len,
+            connection.receive_buffer.len,
+        });
+    }
+}
@@ -0,0 +1,230 @@
+const std = @import("std");
+const time = @import("kernel").time;
+const testdev = @import("kernel").utils.testdev;
+
+const tcp = @import("tcp.zig");
+const device = @import("device.zig");
+const interface = @import("interface.zig");
+
+const CLIENT = [4]u8{ 10, 0, 2, 2 };
+const SERVER = [4]u8{ 10, 0, 2, 15 };
+
+const TIMEOUT_NS = 2 * std.time.ns_per_s;
+
+fn segments() !void {
+    var buffer: [128]u8 = undefined;
+    const syn = tcp.Segment{
+        .source_port = 50000,
+        .destination_port = 80,
+        .sequence = 0xfffffff0,
+        .flags = .{ .syn = true },
+        .window = 4096,
+        .mss = 1460,
+    };
+    const bytes = try tcp.build(&buffer, CLIENT, SERVER, syn);
+    if (bytes.len != tcp.HEADER_SIZE + 4) {
+        return error.WrongLength;
+    }
+    const parsed = try tcp.parse(CLIENT, SERVER, bytes);
+    if (!std.meta.eql(parsed.flags, syn.flags) or parsed.sequence != syn.sequence or parsed.mss != 1460 or parsed.payload.len != 0) {
+        return error.RoundTrip;
+    }
+    if (parsed.length() != 1) {
+        return error.WrongSequenceLength;
+    }
+
+    const data = tcp.Segment{
+        .source_port = 80,
+        .destination_port = 50000,
+        .sequence = 1,
+        .acknowledgment = 0xfffffff1,
+        .flags = .{ .ack = true, .psh = true, .fin = true },
+        .window = 1024,
+        .payload = "HTTP/1.0 200 OK\r\n",
+    };
+    const reply = try tcp.parse(SERVER, CLIENT, try tcp.build(&buffer, SERVER, CLIENT, data));
+    if (!std.mem.eql(u8, reply.payload, data.payload) or reply.mss != null or reply.length() != data.payload.len + 1) {
+        return error.RoundTrip;
+    }
+
+    // the checksum covers the addresses
+    if (tcp.parse(CLIENT, CLIENT, bytes)) |_| {
+        return error.AcceptedWrongAddress;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+}
+
+fn sequenceNumbers() !void {
+    if (!tcp.before(1, 2) or tcp.before(2, 1) or tcp.before(5, 5)) {
+        return error.WrongOrder;
+    }
+    // across the wraparound
+    if (!tcp.before(0xffff_fff0, 0x10) or tcp.before(0x10, 0xffff_fff0)) {
+        return error.WrongWrappedOrder;
+    }
+}
+
+/// Too big for the stack.
+var ring = tcp.Ring{};
+
+fn rings() !void {
+    var chunk: [1000]u8 = undefined;
+    for (&chunk, 0..) |*byte, index| {
+        byte.* = @truncate(index);
+    }
+
+    // fill it, then move the start close to the end so data wraps
+    for (0..4) |_| {
+        if (ring.write(&chunk) != chunk.len) {
+            return error.ShortWrite;
+        }
+    }
+    if (ring.write(&chunk) != 96 or ring.free() != 0) {
+        return error.Overfilled;
+    }
+    ring.discard(3500);
+    _ = ring.write(&chunk);
+
+    var out: [16]u8 = undefined;
+    if (ring.peek(ring.len - 4, &out) != 4 or !std.mem.eql(u8, out[0..4], chunk[996..1000])) {
+        return error.WrongPeek;
+    }
+    const before_read = ring.len;
+    if (ring.read(&out) != out.len or ring.len != before_read - out.len) {
+        return error.WrongRead;
+    }
+    ring.discard(ring.len);
+    if (ring.len != 0 or ring.free() != 4096) {
+        return error.NotEmpty;
+    }
+}
+
+/// Keeps the stack going like the blocking calls do, for `duration_ns`.
+fn run(duration_ns: u64) void {
+    const start = time.nowNs();
+    while (time.nowNs() - start < duration_ns) {
+        device.pollAll();
+        time.sleep(std.time.ns_per_ms);
+    }
+}
+
+/// Waits for a connection nobody blocks on, e.g. one handed back by `close`.
+fn waitForState(connection: *const tcp.Connection, state: tcp.State) !void {
+    const start = time.nowNs();
+    while (connection.state != state) {
+        if (time.nowNs() - start >= TIMEOUT_NS) {
+            return error.StateNotReached;
+        }
+        device.pollAll();
+        time.sleep(std.time.ns_per_ms);
+    }
+}
+
+fn handshakeAndClose() !void {
+    const listener = try tcp.listen(7001);
+    defer listener.close();
+    if (tcp.listen(7001)) |_| {
+        return error.ListenedTwice;
+    } else |err| if (err != error.AlreadyExists) {
+        return err;
+    }
+
+    const client = try tcp.connect(interface.LOOPBACK_ADDRESS, 7001, TIMEOUT_NS);
+    const server = try listener.accept(TIMEOUT_NS);
+    if (client.state != .established or server.state != .established) {
+        return error.NotEstablished;
+    }
+
+    var buffer: [16]u8 = undefined;
+    try client.write("ping", TIMEOUT_NS);
+    if (!std.mem.eql(u8, buffer[0..try server.read(&buffer, TIMEOUT_NS)], "ping")) {
+        return error.WrongRequest;
+    }
+    try server.write("pong", TIMEOUT_NS);
+    if (!std.mem.eql(u8, buffer[0..try client.read(&buffer, TIMEOUT_NS)], "pong")) {
+        return error.WrongReply;
+    }
+
+    // the client closes first and is the one left in TIME-WAIT
+    client.close();
+    if (try server.read(&buffer, TIMEOUT_NS) != 0 or server.state != .close_wait) {
+        return error.FinNotSeen;
+    }
+    try waitForState(client, .fin_wait_2);
+    server.close();
+    try waitForState(server, .closed);
+    try waitForState(client, .time_wait);
+}
+
+fn resets() !void {
+    // nobody listens, the SYN is answered with a reset
+    if (tcp.connect(interface.LOOPBACK_ADDRESS, 7002, TIMEOUT_NS)) |_| {
+        return error.ConnectedToNobody;
+    } else |err| if (err != error.ConnectionReset) {
+        return err;
+    }
+
+    const listener = try tcp.listen(7003);
+    defer listener.close();
+    const client = try tcp.connect(interface.LOOPBACK_ADDRESS, 7003, TIMEOUT_NS);
+    const server = try listener.accept(TIMEOUT_NS);
+
+    client.abort();
+    var buffer: [16]u8 = undefined;
+    if (server.read(&buffer, TIMEOUT_NS)) |_| {
+        return error.ResetNotSeen;
+    } else |err| if (err != error.ConnectionReset) {
+        return err;
+    }
+    if (server.state != .closed) {
+        return error.NotClosed;
+    }
+    server.close();
+}
+
+/// More than the receive buffer holds, too big for the stack.
+var stream: [6000]u8 = undefined;
+var received: [6000]u8 = undefined;
+
+fn retransmit() !void {
+    for (&stream, 0..) |*byte, index| {
+        byte.* = @truncate(index * 7);
+    }
+
+    const listener = try tcp.listen(7004);
+    defer listener.close();
+    const client = try tcp.connect(interface.LOOPBACK_ADDRESS, 7004, TIMEOUT_NS);
+    const server = try listener.accept(TIMEOUT_NS);
+    defer client.close();
+    defer server.close();
+
+    // the server reads nothing, its window closes and the rest waits on
+    // the retransmit timer probing it
+    try client.write(&stream, TIMEOUT_NS);
+    run(std.time.ns_per_s + std.time.ns_per_s / 2);
+    if (client.retransmissions == 0 or client.transmit_buffer.len == 0) {
+        return error.NotRetransmitted;
+    }
+
+    var length: usize = 0;
+    while (length < received.len) {
+        const count = try server.read(received[length..], TIMEOUT_NS);
+        if (count == 0) {
+            return error.ClosedEarly;
+        }
+        length += count;
+    }
+    if (!std.mem.eql(u8, &received, &stream)) {
+        return error.WrongStream;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "tcp.segments", .func = segments },
+    .{ .name = "tcp.sequence_numbers", .func = sequenceNumbers },
+    .{ .name = "tcp.rings", .func = rings },
+    .{ .name = "tcp.handshake_and_close", .func = handshakeAndClose },
+    .{ .name = "tcp.resets", .func = resets },
+    .{ .name = "tcp.retransmit", .func = retransmit },
+};
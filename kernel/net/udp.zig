@@ -0,0 +1,281 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log.scoped(.net);
+const memory = @import("kernel").memory;
+const Error = @import("kernel").Error;
+
+const device = @import("device.zig");
+const ipv4 = @import("ipv4.zig");
+const interface = @import("interface.zig");
+
+// NOTE:
+// UDP sockets. A socket is bound to a local port on every address and
+// queues up to `QUEUE_LENGTH` datagrams; when it is full new ones are
+// dropped and counted, the ones already queued are what the reader expects
+// next. Sockets are owned by whoever uses them, like timers, and have to
+// stay put while bound. Receiving either polls or waits for a datagram,
+// and since there is nothing to switch to, waiting halts between ticks and
+// polls the devices without an interrupt itself. Services that can't wait
+// get a call in interrupt context whenever a datagram is queued.
+
+pub const HEADER_SIZE = 8;
+/// Largest payload that fits an Ethernet frame unfragmented.
+pub const MAX_PAYLOAD = 1500 - ipv4.HEADER_SIZE - HEADER_SIZE;
+const QUEUE_LENGTH = 4;
+
+/// Ports handed out to sockets bound to port 0.
+const EPHEMERAL_FIRST = 49152;
+const EPHEMERAL_LAST = 65535;
+
+pub const Header = struct {
+    source_port: u16,
+    destination_port: u16,
+};
+
+pub const Datagram = struct {
+    header: Header,
+    payload: []const u8,
+};
+
+/// Parses a datagram from `source` to `destination`. A zero checksum means
+/// the sender didn't compute one.
+pub fn parse(source: [4]u8, destination: [4]u8, bytes: []const u8) Error!Datagram {
+    if (bytes.len < HEADER_SIZE) {
+        return error.Corrupted;
+    }
+    const length = std.mem.readInt(u16, bytes[4..6], .big);
+    if (length < HEADER_SIZE or length > bytes.len) {
+        return error.Corrupted;
+    }
+    if (std.mem.readInt(u16, bytes[6..8], .big) != 0 and
+        ipv4.finishChecksum(ipv4.sumWords(ipv4.pseudoHeaderSum(source, destination, .udp, length), bytes[0..length])) != 0)
+    {
+        return error.Corrupted;
+    }
+    return .{
+        .header = .{
+            .source_port = std.mem.readInt(u16, bytes[0..2], .big),
+            .destination_port = std.mem.readInt(u16, bytes[2..4], .big),
+        },
+        .payload = bytes[HEADER_SIZE..length],
+    };
+}
+
+/// Writes the datagram from `source` to `destination` into `buffer`.
+pub fn build(buffer: []u8, source: [4]u8, destination: [4]u8, header: Header, payload: []const u8) Error![]u8 {
+    const length = HEADER_SIZE + payload.len;
+    if (length > buffer.len) {
+        return error.InvalidArgument;
+    }
+    try writeHeader(buffer[0..HEADER_SIZE], source, destination, header, payload);
+    @memcpy(buffer[HEADER_SIZE..length], payload);
+    return buffer[0..length];
+}
+
+/// Writes the header of a datagram carrying `payload`, which the checksum
+/// covers too.
+fn writeHeader(buffer: *[HEADER_SIZE]u8, source: [4]u8, destination: [4]u8, header: Header, payload: []const u8) Error!void {
+    const length = HEADER_SIZE + payload.len;
+    if (length > std.math.maxInt(u16)) {
+        return error.InvalidArgument;
+    }
+    std.mem.writeInt(u16, buffer[0..2], header.source_port, .big);
+    std.mem.writeInt(u16, buffer[2..4], header.destination_port, .big);
+    std.mem.writeInt(u16, buffer[4..6], @intCast(length), .big);
+    std.mem.writeInt(u16, buffer[6..8], 0, .big);
+
+    const header_sum = ipv4.sumWords(ipv4.pseudoHeaderSum(source, destination, .udp, length), buffer);
+    const sum = ipv4.finishChecksum(ipv4.sumWords(header_sum, payload));
+    // zero would mean no checksum, its other representation is all ones
+    std.mem.writeInt(u16, buffer[6..8], if (sum == 0) 0xffff else sum, .big);
+}
+
+/// Where a received datagram came from.
+pub const Sender = struct {
+    address: [4]u8,
+    port: u16,
+    /// Bytes copied out, the rest of a longer datagram is lost.
+    length: usize,
+};
+
+const Slot = struct {
+    sender: Sender,
+    data: [MAX_PAYLOAD]u8,
+};
+
+pub const Socket = struct {
+    /// Zero while unbound.
+    port: u16 = 0,
+    /// Called in interrupt context after a datagram was queued.
+    notify: ?*const fn (socket: *Socket) void = null,
+    /// Datagrams lost to a full queue.
+    dropped: u64 = 0,
+    slots: [QUEUE_LENGTH]Slot = undefined,
+    head: usize = 0,
+    count: usize = 0,
+    next: ?*Socket = null,
+
+    const Self = @This();
+
+    /// Queues a datagram, returns false when there was no room.
+    pub fn enqueue(self: *Self, address: [4]u8, port: u16, payload: []const u8) bool {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        if (self.count == QUEUE_LENGTH) {
+            self.dropped += 1;
+            return false;
+        }
+        const slot = &self.slots[(self.head + self.count) % QUEUE_LENGTH];
+        const length = @min(payload.len, MAX_PAYLOAD);
+        slot.sender = .{ .address = address, .port = port, .length = length };
+        @memcpy(slot.data[0..length], payload[0..length]);
+        self.count += 1;
+        return true;
+    }
+
+    /// Takes the oldest queued datagram into `buffer` without waiting.
+    pub fn pollFrom(self: *Self, buffer: []u8) ?Sender {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        if (self.count == 0) {
+            return null;
+        }
+        const slot = &self.slots[self.head];
+        var sender = slot.sender;
+        sender.length = @min(sender.length, buffer.len);
+        @memcpy(buffer[0..sender.length], slot.data[0..sender.length]);
+        self.head = (self.head + 1) % QUEUE_LENGTH;
+        self.count -= 1;
+        return sender;
+    }
+
+    /// Waits up to `timeout_ns` for a datagram, `error.Timeout` when none
+    /// came. Not for interrupt context.
+    pub fn recvFrom(self: *Self, buffer: []u8, timeout_ns: u64) Error!Sender {
+        const start = time.nowNs();
+        while (true) {
+            device.pollAll();
+            if (self.pollFrom(buffer)) |sender| {
+                return sender;
+            }
+            if (time.nowNs() - start >= timeout_ns) {
+                return error.Timeout;
+            }
+            time.sleep(std.time.ns_per_ms);
+        }
+    }
+
+    /// Sends `payload` to `port` on `destination` from this socket's port.
+    pub fn sendTo(self: *const Self, destination: [4]u8, port: u16, payload: []const u8) Error!void {
+        if (self.port == 0) {
+            return error.InvalidArgument;
+        }
+        const hop = ipv4.routeTo(destination) orelse return error.NotFound;
+        const source = interface.addressOf(hop.device) orelse return error.NotFound;
+
+        var header: [HEADER_SIZE]u8 = undefined;
+        try writeHeader(&header, source, destination, .{ .source_port = self.port, .destination_port = port }, payload);
+
+        // the payload goes out from where the caller has it
+        var vecs: [2]memory.IoVec = undefined;
+        var datagram = memory.SgList.init(&vecs);
+        try datagram.append(&header);
+        try datagram.appendConst(payload);
+        try ipv4.sendFrom(source, destination, .udp, &datagram);
+    }
+};
+
+var sockets: ?*Socket = null;
+var next_ephemeral: u16 = EPHEMERAL_FIRST;
+
+var receiver = ipv4.Receiver{
+    .protocol = .udp,
+    .handler = handle,
+};
+
+pub fn init() void {
+    ipv4.registerReceiver(&receiver);
+}
+
+fn findLocked(port: u16) ?*Socket {
+    var socket = sockets;
+    while (socket) |current| : (socket = current.next) {
+        if (current.port == port) {
+            return current;
+        }
+    }
+    return null;
+}
+
+fn find(port: u16) ?*Socket {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    return findLocked(port);
+}
+
+/// Binds `socket` to `port`, or to a free ephemeral port when it is 0.
+pub fn bind(socket: *Socket, port: u16) Error!void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    if (socket.port != 0) {
+        return error.AlreadyExists;
+    }
+    var chosen = port;
+    if (chosen == 0) {
+        for (EPHEMERAL_FIRST..EPHEMERAL_LAST + 1) |_| {
+            const candidate = next_ephemeral;
+            next_ephemeral = if (candidate == EPHEMERAL_LAST) EPHEMERAL_FIRST else candidate + 1;
+            if (findLocked(candidate) == null) {
+                chosen = candidate;
+                break;
+            }
+        } else return error.OutOfMemory;
+    } else if (findLocked(port) != null) {
+        return error.AlreadyExists;
+    }
+
+    socket.port = chosen;
+    socket.head = 0;
+    socket.count = 0;
+    socket.next = sockets;
+    sockets = socket;
+}
+
+pub fn unbind(socket: *Socket) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    var link = &sockets;
+    while (link.*) |current| : (link = &current.next) {
+        if (current == socket) {
+            link.* = current.next;
+            break;
+        }
+    }
+    socket.port = 0;
+    socket.next = null;
+}
+
+fn handle(packet: *const ipv4.Packet) void {
+    const datagram = parse(packet.header.source, packet.header.destination, packet.payload) catch {
+        packet.device.receiveError();
+        return;
+    };
+
+    const socket = find(datagram.header.destination_port) orelse {
+        log.debug("{s}: no socket on UDP port {}", .{ packet.device.name(), datagram.header.destination_port });
+        return;
+    };
+
+    if (!socket.enqueue(packet.header.source, datagram.header.source_port, datagram.payload)) {
+        return;
+    }
+    if (socket.notify) |notify| {
+        notify(socket);
+    }
+}
@@ -0,0 +1,70 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const udp = @import("udp.zig");
+
+const SOURCE = [4]u8{ 10, 0, 2, 15 };
+const DESTINATION = [4]u8{ 10, 0, 2, 3 };
+
+fn datagrams() !void {
+    var buffer: [64]u8 = undefined;
+    const header = udp.Header{ .source_port = 49152, .destination_port = 123 };
+    const datagram = try udp.build(&buffer, SOURCE, DESTINATION, header, "hello");
+
+    const parsed = try udp.parse(SOURCE, DESTINATION, datagram);
+    if (!std.meta.eql(parsed.header, header) or !std.mem.eql(u8, parsed.payload, "hello")) {
+        return error.RoundTrip;
+    }
+
+    // the pseudo header is covered, a different destination fails
+    if (udp.parse(SOURCE, .{ 10, 0, 2, 4 }, datagram)) |_| {
+        return error.AcceptedWrongAddress;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+
+    // no checksum at all is fine
+    buffer[6] = 0;
+    buffer[7] = 0;
+    _ = try udp.parse(SOURCE, .{ 10, 0, 2, 4 }, datagram);
+
+    // a length past the end is not
+    std.mem.writeInt(u16, buffer[4..6], @intCast(datagram.len + 1), .big);
+    if (udp.parse(SOURCE, DESTINATION, datagram)) |_| {
+        return error.AcceptedTruncated;
+    } else |err| if (err != error.Corrupted) {
+        return err;
+    }
+}
+
+/// Queued by the test, never bound, too big for the stack.
+var socket = udp.Socket{};
+
+fn queue() !void {
+    for (0..5) |index| {
+        const queued = socket.enqueue(SOURCE, 1000 + @as(u16, @intCast(index)), "datagram");
+        if (queued != (index < 4)) {
+            return error.WrongQueueing;
+        }
+    }
+    if (socket.dropped != 1) {
+        return error.WrongDropCount;
+    }
+
+    // datagrams come out in order, cut to the buffer
+    var buffer: [4]u8 = undefined;
+    for (0..4) |index| {
+        const sender = socket.pollFrom(&buffer) orelse return error.MissingDatagram;
+        if (sender.port != 1000 + index or sender.length != 4 or !std.mem.eql(u8, &buffer, "data")) {
+            return error.WrongDatagram;
+        }
+    }
+    if (socket.pollFrom(&buffer) != null) {
+        return error.QueueNotEmpty;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "udp.datagrams", .func = datagrams },
+    .{ .name = "udp.queue", .func = queue },
+};
@@ -0,0 +1,94 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const time = @import("kernel").time;
+const framebuffer = @import("kernel").drivers.framebuffer;
+const device = @import("kernel").drivers.device;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+pub const shutdown = @import("shutdown.zig");
+
+// NOTE:
+// Input drivers report every event with `activity`. Once nothing happened
+// for `idle_timeout_ns` the system counts as idle and every registered hook
+// gets to power something down, the next activity brings it back. The check
+// runs from a timer, so hooks are called in interrupt context.
+
+pub const Hook = struct {
+    idle: *const fn () void,
+    active: *const fn () void,
+    next: ?*Hook = null,
+};
+
+pub var idle_timeout_ns: u64 = 10 * std.time.ns_per_min;
+
+const CHECK_INTERVAL_NS = 100 * std.time.ns_per_ms;
+
+var hooks: ?*Hook = null;
+var last_activity = std.atomic.Value(u64).init(0);
+var idle = false;
+var check_timer = time.Timer{};
+
+var blank_screen = Hook{
+    .idle = framebuffer.blank,
+    .active = framebuffer.unblank,
+};
+
+var stop_idle_check = shutdown.Hook{
+    .name = "idle check",
+    .run = cancelCheck,
+};
+
+pub fn init() void {
+    activity();
+    register(&blank_screen);
+    check_timer.periodic(CHECK_INTERVAL_NS, check);
+    shutdown.register(&stop_idle_check);
+}
+
+fn cancelCheck() void {
+    check_timer.cancel();
+}
+
+pub fn register(hook: *Hook) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    hook.next = hooks;
+    hooks = hook;
+}
+
+/// Called by input drivers whenever the user did something.
+pub fn activity() void {
+    last_activity.store(time.nowNs(), .monotonic);
+}
+
+fn check(_: *time.Timer) void {
+    const quiet = time.nowNs() -| last_activity.load(.monotonic) >= idle_timeout_ns;
+    if (quiet == idle) {
+        return;
+    }
+    idle = quiet;
+
+    var hook = hooks;
+    while (hook) |current| : (hook = current.next) {
+        if (idle) {
+            current.idle();
+        } else {
+            current.active();
+        }
+    }
+}
+
+/// Suspends every device, halts for `duration_ns` and resumes them. This is
+/// the device side of a sleep state, the machine itself keeps running until
+/// there is an S3 path to hand over to.
+pub fn standby(duration_ns: u64) Error!void {
+    try device.suspendAll();
+    log.info("Devices suspended for {} ms", .{duration_ns / std.time.ns_per_ms});
+
+    time.sleep(duration_ns);
+
+    device.resumeAll();
+    log.info("Devices resumed", .{});
+}
@@ -0,0 +1,62 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const acpi = @import("kernel").acpi;
+const log = @import("kernel").utils.log;
+const serial = @import("kernel").drivers.serial;
+
+// NOTE:
+// Subsystems that hold state worth saving or devices that must be stopped
+// register a hook once they are up. Hooks run in reverse order of
+// registration, so something is stopped before whatever it depends on, and
+// with interrupts still enabled so drivers can drain their queues. Only
+// after the last one are interrupts masked and the serial queue flushed,
+// hooks may still log.
+
+pub const Hook = struct {
+    name: []const u8,
+    run: *const fn () void,
+    next: ?*Hook = null,
+};
+
+var hooks: ?*Hook = null;
+var stopped = std.atomic.Value(bool).init(false);
+
+pub fn register(hook: *Hook) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    hook.next = hooks;
+    hooks = hook;
+}
+
+/// Runs every hook and masks interrupts. Only the first call does
+/// anything, the system can't be used afterwards.
+pub fn stop() void {
+    if (stopped.swap(true, .acq_rel)) {
+        return;
+    }
+
+    var hook = hooks;
+    while (hook) |current| : (hook = current.next) {
+        log.debug("Shutdown: {s}", .{current.name});
+        current.run();
+    }
+
+    arch.cpu.disableInterrupts();
+    serial.flush();
+}
+
+/// Stops the system and powers the machine off.
+pub fn powerOff() noreturn {
+    log.info("Powering off", .{});
+    stop();
+
+    acpi.powerOff() catch |err| {
+        log.write("Failed to power off: {s}, it is safe to turn the machine off now", .{@errorName(err)});
+        serial.flush();
+    };
+
+    while (true) {
+        asm volatile ("hlt");
+    }
+}
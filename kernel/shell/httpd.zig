@@ -0,0 +1,69 @@
+const std = @import("std");
+const time = @import("kernel").time;
+const log = @import("kernel").utils.log;
+const serial = @import("kernel").drivers.serial;
+const tcp = @import("kernel").net.tcp;
+
+// NOTE:
+// `httpd` answers every request on a port with the same small page until a
+// key arrives on the serial port, enough to see the TCP stack work from a
+// browser or curl. Requests are read up to the blank line ending their
+// header and otherwise ignored, one connection at a time.
+
+const DEFAULT_PORT = 80;
+const MAX_REQUEST = 1024;
+const ACCEPT_TIMEOUT_NS = 100 * std.time.ns_per_ms;
+const IO_TIMEOUT_NS = 2 * std.time.ns_per_s;
+
+fn print(comptime fmt: []const u8, args: anytype) void {
+    log.writer.print(fmt, args) catch {};
+}
+
+pub fn serve(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const port = if (args.next()) |text| try std.fmt.parseInt(u16, text, 0) else DEFAULT_PORT;
+    const listener = try tcp.listen(port);
+    defer listener.close();
+
+    print("serving on port {}, press any key to stop\n", .{port});
+    var served: usize = 0;
+    while (serial.readByte() == null) {
+        const connection = listener.accept(ACCEPT_TIMEOUT_NS) catch |err| switch (err) {
+            error.Timeout => continue,
+            else => return err,
+        };
+        answer(connection) catch |err| {
+            print("dropped a request: {s}\n", .{@errorName(err)});
+            connection.abort();
+            continue;
+        };
+        connection.close();
+        served += 1;
+    }
+    print("served {} requests\n", .{served});
+}
+
+fn answer(connection: *tcp.Connection) !void {
+    var request: [MAX_REQUEST]u8 = undefined;
+    var length: usize = 0;
+    while (std.mem.indexOf(u8, request[0..length], "\r\n\r\n") == null) {
+        if (length == request.len) {
+            return error.RequestTooLarge;
+        }
+        const count = try connection.read(request[length..], IO_TIMEOUT_NS);
+        if (count == 0) {
+            return error.EndOfStream;
+        }
+        length += count;
+    }
+    const line_end = std.mem.indexOfScalar(u8, request[0..length], '\r') orelse length;
+    print("{s}\n", .{request[0..line_end]});
+
+    var body_buffer: [256]u8 = undefined;
+    const ms = time.uptimeMs();
+    const body = try std.fmt.bufPrint(&body_buffer, "<html><body><h1>ReasonOS</h1><p>Up for {}.{:0>3}s.</p></body></html>\n", .{ ms / 1000, ms % 1000 });
+
+    var header_buffer: [128]u8 = undefined;
+    const header = try std.fmt.bufPrint(&header_buffer, "HTTP/1.0 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", .{body.len});
+    try connection.write(header, IO_TIMEOUT_NS);
+    try connection.write(body, IO_TIMEOUT_NS);
+}
@@ -0,0 +1,228 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const memory = @import("kernel").memory;
+const log = @import("kernel").utils.log;
+const symbols = @import("kernel").utils.symbols;
+
+// NOTE:
+// `rd`, `wr` and `dis` look at and patch live kernel memory. Before any
+// byte is touched the range is walked through the page tables: it has to
+// be canonical, mapped, and not device memory, where a read alone can
+// acknowledge an interrupt or pop a FIFO. Writes also need a writable
+// mapping; `wr -f` goes through the direct map alias of the frame instead,
+// which is how read-only code gets patched. The accesses themselves go
+// through the exception fixup table, so a page that disappears between
+// the check and the access costs an error message, not the kernel.
+
+const PAGE_SIZE = arch.paging.PAGE_SIZE;
+
+const DEFAULT_DUMP_LENGTH = 64;
+const MAX_DUMP_LENGTH = 4096;
+const DEFAULT_INSTRUCTIONS = 16;
+const MAX_INSTRUCTIONS = 256;
+const MAX_PATCH = 32;
+
+/// Where `rd` and `dis` carry on when given no address.
+var next_dump: usize = 0;
+var next_instruction: usize = 0;
+
+fn print(comptime fmt: []const u8, args: anytype) void {
+    log.writer.print(fmt, args) catch {};
+}
+
+fn isCanonical(address: usize) bool {
+    const upper = address >> 47;
+    return upper == 0 or upper == 0x1ffff;
+}
+
+/// Checks that the `length` bytes at `address` can be read, or written
+/// through their own mapping when `write` is set.
+fn checkRange(address: usize, length: usize, write: bool) !void {
+    if (length == 0) {
+        return;
+    }
+    const last = std.math.add(usize, address, length - 1) catch return error.InvalidArgument;
+    if (!isCanonical(address) or !isCanonical(last)) {
+        return error.NonCanonical;
+    }
+
+    var page = std.mem.alignBackward(usize, address, PAGE_SIZE);
+    while (true) : (page += PAGE_SIZE) {
+        const translation = arch.paging.walk(page);
+        const physical = translation.physical(page) orelse return error.NotMapped;
+        const mtrr_type = arch.mtrr.typeOf(physical);
+        const effective = arch.mtrr.effectiveType(mtrr_type, arch.mtrr.patType(translation.patIndex()));
+        if (effective == .uncacheable) {
+            return error.DeviceMemory;
+        }
+        if (write and !translation.flags().write) {
+            return error.ReadOnly;
+        }
+        if (page == std.mem.alignBackward(usize, last, PAGE_SIZE)) {
+            return;
+        }
+    }
+}
+
+/// A writable address for the byte at `address`: itself, or its alias in
+/// the direct map when its own mapping is read-only.
+fn writableAlias(address: usize) !usize {
+    const translation = arch.paging.walk(address);
+    if (translation.flags().write) {
+        return address;
+    }
+    const physical = translation.physical(address) orelse return error.NotMapped;
+    const alias = memory.physicalToVirtual(physical);
+    try checkRange(alias, 1, true);
+    return alias;
+}
+
+fn parseAddress(text: []const u8) !usize {
+    return std.fmt.parseInt(usize, text, 0) catch error.InvalidAddress;
+}
+
+fn printSymbol(address: usize) void {
+    const symbol = symbols.lookup(address) orelse return;
+    print(" <{s}+0x{x}>", .{ symbol.name, symbol.offset });
+}
+
+/// `rd [<address> [<length>]]`: hex dump, 64 bytes by default.
+pub fn read(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const address = if (args.next()) |text| try parseAddress(text) else next_dump;
+    const length = if (args.next()) |text| try std.fmt.parseInt(usize, text, 0) else DEFAULT_DUMP_LENGTH;
+    if (length == 0 or length > MAX_DUMP_LENGTH) {
+        return error.InvalidLength;
+    }
+    try checkRange(address, length, false);
+
+    var offset: usize = 0;
+    while (offset < length) : (offset += 16) {
+        var line: [16]u8 = undefined;
+        const count = @min(16, length - offset);
+        for (line[0..count], 0..) |*byte, index| {
+            byte.* = arch.extable.readByte(address + offset + index) orelse return error.Faulted;
+        }
+
+        print("0x{x:0>16} ", .{address + offset});
+        for (0..16) |index| {
+            if (index < count) {
+                print(" {x:0>2}", .{line[index]});
+            } else {
+                print("   ", .{});
+            }
+        }
+        print("  |", .{});
+        for (line[0..count]) |byte| {
+            print("{c}", .{if (std.ascii.isPrint(byte)) byte else '.'});
+        }
+        print("|\n", .{});
+    }
+    next_dump = address + length;
+}
+
+/// `wr [-f] <address> <byte>...`: writes hex bytes, `-f` patches
+/// read-only memory through the direct map.
+pub fn write(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    var text = args.next() orelse return error.MissingAddress;
+    const force = std.mem.eql(u8, text, "-f");
+    if (force) {
+        text = args.next() orelse return error.MissingAddress;
+    }
+    const address = try parseAddress(text);
+
+    var bytes: [MAX_PATCH]u8 = undefined;
+    var count: usize = 0;
+    while (args.next()) |byte_text| : (count += 1) {
+        if (count == bytes.len) {
+            return error.TooManyBytes;
+        }
+        bytes[count] = std.fmt.parseInt(u8, byte_text, 16) catch return error.InvalidByte;
+    }
+    if (count == 0) {
+        return error.MissingBytes;
+    }
+
+    checkRange(address, count, true) catch |err| {
+        if (err != error.ReadOnly or !force) {
+            return err;
+        }
+    };
+    for (bytes[0..count], 0..) |byte, index| {
+        const target = if (force) try writableAlias(address + index) else address + index;
+        if (!arch.extable.writeByte(target, byte)) {
+            return error.Faulted;
+        }
+    }
+    print("wrote {} bytes at 0x{x}\n", .{ count, address });
+}
+
+/// Reads as much of the instruction at `address` as is safely readable.
+fn fetch(address: usize, buffer: *[arch.decoder.MAX_LENGTH]u8) []const u8 {
+    for (buffer, 0..) |*byte, index| {
+        const current = address + index;
+        if (index != 0 and current % PAGE_SIZE == 0) {
+            checkRange(current, 1, false) catch return buffer[0..index];
+        }
+        byte.* = arch.extable.readByte(current) orelse return buffer[0..index];
+    }
+    return buffer;
+}
+
+/// `dis [<address> [<count>]]`: lists instructions with their bytes and
+/// where calls and jumps go.
+pub fn disassemble(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    var address = if (args.next()) |text| try parseAddress(text) else next_instruction;
+    const count = if (args.next()) |text| try std.fmt.parseInt(usize, text, 0) else DEFAULT_INSTRUCTIONS;
+    if (count == 0 or count > MAX_INSTRUCTIONS) {
+        return error.InvalidCount;
+    }
+    try checkRange(address, 1, false);
+
+    for (0..count) |_| {
+        var buffer: [arch.decoder.MAX_LENGTH]u8 = undefined;
+        const bytes = fetch(address, &buffer);
+        if (bytes.len == 0) {
+            return error.Faulted;
+        }
+
+        // bytes that don't decode are shown one at a time
+        const instruction = arch.decoder.decode(bytes, address) catch arch.decoder.Instruction{ .length = 1 };
+
+        print("0x{x:0>16} ", .{address});
+        for (0..10) |index| {
+            if (index < instruction.length) {
+                print(" {x:0>2}", .{bytes[index]});
+            } else {
+                print("   ", .{});
+            }
+        }
+        print("{s}", .{if (instruction.length > 10) "+" else " "});
+
+        switch (instruction.kind) {
+            .other => {},
+            .@"return" => print(" ret", .{}),
+            else => |kind| {
+                print(" {s}", .{switch (kind) {
+                    .call => "call",
+                    .jump => "jmp",
+                    else => "jcc",
+                }});
+                if (instruction.target) |target| {
+                    print(" 0x{x}", .{target});
+                    printSymbol(target);
+                } else {
+                    print(" *", .{});
+                }
+            },
+        }
+        if (symbols.lookup(address)) |symbol| {
+            if (symbol.offset == 0) {
+                print("  ; {s}", .{symbol.name});
+            }
+        }
+        print("\n", .{});
+
+        address += instruction.length;
+    }
+    next_instruction = address;
+}
@@ -0,0 +1,355 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const acpi = @import("kernel").acpi;
+const memory = @import("kernel").memory;
+const time = @import("kernel").time;
+const power = @import("kernel").power;
+const init_tasks = @import("kernel").init_tasks;
+const utils = @import("kernel").utils;
+const log = utils.log;
+const console = @import("kernel").drivers.console;
+const framebuffer = @import("kernel").drivers.framebuffer;
+const serial = @import("kernel").drivers.serial;
+const pci = @import("kernel").drivers.pci;
+const net = @import("kernel").net;
+const input = @import("kernel").input;
+
+const inspect = @import("inspect.zig");
+const httpd = @import("httpd.zig");
+
+// NOTE:
+// A line-based monitor on the serial port for poking at the kernel without
+// rebuilding it. It is what the boot CPU does once initialization is over,
+// halting between timer ticks while no input arrives. Lines can also be
+// typed on the keyboard, whose key events are read in the active layout;
+// the line editor only takes ASCII, other characters are ignored.
+
+const PROMPT = "> ";
+const MAX_LINE = 128;
+
+const Command = struct {
+    name: []const u8,
+    help: []const u8,
+    run: *const fn (args: *std.mem.TokenIterator(u8, .scalar)) anyerror!void,
+};
+
+const commands = [_]Command{
+    .{ .name = "help", .help = "list the commands", .run = help },
+    .{ .name = "mem", .help = "physical memory and heap usage", .run = mem },
+    .{ .name = "memmap", .help = "memory map regions and heap chunks", .run = memoryMap },
+    .{ .name = "acpi", .help = "list the ACPI tables", .run = acpiTables },
+    .{ .name = "pagetable", .help = "pagetable <address>: show how an address is mapped", .run = pageTable },
+    .{ .name = "rd", .help = "rd [<address> [<length>]]: hex dump memory", .run = inspect.read },
+    .{ .name = "wr", .help = "wr [-f] <address> <byte>...: patch memory, -f for read-only pages", .run = inspect.write },
+    .{ .name = "dis", .help = "dis [<address> [<count>]]: list instructions and branch targets", .run = inspect.disassemble },
+    .{ .name = "ptdump", .help = "list every mapping of the current address space", .run = pageTableDump },
+    .{ .name = "lspci", .help = "PCI functions with their BARs and interrupts", .run = listPci },
+    .{ .name = "ifconfig", .help = "network devices and their counters", .run = interfaces },
+    .{ .name = "arp", .help = "the ARP cache", .run = arpCache },
+    .{ .name = "route", .help = "the IPv4 routing table", .run = routes },
+    .{ .name = "ping", .help = "ping <address> [<count>]: send ICMP echo requests", .run = ping },
+    .{ .name = "tcp", .help = "TCP listeners and connections", .run = tcpConnections },
+    .{ .name = "httpd", .help = "httpd [<port>]: serve a test page until a key is pressed", .run = httpd.serve },
+    .{ .name = "irqmap", .help = "interrupt routing, handlers and counts", .run = irqMap },
+    .{ .name = "sanity", .help = "check the descriptor tables and kernel mappings", .run = sanity },
+    .{ .name = "displays", .help = "displays [<index> [mirror]]: list displays or move the console", .run = displays },
+    .{ .name = "dmesg", .help = "replay the kernel log", .run = dmesg },
+    .{ .name = "log", .help = "log [<sink> on|off]: list log sinks or switch one", .run = logSinks },
+    .{ .name = "loglevel", .help = "loglevel [[<target>] debug|info|warn]: show or set log levels", .run = logLevel },
+    .{ .name = "inittasks", .help = "how every boot task went and how long it took", .run = initTasks },
+    .{ .name = "uptime", .help = "time since boot", .run = uptime },
+    .{ .name = "date", .help = "wall-clock time and how well it is synchronized", .run = date },
+    .{ .name = "suspend", .help = "suspend [<seconds>]: suspend every device for a while", .run = suspendDevices },
+    .{ .name = "poweroff", .help = "stop everything and power off", .run = powerOff },
+    .{ .name = "panic", .help = "panic the kernel", .run = panic },
+};
+
+fn print(comptime fmt: []const u8, args: anytype) void {
+    log.writer.print(fmt, args) catch {};
+}
+
+fn help(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    for (commands) |command| {
+        print("  {s:<10} {s}\n", .{ command.name, command.help });
+    }
+}
+
+fn mem(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const stats = memory.pmm.stats();
+    print("frames: {} used of {} ({} MiB free)\n", .{
+        stats.used_frames,
+        stats.usable_frames,
+        (stats.usable_frames - stats.used_frames) * memory.pmm.PAGE_SIZE / (1024 * 1024),
+    });
+    if (memory.pmm.ownerCounts()) |counts| {
+        for (std.enums.values(memory.pmm.Owner)) |owner| {
+            print("  {s:<12} {} frames\n", .{ @tagName(owner), counts.get(owner) });
+        }
+    }
+    memory.heap.dumpStats();
+
+    const account = memory.quota.current();
+    print("heap account {s}: {} KiB, peak {} KiB\n", .{ account.name, account.allocated / 1024, account.peak / 1024 });
+}
+
+fn memoryMap(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try memory.pmm.dump(log.writer);
+    print("heap:\n", .{});
+    try memory.heap.dumpChunks(log.writer);
+}
+
+fn acpiTables(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    for (acpi.allTables()) |table| {
+        print("  {s} rev {} {} bytes OEM '{s}'\n", .{ table.signature, table.revision, table.length, table.oem_id });
+    }
+}
+
+fn pageTable(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const text = args.next() orelse return error.MissingAddress;
+    const address = try std.fmt.parseInt(usize, text, 0);
+
+    const levels = [_][]const u8{ "PML4", "PDPT", "PD", "PT" };
+    const result = arch.paging.walk(address);
+    for (result.entries[0..result.depth], 0..) |entry, level| {
+        print("  {s:<4} 0x{x:0>16}\n", .{ levels[level], entry });
+    }
+
+    if (result.physical(address)) |physical| {
+        print("0x{x} -> 0x{x}\n", .{ address, physical });
+    } else {
+        print("0x{x} is not mapped\n", .{address});
+    }
+}
+
+fn pageTableDump(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    if (!arch.page_table_view.isReady()) {
+        return error.Unsupported;
+    }
+    try arch.page_table_view.dump(arch.address_space.current(), log.writer);
+}
+
+fn listPci(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try pci.dump(log.writer);
+}
+
+fn interfaces(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try net.device.dump(log.writer);
+}
+
+fn arpCache(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try net.arp.dump(log.writer);
+}
+
+fn routes(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try net.route.dump(log.writer);
+}
+
+fn tcpConnections(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try net.tcp.dump(log.writer);
+}
+
+var ping_sequence: u16 = 0;
+
+fn ping(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const text = args.next() orelse return error.MissingAddress;
+    const destination = try net.interface.parseAddress(text);
+    const count = if (args.next()) |number| try std.fmt.parseInt(u16, number, 0) else 4;
+
+    var received: u16 = 0;
+    for (0..count) |index| {
+        const sequence = ping_sequence;
+        ping_sequence +%= 1;
+        const sent = time.nowNs();
+        try net.icmp.ping(destination, sequence);
+
+        const reply = while (time.nowNs() - sent < std.time.ns_per_s) {
+            // the shell is what polls devices without an interrupt
+            net.device.pollAll();
+            if (net.icmp.replyTo(sequence)) |reply| {
+                break reply;
+            }
+            time.sleep(std.time.ns_per_ms);
+        } else null;
+
+        if (reply) |answer| {
+            received += 1;
+            const us = (answer.received_ns - sent) / std.time.ns_per_us;
+            print("reply from {}: seq={} ttl={} time={}.{:0>3}ms\n", .{ net.interface.formatAddress(answer.source), sequence, answer.ttl, us / 1000, us % 1000 });
+        } else {
+            print("no reply for seq={}\n", .{sequence});
+        }
+        if (index + 1 < count) {
+            time.sleep(std.time.ns_per_s -| (time.nowNs() - sent));
+        }
+    }
+    print("{} sent, {} received\n", .{ count, received });
+}
+
+fn irqMap(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try arch.irq.dump(log.writer);
+}
+
+fn sanity(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const problems = arch.sanity.check() + arch.sanity.checkMappings();
+    print("{} problems\n", .{problems});
+}
+
+fn displays(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const text = args.next() orelse {
+        for (framebuffer.all(), 0..) |display, index| {
+            print("  {} {}x{} {} bpp\n", .{ index, display.width(), display.height(), display.framebuffer.bpp });
+        }
+        return;
+    };
+
+    const index = try std.fmt.parseInt(usize, text, 0);
+    const mirror = if (args.next()) |flag| std.mem.eql(u8, flag, "mirror") else false;
+    try console.select(index, mirror);
+}
+
+fn dmesg(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try utils.dmesg.replay(log.writer);
+}
+
+fn logSinks(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const name = args.next() orelse {
+        var sink = log.firstSink();
+        while (sink) |current| : (sink = current.next) {
+            print("  {s:<8} {s}\n", .{ current.name, if (current.enabled) "on" else "off" });
+        }
+        return;
+    };
+
+    const state = args.next() orelse return error.MissingState;
+    if (!std.mem.eql(u8, state, "on") and !std.mem.eql(u8, state, "off")) {
+        return error.InvalidArgument;
+    }
+    try log.setSinkEnabled(name, std.mem.eql(u8, state, "on"));
+}
+
+fn logLevel(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const first = args.next() orelse {
+        print("  {s:<8} {s}\n", .{ "*", @tagName(log.level()) });
+        for (log.targetLevels()) |*target| {
+            print("  {s:<8} {s}\n", .{ target.name(), @tagName(target.level) });
+        }
+        return;
+    };
+
+    const second = args.next() orelse {
+        log.setLevel(std.meta.stringToEnum(log.Level, first) orelse return error.InvalidArgument);
+        return;
+    };
+    try log.setTargetLevel(first, std.meta.stringToEnum(log.Level, second) orelse return error.InvalidArgument);
+}
+
+fn initTasks(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    try init_tasks.dump(log.writer);
+}
+
+fn uptime(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const ms = time.uptimeMs();
+    print("{}.{:0>3}s\n", .{ ms / 1000, ms % 1000 });
+}
+
+fn date(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const now = time.wall_clock.nowNs() orelse {
+        print("unknown, the wall clock has not been set\n", .{});
+        return;
+    };
+    time.wall_clock.formatTime(now, log.writer) catch {};
+    print(", {}us still to slew in\n", .{@divTrunc(time.wall_clock.pendingNs(), std.time.ns_per_us)});
+}
+
+fn suspendDevices(args: *std.mem.TokenIterator(u8, .scalar)) !void {
+    const seconds = if (args.next()) |text| try std.fmt.parseInt(u64, text, 0) else 2;
+    try power.standby(seconds * std.time.ns_per_s);
+}
+
+fn powerOff(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    power.shutdown.powerOff();
+}
+
+fn panic(_: *std.mem.TokenIterator(u8, .scalar)) !void {
+    @panic("requested from the debug shell");
+}
+
+fn execute(line: []const u8) void {
+    var args = std.mem.tokenizeScalar(u8, line, ' ');
+    const name = args.next() orelse return;
+
+    for (commands) |command| {
+        if (std.mem.eql(u8, command.name, name)) {
+            command.run(&args) catch |err| {
+                print("{s}: {s}\n", .{ name, @errorName(err) });
+            };
+            return;
+        }
+    }
+    print("unknown command '{s}', try 'help'\n", .{name});
+}
+
+/// What the last key press typed and how much of it was read.
+var typed = input.keymap.Output{};
+var typed_read: usize = 0;
+
+/// The next byte typed on the serial port or the keyboard, if there is one.
+fn readByte() ?u8 {
+    if (serial.readByte()) |byte| {
+        return byte;
+    }
+
+    while (true) {
+        if (typed_read < typed.len) {
+            const codepoint = typed.slice()[typed_read];
+            typed_read += 1;
+            if (codepoint < 0x80) {
+                return @intCast(codepoint);
+            }
+            continue;
+        }
+        // releases and modifiers type nothing
+        const event = input.keyboard.next() orelse return null;
+        typed = event.text;
+        typed_read = 0;
+    }
+}
+
+pub fn run() noreturn {
+    var line: [MAX_LINE]u8 = undefined;
+    var len: usize = 0;
+
+    print("\nDebug shell ready, type 'help' for a list of commands\n" ++ PROMPT, .{});
+
+    while (true) {
+        const byte = readByte() orelse {
+            // nothing else picks up frames on devices without an interrupt
+            net.device.pollAll();
+            if (time.isTicking()) {
+                asm volatile ("hlt");
+            } else {
+                std.atomic.spinLoopHint();
+            }
+            continue;
+        };
+
+        switch (byte) {
+            '\r', '\n' => {
+                print("\n", .{});
+                execute(line[0..len]);
+                len = 0;
+                print(PROMPT, .{});
+            },
+            // backspace and delete
+            0x08, 0x7f => if (len > 0) {
+                len -= 1;
+                print("\x08 \x08", .{});
+            },
+            else => if (std.ascii.isPrint(byte) and len < line.len) {
+                line[len] = byte;
+                len += 1;
+                print("{c}", .{byte});
+            },
+        }
+    }
+}
@@ -0,0 +1,14 @@
+const arch = @import("kernel").arch;
+const memory = @import("kernel").memory;
+const fs = @import("kernel").fs;
+const time = @import("kernel").time;
+const net = @import("kernel").net;
+const drivers = @import("kernel").drivers;
+
+/// In-kernel tests, run by `zig build test` in place of the normal boot.
+pub const all = arch.exception_tests.all ++ arch.paging_tests.all ++ arch.decoder_tests.all ++ memory.heap_tests.all ++ memory.pmm_tests.all ++ memory.sg_list_tests.all ++ time.timer_tests.all ++ time.sntp_tests.all ++ drivers.rtc_tests.all ++ fs.tmpfs_tests.all ++ fs.devfs_tests.all ++ fs.block_cache_tests.all ++ fs.ustar_tests.all ++ net.arp_tests.all ++ net.ipv4_tests.all ++ net.udp_tests.all ++ net.tcp_tests.all;
+
+/// The tests that are safe and meaningful on real hardware, run at boot with
+/// `selftest=on`. Exhausting physical memory is left out, it would take the
+/// whole machine down with it if giving the frames back went wrong.
+pub const selftest = arch.exception_tests.all ++ arch.paging_tests.all ++ memory.heap_tests.all ++ time.timer_tests.all;
@@ -0,0 +1,115 @@
+const std = @import("std");
+const cmdline = @import("kernel").cmdline;
+const net = @import("kernel").net;
+const Error = @import("kernel").Error;
+const log = @import("kernel").utils.log;
+
+// NOTE:
+// SNTP (RFC 4330) asks a time server for the time with one 48 byte UDP
+// packet to port 123 and gets one back. The reply carries when the server
+// received the request (t2) and when it answered (t3); with when we sent it
+// (t1) and when the reply arrived (t4) that gives both the clock's error,
+// assuming the network delay is the same each way, and the round trip:
+//
+//     offset = ((t2 - t1) + (t3 - t4)) / 2
+//     delay  = (t4 - t1) - (t3 - t2)
+//
+// NTP timestamps are seconds since 1900 in the upper 32 bits and a binary
+// fraction in the lower 32. Only era 0 is handled, which lasts until 2036.
+// This file only builds and checks packets, sending them is up to whoever
+// has a socket.
+
+pub const PORT = 123;
+pub const PACKET_SIZE = 48;
+
+/// Seconds between 1900 and the Unix epoch.
+const UNIX_OFFSET = 2_208_988_800;
+
+const VERSION = 4;
+const MODE_CLIENT = 3;
+const MODE_SERVER = 4;
+/// Leap indicator saying the server's clock isn't synchronized.
+const LEAP_UNSYNCHRONIZED = 3;
+
+const ORIGINATE = 24;
+const RECEIVE = 32;
+const TRANSMIT = 40;
+
+/// What one exchange with a server says about the local clock.
+pub const Sample = struct {
+    /// Real time minus local time, what to `wall_clock.correct` by.
+    offset_ns: i64,
+    /// Round trip spent on the network.
+    delay_ns: i64,
+    stratum: u8,
+};
+
+/// The server to ask, from `sntp.server=a.b.c.d`, null when not given.
+pub fn configuredServer() ?[4]u8 {
+    const text = cmdline.getOr([]const u8, "sntp.server", "");
+    if (text.len == 0) {
+        return null;
+    }
+    return net.interface.parseAddress(text) catch {
+        log.warn("Ignoring malformed sntp.server", .{});
+        return null;
+    };
+}
+
+pub fn toTimestamp(unix_ns: i64) u64 {
+    const ns: u64 = @intCast(@max(0, unix_ns));
+    const seconds = ns / std.time.ns_per_s + UNIX_OFFSET;
+    const fraction = ((ns % std.time.ns_per_s) << 32) / std.time.ns_per_s;
+    return @as(u64, @as(u32, @truncate(seconds))) << 32 | fraction;
+}
+
+pub fn fromTimestamp(timestamp: u64) i64 {
+    const seconds: i64 = @as(i64, @intCast(timestamp >> 32)) - UNIX_OFFSET;
+    const fraction: i64 = @intCast(((timestamp & 0xffff_ffff) * std.time.ns_per_s) >> 32);
+    return seconds * std.time.ns_per_s + fraction;
+}
+
+/// A client request sent at `transmit_ns`. Keep it around to check the
+/// reply against.
+pub fn request(transmit_ns: i64) [PACKET_SIZE]u8 {
+    var packet = [_]u8{0} ** PACKET_SIZE;
+    packet[0] = VERSION << 3 | MODE_CLIENT;
+    std.mem.writeInt(u64, packet[TRANSMIT..][0..8], toTimestamp(transmit_ns), .big);
+    return packet;
+}
+
+/// Checks `reply` to `sent`, received at `received_ns`, and works out how
+/// far off the clock is. `error.Corrupted` for anything that isn't a reply
+/// to this request, `error.Unsupported` when the server doesn't know the
+/// time either or tells us to go away.
+pub fn parseReply(reply: []const u8, sent: *const [PACKET_SIZE]u8, received_ns: i64) Error!Sample {
+    if (reply.len < PACKET_SIZE) {
+        return error.Corrupted;
+    }
+    const leap = reply[0] >> 6;
+    const mode = reply[0] & 0x7;
+    const stratum = reply[1];
+    if (mode != MODE_SERVER) {
+        return error.Corrupted;
+    }
+    // the server echoes our transmit timestamp, which also shows the reply
+    // isn't stale or spoofed by someone who never saw the request
+    if (!std.mem.eql(u8, reply[ORIGINATE..][0..8], sent[TRANSMIT..][0..8])) {
+        return error.Corrupted;
+    }
+    // stratum 0 is a kiss-o'-death packet
+    if (leap == LEAP_UNSYNCHRONIZED or stratum == 0) {
+        return error.Unsupported;
+    }
+
+    const t1 = fromTimestamp(std.mem.readInt(u64, sent[TRANSMIT..][0..8], .big));
+    const t2 = fromTimestamp(std.mem.readInt(u64, reply[RECEIVE..][0..8], .big));
+    const t3 = fromTimestamp(std.mem.readInt(u64, reply[TRANSMIT..][0..8], .big));
+    const t4 = received_ns;
+
+    return .{
+        .offset_ns = @divTrunc((t2 - t1) + (t3 - t4), 2),
+        .delay_ns = @max(0, (t4 - t1) - (t3 - t2)),
+        .stratum = stratum,
+    };
+}
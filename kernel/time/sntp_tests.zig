@@ -0,0 +1,82 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const sntp = @import("sntp.zig");
+
+/// 2024-01-01 00:00:00 UTC.
+const JANUARY_2024 = 1_704_067_200 * std.time.ns_per_s;
+
+/// The reply a server whose clock is `ahead_ns` in front of ours sends
+/// when the network takes `one_way_ns` each way and it answers at once.
+fn replyTo(sent: *const [sntp.PACKET_SIZE]u8, ahead_ns: i64, one_way_ns: i64) [sntp.PACKET_SIZE]u8 {
+    const t1 = sntp.fromTimestamp(std.mem.readInt(u64, sent[40..48], .big));
+    const server_time = t1 + one_way_ns + ahead_ns;
+
+    var reply = [_]u8{0} ** sntp.PACKET_SIZE;
+    reply[0] = 4 << 3 | 4;
+    reply[1] = 2;
+    @memcpy(reply[24..32], sent[40..48]);
+    std.mem.writeInt(u64, reply[32..40], sntp.toTimestamp(server_time), .big);
+    std.mem.writeInt(u64, reply[40..48], sntp.toTimestamp(server_time), .big);
+    return reply;
+}
+
+/// Timestamps lose under a nanosecond to the 32 bit fraction.
+fn near(value: i64, expected: i64) bool {
+    return @abs(value - expected) <= 2;
+}
+
+fn offsetAndDelay() !void {
+    if (!near(sntp.fromTimestamp(sntp.toTimestamp(JANUARY_2024 + 123_456_789)), JANUARY_2024 + 123_456_789)) {
+        return error.TimestampRoundTrip;
+    }
+
+    const sent_at = JANUARY_2024;
+    const sent = sntp.request(sent_at);
+    const reply = replyTo(&sent, 3 * std.time.ns_per_s, 20 * std.time.ns_per_ms);
+
+    const sample = try sntp.parseReply(&reply, &sent, sent_at + 40 * std.time.ns_per_ms);
+    if (!near(sample.offset_ns, 3 * std.time.ns_per_s)) {
+        return error.WrongOffset;
+    }
+    if (!near(sample.delay_ns, 40 * std.time.ns_per_ms)) {
+        return error.WrongDelay;
+    }
+}
+
+fn expectRejected(reply: []const u8, sent: *const [sntp.PACKET_SIZE]u8, expected: anyerror) !void {
+    if (sntp.parseReply(reply, sent, JANUARY_2024)) |_| {
+        return error.Accepted;
+    } else |err| if (err != expected) {
+        return err;
+    }
+}
+
+/// Replies that aren't answers to our request, or come from a server that
+/// doesn't know the time, must not touch the clock.
+fn badReplies() !void {
+    const sent = sntp.request(JANUARY_2024);
+    const good = replyTo(&sent, 0, 0);
+
+    try expectRejected(good[0..47], &sent, error.Corrupted);
+
+    var client_mode = good;
+    client_mode[0] = 4 << 3 | 3;
+    try expectRejected(&client_mode, &sent, error.Corrupted);
+
+    const other = sntp.request(JANUARY_2024 + std.time.ns_per_s);
+    try expectRejected(&good, &other, error.Corrupted);
+
+    var kiss_of_death = good;
+    kiss_of_death[1] = 0;
+    try expectRejected(&kiss_of_death, &sent, error.Unsupported);
+
+    var unsynchronized = good;
+    unsynchronized[0] |= 3 << 6;
+    try expectRejected(&unsynchronized, &sent, error.Unsupported);
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "sntp.offset_and_delay", .func = offsetAndDelay },
+    .{ .name = "sntp.bad_replies", .func = badReplies },
+};
@@ -0,0 +1,115 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log;
+const Error = @import("kernel").Error;
+
+pub const timer = @import("timer.zig");
+pub const Timer = timer.Timer;
+pub const timer_tests = @import("timer_tests.zig");
+pub const wall_clock = @import("wall_clock.zig");
+pub const sntp = @import("sntp.zig");
+pub const sntp_tests = @import("sntp_tests.zig");
+
+/// Rate of the tick interrupt that expires timers.
+pub const TICK_HZ = 100;
+
+var tsc_frequency: u64 = 0;
+var boot_tsc: u64 = 0;
+/// kvmclock reading at boot when it is the clock source, zero otherwise.
+var boot_kvmclock: u64 = 0;
+
+pub fn init() Error!void {
+    if (arch.kvmclock.init()) {
+        tsc_frequency = arch.kvmclock.tscFrequency();
+        boot_kvmclock = arch.kvmclock.readNs();
+        log.info("Using kvmclock as the clock source", .{});
+    } else |_| {
+        tsc_frequency = try arch.tsc.calibrate();
+        if (!arch.tsc.isInvariant()) {
+            log.warn("TSC is not invariant, the monotonic clock may drift", .{});
+        }
+    }
+    boot_tsc = arch.tsc.read();
+
+    log.info("TSC runs at {} MHz", .{tsc_frequency / 1_000_000});
+
+    arch.apic_timer.calibrate() catch |err| {
+        log.warn("APIC timer unavailable, timers will not fire: {s}", .{@errorName(err)});
+        return;
+    };
+    try arch.apic_timer.setPeriodic(TICK_HZ, tick);
+    ticking = true;
+}
+
+var ticks: u64 = 0;
+var ticking = false;
+
+fn tick() void {
+    ticks += 1;
+    timer.expire();
+}
+
+/// Whether the tick interrupt is running, i.e. whether a halted CPU is
+/// guaranteed to wake up again.
+pub fn isTicking() bool {
+    return ticking;
+}
+
+/// Number of tick interrupts taken since the tick was started.
+pub fn tickCount() u64 {
+    return @atomicLoad(u64, &ticks, .monotonic);
+}
+
+/// Busy-waits for at least `us` microseconds. Meant for the short delays
+/// device drivers need, not for waiting on events.
+pub fn delayUs(us: u64) void {
+    std.debug.assert(tsc_frequency != 0);
+
+    const deadline = arch.tsc.read() + us * tsc_frequency / 1_000_000;
+    while (arch.tsc.read() < deadline) {
+        std.atomic.spinLoopHint();
+    }
+}
+
+pub fn delayMs(ms: u64) void {
+    delayUs(ms * 1000);
+}
+
+/// Waits for at least `ns` nanoseconds. Without a scheduler to yield to, the
+/// CPU halts between ticks instead of spinning, so this is for waits long
+/// enough that a tick period of slack doesn't matter.
+pub fn sleep(ns: u64) void {
+    // without the tick nothing would wake a halted CPU up
+    if (!ticking or !arch.cpu.interruptsEnabled()) {
+        return delayUs(std.math.divCeil(u64, ns, std.time.ns_per_us) catch unreachable);
+    }
+
+    const deadline = nowNs() + ns;
+    while (nowNs() < deadline) {
+        asm volatile ("hlt");
+    }
+}
+
+/// TSC ticks per second, zero before the clock is calibrated.
+pub fn tscFrequency() u64 {
+    return tsc_frequency;
+}
+
+/// Nanoseconds since the clock was calibrated, zero before that.
+pub fn nowNs() u64 {
+    if (tsc_frequency == 0) {
+        return 0;
+    }
+
+    if (arch.kvmclock.isEnabled()) {
+        return arch.kvmclock.readNs() -% boot_kvmclock;
+    }
+
+    const elapsed = arch.tsc.read() - boot_tsc;
+    return @intCast(@as(u128, elapsed) * std.time.ns_per_s / tsc_frequency);
+}
+
+/// Time since boot at millisecond resolution.
+pub fn uptimeMs() u64 {
+    return nowNs() / std.time.ns_per_ms;
+}
@@ -0,0 +1,115 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+
+const time = @import("time.zig");
+
+// NOTE:
+// Timers are intrusive: whoever starts one owns the `Timer` and keeps it
+// alive until it fired or was cancelled, so nothing is allocated from the
+// tick interrupt. Pending timers sit on a list sorted by deadline and the
+// tick only ever looks at its head. Callbacks run in interrupt context with
+// interrupts disabled and must be short.
+
+pub const Callback = *const fn (timer: *Timer) void;
+
+pub const Timer = struct {
+    /// Monotonic time in nanoseconds, see `time.nowNs`.
+    deadline: u64 = 0,
+    /// Zero for one-shot timers.
+    period: u64 = 0,
+    callback: Callback = undefined,
+    next: ?*Timer = null,
+    pending: bool = false,
+
+    const Self = @This();
+
+    /// Calls `callback` once, `duration_ns` from now.
+    pub fn oneShot(self: *Self, duration_ns: u64, callback: Callback) void {
+        self.start(duration_ns, 0, callback);
+    }
+
+    /// Calls `callback` every `period_ns` until cancelled.
+    pub fn periodic(self: *Self, period_ns: u64, callback: Callback) void {
+        std.debug.assert(period_ns != 0);
+        self.start(period_ns, period_ns, callback);
+    }
+
+    fn start(self: *Self, duration_ns: u64, period_ns: u64, callback: Callback) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        if (self.pending) {
+            unlink(self);
+        }
+
+        self.deadline = time.nowNs() + duration_ns;
+        self.period = period_ns;
+        self.callback = callback;
+        insert(self);
+    }
+
+    /// Stops the timer, a no-op if it is not pending.
+    pub fn cancel(self: *Self) void {
+        const guard = arch.interrupts.disable();
+        defer guard.restore();
+
+        if (self.pending) {
+            unlink(self);
+        }
+    }
+};
+
+var queue: ?*Timer = null;
+
+fn insert(timer: *Timer) void {
+    var link = &queue;
+    while (link.*) |current| : (link = &current.next) {
+        if (current.deadline > timer.deadline) {
+            break;
+        }
+    }
+
+    timer.next = link.*;
+    timer.pending = true;
+    link.* = timer;
+}
+
+fn unlink(timer: *Timer) void {
+    var link = &queue;
+    while (link.*) |current| : (link = &current.next) {
+        if (current == timer) {
+            link.* = current.next;
+            break;
+        }
+    }
+
+    timer.next = null;
+    timer.pending = false;
+}
+
+/// Fires every timer whose deadline has passed, called from the tick
+/// interrupt.
+pub fn expire() void {
+    const now = time.nowNs();
+
+    while (queue) |timer| {
+        if (timer.deadline > now) {
+            break;
+        }
+
+        queue = timer.next;
+        timer.next = null;
+        timer.pending = false;
+
+        if (timer.period != 0) {
+            timer.deadline += timer.period;
+            // don't try to catch up on periods that were missed entirely
+            if (timer.deadline <= now) {
+                timer.deadline = now + timer.period;
+            }
+            insert(timer);
+        }
+
+        timer.callback(timer);
+    }
+}
@@ -0,0 +1,56 @@
+const std = @import("std");
+const testdev = @import("kernel").utils.testdev;
+
+const time = @import("time.zig");
+const timer = @import("timer.zig");
+
+var fired = std.atomic.Value(bool).init(false);
+
+fn onFire(_: *timer.Timer) void {
+    fired.store(true, .release);
+}
+
+/// A one-shot timer has to fire from the tick, and not before its deadline.
+fn oneShot() !void {
+    if (!time.isTicking()) {
+        return error.NotTicking;
+    }
+
+    fired.store(false, .release);
+    var pending = timer.Timer{};
+    pending.oneShot(10 * std.time.ns_per_ms, onFire);
+    defer pending.cancel();
+
+    const give_up = time.nowNs() + std.time.ns_per_s;
+    while (!fired.load(.acquire)) {
+        if (time.nowNs() > give_up) {
+            return error.NeverFired;
+        }
+        asm volatile ("hlt");
+    }
+    if (time.nowNs() < pending.deadline) {
+        return error.FiredEarly;
+    }
+}
+
+fn monotonicClock() !void {
+    var previous = time.nowNs();
+    for (0..10_000) |_| {
+        const now = time.nowNs();
+        if (now < previous) {
+            return error.WentBackwards;
+        }
+        previous = now;
+    }
+
+    const before = time.nowNs();
+    time.sleep(5 * std.time.ns_per_ms);
+    if (time.nowNs() - before < 5 * std.time.ns_per_ms) {
+        return error.WokeEarly;
+    }
+}
+
+pub const all = [_]testdev.Test{
+    .{ .name = "timer.one_shot", .func = oneShot },
+    .{ .name = "timer.monotonic_clock", .func = monotonicClock },
+};
@@ -0,0 +1,143 @@
+const std = @import("std");
+const arch = @import("kernel").arch;
+const log = @import("kernel").utils.log;
+const SpinLock = @import("kernel").utils.lock.SpinLock;
+
+const time = @import("time.zig");
+
+// NOTE:
+// The wall clock is the monotonic clock plus an offset. Whoever knows the
+// real time (the RTC at boot, an NTP server later) reports how far off the
+// clock is, and small errors are slewed away: the offset moves towards the
+// target by at most `SLEW_PPM` nanoseconds per millisecond of monotonic
+// time, so the wall clock never jumps and never runs backwards. Errors
+// larger than `STEP_THRESHOLD_NS`, and the first time the clock is set,
+// step it instead, as slewing them away would take hours.
+
+/// Most the clock is sped up or slowed down, in parts per million.
+pub const SLEW_PPM = 500;
+/// Errors past this are stepped instead of slewed.
+pub const STEP_THRESHOLD_NS = 128 * std.time.ns_per_ms;
+
+/// Taken with interrupts disabled, the SNTP client corrects the clock from
+/// its receive handler.
+var lock = SpinLock.init();
+var synchronized = false;
+/// Wall time minus monotonic time, without the pending slew.
+var base_offset: i64 = 0;
+/// Correction still to be slewed in, as of `slew_start`.
+var slew: i64 = 0;
+/// Monotonic time the current slew started at.
+var slew_start: u64 = 0;
+
+/// Part of `pending` slewed in after `elapsed` nanoseconds.
+fn slewed(pending: i64, elapsed: u64) i64 {
+    const limit = std.math.mul(u64, elapsed, SLEW_PPM) catch std.math.maxInt(u64);
+    const magnitude: i64 = @intCast(@min(@abs(pending), limit / 1_000_000));
+    return if (pending < 0) -magnitude else magnitude;
+}
+
+/// Folds the part of the slew applied by `now` into the base offset.
+fn settle(now: u64) void {
+    const applied = slewed(slew, now - slew_start);
+    base_offset += applied;
+    slew -= applied;
+    slew_start = now;
+}
+
+/// Nanoseconds since the Unix epoch, null until the clock has been set.
+pub fn nowNs() ?i64 {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    lock.acquire();
+    defer lock.release();
+
+    if (!synchronized) {
+        return null;
+    }
+    const now = time.nowNs();
+    return @as(i64, @intCast(now)) + base_offset + slewed(slew, now - slew_start);
+}
+
+/// Seconds since the Unix epoch, null until the clock has been set.
+pub fn now() ?i64 {
+    const ns = nowNs() orelse return null;
+    return @divFloor(ns, std.time.ns_per_s);
+}
+
+/// Like `nowNs`, but counting from boot while the clock isn't set, which
+/// is what timestamps sent to a time server need.
+pub fn estimateNs() i64 {
+    return nowNs() orelse @intCast(time.nowNs());
+}
+
+pub fn isSynchronized() bool {
+    return @atomicLoad(bool, &synchronized, .acquire);
+}
+
+/// Sets the clock to `unix_ns` right away.
+pub fn set(unix_ns: i64) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    lock.acquire();
+    defer lock.release();
+
+    const now = time.nowNs();
+    base_offset = unix_ns - @as(i64, @intCast(now));
+    slew = 0;
+    slew_start = now;
+    @atomicStore(bool, &synchronized, true, .release);
+}
+
+/// Corrects the clock by `error_ns`, the real time minus what the clock
+/// reads. Replaces whatever is left of an earlier correction, since the
+/// new measurement already accounts for it.
+pub fn correct(error_ns: i64) void {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    lock.acquire();
+    defer lock.release();
+
+    const now = time.nowNs();
+    if (!synchronized or @abs(error_ns) > STEP_THRESHOLD_NS) {
+        if (synchronized) {
+            log.warn("Wall clock is off by {}ms, stepping it", .{@divTrunc(error_ns, std.time.ns_per_ms)});
+        }
+        settle(now);
+        base_offset += error_ns;
+        slew = 0;
+        @atomicStore(bool, &synchronized, true, .release);
+        return;
+    }
+
+    settle(now);
+    slew = error_ns;
+}
+
+/// Correction still to be slewed in.
+pub fn pendingNs() i64 {
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+    lock.acquire();
+    defer lock.release();
+
+    const now = time.nowNs();
+    return slew - slewed(slew, now - slew_start);
+}
+
+/// Writes the wall time as `YYYY-MM-DD hh:mm:ss UTC`.
+pub fn formatTime(unix_ns: i64, writer: anytype) !void {
+    const seconds = std.time.epoch.EpochSeconds{ .secs = @intCast(@max(0, @divFloor(unix_ns, std.time.ns_per_s))) };
+    const year_day = seconds.getEpochDay().calculateYearDay();
+    const month_day = year_day.calculateMonthDay();
+    const day_seconds = seconds.getDaySeconds();
+
+    try writer.print("{}-{:0>2}-{:0>2} {:0>2}:{:0>2}:{:0>2} UTC", .{
+        year_day.year,
+        month_day.month.numeric(),
+        month_day.day_index + 1,
+        day_seconds.getHoursIntoDay(),
+        day_seconds.getMinutesIntoHour(),
+        day_seconds.getSecondsIntoMinute(),
+    });
+}
@@ -0,0 +1,20 @@
+const std = @import("std");
+
+const log = @import("log.zig");
+const symbols = @import("symbols.zig");
+
+/// Logs the return addresses found by walking the frame pointer chain,
+/// starting at `first_address` (or the caller when null).
+pub fn dump(first_address: ?usize) void {
+    var iterator = std.debug.StackIterator.init(first_address orelse @returnAddress(), null);
+    defer iterator.deinit();
+
+    log.write("Backtrace:", .{});
+    while (iterator.next()) |address| {
+        if (symbols.lookup(address)) |symbol| {
+            log.write("  0x{x:0>16} {s}+0x{x}", .{ address, symbol.name, symbol.offset });
+        } else {
+            log.write("  0x{x:0>16} ???", .{address});
+        }
+    }
+}
@@ -0,0 +1,101 @@
+const std = @import("std");
+
+const SpinLock = @import("lock.zig").SpinLock;
+
+// NOTE:
+// Every line logged through `log.debug/info/warn/write` is also kept here,
+// from the first message on, so that what scrolled past before anyone was
+// watching the serial port can be read back. Positions are byte offsets
+// since boot; once the ring wraps the oldest ones are gone.
+
+const SIZE = 64 * 1024;
+
+var ring: [SIZE]u8 = undefined;
+var written: usize = 0;
+var lock = SpinLock.init();
+
+pub fn append(bytes: []const u8) void {
+    const arch = @import("kernel").arch;
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    // only the tail of a message longer than the ring would survive
+    const kept = bytes[bytes.len -| SIZE..];
+    written += bytes.len - kept.len;
+
+    for (kept) |byte| {
+        ring[written % SIZE] = byte;
+        written += 1;
+    }
+}
+
+/// Offset of the oldest byte still in the ring.
+pub fn oldest() usize {
+    return @atomicLoad(usize, &written, .monotonic) -| SIZE;
+}
+
+/// Offset just past the newest byte.
+pub fn end() usize {
+    return @atomicLoad(usize, &written, .monotonic);
+}
+
+/// Copies the bytes starting at `offset` into `buffer`. Returns how many
+/// were copied, fewer if `offset` was overwritten in the meantime or the
+/// end was reached.
+pub fn read(offset: usize, buffer: []u8) usize {
+    const arch = @import("kernel").arch;
+    const guard = arch.interrupts.disable();
+    defer guard.restore();
+
+    lock.acquire();
+    defer lock.release();
+
+    if (offset < written -| SIZE or offset >= written) {
+        return 0;
+    }
+
+    const len = @min(buffer.len, written - offset);
+    for (buffer[0..len], offset..) |*byte, position| {
+        byte.* = ring[position % SIZE];
+    }
+    return len;
+}
+
+/// Writes everything still in the ring to `writer`, starting at the first
+/// complete line.
+pub fn replay(writer: anytype) !void {
+    return replayFrom(writer, oldest());
+}
+
+/// Writes the ring from `start` on to `writer`, starting at the first
+/// complete line there.
+pub fn replayFrom(writer: anytype, start: usize) !void {
+    var buffer: [256]u8 = undefined;
+    var offset = @max(start, oldest());
+    var skipping = offset != 0;
+
+    while (true) {
+        const len = read(offset, &buffer);
+        if (len == 0) {
+            // wrapped past us, carry on from the new oldest byte
+            if (offset < oldest()) {
+                offset = oldest();
+                skipping = true;
+                continue;
+            }
+            return;
+        }
+        offset += len;
+
+        var chunk = buffer[0..len];
+        if (skipping) {
+            const newline = std.mem.indexOfScalar(u8, chunk, '\n') orelse continue;
+            chunk = chunk[newline + 1 ..];
+            skipping = false;
+        }
+        try writer.writeAll(chunk);
+    }
+}
@@ -0,0 +1,109 @@
+const std = @import("std");
+const options = @import("build_options");
+
+const log = @import("log.zig");
+const SpinLock = @import("lock.zig").SpinLock;
+
+// NOTE:
+// Fault injection is compiled in with `-Dfault-injection=true`. Without it
+// `shouldFail` is comptime-known to be false and every injection point folds
+// away.
+pub const enabled = options.fault_injection;
+
+/// A place in the kernel that may have failures injected into it. Sites are
+/// meant to be declared as globals next to the code they guard.
+pub const Site = struct {
+    name: []const u8,
+    /// Sites that cannot recover from failure (yet) opt out with `false`.
+    enabled: bool = true,
+    hits: u64 = 0,
+    injected: u64 = 0,
+};
+
+const DEFAULT_SEED = 0x5eed;
+
+pub const Config = struct {
+    seed: u64 = DEFAULT_SEED,
+    /// Out of every 1000 hits how many should fail.
+    rate_per_mille: u16 = 10,
+};
+
+var config = Config{};
+var state: u64 = DEFAULT_SEED;
+var lock = SpinLock.init();
+
+pub fn configure(new_config: Config) void {
+    lock.acquire();
+    defer lock.release();
+
+    config = new_config;
+    // xorshift gets stuck on zero
+    state = if (new_config.seed == 0) DEFAULT_SEED else new_config.seed;
+
+    log.info("Fault injection: seed=0x{x} rate={}/1000", .{ new_config.seed, new_config.rate_per_mille });
+}
+
+fn next() u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    return state;
+}
+
+/// Decides whether the operation guarded by `site` should fail this time.
+pub fn shouldFail(site: *Site) bool {
+    if (!enabled) {
+        return false;
+    }
+
+    lock.acquire();
+    defer lock.release();
+
+    site.hits += 1;
+    if (!site.enabled or next() % 1000 >= config.rate_per_mille) {
+        return false;
+    }
+
+    site.injected += 1;
+    return true;
+}
+
+/// Wraps an allocator so that allocations through it fail according to `site`.
+pub const FailingAllocator = struct {
+    backing: std.mem.Allocator,
+    site: *Site,
+
+    const Self = @This();
+
+    pub fn allocator(self: *Self) std.mem.Allocator {
+        return .{
+            .ptr = self,
+            .vtable = &.{
+                .alloc = alloc,
+                .resize = resize,
+                .free = free,
+            },
+        };
+    }
+
+    fn alloc(ctx: *anyopaque, len: usize, ptr_align: u8, ret_addr: usize) ?[*]u8 {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        if (shouldFail(self.site)) {
+            return null;
+        }
+        return self.backing.rawAlloc(len, ptr_align, ret_addr);
+    }
+
+    fn resize(ctx: *anyopaque, buf: []u8, buf_align: u8, new_len: usize, ret_addr: usize) bool {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        if (new_len > buf.len and shouldFail(self.site)) {
+            return false;
+        }
+        return self.backing.rawResize(buf, buf_align, new_len, ret_addr);
+    }
+
+    fn free(ctx: *anyopaque, buf: []u8, buf_align: u8, ret_addr: usize) void {
+        const self: *Self = @ptrCast(@alignCast(ctx));
+        self.backing.rawFree(buf, buf_align, ret_addr);
+    }
+};
@@ -0,0 +1,113 @@
+const std = @import("std");
+
+const log = @import("log.zig");
+const dmesg = @import("dmesg.zig");
+const symbols = @import("symbols.zig");
+
+// NOTE:
+// `std.debug.assert` only says "reached unreachable code" and, outside of
+// safe builds, turns into undefined behaviour. These always check (the
+// `debug` variants only in builds with runtime safety) and report what was
+// being checked, the values involved, where the check is and the general
+// purpose registers at that point, after the last few KiB of the dmesg ring,
+// before panicking, which adds the control registers and a backtrace. The
+// ring is replayed because what led up to the failure may have scrolled off
+// the console or gone out before anyone watched the serial port, or been
+// logged while a sink was switched off. `comptimeAssert` is the same for what
+// can be checked while compiling and fails the build instead.
+
+/// How much of the end of the dmesg ring a failure replays.
+const DMESG_TAIL = 4 * 1024;
+
+/// Fails the build with the described condition when `condition` is false.
+pub fn comptimeAssert(comptime condition: bool, comptime fmt: []const u8, comptime args: anytype) void {
+    if (!condition) {
+        @compileError(std.fmt.comptimePrint("Assertion failed: " ++ fmt, args));
+    }
+}
+
+/// Panics with the described condition when `condition` is false.
+pub inline fn that(condition: bool, comptime fmt: []const u8, args: anytype) void {
+    if (!condition) {
+        fail(snapshot(), fmt, args);
+    }
+}
+
+/// Panics when `actual` differs from `expected`, logging both.
+pub inline fn eq(expected: anytype, actual: @TypeOf(expected), comptime fmt: []const u8, args: anytype) void {
+    if (!std.meta.eql(expected, actual)) {
+        failEq(snapshot(), expected, actual, fmt, args);
+    }
+}
+
+/// Like `that`, but compiled out in builds without runtime safety.
+pub inline fn debug(condition: bool, comptime fmt: []const u8, args: anytype) void {
+    if (std.debug.runtime_safety) {
+        that(condition, fmt, args);
+    }
+}
+
+/// Like `eq`, but compiled out in builds without runtime safety.
+pub inline fn debugEq(expected: anytype, actual: @TypeOf(expected), comptime fmt: []const u8, args: anytype) void {
+    if (std.debug.runtime_safety) {
+        eq(expected, actual, fmt, args);
+    }
+}
+
+const Snapshot = struct {
+    general: @import("kernel").arch.cpu.Registers,
+    rsp: u64,
+    rbp: u64,
+    flags: u64,
+};
+
+inline fn snapshot() Snapshot {
+    const cpu = @import("kernel").arch.cpu;
+    var registers: Snapshot = undefined;
+    // before anything else here clobbers them
+    cpu.saveRegisters(&registers.general);
+    registers.rsp = cpu.stackPointer();
+    registers.rbp = @frameAddress();
+    registers.flags = cpu.readFlags();
+    return registers;
+}
+
+fn report(site: usize, comptime fmt: []const u8, args: anytype) void {
+    log.write("Assertion failed: " ++ fmt, args);
+
+    if (symbols.lookup(site)) |symbol| {
+        log.write("  at 0x{x:0>16} {s}+0x{x}", .{ site, symbol.name, symbol.offset });
+    } else {
+        log.write("  at 0x{x:0>16} ???", .{site});
+    }
+}
+
+fn logSnapshot(registers: Snapshot) void {
+    const general = registers.general;
+    log.write("  RAX=0x{x:0>16} RBX=0x{x:0>16} RCX=0x{x:0>16} RDX=0x{x:0>16}", .{ general.rax, general.rbx, general.rcx, general.rdx });
+    log.write("  RSI=0x{x:0>16} RDI=0x{x:0>16} RBP=0x{x:0>16} RSP=0x{x:0>16}", .{ general.rsi, general.rdi, registers.rbp, registers.rsp });
+    log.write("  R8 =0x{x:0>16} R9 =0x{x:0>16} R10=0x{x:0>16} R11=0x{x:0>16}", .{ general.r8, general.r9, general.r10, general.r11 });
+    log.write("  R12=0x{x:0>16} R13=0x{x:0>16} R14=0x{x:0>16} R15=0x{x:0>16}", .{ general.r12, general.r13, general.r14, general.r15 });
+    log.write("  RFLAGS=0x{x:0>16}", .{registers.flags});
+}
+
+fn replayDmesg() void {
+    // through the plain writer, so the replay isn't recorded again
+    log.writer.writeAll("Last log lines:\n") catch {};
+    dmesg.replayFrom(log.writer, dmesg.end() -| DMESG_TAIL) catch {};
+}
+
+noinline fn fail(registers: Snapshot, comptime fmt: []const u8, args: anytype) noreturn {
+    replayDmesg();
+    report(@returnAddress(), fmt, args);
+    logSnapshot(registers);
+    @panic("assertion failed");
+}
+
+noinline fn failEq(registers: Snapshot, expected: anytype, actual: @TypeOf(expected), comptime fmt: []const u8, args: anytype) noreturn {
+    replayDmesg();
+    report(@returnAddress(), fmt, args);
+    log.write("  expected {any}, got {any}", .{ expected, actual });
+    logSnapshot(registers);
+    @panic("assertion failed");
+}
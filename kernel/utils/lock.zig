@@ -1,4 +1,5 @@
 const std = @import("std");
+const arch = @import("kernel").arch;
 
 const AtomicBool = std.atomic.Value(bool);
 
@@ -18,7 +19,14 @@ pub const SpinLock = struct {
             if (!self.state.swap(true, .acquire)) {
                 return;
             }
-            while (self.state.load(.unordered)) {}
+            // a pause lets the host run a preempted holder instead, which
+            // only costs an exit when the vCPUs are pinned
+            const pause = !arch.hypervisor.vcpusArePinned();
+            while (self.state.load(.unordered)) {
+                if (pause) {
+                    std.atomic.spinLoopHint();
+                }
+            }
         }
     }
 
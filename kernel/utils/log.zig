@@ -1,37 +1,283 @@
 const std = @import("std");
 const builtin = @import("builtin");
 
-const SpinLock = @import("lock.zig").SpinLock;
+const Error = @import("kernel").Error;
 
-const Writer = std.io.Writer(*SpinLock, error{}, writeFn);
-var writerContext = SpinLock.init();
+const dmesg = @import("dmesg.zig");
 
-pub const writer = Writer{ .context = &writerContext };
+// NOTE:
+// Every line goes to each enabled sink in turn. Outputs register themselves,
+// the serial port before the first message and the framebuffer console once
+// it is up, and any sink can be switched off at runtime.
 
-fn writeFn(lock: *SpinLock, bytes: []const u8) error{}!usize {
-    lock.acquire();
-    defer lock.release();
+/// Lets a sink that can't keep up tell what is safe to throw away.
+pub const Priority = enum {
+    normal,
+    debug,
+};
 
-    const cpu = @import("kernel").arch.cpu;
-    for (bytes) |byte| {
-        cpu.writeByte(0x3f8, byte);
+pub const Sink = struct {
+    name: []const u8,
+    write: *const fn (bytes: []const u8, priority: Priority) void,
+    enabled: bool = true,
+    next: ?*Sink = null,
+};
+
+var sinks: ?*Sink = null;
+
+const Context = struct {
+    priority: Priority,
+    /// Whether to keep the output in the dmesg ring. Only log lines are,
+    /// not what is written straight to `writer` (shell output, trace dumps).
+    record: bool,
+};
+
+const Writer = std.io.Writer(Context, error{}, writeFn);
+
+pub const writer = Writer{ .context = .{ .priority = .normal, .record = false } };
+const line_writer = Writer{ .context = .{ .priority = .normal, .record = true } };
+const debug_writer = Writer{ .context = .{ .priority = .debug, .record = true } };
+
+fn writeFn(context: Context, bytes: []const u8) error{}!usize {
+    if (context.record) {
+        dmesg.append(bytes);
     }
 
+    // sinks keep interrupts off while they hold their locks, interrupt
+    // handlers log too
+    var sink = sinks;
+    while (sink) |current| : (sink = current.next) {
+        if (current.enabled) {
+            current.write(bytes, context.priority);
+        }
+    }
     return bytes.len;
 }
 
+/// Adds `sink`, which receives every line logged from now on.
+pub fn register(sink: *Sink) void {
+    const guard = @import("kernel").arch.interrupts.disable();
+    defer guard.restore();
+
+    sink.next = sinks;
+    sinks = sink;
+}
+
+/// Head of the list of sinks, follow `next` for the rest.
+pub fn firstSink() ?*const Sink {
+    return sinks;
+}
+
+pub fn findSink(name: []const u8) ?*Sink {
+    var sink = sinks;
+    while (sink) |current| : (sink = current.next) {
+        if (std.mem.eql(u8, current.name, name)) {
+            return current;
+        }
+    }
+    return null;
+}
+
+/// Turns the sink called `name` on or off.
+pub fn setSinkEnabled(name: []const u8, enabled: bool) Error!void {
+    const sink = findSink(name) orelse return error.NotFound;
+    sink.enabled = enabled;
+}
+
+/// Messages below the current level are dropped before they are formatted.
+/// `write` is not subject to it.
+pub const Level = enum(u8) {
+    debug,
+    info,
+    warn,
+};
+
+var current_level: Level = if (builtin.mode == .Debug) .debug else .info;
+
+pub fn setLevel(new_level: Level) void {
+    @atomicStore(Level, &current_level, new_level, .monotonic);
+}
+
+pub fn level() Level {
+    return @atomicLoad(Level, &current_level, .monotonic);
+}
+
+// NOTE:
+// Messages can carry a target naming the subsystem they come from, see
+// `scoped`. A target may have a level of its own that replaces the global
+// one, set at boot with `loglevel.<target>=<level>` on the kernel command
+// line (`loglevel=<level>` sets the global one) or later from the shell.
+
+const MAX_TARGETS = 16;
+const MAX_TARGET_LENGTH = 16;
+
+pub const TargetLevel = struct {
+    name_buffer: [MAX_TARGET_LENGTH]u8,
+    name_length: u8,
+    level: Level,
+
+    pub fn name(self: *const TargetLevel) []const u8 {
+        return self.name_buffer[0..self.name_length];
+    }
+};
+
+var target_levels: [MAX_TARGETS]TargetLevel = undefined;
+var target_count: usize = 0;
+
+/// Gives `target` a level of its own.
+pub fn setTargetLevel(target: []const u8, new_level: Level) Error!void {
+    if (target.len == 0 or target.len > MAX_TARGET_LENGTH) {
+        return error.InvalidArgument;
+    }
+
+    const guard = @import("kernel").arch.interrupts.disable();
+    defer guard.restore();
+
+    for (target_levels[0..target_count]) |*entry| {
+        if (std.mem.eql(u8, entry.name(), target)) {
+            entry.level = new_level;
+            return;
+        }
+    }
+    if (target_count == MAX_TARGETS) {
+        return error.OutOfMemory;
+    }
+
+    const entry = &target_levels[target_count];
+    @memcpy(entry.name_buffer[0..target.len], target);
+    entry.name_length = @intCast(target.len);
+    entry.level = new_level;
+    target_count += 1;
+}
+
+/// The targets that have a level of their own.
+pub fn targetLevels() []const TargetLevel {
+    return target_levels[0..target_count];
+}
+
+/// The level messages for `target` are held to.
+pub fn targetLevel(target: []const u8) Level {
+    for (targetLevels()) |*entry| {
+        if (std.mem.eql(u8, entry.name(), target)) {
+            return entry.level;
+        }
+    }
+    return level();
+}
+
+fn passes(target: ?[]const u8, message_level: Level) bool {
+    const threshold = if (target) |name| targetLevel(name) else level();
+    return @intFromEnum(message_level) >= @intFromEnum(threshold);
+}
+
+fn print(comptime target: ?[]const u8, comptime message_level: Level, comptime fmt: []const u8, args: anytype) void {
+    if (!passes(target, message_level)) {
+        return;
+    }
+
+    const prefix = switch (message_level) {
+        .debug => "[DEBUG]",
+        .info => "[INFO]",
+        .warn => "[WARN]",
+    };
+    const line_start = if (target) |name| prefix ++ " " ++ name ++ ": " else prefix ++ ": ";
+    const output = if (message_level == .debug) debug_writer else line_writer;
+    std.fmt.format(output, line_start ++ fmt ++ "\n", args) catch return;
+}
+
+/// Longest line `write` produces, longer ones are cut short.
+const MAX_WRITE_LENGTH = 256;
+
+/// Writes a line whatever the log level, for what must be seen: fatal
+/// errors, panics and their backtraces. The line is formatted into a buffer
+/// on the stack and handed to the sinks in one piece, so this is safe to
+/// call before the heap is up or once it is corrupt. A line that doesn't
+/// fit ends in "...".
+pub fn write(comptime fmt: []const u8, args: anytype) void {
+    var buffer: [MAX_WRITE_LENGTH]u8 = undefined;
+    var stream = std.io.fixedBufferStream(buffer[0 .. buffer.len - 1]);
+    var length = buffer.len - 1;
+    if (std.fmt.format(stream.writer(), fmt, args)) |_| {
+        length = stream.pos;
+    } else |_| {
+        @memcpy(buffer[length - 3 .. length], "...");
+    }
+    buffer[length] = '\n';
+    line_writer.writeAll(buffer[0 .. length + 1]) catch return;
+}
+
 pub fn debug(comptime fmt: []const u8, args: anytype) void {
-    std.fmt.format(writer, "[DEBUG]: " ++ fmt ++ "\n", args) catch return;
+    print(null, .debug, fmt, args);
 }
 
 pub fn info(comptime fmt: []const u8, args: anytype) void {
-    std.fmt.format(writer, "[INFO]: " ++ fmt ++ "\n", args) catch return;
+    print(null, .info, fmt, args);
 }
 
 pub fn warn(comptime fmt: []const u8, args: anytype) void {
-    std.fmt.format(writer, "[WARN]: " ++ fmt ++ "\n", args) catch return;
+    print(null, .warn, fmt, args);
 }
 
-pub fn write(comptime fmt: []const u8, args: anytype) void {
-    std.fmt.format(writer, fmt ++ "\n", args) catch return;
+/// Logging functions whose messages carry `target`, e.g.
+/// `const log = @import("kernel").utils.log.scoped(.pmm);`.
+pub fn scoped(comptime target: @Type(.EnumLiteral)) type {
+    return struct {
+        pub fn debug(comptime fmt: []const u8, args: anytype) void {
+            print(@tagName(target), .debug, fmt, args);
+        }
+
+        pub fn info(comptime fmt: []const u8, args: anytype) void {
+            print(@tagName(target), .info, fmt, args);
+        }
+
+        pub fn warn(comptime fmt: []const u8, args: anytype) void {
+            print(@tagName(target), .warn, fmt, args);
+        }
+    };
+}
+
+/// Backs `std.log`, set as `std_options.logFn` in `main.zig`. Packages that
+/// only know the standard library log through the kernel's sinks this way,
+/// their scope becoming the target. `std.log` has no counterpart of `write`
+/// and its errors are logged as warnings.
+pub fn stdLogFn(
+    comptime message_level: std.log.Level,
+    comptime scope: @Type(.EnumLiteral),
+    comptime fmt: []const u8,
+    args: anytype,
+) void {
+    const mapped: Level = switch (message_level) {
+        .err, .warn => .warn,
+        .info => .info,
+        .debug => .debug,
+    };
+    print(if (scope == .default) null else @tagName(scope), mapped, fmt, args);
+}
+
+const OPTION = "loglevel";
+
+/// Applies the log options given on the kernel command line.
+pub fn init() void {
+    const cmdline = @import("kernel").cmdline;
+
+    var options = cmdline.options();
+    while (options.next()) |option| {
+        if (!std.mem.startsWith(u8, option.key, OPTION)) {
+            continue;
+        }
+
+        const target = option.key[OPTION.len..];
+        const new_level = cmdline.parse(Level, option.value) catch {
+            warn("Ignoring unknown log level {s}={s}", .{ option.key, option.value });
+            continue;
+        };
+
+        if (target.len == 0) {
+            setLevel(new_level);
+        } else if (target[0] == '.') {
+            setTargetLevel(target[1..], new_level) catch |err| {
+                warn("Ignoring {s}: {s}", .{ option.key, @errorName(err) });
+            };
+        }
+    }
 }
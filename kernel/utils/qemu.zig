@@ -0,0 +1,71 @@
+const std = @import("std");
+const acpi = @import("kernel").acpi;
+const arch = @import("kernel").arch;
+const cmdline = @import("kernel").cmdline;
+const log = @import("log.zig");
+
+// NOTE:
+// The exit device only exists when QEMU is started with
+// `-device isa-debug-exit,iobase=<port>`, on real hardware the port may
+// belong to anything. The port defaults to what `build.zig` and the test
+// runner pass, `qemu.exit_port=<port>` on the kernel command line overrides
+// it and `qemu.exit_port=off` disables the device. Only QEMU, with or
+// without KVM, has the device, under anything else nothing is written and
+// exiting powers the machine off through ACPI instead, losing the code.
+
+/// Must match the `isa-debug-exit` device passed to QEMU in `build.zig`.
+const DEFAULT_EXIT_PORT = 0xf4;
+
+const OPTION = "qemu.exit_port";
+
+/// QEMU exits with `(code << 1) | 1`, so a successful run reports 0x21
+/// and a failed one 0x23.
+pub const ExitCode = enum(u8) {
+    success = 0x10,
+    failed = 0x11,
+};
+
+fn configuredPort() ?u16 {
+    const value = cmdline.getOr([]const u8, OPTION, "");
+    if (value.len == 0) {
+        return DEFAULT_EXIT_PORT;
+    }
+    if (std.mem.eql(u8, value, "off")) {
+        return null;
+    }
+    return cmdline.parse(u16, value) catch {
+        log.warn("Ignoring malformed {s}={s}", .{ OPTION, value });
+        return DEFAULT_EXIT_PORT;
+    };
+}
+
+/// The port of the exit device, null when there can't be one.
+pub fn exitPort() ?u16 {
+    return switch (arch.hypervisor.detected()) {
+        .kvm, .tcg => configuredPort(),
+        else => null,
+    };
+}
+
+pub fn exit(code: ExitCode) noreturn {
+    const serial = @import("kernel").drivers.serial;
+    const port = exitPort();
+    if (port == null) {
+        log.write("No QEMU exit device, powering off with code 0x{x}", .{@intFromEnum(code)});
+    }
+
+    // QEMU quits right away, anything still queued would be lost
+    serial.flush();
+    if (port) |exit_port| {
+        arch.cpu.writeByte(exit_port, @intFromEnum(code));
+    }
+
+    // not running under QEMU, or the exit device is missing
+    acpi.powerOff() catch |err| {
+        log.write("Failed to power off: {s}, halting", .{@errorName(err)});
+        serial.flush();
+    };
+    while (true) {
+        asm volatile ("hlt");
+    }
+}
@@ -0,0 +1,42 @@
+const cmdline = @import("kernel").cmdline;
+const log = @import("log.zig").scoped(.selftest);
+
+const testdev = @import("testdev.zig");
+
+// NOTE:
+// `testdev` needs QEMU's debugcon and exit devices, neither of which exists
+// on real hardware. With `selftest=on` on the kernel command line the boot
+// instead runs `tests.selftest` once the console is up and logs every result
+// and a summary, so they show on the screen and in `dmesg`, then carries on
+// to the shell. A failing test never stops the boot.
+
+pub const Summary = struct {
+    passed: usize = 0,
+    failed: usize = 0,
+};
+
+/// Whether the kernel command line asks for the self-test.
+pub fn requested() bool {
+    return cmdline.getOr(bool, "selftest", false);
+}
+
+pub fn run(tests: []const testdev.Test) Summary {
+    var summary = Summary{};
+
+    for (tests) |t| {
+        t.func() catch |err| {
+            log.warn("FAIL {s}: {s}", .{ t.name, @errorName(err) });
+            summary.failed += 1;
+            continue;
+        };
+        log.info("pass {s}", .{t.name});
+        summary.passed += 1;
+    }
+
+    if (summary.failed == 0) {
+        log.info("All {} tests passed", .{summary.passed});
+    } else {
+        log.warn("{} of {} tests FAILED", .{ summary.failed, tests.len });
+    }
+    return summary;
+}
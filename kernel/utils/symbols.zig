@@ -0,0 +1,158 @@
+const std = @import("std");
+const boot = @import("kernel").boot;
+const kernel_image = @import("kernel").kernel_image;
+
+const elf = std.elf;
+
+// NOTE:
+// Addresses are symbolized from the kernel ELF Limine loaded from disk when
+// the kernel file request is answered, since it has every symbol. Otherwise
+// we fall back to the map `tools/symbol_map.zig` writes after linking, which
+// the build copies into the `.kernel_symbols` section with `objcopy
+// --update-section`. That section is the last thing in the image, so it
+// grows to fit the map without moving anything else. Both describe
+// link-time addresses, so lookups first undo the slide reported by the
+// kernel address request.
+
+pub const MAP_MAGIC = "KSYM".*;
+
+/// Layout of the embedded map: a header, `count` entries sorted by address,
+/// then the NUL-terminated names the entries point into.
+pub const MapHeader = extern struct {
+    magic: [4]u8,
+    count: u32,
+    /// Size of the whole map, header included.
+    size: u64,
+};
+
+pub const MapEntry = extern struct {
+    address: u64,
+    size: u32,
+    name_offset: u32,
+};
+
+pub const Symbol = struct {
+    name: []const u8,
+    offset: usize,
+};
+
+fn lookupElf(image: []const u8, address: usize) ?Symbol {
+    if (image.len < @sizeOf(elf.Elf64_Ehdr)) {
+        return null;
+    }
+
+    const header: *align(1) const elf.Elf64_Ehdr = @ptrCast(image.ptr);
+    if (!std.mem.eql(u8, header.e_ident[0..4], elf.MAGIC)) {
+        return null;
+    }
+    if (header.e_shoff + @as(usize, header.e_shnum) * @sizeOf(elf.Elf64_Shdr) > image.len) {
+        return null;
+    }
+
+    const section_headers: [*]align(1) const elf.Elf64_Shdr = @ptrCast(image.ptr + header.e_shoff);
+    const sections = section_headers[0..header.e_shnum];
+
+    for (sections) |section| {
+        if (section.sh_type != elf.SHT_SYMTAB or section.sh_link >= sections.len) {
+            continue;
+        }
+
+        const strtab = sections[section.sh_link];
+        if (section.sh_offset + section.sh_size > image.len or strtab.sh_offset + strtab.sh_size > image.len) {
+            return null;
+        }
+
+        const strings = image[strtab.sh_offset..][0..strtab.sh_size];
+        const symbols: [*]align(1) const elf.Elf64_Sym = @ptrCast(image.ptr + section.sh_offset);
+
+        for (symbols[0 .. section.sh_size / @sizeOf(elf.Elf64_Sym)]) |symbol| {
+            if (symbol.st_type() != elf.STT_FUNC or symbol.st_name >= strings.len) {
+                continue;
+            }
+            if (address >= symbol.st_value and address < symbol.st_value + symbol.st_size) {
+                return .{
+                    .name = std.mem.sliceTo(strings[symbol.st_name..], 0),
+                    .offset = address - symbol.st_value,
+                };
+            }
+        }
+    }
+
+    return null;
+}
+
+/// An empty map, replaced by the real one after linking. Exported so that
+/// the linker keeps it and the compiler cannot assume it stays empty.
+export var kernel_symbol_map: MapHeader linksection(".kernel_symbols") = .{
+    .magic = MAP_MAGIC,
+    .count = 0,
+    .size = @sizeOf(MapHeader),
+};
+
+// Defined in `linker.ld`.
+extern const __kernel_symbols_start: MapHeader;
+
+/// The map in `.kernel_symbols`, as large as its header says.
+fn symbolMap() []align(8) const u8 {
+    const start: [*]align(8) const u8 = @ptrCast(&__kernel_symbols_start);
+    return start[0..__kernel_symbols_start.size];
+}
+
+fn lookupMap(address: usize) ?Symbol {
+    const map = symbolMap();
+    if (map.len < @sizeOf(MapHeader)) {
+        return null;
+    }
+    const header = std.mem.bytesToValue(MapHeader, map[0..@sizeOf(MapHeader)]);
+    if (!std.mem.eql(u8, &header.magic, &MAP_MAGIC)) {
+        return null;
+    }
+
+    const entries_size = @as(usize, header.count) * @sizeOf(MapEntry);
+    if (@sizeOf(MapHeader) + entries_size > map.len) {
+        return null;
+    }
+
+    const entries = std.mem.bytesAsSlice(MapEntry, map[@sizeOf(MapHeader)..][0..entries_size]);
+    const strings = map[@sizeOf(MapHeader) + entries_size ..];
+
+    // find the last entry starting at or before the address
+    var low: usize = 0;
+    var high: usize = entries.len;
+    while (low < high) {
+        const middle = low + (high - low) / 2;
+        if (entries[middle].address <= address) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    if (low == 0) {
+        return null;
+    }
+
+    const entry = entries[low - 1];
+    if (address >= entry.address + entry.size or entry.name_offset >= strings.len) {
+        return null;
+    }
+
+    return .{
+        .name = std.mem.sliceTo(strings[entry.name_offset..], 0),
+        .offset = address - entry.address,
+    };
+}
+
+/// Finds the function containing the (runtime) `address`.
+pub fn lookup(address: usize) ?Symbol {
+    const link_address = address -% kernel_image.slide();
+
+    if (boot.kernel_file_request.response) |response| {
+        const file = response.kernel_file;
+        if (lookupElf(file.address[0..file.size], link_address)) |symbol| {
+            return symbol;
+        }
+    }
+
+    return lookupMap(link_address);
+}
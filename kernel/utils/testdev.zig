@@ -0,0 +1,59 @@
+const std = @import("std");
+const options = @import("build_options");
+
+const qemu = @import("qemu.zig");
+
+// NOTE:
+// Results are written to QEMU's debugcon device as one line per event which
+// `tools/test_runner.zig` parses on the host:
+//
+//   testdev: begin <name>
+//   testdev: pass <name>
+//   testdev: fail <name> <error>
+//   testdev: done <passed> <failed>
+//
+// The exit code alone only says whether *something* failed.
+const DEBUGCON_PORT = 0xe9;
+
+/// Whether this kernel was built to run the in-kernel tests.
+pub const enabled = options.testing;
+
+pub const Test = struct {
+    name: []const u8,
+    func: *const fn () anyerror!void,
+};
+
+const Writer = std.io.Writer(void, error{}, writeFn);
+const writer = Writer{ .context = {} };
+
+fn writeFn(_: void, bytes: []const u8) error{}!usize {
+    const cpu = @import("kernel").arch.cpu;
+    for (bytes) |byte| {
+        cpu.writeByte(DEBUGCON_PORT, byte);
+    }
+
+    return bytes.len;
+}
+
+fn report(comptime fmt: []const u8, args: anytype) void {
+    std.fmt.format(writer, "testdev: " ++ fmt ++ "\n", args) catch return;
+}
+
+/// Runs every test in `tests`, reports the results and exits QEMU.
+pub fn run(tests: []const Test) noreturn {
+    var failed: usize = 0;
+
+    for (tests) |t| {
+        report("begin {s}", .{t.name});
+        t.func() catch |err| {
+            report("fail {s} {s}", .{ t.name, @errorName(err) });
+            failed += 1;
+            continue;
+        };
+        report("pass {s}", .{t.name});
+    }
+
+    report("done {} {}", .{ tests.len - failed, failed });
+    @import("kernel").power.shutdown.stop();
+    qemu.exit(if (failed == 0) .success else .failed);
+}
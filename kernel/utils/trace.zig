@@ -0,0 +1,115 @@
+const std = @import("std");
+
+const log = @import("log.zig");
+
+// NOTE:
+// Formatting text over a 115200 baud UART is far too slow to trace events
+// at interrupt rates, so trace events are appended to a buffer as compact
+// binary records and dumped in one go. `tools/trace_decode.zig` turns a dump
+// back into text and resolves call sites against the kernel ELF.
+//
+// A dump is a single frame:
+//
+//   "RTRC" version:u8 base:varint records:varint dropped:varint length:varint
+//   <length bytes of records>
+//
+// where every record is
+//
+//   tsc:varint site:varint argc:varint arg:varint...
+//
+// and `site` is the return address of the `event` call minus `base`.
+const MAGIC = "RTRC";
+const VERSION = 1;
+
+const MAX_ARGS = 4;
+const MAX_RECORD_SIZE = (3 + MAX_ARGS) * 10;
+
+var buffer: [64 * 1024]u8 = undefined;
+var reserved = std.atomic.Value(usize).init(0);
+var committed = std.atomic.Value(usize).init(0);
+var records = std.atomic.Value(usize).init(0);
+var dropped = std.atomic.Value(usize).init(0);
+var enabled = std.atomic.Value(bool).init(false);
+var start_tsc: u64 = 0;
+
+pub fn enable() void {
+    const cpu = @import("kernel").arch.cpu;
+    start_tsc = cpu.readTsc();
+    enabled.store(true, .release);
+}
+
+pub fn disable() void {
+    enabled.store(false, .release);
+}
+
+fn encode(out: []u8, value: u64) usize {
+    var remaining = value;
+    var i: usize = 0;
+    while (remaining >= 0x80) : (i += 1) {
+        out[i] = @as(u8, @truncate(remaining)) | 0x80;
+        remaining >>= 7;
+    }
+    out[i] = @truncate(remaining);
+    return i + 1;
+}
+
+/// Records an event with up to four integer arguments. This is lock-free so
+/// that it can be called from interrupt handlers; when the buffer is full
+/// the event is counted as dropped.
+pub noinline fn event(args: []const u64) void {
+    if (!enabled.load(.acquire)) {
+        return;
+    }
+
+    const cpu = @import("kernel").arch.cpu;
+    const kernel_image = @import("kernel").kernel_image;
+    const argc = @min(args.len, MAX_ARGS);
+
+    var record: [MAX_RECORD_SIZE]u8 = undefined;
+    var len: usize = 0;
+    len += encode(record[len..], cpu.readTsc() -% start_tsc);
+    len += encode(record[len..], @returnAddress() -% kernel_image.slide() -% kernel_image.LINK_BASE);
+    len += encode(record[len..], argc);
+    for (args[0..argc]) |arg| {
+        len += encode(record[len..], arg);
+    }
+
+    // Reservations only grow, so once one does not fit neither will any of
+    // the following ones and the committed records form a prefix of `buffer`.
+    const offset = reserved.fetchAdd(len, .monotonic);
+    if (offset + len > buffer.len) {
+        _ = dropped.fetchAdd(1, .monotonic);
+        return;
+    }
+
+    @memcpy(buffer[offset..][0..len], record[0..len]);
+    _ = committed.fetchAdd(len, .release);
+    _ = records.fetchAdd(1, .monotonic);
+}
+
+/// Writes the buffered records as one binary frame to the log output and
+/// starts over with an empty buffer. Tracing must be disabled while dumping.
+pub fn dump() void {
+    std.debug.assert(!enabled.load(.acquire));
+
+    const kernel_image = @import("kernel").kernel_image;
+    const length = committed.load(.acquire);
+
+    var header: [MAGIC.len + 1 + 4 * 10]u8 = undefined;
+    @memcpy(header[0..MAGIC.len], MAGIC);
+    header[MAGIC.len] = VERSION;
+
+    var len: usize = MAGIC.len + 1;
+    len += encode(header[len..], kernel_image.LINK_BASE);
+    len += encode(header[len..], records.load(.acquire));
+    len += encode(header[len..], dropped.load(.acquire));
+    len += encode(header[len..], length);
+
+    log.writer.writeAll(header[0..len]) catch return;
+    log.writer.writeAll(buffer[0..length]) catch return;
+
+    reserved.store(0, .release);
+    committed.store(0, .release);
+    records.store(0, .release);
+    dropped.store(0, .release);
+}
@@ -1,2 +1,11 @@
 pub const lock = @import("lock.zig");
 pub const log = @import("log.zig");
+pub const dmesg = @import("dmesg.zig");
+pub const backtrace = @import("backtrace.zig");
+pub const kassert = @import("kassert.zig");
+pub const symbols = @import("symbols.zig");
+pub const fault_injection = @import("fault_injection.zig");
+pub const qemu = @import("qemu.zig");
+pub const testdev = @import("testdev.zig");
+pub const selftest = @import("selftest.zig");
+pub const trace = @import("trace.zig");
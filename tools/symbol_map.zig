@@ -0,0 +1,99 @@
+const std = @import("std");
+
+const elf = std.elf;
+
+// Must match `kernel/utils/symbols.zig`.
+const MAP_MAGIC = "KSYM";
+const MAP_HEADER_SIZE = 16;
+
+const MapEntry = extern struct {
+    address: u64,
+    size: u32,
+    name_offset: u32,
+};
+
+const Symbol = struct {
+    name: []const u8,
+    address: u64,
+    size: u64,
+
+    fn lessThan(_: void, a: Symbol, b: Symbol) bool {
+        return a.address < b.address;
+    }
+};
+
+/// Writes a sorted map of the kernel ELF's function symbols, which the build
+/// copies into the kernel's `.kernel_symbols` section. The kernel uses it to
+/// symbolize backtraces when Limine does not hand over its own ELF file.
+pub fn main() !void {
+    var gpa = std.heap.GeneralPurposeAllocator(.{}){};
+    defer _ = gpa.deinit();
+    var arena = std.heap.ArenaAllocator.init(gpa.allocator());
+    defer arena.deinit();
+    const allocator = arena.allocator();
+
+    const args = try std.process.argsAlloc(allocator);
+    if (args.len != 3) {
+        std.debug.print("usage: {s} <kernel> <output>\n", .{args[0]});
+        std.process.exit(2);
+    }
+
+    const image = try std.fs.cwd().readFileAlloc(allocator, args[1], 1 << 30);
+
+    var stream = std.io.fixedBufferStream(image);
+    const header = try elf.Header.read(&stream);
+
+    var sections = std.ArrayList(elf.Elf64_Shdr).init(allocator);
+    var iterator = header.section_header_iterator(&stream);
+    while (try iterator.next()) |section| {
+        try sections.append(section);
+    }
+
+    var symbols = std.ArrayList(Symbol).init(allocator);
+
+    for (sections.items) |section| {
+        if (section.sh_type != elf.SHT_SYMTAB) {
+            continue;
+        }
+
+        const strtab = sections.items[section.sh_link];
+        const strings = image[strtab.sh_offset..][0..strtab.sh_size];
+
+        var offset = section.sh_offset;
+        while (offset + @sizeOf(elf.Elf64_Sym) <= section.sh_offset + section.sh_size) : (offset += @sizeOf(elf.Elf64_Sym)) {
+            const symbol = std.mem.bytesToValue(elf.Elf64_Sym, image[offset..][0..@sizeOf(elf.Elf64_Sym)]);
+            if (symbol.st_type() != elf.STT_FUNC or symbol.st_value == 0) {
+                continue;
+            }
+            try symbols.append(.{
+                .name = std.mem.sliceTo(strings[symbol.st_name..], 0),
+                .address = symbol.st_value,
+                .size = symbol.st_size,
+            });
+        }
+    }
+
+    std.mem.sort(Symbol, symbols.items, {}, Symbol.lessThan);
+
+    var map = std.ArrayList(u8).init(allocator);
+    var strings = std.ArrayList(u8).init(allocator);
+
+    try map.appendSlice(MAP_MAGIC);
+    try map.writer().writeInt(u32, @intCast(symbols.items.len), .little);
+    // the size of the whole map, filled in once the names are appended
+    try map.writer().writeInt(u64, 0, .little);
+    for (symbols.items) |symbol| {
+        const entry = MapEntry{
+            .address = symbol.address,
+            .size = @intCast(@min(symbol.size, std.math.maxInt(u32))),
+            .name_offset = @intCast(strings.items.len),
+        };
+        try map.appendSlice(std.mem.asBytes(&entry));
+        try strings.appendSlice(symbol.name);
+        try strings.append(0);
+    }
+    try map.appendSlice(strings.items);
+    std.mem.writeInt(u64, map.items[8..MAP_HEADER_SIZE], map.items.len, .little);
+
+    try std.fs.cwd().writeFile(args[2], map.items);
+}
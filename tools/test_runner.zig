@@ -0,0 +1,84 @@
+const std = @import("std");
+
+// QEMU reports `(code << 1) | 1` for writes to isa-debug-exit, see
+// `kernel/utils/qemu.zig`.
+const QEMU_EXIT_SUCCESS = (0x10 << 1) | 1;
+
+/// Boots the test ISO in QEMU, parses the `testdev:` lines the kernel writes
+/// to debugcon and turns them into a per-test report and an exit status.
+pub fn main() !void {
+    var gpa = std.heap.GeneralPurposeAllocator(.{}){};
+    defer _ = gpa.deinit();
+    const allocator = gpa.allocator();
+
+    const args = try std.process.argsAlloc(allocator);
+    defer std.process.argsFree(allocator, args);
+
+    if (args.len != 3) {
+        std.debug.print("usage: {s} <qemu> <iso>\n", .{args[0]});
+        std.process.exit(2);
+    }
+
+    var qemu = std.ChildProcess.init(&.{
+        args[1],
+        "-display",
+        "none",
+        "-serial",
+        "file:.qemu-test-serial.txt",
+        "-debugcon",
+        "stdio",
+        "-M",
+        "smm=off",
+        "-device",
+        "isa-debug-exit,iobase=0xf4,iosize=0x0f",
+        "-cdrom",
+        args[2],
+    }, allocator);
+    qemu.stdout_behavior = .Pipe;
+
+    try qemu.spawn();
+
+    var passed: usize = 0;
+    var failed: usize = 0;
+    var finished = false;
+
+    var line_buffer: [1024]u8 = undefined;
+    const reader = qemu.stdout.?.reader();
+    while (try reader.readUntilDelimiterOrEof(&line_buffer, '\n')) |line| {
+        const prefix = "testdev: ";
+        if (!std.mem.startsWith(u8, line, prefix)) {
+            continue;
+        }
+
+        var tokens = std.mem.tokenizeScalar(u8, line[prefix.len..], ' ');
+        const event = tokens.next() orelse continue;
+
+        if (std.mem.eql(u8, event, "pass")) {
+            passed += 1;
+            std.debug.print("PASS {s}\n", .{tokens.rest()});
+        } else if (std.mem.eql(u8, event, "fail")) {
+            failed += 1;
+            const name = tokens.next() orelse "?";
+            std.debug.print("FAIL {s} ({s})\n", .{ name, tokens.rest() });
+        } else if (std.mem.eql(u8, event, "done")) {
+            finished = true;
+        }
+    }
+
+    const term = try qemu.wait();
+    const exit_code: u32 = switch (term) {
+        .Exited => |code| code,
+        else => 0,
+    };
+
+    std.debug.print("{} passed, {} failed\n", .{ passed, failed });
+
+    if (!finished) {
+        std.debug.print("kernel stopped before reporting all results\n", .{});
+        std.process.exit(1);
+    }
+
+    if (failed != 0 or exit_code != QEMU_EXIT_SUCCESS) {
+        std.process.exit(1);
+    }
+}
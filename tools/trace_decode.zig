@@ -0,0 +1,163 @@
+const std = @import("std");
+
+// Must match `kernel/utils/trace.zig`.
+const MAGIC = "RTRC";
+const VERSION = 1;
+
+const Symbol = struct {
+    name: []const u8,
+    address: u64,
+    size: u64,
+
+    fn lessThan(_: void, a: Symbol, b: Symbol) bool {
+        return a.address < b.address;
+    }
+};
+
+/// Decodes binary trace frames dumped by the kernel (e.g. a serial capture)
+/// and prints one line per event with its call site resolved against the
+/// kernel ELF.
+pub fn main() !void {
+    var gpa = std.heap.GeneralPurposeAllocator(.{}){};
+    defer _ = gpa.deinit();
+    var arena = std.heap.ArenaAllocator.init(gpa.allocator());
+    defer arena.deinit();
+    const allocator = arena.allocator();
+
+    const args = try std.process.argsAlloc(allocator);
+    if (args.len != 3) {
+        std.debug.print("usage: {s} <kernel-elf> <capture>\n", .{args[0]});
+        std.process.exit(2);
+    }
+
+    const symbols = try loadSymbols(allocator, args[1]);
+    const capture = try std.fs.cwd().readFileAlloc(allocator, args[2], 1 << 30);
+
+    const stdout = std.io.getStdOut().writer();
+
+    var rest: []const u8 = capture;
+    while (std.mem.indexOf(u8, rest, MAGIC)) |start| {
+        rest = try decodeFrame(stdout, symbols, rest[start + MAGIC.len ..]);
+    }
+}
+
+const Reader = struct {
+    bytes: []const u8,
+    position: usize = 0,
+
+    fn byte(self: *Reader) !u8 {
+        if (self.position >= self.bytes.len) {
+            return error.Truncated;
+        }
+        defer self.position += 1;
+        return self.bytes[self.position];
+    }
+
+    fn varint(self: *Reader) !u64 {
+        var value: u64 = 0;
+        var shift: u7 = 0;
+        while (shift < 64) : (shift += 7) {
+            const b = try self.byte();
+            value |= @as(u64, b & 0x7f) << @intCast(shift);
+            if (b & 0x80 == 0) {
+                return value;
+            }
+        }
+        return error.Overlong;
+    }
+};
+
+fn decodeFrame(out: anytype, symbols: []const Symbol, bytes: []const u8) ![]const u8 {
+    var reader = Reader{ .bytes = bytes };
+
+    const version = try reader.byte();
+    if (version != VERSION) {
+        std.debug.print("skipping frame with unknown version {}\n", .{version});
+        return bytes;
+    }
+
+    const base = try reader.varint();
+    const records = try reader.varint();
+    const dropped = try reader.varint();
+    const length = try reader.varint();
+
+    try out.print("frame: {} events, {} dropped, {} bytes\n", .{ records, dropped, length });
+
+    for (0..records) |_| {
+        const tsc = try reader.varint();
+        const site = base +% try reader.varint();
+        const argc = try reader.varint();
+
+        try out.print("{d:>16} ", .{tsc});
+        try printSite(out, symbols, site);
+        for (0..argc) |_| {
+            try out.print(" 0x{x}", .{try reader.varint()});
+        }
+        try out.writeByte('\n');
+    }
+
+    return bytes[reader.position..];
+}
+
+fn printSite(out: anytype, symbols: []const Symbol, address: u64) !void {
+    var low: usize = 0;
+    var high: usize = symbols.len;
+    while (low < high) {
+        const middle = low + (high - low) / 2;
+        if (symbols[middle].address <= address) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    if (low > 0) {
+        const symbol = symbols[low - 1];
+        if (address < symbol.address + @max(symbol.size, 1)) {
+            return out.print("{s}+0x{x}", .{ symbol.name, address - symbol.address });
+        }
+    }
+
+    try out.print("0x{x}", .{address});
+}
+
+fn loadSymbols(allocator: std.mem.Allocator, path: []const u8) ![]Symbol {
+    const file = try std.fs.cwd().openFile(path, .{});
+    defer file.close();
+
+    const header = try std.elf.Header.read(file);
+
+    var sections = std.ArrayList(std.elf.Elf64_Shdr).init(allocator);
+    var iterator = header.section_header_iterator(file);
+    while (try iterator.next()) |section| {
+        try sections.append(section);
+    }
+
+    var symbols = std.ArrayList(Symbol).init(allocator);
+    for (sections.items) |section| {
+        if (section.sh_type != std.elf.SHT_SYMTAB) {
+            continue;
+        }
+
+        const strtab_section = sections.items[section.sh_link];
+        const strtab = try allocator.alloc(u8, strtab_section.sh_size);
+        _ = try file.preadAll(strtab, strtab_section.sh_offset);
+
+        const raw = try allocator.alignedAlloc(u8, @alignOf(std.elf.Elf64_Sym), section.sh_size);
+        _ = try file.preadAll(raw, section.sh_offset);
+
+        for (std.mem.bytesAsSlice(std.elf.Elf64_Sym, raw)) |sym| {
+            if (sym.st_type() != std.elf.STT_FUNC or sym.st_value == 0) {
+                continue;
+            }
+            try symbols.append(.{
+                .name = std.mem.sliceTo(strtab[sym.st_name..], 0),
+                .address = sym.st_value,
+                .size = sym.st_size,
+            });
+        }
+    }
+
+    std.mem.sort(Symbol, symbols.items, {}, Symbol.lessThan);
+    return symbols.items;
+}